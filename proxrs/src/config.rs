@@ -0,0 +1,514 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use regex::Regex;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_yaml::Mapping;
+use serde_yaml::Value;
+use tracing::warn;
+
+use crate::protocol::Proxy;
+
+/// 内置的策略，无需在配置中定义
+const BUILTIN_POLICIES: [&str; 5] = ["DIRECT", "REJECT", "REJECT-DROP", "PASS", "COMPATIBLE"];
+
+#[derive(Debug)]
+pub struct ConfigError {
+    message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl ConfigError {
+    fn new(message: impl Into<String>) -> Self {
+        ConfigError {
+            message: message.into(),
+        }
+    }
+}
+
+/// 完整的 clash 配置，proxies、proxy-groups、rules 解析为结构体，其余配置项原样保留
+#[derive(Debug, Clone, Default)]
+pub struct ClashConfig {
+    pub proxies: Vec<Proxy>,
+    pub proxy_groups: Vec<ProxyGroup>,
+    pub rules: Vec<Rule>,
+    /// 原始配置，输出时保持原有的键顺序
+    pub extra: Mapping,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProxyGroupType {
+    Select,
+    UrlTest,
+    Fallback,
+    LoadBalance,
+    Relay,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProxyGroup {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub group_type: ProxyGroupType,
+    #[serde(default)]
+    pub proxies: Vec<String>,
+    #[serde(default, rename = "use", skip_serializing_if = "Vec::is_empty")]
+    pub providers: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interval: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tolerance: Option<u32>,
+    /// 节点名称正则，添加节点时自动加入匹配的节点
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<String>,
+    /// 其他字段，如 lazy、strategy、include-all 等
+    #[serde(flatten)]
+    pub extra: Mapping,
+}
+
+impl ProxyGroup {
+    /// 策略组是否会从 provider 或全部节点中自动引入节点
+    pub fn has_implicit_proxies(&self) -> bool {
+        !self.providers.is_empty()
+            || [
+                "include-all",
+                "include-all-proxies",
+                "include-all-providers",
+            ]
+            .iter()
+            .any(|key| {
+                self.extra
+                    .get(*key)
+                    .and_then(Value::as_bool)
+                    .unwrap_or(false)
+            })
+    }
+}
+
+/// 规则，如 `DOMAIN-SUFFIX,google.com,PROXY`、`GEOIP,CN,DIRECT,no-resolve`、`MATCH,PROXY`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Rule {
+    pub rule_type: String,
+    /// MATCH 规则没有匹配内容
+    pub payload: Option<String>,
+    pub target: String,
+    /// 附加参数，如 no-resolve
+    pub options: Vec<String>,
+}
+
+impl FromStr for Rule {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = split_rule(s).into_iter();
+        let rule_type = parts.next().unwrap_or_default();
+        let payload = if rule_type.eq_ignore_ascii_case("MATCH") {
+            None
+        } else {
+            parts.next()
+        };
+        let target = parts.next();
+        match target {
+            Some(target) if !rule_type.is_empty() && !target.is_empty() => Ok(Rule {
+                rule_type,
+                payload,
+                target,
+                options: parts.collect(),
+            }),
+            _ => Err(ConfigError::new(format!("Invalid rule: {}", s))),
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.rule_type)?;
+        if let Some(payload) = &self.payload {
+            write!(f, ",{}", payload)?;
+        }
+        write!(f, ",{}", self.target)?;
+        for option in &self.options {
+            write!(f, ",{}", option)?;
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<String> for Rule {
+    type Error = ConfigError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Rule> for String {
+    fn from(rule: Rule) -> Self {
+        rule.to_string()
+    }
+}
+
+/// 按逗号切分规则，逻辑规则 AND/OR/NOT 括号中的逗号不切分
+fn split_rule(rule: &str) -> Vec<String> {
+    let mut parts = vec![];
+    let mut depth = 0;
+    let mut current = String::new();
+    for c in rule.chars() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(current.trim().to_string());
+                current.clear();
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+    parts.push(current.trim().to_string());
+    parts
+}
+
+impl ClashConfig {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        let content = fs::read_to_string(&path).map_err(|e| {
+            ConfigError::new(format!("Error reading {}: {}", path.as_ref().display(), e))
+        })?;
+        content.parse()
+    }
+
+    pub fn to_yaml(&self) -> Result<String, ConfigError> {
+        let mut yaml = self.extra.clone();
+        let proxies = self
+            .proxies
+            .iter()
+            .map(|proxy| {
+                proxy
+                    .to_clash_value()
+                    .map_err(|e| ConfigError::new(e.to_string()))
+                    .and_then(|v| {
+                        serde_yaml::to_value(v).map_err(|e| ConfigError::new(e.to_string()))
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        // 已存在的键原地替换，保持模板中的顺序
+        yaml.insert("proxies".into(), Value::Sequence(proxies));
+        yaml.insert("proxy-groups".into(), to_yaml_value(&self.proxy_groups)?);
+        yaml.insert("rules".into(), to_yaml_value(&self.rules)?);
+        serde_yaml::to_string(&yaml).map_err(|e| ConfigError::new(e.to_string()))
+    }
+
+    pub fn group(&self, name: &str) -> Option<&ProxyGroup> {
+        self.proxy_groups.iter().find(|group| group.name == name)
+    }
+
+    /// 添加节点，并加入 filter 匹配的策略组，匹配到节点时移除占位的 PROXY
+    pub fn add_proxies(&mut self, proxies: &[Proxy]) {
+        self.proxies.extend(proxies.iter().cloned());
+        for group in &mut self.proxy_groups {
+            let Some(filter) = &group.filter else {
+                continue;
+            };
+            let regex = match Regex::new(filter) {
+                Ok(regex) => regex,
+                Err(e) => {
+                    warn!("策略组 {} 的 filter 不合法，已跳过：{}", group.name, e);
+                    continue;
+                }
+            };
+            let mut removed_default = false;
+            for proxy in proxies {
+                if !regex.is_match(proxy.get_name()) {
+                    continue;
+                }
+                if !removed_default && group.proxies.first().is_some_and(|p| p == "PROXY") {
+                    group.proxies.remove(0);
+                    removed_default = true;
+                }
+                group.proxies.push(proxy.get_name().to_string());
+            }
+        }
+    }
+
+    /// 移除策略组中不存在的节点及策略组引用，没有任何节点的策略组使用 DIRECT 兜底
+    pub fn prune_groups(&mut self) {
+        let mut names = self
+            .proxies
+            .iter()
+            .map(|proxy| proxy.get_name().to_string())
+            .collect::<Vec<_>>();
+        names.extend(self.proxy_groups.iter().map(|group| group.name.clone()));
+        names.extend(BUILTIN_POLICIES.iter().map(|name| name.to_string()));
+
+        for group in &mut self.proxy_groups {
+            group.proxies.retain(|name| {
+                let exists = names.contains(name);
+                if !exists {
+                    warn!("策略组 {} 引用的 {} 不存在，已移除", group.name, name);
+                }
+                exists
+            });
+            if group.proxies.is_empty() && !group.has_implicit_proxies() {
+                group.proxies.push("DIRECT".to_string());
+            }
+        }
+    }
+
+    /// 检查策略组及规则的引用关系，返回发现的问题，用于在启动内核前发现模板中的错误
+    pub fn lint(&self) -> Vec<String> {
+        let mut issues = vec![];
+        let mut names = HashSet::new();
+        for group in &self.proxy_groups {
+            if !names.insert(group.name.as_str()) {
+                issues.push(format!("duplicate proxy group {}", group.name));
+            }
+            if let Some(Err(e)) = group.filter.as_deref().map(Regex::new) {
+                issues.push(format!(
+                    "invalid filter of proxy group {}: {}",
+                    group.name, e
+                ));
+            }
+        }
+        names.extend(self.proxies.iter().map(|proxy| proxy.get_name()));
+        names.extend(BUILTIN_POLICIES);
+
+        for group in &self.proxy_groups {
+            for name in &group.proxies {
+                if !names.contains(name.as_str()) {
+                    issues.push(format!(
+                        "proxy group {} references unknown {}",
+                        group.name, name
+                    ));
+                }
+            }
+        }
+        for rule in &self.rules {
+            // SUB-RULE 的目标是子规则名称
+            if rule.rule_type.eq_ignore_ascii_case("SUB-RULE") {
+                continue;
+            }
+            if !names.contains(rule.target.as_str()) {
+                issues.push(format!(
+                    "rule {} targets unknown policy {}",
+                    rule, rule.target
+                ));
+            }
+        }
+        issues
+    }
+}
+
+impl FromStr for ClashConfig {
+    type Err = ConfigError;
+
+    fn from_str(content: &str) -> Result<Self, Self::Err> {
+        let mut extra = parse_yaml(content)
+            .and_then(serde_yaml::from_value::<Mapping>)
+            .map_err(|e| ConfigError::new(format!("Invalid clash config: {}", e)))?;
+
+        let mut proxies = vec![];
+        if let Some(Value::Sequence(values)) = extra.get("proxies") {
+            for value in values {
+                let proxy = serde_yaml::from_value::<serde_json::Value>(value.clone())
+                    .map_err(|e| ConfigError::new(e.to_string()))
+                    .and_then(|v| {
+                        Proxy::from_clash_value(&v).map_err(|e| ConfigError::new(e.to_string()))
+                    })
+                    .and_then(|p| {
+                        p.validate()
+                            .map(|_| p)
+                            .map_err(|e| ConfigError::new(e.to_string()))
+                    });
+                match proxy {
+                    Ok(proxy) => proxies.push(proxy),
+                    Err(e) => warn!("跳过无法解析的节点 {:?}：{}", value, e),
+                }
+            }
+        }
+
+        let proxy_groups = match extra.remove("proxy-groups") {
+            Some(Value::Null) | None => vec![],
+            Some(value) => serde_yaml::from_value(value)
+                .map_err(|e| ConfigError::new(format!("Invalid proxy-groups: {}", e)))?,
+        };
+        let rules = match extra.remove("rules") {
+            Some(Value::Null) | None => vec![],
+            Some(value) => serde_yaml::from_value(value)
+                .map_err(|e| ConfigError::new(format!("Invalid rules: {}", e)))?,
+        };
+        // 占位保留键的位置，输出时原地替换
+        extra.insert("proxies".into(), Value::Null);
+        extra.insert("proxy-groups".into(), Value::Null);
+        extra.insert("rules".into(), Value::Null);
+
+        Ok(ClashConfig {
+            proxies,
+            proxy_groups,
+            rules,
+            extra,
+        })
+    }
+}
+
+/// 解析 yaml 并展开 `<<:` 合并键，部分订阅用锚点及合并键共享 TLS 等选项
+/// 锚点与别名在解析时已由 serde_yaml 展开，合并键需要单独处理
+pub fn parse_yaml(content: &str) -> Result<Value, serde_yaml::Error> {
+    let mut value = serde_yaml::from_str::<Value>(content)?;
+    value.apply_merge()?;
+    Ok(value)
+}
+
+fn to_yaml_value<T: Serialize>(value: &T) -> Result<Value, ConfigError> {
+    serde_yaml::to_value(value).map_err(|e| ConfigError::new(e.to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CONFIG: &str = r#"
+mixed-port: 7890
+mode: rule
+proxies:
+  - {name: HK 01, type: ss, server: 1.1.1.1, port: 443, cipher: aes-128-gcm, password: pwd}
+proxy-groups:
+  - name: PROXY
+    type: select
+    proxies: [HK, US, Missing]
+  - name: HK
+    type: url-test
+    url: http://www.google.com/generate_204
+    interval: 600
+    lazy: true
+    proxies: [PROXY]
+    filter: "(?i)港|HK"
+  - name: US
+    type: url-test
+    proxies: []
+    filter: "(?i)US"
+rules:
+  - AND,((DOMAIN,baidu.com),(NETWORK,UDP)),REJECT
+  - GEOIP,CN,DIRECT,no-resolve
+  - MATCH,PROXY
+rule-providers:
+  ads: {type: http, behavior: domain}
+"#;
+
+    #[test]
+    fn test_parse_rule() {
+        let rule = "GEOIP,CN,DIRECT,no-resolve".parse::<Rule>().unwrap();
+        assert_eq!(rule.rule_type, "GEOIP");
+        assert_eq!(rule.payload.as_deref(), Some("CN"));
+        assert_eq!(rule.target, "DIRECT");
+        assert_eq!(rule.options, vec!["no-resolve"]);
+
+        let rule = "MATCH,PROXY".parse::<Rule>().unwrap();
+        assert_eq!(rule.payload, None);
+        assert_eq!(rule.target, "PROXY");
+
+        let logic = "OR,((DOMAIN,a.com),(DOMAIN-SUFFIX,b.com)),PROXY";
+        let rule = logic.parse::<Rule>().unwrap();
+        assert_eq!(
+            rule.payload.as_deref(),
+            Some("((DOMAIN,a.com),(DOMAIN-SUFFIX,b.com))")
+        );
+        assert_eq!(rule.to_string(), logic);
+
+        assert!("DOMAIN,google.com".parse::<Rule>().is_err());
+    }
+
+    #[test]
+    fn test_parse_config() {
+        let config = CONFIG.parse::<ClashConfig>().unwrap();
+        assert_eq!(config.proxies.len(), 1);
+        assert_eq!(config.proxy_groups.len(), 3);
+        assert_eq!(config.rules.len(), 3);
+        let hk = config.group("HK").unwrap();
+        assert_eq!(hk.group_type, ProxyGroupType::UrlTest);
+        assert_eq!(hk.interval, Some(600));
+        assert_eq!(hk.extra.get("lazy"), Some(&Value::Bool(true)));
+    }
+
+    #[test]
+    fn test_add_proxies_and_prune() {
+        let mut config = CONFIG.parse::<ClashConfig>().unwrap();
+        let proxy = Proxy::from_link(
+            "ss://YWVzLTEyOC1nY206ZDljNTc3MzI4ZmIzNDlmZQ==@120.232.73.68:40676#%E9%A6%99%E6%B8%AF",
+        )
+        .unwrap();
+        config.add_proxies(&[proxy]);
+        config.prune_groups();
+
+        assert_eq!(config.group("HK").unwrap().proxies, vec!["香港"]);
+        assert_eq!(config.group("US").unwrap().proxies, vec!["DIRECT"]);
+        assert_eq!(config.group("PROXY").unwrap().proxies, vec!["HK", "US"]);
+    }
+
+    #[test]
+    fn test_lint() {
+        let config = CONFIG.parse::<ClashConfig>().unwrap();
+        assert_eq!(
+            config.lint(),
+            vec!["proxy group PROXY references unknown Missing"]
+        );
+
+        let config = r#"
+proxy-groups:
+  - {name: A, type: select, proxies: [DIRECT], filter: "(unclosed"}
+  - {name: A, type: select, proxies: [HK 01]}
+rules:
+  - MATCH,B
+"#
+        .parse::<ClashConfig>()
+        .unwrap();
+        let issues = config.lint();
+        assert_eq!(issues.len(), 4);
+        assert!(issues[0].starts_with("invalid filter of proxy group A"));
+        assert_eq!(issues[1], "duplicate proxy group A");
+        assert_eq!(issues[2], "proxy group A references unknown HK 01");
+        assert_eq!(issues[3], "rule MATCH,B targets unknown policy B");
+    }
+
+    #[test]
+    fn test_to_yaml_keeps_order() {
+        let config = CONFIG.parse::<ClashConfig>().unwrap();
+        let yaml = config.to_yaml().unwrap();
+        let keys = serde_yaml::from_str::<Mapping>(&yaml)
+            .unwrap()
+            .keys()
+            .filter_map(|k| k.as_str().map(String::from))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            vec![
+                "mixed-port",
+                "mode",
+                "proxies",
+                "proxy-groups",
+                "rules",
+                "rule-providers"
+            ]
+        );
+
+        let reparsed = yaml.parse::<ClashConfig>().unwrap();
+        assert_eq!(reparsed.proxies, config.proxies);
+        assert_eq!(reparsed.proxy_groups, config.proxy_groups);
+        assert_eq!(reparsed.rules, config.rules);
+    }
+}
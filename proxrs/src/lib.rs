@@ -1,18 +1,16 @@
+//! 解析并转换 clash、分享链接、base64 订阅、sing-box 等格式的代理节点
+//!
+//! ```
+//! use proxrs::protocol::Proxy;
+//!
+//! let proxy = Proxy::from_link("ss://YWVzLTEyOC1nY206cGFzc3dvcmQ=@1.2.3.4:8388#HK").unwrap();
+//! let clash = proxy.to_clash_value().unwrap();
+//! assert_eq!(clash["type"], "ss");
+//! assert_eq!(Proxy::from_clash_value(&clash).unwrap(), proxy);
+//! ```
+
 pub mod base64;
+pub mod config;
 pub mod protocol;
+pub mod singbox;
 pub mod sub;
-
-pub fn add(left: u64, right: u64) -> u64 {
-    left + right
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
-    }
-}
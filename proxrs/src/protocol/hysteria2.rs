@@ -1,4 +1,3 @@
-use std::any::Any;
 use std::collections::HashMap;
 use std::hash::Hash;
 use std::hash::Hasher;
@@ -7,21 +6,41 @@ use serde::Deserialize;
 use serde::Serialize;
 use serde_json::Error;
 
+use crate::protocol::deserialize_bool_or_string;
 use crate::protocol::deserialize_from_string;
+use crate::protocol::deserialize_option_u16_or_string;
+use crate::protocol::deserialize_string;
 use crate::protocol::deserialize_u16_or_string;
+use crate::protocol::deserialize_vec_or_string;
+use crate::protocol::format_server_port;
+use crate::protocol::parse_server_port;
+use crate::protocol::strip_scheme;
+use crate::protocol::url_decode;
+use crate::protocol::validate::check_not_empty;
+use crate::protocol::validate::check_one_of;
+use crate::protocol::validate::check_port;
+use crate::protocol::validate::check_server;
 use crate::protocol::ProxyAdapter;
 use crate::protocol::UnsupportedLinkError;
+use crate::protocol::ValidationError;
 
 #[derive(Deserialize, Serialize, Debug, Eq, Clone)]
 pub struct Hysteria2 {
+    #[serde(deserialize_with = "deserialize_string")]
     pub name: String,
     pub server: String,
+    #[serde(deserialize_with = "deserialize_string")]
     pub password: String,
     #[serde(deserialize_with = "deserialize_u16_or_string")]
     pub port: u16,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub ports: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none", rename = "hop-interval")]
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "hop-interval",
+        deserialize_with = "deserialize_option_u16_or_string"
+    )]
     pub hop_interval: Option<u16>,
     #[serde(
         default,
@@ -37,17 +56,30 @@ pub struct Hysteria2 {
     pub down: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub obfs: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        rename = "obfs-password",
+        alias = "obfs_password"
+    )]
     pub obfs_password: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub sni: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none", rename = "skip-cert-verify")]
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "skip-cert-verify",
+        deserialize_with = "deserialize_bool_or_string"
+    )]
     pub skip_cert_verify: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub fingerprint: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none", rename = "client-fingerprint")]
     pub client_fingerprint: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "deserialize_vec_or_string"
+    )]
     pub alpn: Option<Vec<String>>,
 }
 
@@ -71,12 +103,10 @@ impl ProxyAdapter for Hysteria2 {
     }
 
     fn to_link(&self) -> String {
-        let mut params = "insecure=".to_string()
-            + if self.skip_cert_verify.unwrap_or(false) {
-                "1"
-            } else {
-                "0"
-            };
+        let mut params = String::new();
+        if let Some(skip_cert_verify) = self.skip_cert_verify {
+            params += &format!("&insecure={}", skip_cert_verify as u8);
+        }
         if let Some(sni) = &self.sni {
             params += &format!("&sni={}", urlencoding::encode(sni));
         }
@@ -84,7 +114,7 @@ impl ProxyAdapter for Hysteria2 {
             params += &format!("&obfs={}", obfs);
         }
         if let Some(obfs_password) = &self.obfs_password {
-            params += &format!("&obfs-password={}", obfs_password);
+            params += &format!("&obfs-password={}", urlencoding::encode(obfs_password));
         }
         if let Some(ports) = &self.ports {
             params += &format!("&mport={}", ports);
@@ -99,11 +129,10 @@ impl ProxyAdapter for Hysteria2 {
             params += &format!("&alpn={}", alpn.join(","));
         }
         format!(
-            "hysteria2://{}@{}:{}/?{}#{}",
+            "hysteria2://{}@{}/?{}#{}",
             &self.password,
-            &self.server,
-            &self.port,
-            &params,
+            format_server_port(&self.server, self.port),
+            params.trim_start_matches('&'),
             urlencoding::encode(&self.name)
         )
     }
@@ -112,18 +141,19 @@ impl ProxyAdapter for Hysteria2 {
        https://github.com/apernet/hysteria/blob/21ea2a024a5bd2d85b8c3e1350038fa178f0901b/app/cmd/client.go#L346
        hysteria2://auth@server:port/?insecure=1&sni=&obfs=&obfs-password=&pinSHA256=
     */
-    fn from_link(link: String) -> Result<Self, UnsupportedLinkError>
+    fn from_link(link: &str) -> Result<Self, UnsupportedLinkError>
     where
         Self: Sized,
     {
         // hysteria2://bfbe4deb-07c8-450b-945e-e3c7676ba5ed@163.123.192.167:50000/?insecure=1&
         // sni=www.microsoft.com&mport=50000-50080#%E5%89%A9%E4%BD%99%E6%B5%81%E9%87%8F%EF%BC%9A163.
         // 97%20GB
-        let url = &link[12..];
+        // hy2:// 为官方客户端同样接受的简写
+        let url = strip_scheme(link, "hysteria2://").or_else(|_| strip_scheme(link, "hy2://"))?;
         let parts = url.split("#").collect::<Vec<_>>();
         let mut name = "".to_string();
         if parts.len() > 1 {
-            name = urlencoding::decode(parts[1]).unwrap().to_string();
+            name = url_decode(parts[1]);
         }
 
         let url = parts[0];
@@ -132,19 +162,22 @@ impl ProxyAdapter for Hysteria2 {
             parts = url.split("?").collect::<Vec<_>>();
         }
 
-        let params = parts[1];
+        let params = parts.get(1).copied().unwrap_or_default();
         let mut params_map: HashMap<&str, String> = HashMap::new();
         for param in params.split("&") {
             if let Some((key, value)) = param.split_once('=') {
-                let value = value.parse::<String>().unwrap();
-                params_map.insert(key, value);
+                params_map.insert(key, value.to_string());
             }
         }
 
-        let skip_cert_verify = params_map.get("insecure").is_some_and(|s| s == "1");
-        let sni = params_map.get("sni").or(params_map.get("peer")).cloned();
-        let up = params_map.get("up").cloned();
-        let down = params_map.get("down").cloned();
+        // 链接未声明时不跳过证书校验
+        let skip_cert_verify = params_map.get("insecure").map(|s| s == "1");
+        let sni = params_map
+            .get("sni")
+            .or(params_map.get("peer"))
+            .map(|s| url_decode(s));
+        let up = params_map.get("up").map(|s| url_decode(s));
+        let down = params_map.get("down").map(|s| url_decode(s));
         let mut ports = params_map.get("mport").cloned();
         let mut alpn = None;
         if let Some(value) = params_map.get("alpn").cloned() {
@@ -158,23 +191,20 @@ impl ProxyAdapter for Hysteria2 {
             );
         }
         let obfs = params_map.get("obfs").cloned();
-        let obfs_password = params_map.get("obfs-password").cloned();
+        let obfs_password = params_map.get("obfs-password").map(|s| url_decode(s));
 
-        let url = parts[0];
-        let parts: Vec<&str> = url.split("@").collect();
-        let password = String::from(parts[0]);
-        let parts: Vec<&str> = parts[1].split(":").collect();
-        let server = String::from(parts[0]);
-        let port;
-        match parts[1].parse::<u16>() {
-            Ok(p) => {
-                port = p;
-            }
-            Err(_) => {
-                let parts = parts[1].split(",").collect::<Vec<_>>();
-                port = parts[0].parse::<u16>().unwrap();
-                ports = Some(String::from(parts[1]));
-            }
+        let (password, addr) = parts[0]
+            .rsplit_once("@")
+            .ok_or_else(|| UnsupportedLinkError::invalid(link, "server"))?;
+        let password = String::from(password);
+        // 端口跳跃格式：server:443,20000-30000
+        let (addr, hop_ports) = match addr.split_once(",") {
+            Some((addr, hop_ports)) => (addr, Some(hop_ports)),
+            None => (addr, None),
+        };
+        let (server, port) = parse_server_port(addr, link)?;
+        if let Some(hop_ports) = hop_ports {
+            ports = Some(String::from(hop_ports));
         }
 
         if name.is_empty() {
@@ -194,8 +224,8 @@ impl ProxyAdapter for Hysteria2 {
             obfs,
             obfs_password,
             sni,
-            skip_cert_verify: Some(skip_cert_verify),
-            client_fingerprint: Some("chrome".to_string()),
+            skip_cert_verify,
+            client_fingerprint: None,
             fingerprint: None,
         })
     }
@@ -204,22 +234,36 @@ impl ProxyAdapter for Hysteria2 {
         serde_json::to_string(self)
     }
 
-    fn as_any(&self) -> &dyn Any {
-        self
-    }
-
-    fn eq(&self, other: &dyn ProxyAdapter) -> bool {
-        if let Some(other) = other.as_any().downcast_ref::<Hysteria2>() {
-            self == other
-        } else {
-            false
+    fn validate(&self) -> Result<(), ValidationError> {
+        check_server(&self.server)?;
+        check_port(self.port)?;
+        check_not_empty("password", &self.password)?;
+        if let Some(ports) = &self.ports {
+            // 端口跳跃格式：443,20000-30000
+            if ports.is_empty()
+                || !ports
+                    .chars()
+                    .all(|c| c.is_ascii_digit() || c == ',' || c == '-')
+            {
+                return Err(ValidationError::new("ports", format!("{:?}", ports)));
+            }
+        }
+        if let Some(obfs) = &self.obfs {
+            check_one_of("obfs", obfs, &["salamander"])?;
+            check_not_empty(
+                "obfs-password",
+                self.obfs_password.as_deref().unwrap_or_default(),
+            )?;
         }
+        Ok(())
     }
+}
 
-    fn hash(&self, mut state: &mut dyn Hasher) {
-        self.server.hash(&mut state);
-        self.password.hash(&mut state);
-        self.ports.hash(&mut state);
+impl Hash for Hysteria2 {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.server.hash(state);
+        self.password.hash(state);
+        self.ports.hash(state);
     }
 }
 
@@ -230,21 +274,38 @@ mod test {
     #[test]
     fn test_parse_hysteria2() {
         let link = String::from("hysteria2://bfbe4deb-07c8-450b-945e-e3c7676ba5ed@163.123.192.167:50000/?insecure=1&sni=www.microsoft.com&mport=50000-50080#%E5%89%A9%E4%BD%99%E6%B5%81%E9%87%8F%EF%BC%9A163.97%20GB");
-        let hysteria2 = Hysteria2::from_link(link.clone()).unwrap();
+        let hysteria2 = Hysteria2::from_link(&link).unwrap();
         assert_eq!(hysteria2.server, "163.123.192.167");
         assert_eq!(hysteria2.port, 50000);
         assert_eq!(hysteria2.ports, Some("50000-50080".to_string()));
         assert_eq!(hysteria2.password, "bfbe4deb-07c8-450b-945e-e3c7676ba5ed");
         assert_eq!(hysteria2.sni, Some("www.microsoft.com".to_string()));
         assert_eq!(hysteria2.skip_cert_verify, Some(true));
-        assert_eq!(hysteria2.client_fingerprint, Some("chrome".to_string()));
+        assert_eq!(hysteria2.client_fingerprint, None);
         assert_eq!(hysteria2.to_link(), link);
     }
 
+    #[test]
+    fn test_parse_hy2_scheme() {
+        let link = "hy2://pwd@1.2.3.4:443?sni=a.com&obfs=salamander&obfs-password=p%40ss&up=50%20Mbps&down=100%20Mbps#HK";
+        let hysteria2 = Hysteria2::from_link(link).unwrap();
+        assert_eq!(hysteria2.server, "1.2.3.4");
+        assert_eq!(hysteria2.password, "pwd");
+        assert_eq!(hysteria2.obfs, Some("salamander".to_string()));
+        assert_eq!(hysteria2.obfs_password, Some("p@ss".to_string()));
+        assert_eq!(hysteria2.up, Some("50 Mbps".to_string()));
+        assert_eq!(hysteria2.down, Some("100 Mbps".to_string()));
+        assert!(hysteria2.validate().is_ok());
+        // 统一输出 hysteria2://
+        assert!(hysteria2
+            .to_link()
+            .starts_with("hysteria2://pwd@1.2.3.4:443/?"));
+    }
+
     #[test]
     fn test_ok() {
         let link = "hysteria2://jfVRhwnzx1PNAvCOYM7Vf0sheE4@hy2.jimsblog.us.kg:35808/?mport=35808,35808-35850&sni=hy2.jimsblog.us.kg#Hysteria2".to_string();
-        let hysteria2 = Hysteria2::from_link(link);
+        let hysteria2 = Hysteria2::from_link(&link);
         assert!(hysteria2.is_ok())
     }
 
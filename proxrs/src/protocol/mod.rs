@@ -1,11 +1,16 @@
-mod ss;
-mod ssr;
-mod trojan;
-pub(crate) mod vless;
-pub(crate) mod vmess;
-pub(crate) mod hysteria2;
-
-use std::any::Any;
+pub mod hysteria2;
+pub mod ss;
+pub mod ssr;
+pub mod trojan;
+pub mod tuic;
+pub mod vless;
+pub mod vmess;
+
+mod validate;
+
+#[cfg(test)]
+mod roundtrip;
+
 use std::collections::HashMap;
 use std::fmt;
 use std::fmt::Debug;
@@ -14,18 +19,21 @@ use std::hash::Hash;
 use std::hash::Hasher;
 use std::str::FromStr;
 
+use serde::de::DeserializeOwned;
 use serde::Deserialize;
 use serde::Deserializer;
 use serde::Serialize;
 use serde_json::json;
 use serde_json::Value;
 
-use crate::protocol::hysteria2::Hysteria2;
-use crate::protocol::ss::SS;
-use crate::protocol::ssr::Ssr;
-use crate::protocol::trojan::Trojan;
-use crate::protocol::vless::Vless;
-use crate::protocol::vmess::Vmess;
+pub use crate::protocol::hysteria2::Hysteria2;
+pub use crate::protocol::ss::SS;
+pub use crate::protocol::ssr::Ssr;
+pub use crate::protocol::trojan::Trojan;
+pub use crate::protocol::tuic::Tuic;
+pub use crate::protocol::validate::ValidationError;
+pub use crate::protocol::vless::Vless;
+pub use crate::protocol::vmess::Vmess;
 
 #[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize, Ord, PartialOrd, Clone)]
 pub enum ProxyType {
@@ -43,25 +51,82 @@ pub enum ProxyType {
     Hysteria2,
     #[serde(rename = "hysteria")]
     Hysteria,
+    #[serde(rename = "tuic")]
+    Tuic,
     #[serde(rename = "wireguard")]
     WireGuard,
     #[serde(rename = "unknown")]
     Unknown,
 }
 
-#[derive(Deserialize, Debug, Serialize, Clone, PartialEq, Eq)]
+/// ws 传输，mihomo 中 httpupgrade 也使用 ws-opts，通过 v2ray-http-upgrade 开启
+#[derive(Deserialize, Debug, Serialize, Clone, PartialEq, Eq, Default)]
 pub struct WSOptions {
     #[serde(skip_serializing_if = "Option::is_none")]
-    path: Option<String>,
+    pub path: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    headers: Option<HashMap<String, String>>,
+    pub headers: Option<HashMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none", rename = "max-early-data")]
+    pub max_early_data: Option<u32>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        rename = "early-data-header-name"
+    )]
+    pub early_data_header_name: Option<String>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "v2ray-http-upgrade",
+        deserialize_with = "deserialize_bool_or_string"
+    )]
+    pub v2ray_http_upgrade: Option<bool>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "v2ray-http-upgrade-fast-open",
+        deserialize_with = "deserialize_bool_or_string"
+    )]
+    pub v2ray_http_upgrade_fast_open: Option<bool>,
+}
+
+impl WSOptions {
+    /// headers 中的 Host，忽略大小写
+    pub fn host(&self) -> Option<&str> {
+        self.headers.as_ref().and_then(|headers| {
+            headers
+                .iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case("host"))
+                .map(|(_, v)| v.as_str())
+        })
+    }
+
+    pub fn is_http_upgrade(&self) -> bool {
+        self.v2ray_http_upgrade.unwrap_or(false)
+    }
+}
+
+#[derive(Deserialize, Debug, Serialize, Clone, PartialEq, Eq, Default)]
+pub struct H2Options {
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "deserialize_vec_or_string"
+    )]
+    pub host: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
 }
 
 #[derive(Deserialize, Debug, Serialize, Clone, PartialEq, Eq)]
 pub struct RealtyOptions {
     #[serde(skip_serializing_if = "Option::is_none", rename = "public-key")]
     pub public_key: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none", rename = "short-id")]
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "short-id",
+        deserialize_with = "deserialize_from_string"
+    )]
     pub short_id: Option<String>,
 }
 
@@ -71,6 +136,110 @@ pub struct GrpcOptions {
     pub grpc_service_name: Option<String>,
 }
 
+/// 分享链接中的传输层配置，对应 clash 中的 network 及各 *-opts
+#[derive(Debug, Default)]
+pub(crate) struct Transport {
+    pub network: Option<String>,
+    pub ws_opts: Option<WSOptions>,
+    pub h2_opts: Option<H2Options>,
+    pub grpc_opts: Option<GrpcOptions>,
+}
+
+impl Transport {
+    /// 通过链接中的 type、host、path、serviceName 解析，参数需已解码
+    /// httpupgrade 转为 ws 并开启 v2ray-http-upgrade，http 即 h2
+    pub(crate) fn from_params(
+        network: Option<&str>,
+        host: Option<&str>,
+        path: Option<&str>,
+        service_name: Option<&str>,
+    ) -> Self {
+        let mut transport = Transport {
+            network: network.map(|s| s.to_string()),
+            ..Default::default()
+        };
+        match network {
+            Some("ws") | Some("httpupgrade") => {
+                let mut headers = HashMap::new();
+                if let Some(host) = host {
+                    headers.insert(String::from("host"), host.to_string());
+                }
+                let http_upgrade = network == Some("httpupgrade");
+                transport.network = Some("ws".to_string());
+                transport.ws_opts = Some(WSOptions {
+                    path: path.map(|s| s.to_string()),
+                    headers: Some(headers),
+                    v2ray_http_upgrade: http_upgrade.then_some(true),
+                    ..Default::default()
+                });
+            }
+            Some("h2") | Some("http") => {
+                transport.network = Some("h2".to_string());
+                transport.h2_opts = Some(H2Options {
+                    host: host
+                        .filter(|s| !s.is_empty())
+                        .map(|s| s.split(',').map(|h| h.trim().to_string()).collect()),
+                    path: path.map(|s| s.to_string()),
+                });
+            }
+            Some("grpc") => {
+                transport.grpc_opts = Some(GrpcOptions {
+                    grpc_service_name: service_name.map(|s| s.to_string()),
+                });
+            }
+            _ => {}
+        }
+        transport
+    }
+
+    /// 生成链接中的 type、host、path、serviceName 参数，已编码
+    pub(crate) fn link_params(
+        network: Option<&str>,
+        ws_opts: Option<&WSOptions>,
+        h2_opts: Option<&H2Options>,
+        grpc_opts: Option<&GrpcOptions>,
+    ) -> Vec<String> {
+        let mut params = vec![];
+        let Some(network) = network else {
+            return params;
+        };
+        match network {
+            "ws" => {
+                let ws_opts = ws_opts.cloned().unwrap_or_default();
+                if ws_opts.is_http_upgrade() {
+                    params.push("type=httpupgrade".to_string());
+                } else {
+                    params.push("type=ws".to_string());
+                }
+                if let Some(host) = ws_opts.host() {
+                    params.push(format!("host={}", urlencoding::encode(host)));
+                }
+                if let Some(path) = &ws_opts.path {
+                    params.push(format!("path={}", urlencoding::encode(path)));
+                }
+            }
+            "h2" => {
+                params.push("type=h2".to_string());
+                if let Some(h2_opts) = h2_opts {
+                    if let Some(host) = &h2_opts.host {
+                        params.push(format!("host={}", urlencoding::encode(&host.join(","))));
+                    }
+                    if let Some(path) = &h2_opts.path {
+                        params.push(format!("path={}", urlencoding::encode(path)));
+                    }
+                }
+            }
+            network => {
+                params.push(format!("type={}", network));
+                if let Some(service_name) = grpc_opts.and_then(|o| o.grpc_service_name.as_ref()) {
+                    params.push(format!("serviceName={}", urlencoding::encode(service_name)));
+                }
+            }
+        }
+        params
+    }
+}
+
 #[derive(Debug)]
 pub struct UnsupportedLinkError {
     message: String,
@@ -84,173 +253,321 @@ impl fmt::Display for UnsupportedLinkError {
 
 impl std::error::Error for UnsupportedLinkError {}
 
-pub trait ProxyAdapter: ProxyAdapterClone {
+impl UnsupportedLinkError {
+    pub(crate) fn new(message: impl Into<String>) -> Self {
+        UnsupportedLinkError {
+            message: message.into(),
+        }
+    }
+
+    /// 链接缺少字段或字段格式错误
+    pub(crate) fn invalid(link: &str, field: &str) -> Self {
+        UnsupportedLinkError::new(format!("Invalid {} in link: {}", field, link))
+    }
+}
+
+/// 去掉链接协议头，同时校验协议是否匹配
+pub(crate) fn strip_scheme<'a>(
+    link: &'a str,
+    scheme: &str,
+) -> Result<&'a str, UnsupportedLinkError> {
+    link.strip_prefix(scheme)
+        .ok_or_else(|| UnsupportedLinkError::invalid(link, "scheme"))
+}
+
+/// 解析 server:port，兼容 [IPv6]:port 及端口后多余的 /
+pub(crate) fn parse_server_port(
+    addr: &str,
+    link: &str,
+) -> Result<(String, u16), UnsupportedLinkError> {
+    let (server, port) = addr
+        .rsplit_once(':')
+        .ok_or_else(|| UnsupportedLinkError::invalid(link, "server"))?;
+    let port = port
+        .trim_end_matches('/')
+        .parse::<u16>()
+        .map_err(|_| UnsupportedLinkError::invalid(link, "port"))?;
+    let server = server.trim_start_matches('[').trim_end_matches(']');
+    if server.is_empty() {
+        return Err(UnsupportedLinkError::invalid(link, "server"));
+    }
+    Ok((server.to_string(), port))
+}
+
+/// 生成链接中的 server:port，IPv6 地址需要使用中括号包裹
+pub(crate) fn format_server_port(server: &str, port: u16) -> String {
+    if server.contains(':') {
+        format!("[{}]:{}", server, port)
+    } else {
+        format!("{}:{}", server, port)
+    }
+}
+
+/// URL 解码，失败时保留原始内容
+pub(crate) fn url_decode(content: &str) -> String {
+    urlencoding::decode(content)
+        .map(|s| s.into_owned())
+        .unwrap_or_else(|_| content.to_string())
+}
+
+/// 各协议节点的公共行为
+pub trait ProxyAdapter {
     fn get_name(&self) -> &str;
     fn set_name(&mut self, name: &str);
     fn get_server(&self) -> &str;
     fn to_link(&self) -> String;
-    fn from_link(link: String) -> Result<Self, UnsupportedLinkError>
+    fn from_link(link: &str) -> Result<Self, UnsupportedLinkError>
     where
         Self: Sized;
 
     fn to_json(&self) -> Result<String, serde_json::Error>;
 
-    fn as_any(&self) -> &dyn Any;
-
-    fn eq(&self, other: &dyn ProxyAdapter) -> bool;
-
-    fn hash(&self, state: &mut dyn Hasher);
+    /// 校验字段是否合法，如 uuid 格式、端口范围、加密方式等
+    fn validate(&self) -> Result<(), ValidationError>;
 }
 
-pub trait ProxyAdapterClone {
-    fn clone_box(&self) -> Box<dyn ProxyAdapter>;
+/// 节点，相等及哈希仅比较服务器地址、端口及认证信息，用于去重
+#[derive(Clone)]
+pub enum Proxy {
+    SS(SS),
+    SSR(Ssr),
+    Vmess(Vmess),
+    Vless(Vless),
+    Trojan(Trojan),
+    Hysteria2(Hysteria2),
+    Tuic(Tuic),
 }
 
-impl<T> ProxyAdapterClone for T
-where
-    T: 'static + ProxyAdapter + Clone,
-{
-    fn clone_box(&self) -> Box<dyn ProxyAdapter> {
-        Box::new(self.clone())
-    }
-}
+macro_rules! impl_proxy_conversions {
+    ($($variant:ident($adapter:ty)),* $(,)?) => {
+        $(
+            impl From<$adapter> for Proxy {
+                fn from(adapter: $adapter) -> Self {
+                    Proxy::$variant(adapter)
+                }
+            }
 
-impl Clone for Box<dyn ProxyAdapter> {
-    fn clone(&self) -> Box<dyn ProxyAdapter> {
-        self.clone_box()
-    }
-}
+            impl TryFrom<Proxy> for $adapter {
+                type Error = Proxy;
 
-pub struct Proxy {
-    pub proxy_type: ProxyType,
-    pub adapter: Box<dyn ProxyAdapter>,
+                fn try_from(proxy: Proxy) -> Result<Self, Self::Error> {
+                    match proxy {
+                        Proxy::$variant(adapter) => Ok(adapter),
+                        other => Err(other),
+                    }
+                }
+            }
+        )*
+    };
 }
 
+impl_proxy_conversions!(
+    SS(SS),
+    SSR(Ssr),
+    Vmess(Vmess),
+    Vless(Vless),
+    Trojan(Trojan),
+    Hysteria2(Hysteria2),
+    Tuic(Tuic),
+);
+
 impl Proxy {
-    fn new(proxy_type: ProxyType, proxy_adapter: Box<dyn ProxyAdapter>) -> Proxy {
-        Proxy {
-            proxy_type,
-            adapter: proxy_adapter,
+    pub fn proxy_type(&self) -> ProxyType {
+        match self {
+            Proxy::SS(_) => ProxyType::SS,
+            Proxy::SSR(_) => ProxyType::SSR,
+            Proxy::Vmess(_) => ProxyType::Vmess,
+            Proxy::Vless(_) => ProxyType::Vless,
+            Proxy::Trojan(_) => ProxyType::Trojan,
+            Proxy::Hysteria2(_) => ProxyType::Hysteria2,
+            Proxy::Tuic(_) => ProxyType::Tuic,
+        }
+    }
+
+    pub fn adapter(&self) -> &dyn ProxyAdapter {
+        match self {
+            Proxy::SS(p) => p,
+            Proxy::SSR(p) => p,
+            Proxy::Vmess(p) => p,
+            Proxy::Vless(p) => p,
+            Proxy::Trojan(p) => p,
+            Proxy::Hysteria2(p) => p,
+            Proxy::Tuic(p) => p,
+        }
+    }
+
+    pub fn adapter_mut(&mut self) -> &mut dyn ProxyAdapter {
+        match self {
+            Proxy::SS(p) => p,
+            Proxy::SSR(p) => p,
+            Proxy::Vmess(p) => p,
+            Proxy::Vless(p) => p,
+            Proxy::Trojan(p) => p,
+            Proxy::Hysteria2(p) => p,
+            Proxy::Tuic(p) => p,
         }
     }
 
     pub fn get_name(&self) -> &str {
-        self.adapter.get_name()
+        self.adapter().get_name()
     }
 
     pub fn set_name(&mut self, name: &str) {
-        self.adapter.set_name(name);
+        self.adapter_mut().set_name(name);
     }
 
     pub fn get_server(&self) -> &str {
-        self.adapter.get_server()
+        self.adapter().get_server()
     }
 
-    pub fn to_json(&self) -> Result<String, serde_json::Error> {
-        match self.adapter.to_json() {
-            Ok(json) => {
-                let mut json_value: Value = serde_json::from_str(&json)?;
-                if let Value::Object(ref mut map) = json_value {
-                    map.insert("type".to_string(), json!(self.proxy_type));
-                }
-                serde_json::to_string(&json_value)
-            }
-            Err(e) => Err(e),
+    pub fn get_port(&self) -> u16 {
+        match self {
+            Proxy::SS(p) => p.port,
+            Proxy::SSR(p) => p.port,
+            Proxy::Vmess(p) => p.port,
+            Proxy::Vless(p) => p.port,
+            Proxy::Trojan(p) => p.port,
+            Proxy::Hysteria2(p) => p.port,
+            Proxy::Tuic(p) => p.port,
+        }
+    }
+
+    /// 是否使用 TLS，trojan、hysteria2 及 tuic 始终使用，vless 的 reality 也算在内
+    pub fn uses_tls(&self) -> bool {
+        match self {
+            Proxy::Vmess(p) => p.tls.unwrap_or(false),
+            Proxy::Vless(p) => p.tls.unwrap_or(false) || p.reality_opts.is_some(),
+            Proxy::Trojan(_) | Proxy::Hysteria2(_) | Proxy::Tuic(_) => true,
+            Proxy::SS(_) | Proxy::SSR(_) => false,
+        }
+    }
+
+    pub fn to_link(&self) -> String {
+        self.adapter().to_link()
+    }
+
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        self.adapter().validate()
+    }
+
+    /// 订阅未声明 skip-cert-verify 的 TLS 节点默认跳过证书校验，需要用户显式开启
+    pub fn apply_insecure_default(&mut self) {
+        let skip_cert_verify = match self {
+            Proxy::Vmess(p) => &mut p.skip_cert_verify,
+            Proxy::Vless(p) => &mut p.skip_cert_verify,
+            Proxy::Trojan(p) => &mut p.skip_cert_verify,
+            Proxy::Hysteria2(p) => &mut p.skip_cert_verify,
+            Proxy::Tuic(p) => &mut p.skip_cert_verify,
+            Proxy::SS(_) | Proxy::SSR(_) => return,
+        };
+        skip_cert_verify.get_or_insert(true);
+    }
+
+    /// clash 配置中的节点，包含 type 字段
+    pub fn to_clash_value(&self) -> Result<Value, serde_json::Error> {
+        let mut value = match self {
+            Proxy::SS(p) => serde_json::to_value(p)?,
+            Proxy::SSR(p) => serde_json::to_value(p)?,
+            Proxy::Vmess(p) => serde_json::to_value(p)?,
+            Proxy::Vless(p) => serde_json::to_value(p)?,
+            Proxy::Trojan(p) => serde_json::to_value(p)?,
+            Proxy::Hysteria2(p) => serde_json::to_value(p)?,
+            Proxy::Tuic(p) => serde_json::to_value(p)?,
+        };
+        if let Value::Object(ref mut map) = value {
+            map.insert("type".to_string(), json!(self.proxy_type()));
         }
+        Ok(value)
     }
 
-    pub fn from_link(link: String) -> Result<Proxy, UnsupportedLinkError> {
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(&self.to_clash_value()?)
+    }
+
+    pub fn from_link(link: &str) -> Result<Proxy, UnsupportedLinkError> {
         if link.starts_with("ss://") {
-            Ok(Proxy::new(ProxyType::SS, Box::new(SS::from_link(link)?)))
+            SS::from_link(link).map(Proxy::from)
         } else if link.starts_with("ssr://") {
-            Ok(Proxy::new(ProxyType::SSR, Box::new(Ssr::from_link(link)?)))
+            Ssr::from_link(link).map(Proxy::from)
         } else if link.starts_with("vmess://") {
-            Ok(Proxy::new(
-                ProxyType::Vmess,
-                Box::new(Vmess::from_link(link)?),
-            ))
+            Vmess::from_link(link).map(Proxy::from)
         } else if link.starts_with("trojan://") {
-            Ok(Proxy::new(
-                ProxyType::Trojan,
-                Box::new(Trojan::from_link(link)?),
-            ))
-        } else if link.starts_with("hysteria2://") {
-            Ok(Proxy::new(
-                ProxyType::Hysteria2,
-                Box::new(Hysteria2::from_link(link)?),
-            ))
+            Trojan::from_link(link).map(Proxy::from)
+        } else if link.starts_with("hysteria2://") || link.starts_with("hy2://") {
+            Hysteria2::from_link(link).map(Proxy::from)
+        } else if link.starts_with("tuic://") {
+            Tuic::from_link(link).map(Proxy::from)
         } else if link.starts_with("vless://") {
-            Ok(Proxy::new(
-                ProxyType::Vless,
-                Box::new(Vless::from_link(link)?),
-            ))
+            Vless::from_link(link).map(Proxy::from)
         } else {
-            Err(UnsupportedLinkError {
-                message: format!("Unsupported link format: {}", link),
-            })
+            Err(UnsupportedLinkError::new(format!(
+                "Unsupported link format: {}",
+                link
+            )))
         }
     }
 
-    pub fn from_json(json: &str) -> Result<Proxy, UnsupportedLinkError> {
-        let value = serde_json::from_str::<Value>(json).unwrap();
-        if let Some(proxy_type) = value.get("type") {
-            if proxy_type.as_str().unwrap() == "ss" {
-                return match serde_json::from_str::<SS>(json) {
-                    Ok(ss) => Ok(Proxy::new(ProxyType::SS, Box::new(ss))),
-                    Err(e) => Err(UnsupportedLinkError {
-                        message: format!("{}", e),
-                    }),
-                };
-            } else if proxy_type.as_str().unwrap() == "ssr" {
-                return match serde_json::from_str::<Ssr>(json) {
-                    Ok(ssr) => Ok(Proxy::new(ProxyType::SSR, Box::new(ssr))),
-                    Err(e) => Err(UnsupportedLinkError {
-                        message: format!("{}", e),
-                    }),
-                };
-            } else if proxy_type.as_str().unwrap() == "vmess" {
-                return match serde_json::from_str::<Vmess>(json) {
-                    Ok(vmess) => Ok(Proxy::new(ProxyType::Vmess, Box::new(vmess))),
-                    Err(e) => Err(UnsupportedLinkError {
-                        message: format!("{}", e),
-                    }),
-                };
-            } else if proxy_type.as_str().unwrap() == "vless" {
-                return match serde_json::from_str::<Vless>(json) {
-                    Ok(vless) => Ok(Proxy::new(ProxyType::Vless, Box::new(vless))),
-                    Err(e) => Err(UnsupportedLinkError {
-                        message: format!("{}", e),
-                    }),
-                };
-            } else if proxy_type.as_str().unwrap() == "trojan" {
-                return match serde_json::from_str::<Trojan>(json) {
-                    Ok(trojan) => Ok(Proxy::new(ProxyType::Trojan, Box::new(trojan))),
-                    Err(e) => Err(UnsupportedLinkError {
-                        message: format!("{}", e),
-                    }),
-                };
-            } else if proxy_type.as_str().unwrap() == "hysteria2" {
-                return match serde_json::from_str::<Hysteria2>(json) {
-                    Ok(hysteria2) => Ok(Proxy::new(ProxyType::Hysteria2, Box::new(hysteria2))),
-                    Err(e) => Err(UnsupportedLinkError {
-                        message: format!("{}", e),
-                    }),
-                };
-            }
-        } else {
-            return Err(UnsupportedLinkError {
-                message: format!("proxy_type fetch error {}", json),
-            });
+    /// 从 clash 配置中的单个节点解析，根据 type 字段区分协议
+    pub fn from_clash_value(value: &Value) -> Result<Proxy, UnsupportedLinkError> {
+        fn parse<T: DeserializeOwned + Into<Proxy>>(
+            value: &Value,
+        ) -> Result<Proxy, UnsupportedLinkError> {
+            T::deserialize(value)
+                .map(Into::into)
+                .map_err(|e| UnsupportedLinkError::new(e.to_string()))
         }
-        Err(UnsupportedLinkError {
-            message: json.to_string(),
-        })
+
+        match value.get("type").and_then(Value::as_str) {
+            Some("ss") => parse::<SS>(value),
+            Some("ssr") => parse::<Ssr>(value),
+            Some("vmess") => parse::<Vmess>(value),
+            Some("vless") => parse::<Vless>(value),
+            Some("trojan") => parse::<Trojan>(value),
+            Some("hysteria2") => parse::<Hysteria2>(value),
+            Some("tuic") => parse::<Tuic>(value),
+            Some(_) => Err(UnsupportedLinkError::new(value.to_string())),
+            None => Err(UnsupportedLinkError::new(format!(
+                "proxy_type fetch error {}",
+                value
+            ))),
+        }
+    }
+
+    pub fn from_json(json: &str) -> Result<Proxy, UnsupportedLinkError> {
+        let value = serde_json::from_str::<Value>(json)
+            .map_err(|e| UnsupportedLinkError::new(e.to_string()))?;
+        Proxy::from_clash_value(&value)
+    }
+}
+
+impl TryFrom<&str> for Proxy {
+    type Error = UnsupportedLinkError;
+
+    fn try_from(link: &str) -> Result<Self, Self::Error> {
+        Proxy::from_link(link)
+    }
+}
+
+impl TryFrom<&Value> for Proxy {
+    type Error = UnsupportedLinkError;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        Proxy::from_clash_value(value)
     }
 }
 
 impl PartialEq for Proxy {
     fn eq(&self, other: &Self) -> bool {
-        self.proxy_type == other.proxy_type && self.adapter.eq(other.adapter.as_ref())
+        match (self, other) {
+            (Proxy::SS(a), Proxy::SS(b)) => a == b,
+            (Proxy::SSR(a), Proxy::SSR(b)) => a == b,
+            (Proxy::Vmess(a), Proxy::Vmess(b)) => a == b,
+            (Proxy::Vless(a), Proxy::Vless(b)) => a == b,
+            (Proxy::Trojan(a), Proxy::Trojan(b)) => a == b,
+            (Proxy::Hysteria2(a), Proxy::Hysteria2(b)) => a == b,
+            (Proxy::Tuic(a), Proxy::Tuic(b)) => a == b,
+            _ => false,
+        }
     }
 }
 
@@ -258,41 +575,127 @@ impl Eq for Proxy {}
 
 impl Hash for Proxy {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        self.proxy_type.hash(state);
-        self.adapter.hash(state);
+        self.proxy_type().hash(state);
+        match self {
+            Proxy::SS(p) => p.hash(state),
+            Proxy::SSR(p) => p.hash(state),
+            Proxy::Vmess(p) => p.hash(state),
+            Proxy::Vless(p) => p.hash(state),
+            Proxy::Trojan(p) => p.hash(state),
+            Proxy::Hysteria2(p) => p.hash(state),
+            Proxy::Tuic(p) => p.hash(state),
+        }
     }
 }
 
 impl Debug for Proxy {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.write_str(self.to_json().unwrap().as_str())
-    }
-}
-
-impl Clone for Proxy {
-    fn clone(&self) -> Self {
-        Proxy {
-            proxy_type: self.proxy_type.clone(), // 确保 ProxyType 实现了 Clone
-            adapter: self.adapter.clone(),       // 使用 adapter 的 clone_box 方法
+        match self.to_json() {
+            Ok(json) => f.write_str(&json),
+            Err(_) => write!(f, "{}", self.get_name()),
         }
     }
 }
 
+// 订阅中字段类型很不统一，如 port: "443"、tls: "true"、alpn: "h2,http/1.1"，以下函数统一兼容
+// 可选字段使用时需同时加上 #[serde(default)]，否则缺少该字段时会报错
+
 pub fn deserialize_u16_or_string<'de, D>(deserializer: D) -> Result<u16, D::Error>
 where
     D: Deserializer<'de>,
 {
     let value: Value = Deserialize::deserialize(deserializer)?;
+    u16_from_value(value)
+}
+
+/// 同 deserialize_u16_or_string，null 及空字符串视为未设置
+pub fn deserialize_option_u16_or_string<'de, D>(deserializer: D) -> Result<Option<u16>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value: Value = Deserialize::deserialize(deserializer)?;
+    match value {
+        Value::Null => Ok(None),
+        Value::String(s) if s.trim().is_empty() => Ok(None),
+        value => u16_from_value(value).map(Some),
+    }
+}
+
+fn u16_from_value<E: serde::de::Error>(value: Value) -> Result<u16, E> {
     match value {
         Value::Number(num) => num
             .as_u64()
             .and_then(|n| u16::try_from(n).ok())
-            .ok_or_else(|| serde::de::Error::custom("Invalid u16 value")),
-        Value::String(s) => u16::from_str(&s).map_err(serde::de::Error::custom),
-        _ => Err(serde::de::Error::custom("Expected a string or number")),
+            .ok_or_else(|| E::custom("Invalid u16 value")),
+        Value::String(s) => u16::from_str(s.trim()).map_err(E::custom),
+        _ => Err(E::custom("Expected a string or number")),
+    }
+}
+
+/// 布尔值，兼容 "true"、"1"、1 等写法，null 及空字符串视为未设置
+pub fn deserialize_bool_or_string<'de, D>(deserializer: D) -> Result<Option<bool>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value: Value = Deserialize::deserialize(deserializer)?;
+    match value {
+        Value::Null => Ok(None),
+        Value::Bool(b) => Ok(Some(b)),
+        Value::Number(n) => match n.as_u64() {
+            Some(0) => Ok(Some(false)),
+            Some(1) => Ok(Some(true)),
+            _ => Err(serde::de::Error::custom(format!(
+                "Invalid bool value: {}",
+                n
+            ))),
+        },
+        Value::String(s) => match s.trim().to_ascii_lowercase().as_str() {
+            "" => Ok(None),
+            "true" | "1" => Ok(Some(true)),
+            "false" | "0" => Ok(Some(false)),
+            _ => Err(serde::de::Error::custom(format!(
+                "Invalid bool value: {}",
+                s
+            ))),
+        },
+        _ => Err(serde::de::Error::custom("Expected a bool or string")),
+    }
+}
+
+/// 字符串列表，兼容逗号分隔的字符串及单个值，null 视为未设置
+pub fn deserialize_vec_or_string<'de, D>(deserializer: D) -> Result<Option<Vec<String>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value: Value = Deserialize::deserialize(deserializer)?;
+    match value {
+        Value::Null => Ok(None),
+        Value::Array(values) => values
+            .into_iter()
+            .map(string_from_value)
+            .collect::<Result<Vec<_>, _>>()
+            .map(Some),
+        Value::String(s) => Ok(Some(
+            s.split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(str::to_string)
+                .collect(),
+        )),
+        value => string_from_value(value).map(|s| Some(vec![s])),
     }
 }
 
+/// 字符串，兼容数字、布尔值，如 password: 123456
+pub fn deserialize_string<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value: Value = Deserialize::deserialize(deserializer)?;
+    string_from_value(value)
+}
+
+/// 同 deserialize_string，null 视为未设置
 pub fn deserialize_from_string<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
 where
     D: Deserializer<'de>,
@@ -300,12 +703,40 @@ where
     // 使用 Option<serde_json::Value> 来处理任意类型
     let value: Value = Deserialize::deserialize(deserializer)?;
     match value {
-        Value::Number(n) => Ok(Some(n.to_string())),
-        Value::String(s) => Ok(Some(s)),
-        _ => Err(serde::de::Error::custom("Expected a string")),
+        Value::Null => Ok(None),
+        value => string_from_value(value).map(Some),
+    }
+}
+
+fn string_from_value<E: serde::de::Error>(value: Value) -> Result<String, E> {
+    match value {
+        Value::String(s) => Ok(s),
+        Value::Number(n) => Ok(n.to_string()),
+        Value::Bool(b) => Ok(b.to_string()),
+        _ => Err(E::custom("Expected a string")),
     }
 }
 
+/// 值为字符串的映射，兼容 clash 配置中布尔、数字类型的值，如 v2ray-plugin 的 tls: true
+pub fn deserialize_string_map<'de, D>(
+    deserializer: D,
+) -> Result<Option<HashMap<String, String>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value: Option<HashMap<String, Value>> = Deserialize::deserialize(deserializer)?;
+    Ok(value.map(|map| {
+        map.into_iter()
+            .filter_map(|(key, value)| match value {
+                Value::String(s) => Some((key, s)),
+                Value::Bool(b) => Some((key, b.to_string())),
+                Value::Number(n) => Some((key, n.to_string())),
+                _ => None,
+            })
+            .collect()
+    }))
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -322,35 +753,38 @@ mod test {
     #[test]
     fn test_proxy_type() {
         let ss_link = "ss://YWVzLTEyOC1nY206ZDljNTc3MzI4ZmIzNDlmZQ==@120.232.73.68:40676#%F0%9F%87%AD%F0%9F%87%B0HK".to_string();
-        assert_eq!(Proxy::from_link(ss_link).unwrap().proxy_type, ProxyType::SS);
+        assert_eq!(
+            Proxy::from_link(&ss_link).unwrap().proxy_type(),
+            ProxyType::SS
+        );
 
         let ssr_link = "ssr://dmlwLmJhc2ljbm9kZS5ob3N0OjExODQ1OmF1dGhfYWVzMTI4X3NoYTE6Y2hhY2hhMjAtaWV0Zjp0bHMxLjJfdGlja2V0X2F1dGg6Um1oaVpUQjYvP3JlbWFya3M9VUhKdkxlbW1tZWE0cnlCSVMwZmt1S2psaGFqb3A2UHBsSUhrdUtoQk1nPT0mb2Jmc3BhcmFtPU5tWTBNV0l5TkM1dGFXTnliM052Wm5RdVkyOXQmcHJvdG9wYXJhbT1NalE2VTNCWlZYUlFaVXBaYUZKck5FWlhRdz09".to_string();
         assert_eq!(
-            Proxy::from_link(ssr_link).unwrap().proxy_type,
+            Proxy::from_link(&ssr_link).unwrap().proxy_type(),
             ProxyType::SSR
         );
 
         let hysteria2_link = "hysteria2://bfbe4deb-07c8-450b-945e-e3c7676ba5ed@163.123.192.167:50000/?insecure=1&sni=www.microsoft.com&mport=50000-50080#%E5%89%A9%E4%BD%99%E6%B5%81%E9%87%8F%EF%BC%9A163.97%20GB".to_string();
         assert_eq!(
-            Proxy::from_link(hysteria2_link).unwrap().proxy_type,
+            Proxy::from_link(&hysteria2_link).unwrap().proxy_type(),
             ProxyType::Hysteria2
         );
 
         let trojan_link = "trojan://4fee57cc-ee15-4800-888f-3493f7b261f2@hk1.ee2c9087-71b0-70af-7924-09d714b25b96.6df03129.the-best-airport.com:443?type=tcp&sni=new.download.the-best-airport.com&allowInsecure=1#%F0%9F%87%AD%F0%9F%87%B0%E9%A6%99%E6%B8%AF%2001%20%7C%20%E4%B8%93%E7%BA%BF%0D".to_string();
         assert_eq!(
-            Proxy::from_link(trojan_link).unwrap().proxy_type,
+            Proxy::from_link(&trojan_link).unwrap().proxy_type(),
             ProxyType::Trojan
         );
 
         let vmess_link = "vmess://eyJ2IjoiMiIsInBzIjoiQHZwbnBvb2wiLCJhZGQiOiJrci5haWt1bmFwcC5jb20iLCJwb3J0IjoyMDAwNiwiaWQiOiIyMTM2ZGM2Yy01ZmQ0LTRiZmQtODhhMS0yYWVlYTk4ODhmOGIiLCJhaWQiOjAsInNjeSI6ImF1dG8iLCJuZXQiOiIiLCJ0bHMiOiIifQ==".to_string();
         assert_eq!(
-            Proxy::from_link(vmess_link).unwrap().proxy_type,
+            Proxy::from_link(&vmess_link).unwrap().proxy_type(),
             ProxyType::Vmess
         );
 
         let vless_link = "vless://2cd6ed0f-636e-4e6c-9449-5a263d7a0fa5@192.9.165.253:20001?encryption=none&security=tls&sni=cfed.tgzdyz2.top&fp=random&type=ws&host=cfed.tgzdyz2.top&path=%2FTG%40ZDYZ2%3Fed%3D2560#TG%40ZDYZ2%20-%E6%BE%B3%E5%A4%A7%E5%88%A9%E4%BA%9A%F0%9F%87%A6%F0%9F%87%BA".to_string();
         assert_eq!(
-            Proxy::from_link(vless_link).unwrap().proxy_type,
+            Proxy::from_link(&vless_link).unwrap().proxy_type(),
             ProxyType::Vless
         );
     }
@@ -358,10 +792,125 @@ mod test {
     #[test]
     fn test_proxy() {
         let link = "ss://YWVzLTEyOC1nY206ZDljNTc3MzI4ZmIzNDlmZQ==@120.232.73.68:40676#%F0%9F%87%AD%F0%9F%87%B0HK".to_string();
-        let proxy1 = Proxy::from_link(link.clone()).unwrap();
-        let proxy2 = Proxy::from_link(link.clone()).unwrap();
+        let proxy1 = Proxy::from_link(&link).unwrap();
+        let proxy2 = Proxy::from_link(&link).unwrap();
         println!("{:?}", proxy1);
         println!("{:?}", proxy2);
         assert_eq!(proxy1, proxy2);
     }
+
+    #[test]
+    fn test_conversions() {
+        let link = "ss://YWVzLTEyOC1nY206ZDljNTc3MzI4ZmIzNDlmZQ==@120.232.73.68:40676#HK";
+        let proxy = Proxy::try_from(link).unwrap();
+        let value = proxy.to_clash_value().unwrap();
+        assert_eq!(value["type"], "ss");
+        assert_eq!(Proxy::from_clash_value(&value).unwrap(), proxy);
+
+        let ss = SS::try_from(proxy.clone()).unwrap();
+        assert_eq!(ss.port, 40676);
+        assert_eq!(Proxy::from(ss), proxy);
+        assert!(Vmess::try_from(proxy).is_err());
+    }
+
+    #[test]
+    fn test_malformed_links() {
+        let links = [
+            "ss://",
+            "ss://YWVzLTEyOC1nY20=",
+            "ss://YWVzLTEyOC1nY206cHdk@1.2.3.4:port",
+            "ssr://",
+            "ssr://MS4yLjMuNDo0NDM",
+            "vmess://",
+            "vmess://eyJhZGQiOiIxLjIuMy40In0=",
+            "vmess://eyJhZGQiOiIxLjIuMy40IiwiaWQiOiJ1dWlkIiwicG9ydCI6Ijk5OTk5In0=",
+            "vless://uuid",
+            "vless://uuid@1.2.3.4",
+            "trojan://password",
+            "trojan://password@1.2.3.4:abc",
+            "hysteria2://password@1.2.3.4",
+            "hysteria2://",
+            "tuic://2cd6ed0f-636e-4e6c-9449-5a263d7a0fa5@1.2.3.4:443",
+            "tuic://",
+            "socks5://1.2.3.4:1080",
+        ];
+        for link in links {
+            assert!(Proxy::from_link(link).is_err(), "{}", link);
+        }
+        assert!(Proxy::from_json("not json").is_err());
+        assert!(Proxy::from_json(r#"{"type": 1}"#).is_err());
+    }
+
+    #[test]
+    fn test_apply_insecure_default() {
+        let link =
+            "vless://2cd6ed0f-636e-4e6c-9449-5a263d7a0fa5@1.1.1.1:443?security=tls&sni=a.com#v";
+        let mut proxy = Proxy::from_link(link).unwrap();
+        assert_eq!(
+            proxy.to_clash_value().unwrap().get("skip-cert-verify"),
+            None
+        );
+        proxy.apply_insecure_default();
+        assert_eq!(proxy.to_clash_value().unwrap()["skip-cert-verify"], true);
+
+        // 链接中明确开启校验时不覆盖
+        let link = "trojan://pwd@1.1.1.1:443?sni=a.com&allowInsecure=0#t";
+        let mut proxy = Proxy::from_link(link).unwrap();
+        proxy.apply_insecure_default();
+        assert_eq!(proxy.to_clash_value().unwrap()["skip-cert-verify"], false);
+    }
+
+    #[test]
+    fn test_tolerant_types() {
+        let value = json!({
+            "name": 123, "type": "vmess", "server": "1.1.1.1", "port": " 443",
+            "uuid": "2cd6ed0f-636e-4e6c-9449-5a263d7a0fa5", "alterId": "0", "cipher": "auto",
+            "tls": "true", "udp": 1, "skip-cert-verify": "", "alpn": "h2, http/1.1",
+            "network": "h2", "h2-opts": {"host": "a.com"}
+        });
+        let Proxy::Vmess(vmess) = Proxy::from_clash_value(&value).unwrap() else {
+            panic!("expected vmess");
+        };
+        assert_eq!(vmess.name, "123");
+        assert_eq!(vmess.port, 443);
+        assert_eq!(vmess.tls, Some(true));
+        assert_eq!(vmess.udp, Some(true));
+        assert_eq!(vmess.skip_cert_verify, None);
+        assert_eq!(
+            vmess.alpn,
+            Some(vec!["h2".to_string(), "http/1.1".to_string()])
+        );
+        assert_eq!(vmess.h2_opts.unwrap().host, Some(vec!["a.com".to_string()]));
+
+        let value = json!({
+            "name": "hy", "type": "hysteria2", "server": "1.1.1.1", "port": 443,
+            "password": 123456, "hop-interval": "30", "alpn": ["h3"]
+        });
+        let Proxy::Hysteria2(hy) = Proxy::from_clash_value(&value).unwrap() else {
+            panic!("expected hysteria2");
+        };
+        assert_eq!(hy.password, "123456");
+        assert_eq!(hy.hop_interval, Some(30));
+        assert_eq!(hy.alpn, Some(vec!["h3".to_string()]));
+
+        let value = json!({
+            "name": "v", "type": "vless", "server": "1.1.1.1", "port": 443,
+            "uuid": "2cd6ed0f-636e-4e6c-9449-5a263d7a0fa5", "tls": "yes"
+        });
+        assert!(Proxy::from_clash_value(&value).is_err());
+    }
+
+    #[test]
+    fn test_port_and_tls() {
+        let proxy = Proxy::from_link(
+            "vless://2cd6ed0f-636e-4e6c-9449-5a263d7a0fa5@1.1.1.1:8443?security=tls&sni=a.com#v",
+        )
+        .unwrap();
+        assert_eq!(proxy.get_port(), 8443);
+        assert!(proxy.uses_tls());
+
+        let proxy = Proxy::from_link("ss://YWVzLTI1Ni1nY206cGFzc3dvcmQ@1.2.3.4:8388#s").unwrap();
+        assert_eq!(proxy.get_port(), 8388);
+        assert!(!proxy.uses_tls());
+    }
 }
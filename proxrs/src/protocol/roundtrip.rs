@@ -0,0 +1,106 @@
+//! 分享链接与 clash 配置之间的往返一致性测试
+//!
+//! 语料位于 `fixtures/links.txt`，每条链接都要经过
+//! link -> 结构体 -> clash yaml -> 结构体 -> link -> 结构体，
+//! 期间 clash 配置不能丢字段或变值，重新生成的链接也要稳定。
+
+use std::collections::HashSet;
+
+use serde_json::Value;
+
+use crate::protocol::Proxy;
+use crate::protocol::ProxyType;
+
+const CORPUS: &str = include_str!("../../fixtures/links.txt");
+
+// 容易在编码过程中出问题的节点名
+const TRICKY_NAMES: [&str; 5] = [
+    "🇭🇰 香港 01",
+    "a#b&c=d?e",
+    "100% /path",
+    "name with \"quotes\"",
+    "[IPv6] 节点|专线",
+];
+
+fn corpus() -> Vec<&'static str> {
+    CORPUS
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect()
+}
+
+fn clash_yaml_roundtrip(proxy: &Proxy) -> Proxy {
+    let value = proxy.to_clash_value().unwrap();
+    let yaml = serde_yaml::to_string(&value).unwrap();
+    let value = serde_yaml::from_str::<Value>(&yaml).unwrap();
+    Proxy::from_clash_value(&value)
+        .unwrap_or_else(|e| panic!("clash 配置无法还原: {}\n{}", e, yaml))
+}
+
+fn assert_roundtrip(origin: &Proxy, source: &str) {
+    let expected = origin.to_clash_value().unwrap();
+
+    let from_yaml = clash_yaml_roundtrip(origin);
+    assert_eq!(
+        from_yaml.to_clash_value().unwrap(),
+        expected,
+        "clash yaml 往返后不一致: {}",
+        source
+    );
+
+    let link = from_yaml.to_link();
+    let reparsed =
+        Proxy::from_link(&link).unwrap_or_else(|e| panic!("生成的链接无法解析: {}\n{}", e, link));
+    assert_eq!(
+        reparsed.to_clash_value().unwrap(),
+        expected,
+        "链接往返后不一致: {}\n生成的链接: {}",
+        source,
+        link
+    );
+    assert_eq!(reparsed.to_link(), link, "链接不稳定: {}", source);
+}
+
+#[test]
+fn test_corpus_roundtrip() {
+    let mut covered = HashSet::new();
+    for link in corpus() {
+        let proxy = Proxy::from_link(link).unwrap_or_else(|e| panic!("{}: {}", e, link));
+        covered.insert(proxy.proxy_type());
+        proxy
+            .validate()
+            .unwrap_or_else(|e| panic!("{}: {}", e, link));
+        assert_roundtrip(&proxy, link);
+    }
+
+    for proxy_type in [
+        ProxyType::SS,
+        ProxyType::SSR,
+        ProxyType::Vmess,
+        ProxyType::Vless,
+        ProxyType::Trojan,
+        ProxyType::Hysteria2,
+        ProxyType::Tuic,
+    ] {
+        assert!(
+            covered.contains(&proxy_type),
+            "语料缺少 {:?} 链接",
+            proxy_type
+        );
+    }
+}
+
+#[test]
+fn test_tricky_name_roundtrip() {
+    for link in corpus() {
+        let origin = Proxy::from_link(link).unwrap();
+        for name in TRICKY_NAMES {
+            let mut proxy = origin.clone();
+            proxy.set_name(name);
+            assert_roundtrip(&proxy, &format!("{} ({})", link, name));
+            assert_eq!(clash_yaml_roundtrip(&proxy).get_name(), name);
+            assert_eq!(Proxy::from_link(&proxy.to_link()).unwrap().get_name(), name);
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use std::any::Any;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::hash::Hash;
 use std::hash::Hasher;
@@ -9,21 +9,40 @@ use serde_json::Error;
 
 use crate::base64::base64decode;
 use crate::base64::base64encode;
+use crate::protocol::deserialize_string;
+use crate::protocol::deserialize_string_map;
 use crate::protocol::deserialize_u16_or_string;
+use crate::protocol::format_server_port;
+use crate::protocol::parse_server_port;
+use crate::protocol::strip_scheme;
+use crate::protocol::url_decode;
+use crate::protocol::validate::check_not_empty;
+use crate::protocol::validate::check_one_of;
+use crate::protocol::validate::check_port;
+use crate::protocol::validate::check_server;
+use crate::protocol::validate::SS_CIPHERS;
 use crate::protocol::ProxyAdapter;
 use crate::protocol::UnsupportedLinkError;
+use crate::protocol::ValidationError;
 
 #[derive(Deserialize, Debug, Serialize, Eq, Clone)]
 pub struct SS {
+    #[serde(deserialize_with = "deserialize_string")]
     pub name: String,
     pub server: String,
     #[serde(deserialize_with = "deserialize_u16_or_string")]
     pub port: u16,
+    #[serde(deserialize_with = "deserialize_string")]
     pub password: String,
     pub cipher: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub plugin: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none", rename = "plugin-opts")]
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "plugin-opts",
+        deserialize_with = "deserialize_string_map"
+    )]
     pub plugin_opts: Option<HashMap<String, String>>,
 }
 
@@ -52,22 +71,10 @@ impl ProxyAdapter for SS {
     /// https://github.com/v2rayA/v2rayA/blob/main/service/core/serverObj/shadowsocks.go#L354
     fn to_link(&self) -> String {
         let cipher_pwd = base64encode(format!("{}:{}", &self.cipher, &self.password));
-        let server_port = format!("{}:{}", &self.server, &self.port);
-        if let Some(plugin) = &self.plugin {
-            let mut plugin = format!("plugin={plugin};");
-            if let Some(plugin_opts) = &self.plugin_opts {
-                let str = plugin_opts
-                    .iter()
-                    .map(|(key, value)| {
-                        let v = key.clone() + "=" + value;
-                        urlencoding::encode(&v).into_owned()
-                    })
-                    .collect::<Vec<_>>()
-                    .join(";");
-                plugin.push_str(&str);
-            }
+        let server_port = format_server_port(&self.server, self.port);
+        if let Some(plugin) = self.sip002_plugin() {
             format!(
-                "ss://{}@{}?{}#{}",
+                "ss://{}@{}?plugin={}#{}",
                 cipher_pwd,
                 server_port,
                 plugin,
@@ -83,46 +90,49 @@ impl ProxyAdapter for SS {
         }
     }
 
-    fn from_link(link: String) -> Result<Self, UnsupportedLinkError> {
-        let url = base64decode(&link[5..]);
+    fn from_link(link: &str) -> Result<Self, UnsupportedLinkError> {
+        let url = base64decode(strip_scheme(link, "ss://")?);
         // parse name
-        let mut name = String::from("");
-        let parts: Vec<&str> = url.split("#").collect();
-        if parts.len() > 1 {
-            name = urlencoding::decode(parts[1])
-                .unwrap_or_default()
-                .trim()
-                .to_string();
-        }
+        let (url, name) = match url.split_once("#") {
+            Some((url, name)) => (url.to_string(), url_decode(name).trim().to_string()),
+            None => (url.clone(), String::from("")),
+        };
 
         // parse plugin
-        let url = base64decode(parts[0]);
-        let parts: Vec<&str> = url.split("?").collect();
+        let url = base64decode(&url);
+        let (url, params) = url.split_once("?").unwrap_or((&url, ""));
         let mut plugin = None;
         let mut plugin_opts = None;
-        if parts.len() > 1 {
-            let params = parts[1];
+        if !params.is_empty() {
             let mut params_map: HashMap<&str, String> = HashMap::new();
             for param in params.split("&") {
                 if let Some((key, value)) = param.split_once('=') {
-                    let value = value.parse::<String>().unwrap();
-                    params_map.insert(key, value);
+                    params_map.insert(key, value.to_string());
                 }
             }
 
             if let Some(item) = params_map.get("plugin") {
+                // SIP002 中整个 plugin 参数会被编码，分号变为 %3B
+                let item = if item.contains(';') {
+                    item.clone()
+                } else {
+                    url_decode(item)
+                };
                 let plugin_params = item.split(";").collect::<Vec<_>>();
                 plugin = Some(plugin_params[0].to_string());
                 if plugin_params.len() > 1 {
                     let mut map: HashMap<String, String> = HashMap::new();
                     plugin_params[1..].iter().for_each(|param| {
-                        let value = urlencoding::decode(param)
-                            .unwrap_or_default()
-                            .trim()
-                            .to_string();
-                        let kvs = value.split("=").collect::<Vec<_>>();
-                        if kvs.len() == 2 {
-                            map.insert(kvs[0].to_string(), kvs[1].to_string());
+                        let value = url_decode(param).trim().to_string();
+                        match value.split_once("=") {
+                            Some((key, value)) => {
+                                map.insert(key.to_string(), value.to_string());
+                            }
+                            // 没有值的参数为开关，如 v2ray-plugin 的 tls
+                            None if !value.is_empty() => {
+                                map.insert(value, "true".to_string());
+                            }
+                            None => {}
                         }
                     });
                     plugin_opts = Some(map);
@@ -131,25 +141,21 @@ impl ProxyAdapter for SS {
         }
 
         // parse server port
-        let url = parts[0];
-        let secret_server_port_parts: Vec<&str> = url.split("@").collect();
-
-        let secret = base64decode(secret_server_port_parts[0]);
-        let cipher_pwd_parts: Vec<&str> = secret.splitn(2, ":").collect();
-        let cipher = cipher_pwd_parts[0].parse().unwrap();
-        let password = cipher_pwd_parts[1].parse().unwrap();
-
-        let server_port = secret_server_port_parts[1];
-        let server_port_parts: Vec<&str> = server_port.split(":").collect();
-        let server = server_port_parts[0].parse::<String>().unwrap();
-        let port = server_port_parts[1].parse::<u16>().unwrap();
+        let (secret, server_port) = url
+            .rsplit_once("@")
+            .ok_or_else(|| UnsupportedLinkError::invalid(link, "server"))?;
+        let secret = base64decode(secret);
+        let (cipher, password) = secret
+            .split_once(":")
+            .ok_or_else(|| UnsupportedLinkError::invalid(link, "cipher"))?;
+        let (server, port) = parse_server_port(server_port, link)?;
 
         Ok(SS {
             name,
             server,
             port,
-            password,
-            cipher,
+            password: password.to_string(),
+            cipher: cipher.to_string(),
             plugin,
             plugin_opts,
         })
@@ -159,22 +165,51 @@ impl ProxyAdapter for SS {
         serde_json::to_string(self)
     }
 
-    fn as_any(&self) -> &dyn Any {
-        self
+    fn validate(&self) -> Result<(), ValidationError> {
+        check_server(&self.server)?;
+        check_port(self.port)?;
+        check_not_empty("password", &self.password)?;
+        check_one_of("cipher", &self.cipher, SS_CIPHERS)
     }
+}
 
-    fn eq(&self, other: &dyn ProxyAdapter) -> bool {
-        if let Some(other) = other.as_any().downcast_ref::<SS>() {
-            self == other
-        } else {
-            false
+impl SS {
+    /// 转为 SIP002 链接中的 plugin 参数，clash 中的 obfs 插件对应 obfs-local
+    fn sip002_plugin(&self) -> Option<String> {
+        let plugin = self.plugin.as_ref()?;
+        let opts = self.plugin_opts.clone().unwrap_or_default();
+        // 按键排序，保证生成的链接稳定
+        let (plugin, opts) = match plugin.as_str() {
+            "obfs" => {
+                let opts = opts
+                    .into_iter()
+                    .map(|(key, value)| match key.as_str() {
+                        "mode" => ("obfs".to_string(), value),
+                        "host" => ("obfs-host".to_string(), value),
+                        _ => (key, value),
+                    })
+                    .collect::<BTreeMap<_, _>>();
+                ("obfs-local", opts)
+            }
+            plugin => (plugin, opts.into_iter().collect()),
+        };
+        let mut items = vec![plugin.to_string()];
+        for (key, value) in opts {
+            match value.as_str() {
+                "true" => items.push(key),
+                "false" => {}
+                _ => items.push(urlencoding::encode(&format!("{}={}", key, value)).into_owned()),
+            }
         }
+        Some(items.join(";"))
     }
+}
 
-    fn hash(&self, mut state: &mut dyn Hasher) {
-        self.server.hash(&mut state);
-        self.port.hash(&mut state);
-        self.password.hash(&mut state);
+impl Hash for SS {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.server.hash(state);
+        self.port.hash(state);
+        self.password.hash(state);
     }
 }
 
@@ -185,7 +220,7 @@ mod test {
     #[test]
     fn test_parse_ss() {
         let link = String::from("ss://YWVzLTEyOC1nY206ZDljNTc3MzI4ZmIzNDlmZQ==@120.232.73.68:40676#%F0%9F%87%AD%F0%9F%87%B0HK");
-        let result = SS::from_link(link.clone());
+        let result = SS::from_link(&link);
         assert!(result.is_ok());
         let proxy = result.unwrap();
         assert_eq!(proxy.name, "🇭🇰HK");
@@ -201,7 +236,7 @@ mod test {
         let link = String::from(
             "ss://YWVzLTI1Ni1nY206UTFHVVo3VkRQWk9BU0M5SEAxMjAuMjQxLjQ1LjUwOjE3MDAxI1VTLTAx",
         );
-        let result = SS::from_link(link.clone()).unwrap();
+        let result = SS::from_link(&link).unwrap();
         assert_eq!("Q1GUZ7VDPZOASC9H", result.password);
         assert_eq!("aes-256-gcm", result.cipher);
     }
@@ -211,7 +246,7 @@ mod test {
         let link = String::from(
             "ss://MjAyMi1ibGFrZTMtYWVzLTI1Ni1nY206emtWV2lPU1o4OEVnZi9LSlE1azFlWFRZUFNMNXhZWEZ6OTFPanBFRWE1UT06dzZLQTFFYkNrM2hpdWJQZWlMMktkUUJjcG9kbUl3c1VlcDJBLzFVd3hLbz1AYXdzMS5pb2xvZnQubWU6NDg1Njc#%F0%9F%87%AF%F0%9F%87%B5%20AWS",
         );
-        let result = SS::from_link(link.clone()).unwrap();
+        let result = SS::from_link(&link).unwrap();
         assert_eq!("zkVWiOSZ88Egf/KJQ5k1eXTYPSL5xYXFz91OjpEEa5Q=:w6KA1EbCk3hiubPeiL2KdQBcpodmIwsUep2A/1UwxKo=", result.password);
         assert_eq!("2022-blake3-aes-256-gcm", result.cipher);
     }
@@ -219,7 +254,7 @@ mod test {
     #[test]
     fn test_ss2() {
         let link = String::from("ss://Y2hhY2hhMjAtaWV0ZjpIdVRhb0Nsb3Vk@cm1-hk.hutaonode3.top:12452?plugin=obfs-local;mode%3Dwebsocket#%E9%A6%99%E6%B8%AF%40vpnhat");
-        let result = SS::from_link(link.clone()).unwrap();
+        let result = SS::from_link(&link).unwrap();
         assert!(result.plugin.is_some());
         assert_eq!(result.to_link(), link);
     }
@@ -227,7 +262,7 @@ mod test {
     #[test]
     fn test_ss3() {
         let link = String::from("ss://cmM0LW1kNToydnpobzU=@120.241.144.101:2410?plugin=obfs-local;obfs%3Dhttp;obfs-host%3D89c19109670.microsoft.com#%E9%A6%99%E6%B8%AFAkari-P");
-        let ss1 = SS::from_link(link.clone()).unwrap();
+        let ss1 = SS::from_link(&link).unwrap();
         assert_eq!(ss1.cipher, "rc4-md5");
         assert_eq!(ss1.password, "2vzho5");
         assert_eq!(ss1.server, "120.241.144.101");
@@ -242,7 +277,7 @@ mod test {
         assert_eq!(ss1.plugin_opts, Some(map));
 
         let b_link = ss1.to_link();
-        let ss2 = SS::from_link(b_link.clone()).unwrap();
+        let ss2 = SS::from_link(&b_link).unwrap();
         assert_eq!(ss2.cipher, "rc4-md5");
         assert_eq!(ss2.password, "2vzho5");
         assert_eq!(ss2.server, "120.241.144.101");
@@ -256,4 +291,25 @@ mod test {
         );
         assert_eq!(ss2.plugin_opts, Some(map));
     }
+
+    #[test]
+    fn test_clash_plugin_to_link() {
+        let clash = r#"{"name":"obfs","server":"1.1.1.1","port":443,"cipher":"aes-128-gcm","password":"pwd","plugin":"obfs","plugin-opts":{"mode":"tls","host":"bing.com"}}"#;
+        let ss = serde_json::from_str::<SS>(clash).unwrap();
+        assert_eq!(
+            ss.to_link(),
+            "ss://YWVzLTEyOC1nY206cHdk@1.1.1.1:443?plugin=obfs-local;obfs%3Dtls;obfs-host%3Dbing.com#obfs"
+        );
+
+        let clash = r#"{"name":"v2ray","server":"::1","port":443,"cipher":"aes-128-gcm","password":"pwd","plugin":"v2ray-plugin","plugin-opts":{"mode":"websocket","host":"bing.com","path":"/ws","tls":true}}"#;
+        let ss = serde_json::from_str::<SS>(clash).unwrap();
+        let link = ss.to_link();
+        assert_eq!(
+            link,
+            "ss://YWVzLTEyOC1nY206cHdk@[::1]:443?plugin=v2ray-plugin;host%3Dbing.com;mode%3Dwebsocket;path%3D%2Fws;tls#v2ray"
+        );
+        let parsed = SS::from_link(&link).unwrap();
+        assert_eq!(parsed.server, "::1");
+        assert_eq!(parsed.plugin_opts, ss.plugin_opts);
+    }
 }
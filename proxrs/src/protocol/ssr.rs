@@ -1,4 +1,3 @@
-use std::any::Any;
 use std::collections::HashMap;
 use std::hash::Hash;
 use std::hash::Hasher;
@@ -8,16 +7,28 @@ use serde::Serialize;
 use serde_json::Error;
 
 use crate::base64::base64decode;
+use crate::base64::base64encode;
+use crate::protocol::deserialize_string;
 use crate::protocol::deserialize_u16_or_string;
+use crate::protocol::strip_scheme;
+use crate::protocol::validate::check_one_of;
+use crate::protocol::validate::check_port;
+use crate::protocol::validate::check_server;
+use crate::protocol::validate::SSR_CIPHERS;
+use crate::protocol::validate::SSR_OBFS;
+use crate::protocol::validate::SSR_PROTOCOLS;
 use crate::protocol::ProxyAdapter;
 use crate::protocol::UnsupportedLinkError;
+use crate::protocol::ValidationError;
 
 #[derive(Deserialize, Debug, Serialize, Eq, Clone)]
 pub struct Ssr {
+    #[serde(deserialize_with = "deserialize_string")]
     pub name: String,
     pub server: String,
     #[serde(deserialize_with = "deserialize_u16_or_string")]
     pub port: u16,
+    #[serde(deserialize_with = "deserialize_string")]
     pub password: String,
     pub cipher: String,
     pub obfs: String,
@@ -48,33 +59,64 @@ impl ProxyAdapter for Ssr {
     }
 
     fn to_link(&self) -> String {
-        todo!()
+        // ssr 链接使用不带填充的 URL safe base64
+        let encode = |content: &str| {
+            base64encode(content.to_string())
+                .replace('+', "-")
+                .replace('/', "_")
+                .trim_end_matches('=')
+                .to_string()
+        };
+        let mut params = vec![];
+        if let Some(obfs_param) = &self.obfs_param {
+            params.push(format!("obfsparam={}", encode(obfs_param)));
+        }
+        if let Some(protocol_param) = &self.protocol_param {
+            params.push(format!("protoparam={}", encode(protocol_param)));
+        }
+        params.push(format!("remarks={}", encode(&self.name)));
+        let content = format!(
+            "{}:{}:{}:{}:{}:{}/?{}",
+            self.server,
+            self.port,
+            self.protocol,
+            self.cipher,
+            self.obfs,
+            encode(&self.password),
+            params.join("&")
+        );
+        format!("ssr://{}", encode(&content))
     }
 
-    fn from_link(link: String) -> Result<Self, UnsupportedLinkError>
+    fn from_link(link: &str) -> Result<Self, UnsupportedLinkError>
     where
         Self: Sized,
     {
-        let url = base64decode(&link[6..]);
-        let parts: Vec<&str> = url.split("/?").collect();
+        // 兼容 URL safe base64
+        let decode = |content: &str| base64decode(&content.replace('-', "+"));
+        let url = decode(strip_scheme(link, "ssr://")?);
+        let (url, params) = url.split_once("/?").unwrap_or((&url, ""));
 
-        let params = parts[1];
         let mut params_map: HashMap<&str, String> = HashMap::new();
         for param in params.split("&") {
             if let Some((key, value)) = param.split_once('=') {
-                let value = base64decode(&value.parse::<String>().unwrap());
-                params_map.insert(key, value);
+                params_map.insert(key, decode(value));
             }
         }
 
-        let url = parts[0];
-        let values: Vec<&str> = url.split(":").collect();
-        let server = String::from(values[0]);
-        let port = values[1].parse::<u16>().unwrap();
-        let protocol = String::from(values[2]);
-        let cipher = String::from(values[3]);
-        let obfs = String::from(values[4]);
-        let password = base64decode(values[5]);
+        // server:port:protocol:method:obfs:password，server 可能为 IPv6
+        let values: Vec<&str> = url.rsplitn(6, ":").collect();
+        if values.len() != 6 {
+            return Err(UnsupportedLinkError::invalid(link, "server"));
+        }
+        let server = String::from(values[5]);
+        let port = values[4]
+            .parse::<u16>()
+            .map_err(|_| UnsupportedLinkError::invalid(link, "port"))?;
+        let protocol = String::from(values[3]);
+        let cipher = String::from(values[2]);
+        let obfs = String::from(values[1]);
+        let password = decode(values[0]);
 
         let mut name = String::from("");
         if let Some(result) = params_map.get("remarks") {
@@ -98,22 +140,20 @@ impl ProxyAdapter for Ssr {
         serde_json::to_string(self)
     }
 
-    fn as_any(&self) -> &dyn Any {
-        self
-    }
-
-    fn eq(&self, other: &dyn ProxyAdapter) -> bool {
-        if let Some(other) = other.as_any().downcast_ref::<Ssr>() {
-            self == other
-        } else {
-            false
-        }
+    fn validate(&self) -> Result<(), ValidationError> {
+        check_server(&self.server)?;
+        check_port(self.port)?;
+        check_one_of("cipher", &self.cipher, SSR_CIPHERS)?;
+        check_one_of("protocol", &self.protocol, SSR_PROTOCOLS)?;
+        check_one_of("obfs", &self.obfs, SSR_OBFS)
     }
+}
 
-    fn hash(&self, mut state: &mut dyn Hasher) {
-        self.server.hash(&mut state);
-        self.port.hash(&mut state);
-        self.password.hash(&mut state);
+impl Hash for Ssr {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.server.hash(state);
+        self.port.hash(state);
+        self.password.hash(state);
     }
 }
 
@@ -124,7 +164,7 @@ mod test {
     #[test]
     fn test_parse_ssr() {
         let link = String::from("ssr://dmlwLmJhc2ljbm9kZS5ob3N0OjExODQ1OmF1dGhfYWVzMTI4X3NoYTE6Y2hhY2hhMjAtaWV0Zjp0bHMxLjJfdGlja2V0X2F1dGg6Um1oaVpUQjYvP3JlbWFya3M9VUhKdkxlbW1tZWE0cnlCSVMwZmt1S2psaGFqb3A2UHBsSUhrdUtoQk1nPT0mb2Jmc3BhcmFtPU5tWTBNV0l5TkM1dGFXTnliM052Wm5RdVkyOXQmcHJvdG9wYXJhbT1NalE2VTNCWlZYUlFaVXBaYUZKck5FWlhRdz09");
-        let ssr = Ssr::from_link(link).unwrap();
+        let ssr = Ssr::from_link(&link).unwrap();
         assert_eq!(ssr.server, "vip.basicnode.host");
         assert_eq!(ssr.port, 11845);
         assert_eq!(ssr.password, "Fhbe0z");
@@ -136,10 +176,21 @@ mod test {
         println!("{}", ssr.to_json().unwrap());
     }
 
+    #[test]
+    fn test_ssr_to_link() {
+        let link = String::from("ssr://dmlwLmJhc2ljbm9kZS5ob3N0OjExODQ1OmF1dGhfYWVzMTI4X3NoYTE6Y2hhY2hhMjAtaWV0Zjp0bHMxLjJfdGlja2V0X2F1dGg6Um1oaVpUQjYvP3JlbWFya3M9VUhKdkxlbW1tZWE0cnlCSVMwZmt1S2psaGFqb3A2UHBsSUhrdUtoQk1nPT0mb2Jmc3BhcmFtPU5tWTBNV0l5TkM1dGFXTnliM052Wm5RdVkyOXQmcHJvdG9wYXJhbT1NalE2VTNCWlZYUlFaVXBaYUZKck5FWlhRdz09");
+        let ssr = Ssr::from_link(&link).unwrap();
+        let converted = Ssr::from_link(&ssr.to_link()).unwrap();
+        assert_eq!(converted, ssr);
+        assert_eq!(converted.name, ssr.name);
+        assert_eq!(converted.obfs_param, ssr.obfs_param);
+        assert_eq!(converted.protocol_param, ssr.protocol_param);
+    }
+
     #[test]
     fn test_parse_ssr2() {
         let link = String::from("ssr://dXMtYW0zLmVxbm9kZS5uZXQ6ODA4MTpvcmlnaW46YWVzLTI1Ni1jZmI6dGxzMS4yX3RpY2tldF9hdXRoOlptOTFPRTFDUjJscS8/b2Jmc3BhcmFtPSZwcm90b3BhcmFtPSZyZW1hcmtzPXNzcl9tZXRhXzExJnByb3RvcGFyYW09Jm9iZnNwYXJhbT0=");
-        let ssr = Ssr::from_link(link).unwrap();
+        let ssr = Ssr::from_link(&link).unwrap();
         assert_eq!(ssr.server, "us-am3.eqnode.net");
         assert_eq!(ssr.port, 8081);
         assert_eq!(ssr.password, "fou8MBGij");
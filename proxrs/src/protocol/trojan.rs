@@ -1,4 +1,3 @@
-use std::any::Any;
 use std::collections::HashMap;
 use std::hash::Hash;
 use std::hash::Hasher;
@@ -7,23 +6,47 @@ use serde::Deserialize;
 use serde::Serialize;
 use serde_json::Error;
 
+use crate::protocol::deserialize_bool_or_string;
+use crate::protocol::deserialize_string;
 use crate::protocol::deserialize_u16_or_string;
+use crate::protocol::format_server_port;
+use crate::protocol::parse_server_port;
+use crate::protocol::strip_scheme;
+use crate::protocol::url_decode;
+use crate::protocol::validate::check_not_empty;
+use crate::protocol::validate::check_port;
+use crate::protocol::validate::check_server;
+use crate::protocol::GrpcOptions;
 use crate::protocol::ProxyAdapter;
+use crate::protocol::Transport;
 use crate::protocol::UnsupportedLinkError;
+use crate::protocol::ValidationError;
+use crate::protocol::WSOptions;
 
 #[derive(Deserialize, Debug, Serialize, Eq, Clone)]
 pub struct Trojan {
+    #[serde(deserialize_with = "deserialize_string")]
     pub name: String,
     pub server: String,
     #[serde(deserialize_with = "deserialize_u16_or_string")]
     pub port: u16,
+    #[serde(deserialize_with = "deserialize_string")]
     pub password: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub sni: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none", rename = "skip-cert-verify")]
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "skip-cert-verify",
+        deserialize_with = "deserialize_bool_or_string"
+    )]
     pub skip_cert_verify: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub network: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", rename = "ws-opts")]
+    pub ws_opts: Option<WSOptions>,
+    #[serde(skip_serializing_if = "Option::is_none", rename = "grpc-opts")]
+    pub grpc_opts: Option<GrpcOptions>,
 }
 
 impl PartialEq for Trojan {
@@ -46,10 +69,32 @@ impl ProxyAdapter for Trojan {
     }
 
     fn to_link(&self) -> String {
-        todo!()
+        let mut params = Vec::new();
+        if let Some(sni) = &self.sni {
+            params.push(format!("sni={}", urlencoding::encode(sni)));
+        }
+        if let Some(skip_cert_verify) = self.skip_cert_verify {
+            params.push(format!("allowInsecure={}", skip_cert_verify as u8));
+        }
+        // mihomo 中 trojan 仅支持 ws 与 grpc 传输
+        params.extend(Transport::link_params(
+            self.network.as_deref(),
+            self.ws_opts.as_ref(),
+            None,
+            self.grpc_opts.as_ref(),
+        ));
+        let mut link = format!(
+            "trojan://{}@{}",
+            urlencoding::encode(&self.password),
+            format_server_port(&self.server, self.port)
+        );
+        if !params.is_empty() {
+            link += &format!("?{}", params.join("&"));
+        }
+        link + &format!("#{}", urlencoding::encode(&self.name))
     }
 
-    fn from_link(link: String) -> Result<Self, UnsupportedLinkError>
+    fn from_link(link: &str) -> Result<Self, UnsupportedLinkError>
     where
         Self: Sized,
     {
@@ -57,12 +102,12 @@ impl ProxyAdapter for Trojan {
         // 6df03129.the-best-airport.com:443?type=tcp&sni=new.download.the-best-airport.com&
         // allowInsecure=1#%F0%9F%87%AD%F0%9F%87%B0%E9%A6%99%E6%B8%AF%2001%20%7C%20%E4%B8%93%E7%BA%
         // BF%0D
-        let mut url = &link[9..];
+        let mut url = strip_scheme(link, "trojan://")?;
 
         let mut name = String::from("");
         if let Some((v1, v2)) = url.rsplit_once("#") {
             url = v1;
-            name = urlencoding::decode(v2).unwrap().to_string();
+            name = url_decode(v2);
         }
         // b7c0a9b4-0b85-4e93-921e-63bef702172b@111.38.53.159:41001
         // 4fee57cc-ee15-4800-888f-3493f7b261f2@hk1.ee2c9087-71b0-70af-7924-09d714b25b96.6df03129.
@@ -75,38 +120,50 @@ impl ProxyAdapter for Trojan {
         let mut network = None;
         let mut sni = None;
         let mut skip_cert_verify = None;
+        let mut ws_opts = None;
+        let mut grpc_opts = None;
         if parts.len() > 1 {
             let params = parts[1];
             let mut params_map: HashMap<&str, String> = HashMap::new();
             for param in params.split("&") {
                 if let Some((key, value)) = param.split_once('=') {
-                    let value = value.parse::<String>().unwrap();
-                    params_map.insert(key, value);
+                    params_map.insert(key, value.to_string());
                 }
             }
-            network = params_map.get("type").cloned();
-            sni = params_map.get("sni").cloned();
+            sni = params_map.get("sni").map(|s| url_decode(s));
             skip_cert_verify = params_map.get("allowInsecure").map(|value| value == "1");
+            let transport = Transport::from_params(
+                params_map.get("type").map(|s| s.as_str()),
+                params_map.get("host").map(|s| url_decode(s)).as_deref(),
+                params_map.get("path").map(|s| url_decode(s)).as_deref(),
+                params_map
+                    .get("serviceName")
+                    .map(|s| url_decode(s))
+                    .as_deref(),
+            );
+            network = transport.network;
+            ws_opts = transport.ws_opts;
+            grpc_opts = transport.grpc_opts;
         }
 
         let url = parts[0];
         // 4fee57cc-ee15-4800-888f-3493f7b261f2@hk1.ee2c9087-71b0-70af-7924-09d714b25b96.6df03129.
         // the-best-airport.com:443
-        let parts: Vec<&str> = url.split("@").collect();
-        let password = String::from(parts[0]);
-
-        let parts: Vec<&str> = parts[1].split(":").collect();
-        let server = String::from(parts[0]);
-        let port = parts[1].parse::<u16>().unwrap();
+        let (password, addr) = url
+            .rsplit_once("@")
+            .ok_or_else(|| UnsupportedLinkError::invalid(link, "server"))?;
+        let (server, port) = parse_server_port(addr, link)?;
 
         Ok(Trojan {
             name,
             server,
             port,
-            password,
+            password: url_decode(password),
             sni,
             skip_cert_verify,
             network,
+            ws_opts,
+            grpc_opts,
         })
     }
 
@@ -114,22 +171,18 @@ impl ProxyAdapter for Trojan {
         serde_json::to_string(self)
     }
 
-    fn as_any(&self) -> &dyn Any {
-        self
-    }
-
-    fn eq(&self, other: &dyn ProxyAdapter) -> bool {
-        if let Some(other) = other.as_any().downcast_ref::<Trojan>() {
-            self == other
-        } else {
-            false
-        }
+    fn validate(&self) -> Result<(), ValidationError> {
+        check_server(&self.server)?;
+        check_port(self.port)?;
+        check_not_empty("password", &self.password)
     }
+}
 
-    fn hash(&self, mut state: &mut dyn Hasher) {
-        self.server.hash(&mut state);
-        self.port.hash(&mut state);
-        self.password.hash(&mut state);
+impl Hash for Trojan {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.server.hash(state);
+        self.port.hash(state);
+        self.password.hash(state);
     }
 }
 
@@ -140,7 +193,7 @@ mod test {
     #[test]
     fn test_parse_trojan() {
         let link = String::from("trojan://4fee57cc-ee15-4800-888f-3493f7b261f2@hk1.ee2c9087-71b0-70af-7924-09d714b25b96.6df03129.the-best-airport.com:443?type=tcp&sni=new.download.the-best-airport.com&allowInsecure=1#%F0%9F%87%AD%F0%9F%87%B0%E9%A6%99%E6%B8%AF%2001%20%7C%20%E4%B8%93%E7%BA%BF%0D");
-        let trojan = Trojan::from_link(link).unwrap();
+        let trojan = Trojan::from_link(&link).unwrap();
         assert_eq!(
             trojan.server,
             "hk1.ee2c9087-71b0-70af-7924-09d714b25b96.6df03129.the-best-airport.com"
@@ -156,9 +209,45 @@ mod test {
         println!("{:?}", trojan.to_json());
     }
 
+    #[test]
+    fn test_trojan_to_link() {
+        let link = String::from("trojan://4fee57cc-ee15-4800-888f-3493f7b261f2@hk1.example.com:443?sni=new.download.example.com&allowInsecure=1&type=tcp#%E9%A6%99%E6%B8%AF%2001");
+        let trojan = Trojan::from_link(&link).unwrap();
+        assert_eq!(trojan.to_link(), link);
+    }
+
     #[test]
     fn test_parse_trojan1() {
         let link = String::from("trojan://ed4f18fc-fdc9-4296-a69a-a2c908f9b09e@211.99.98.83:32039?security=tls&type=tcp&headerType=none#%F0%9F%87%A8%F0%9F%87%A6%20%E5%8A%A0%E6%8B%BF%E5%A4%A7-BGP");
-        println!("{:?}", Trojan::from_link(link).unwrap().to_json());
+        println!("{:?}", Trojan::from_link(&link).unwrap().to_json());
+    }
+
+    #[test]
+    fn test_trojan_ws_grpc_link() {
+        let link = String::from(
+            "trojan://p%40ss@1.1.1.1:443?sni=a.com&type=ws&host=a.com&path=%2Fws%3Fed%3D2048#ws",
+        );
+        let trojan = Trojan::from_link(&link).unwrap();
+        assert_eq!(trojan.password, "p@ss");
+        let ws_opts = trojan.ws_opts.as_ref().unwrap();
+        assert_eq!(ws_opts.path.as_deref(), Some("/ws?ed=2048"));
+        assert_eq!(ws_opts.host(), Some("a.com"));
+        assert_eq!(trojan.to_link(), link);
+
+        let link = String::from(
+            "trojan://pwd@[2001:db8::1]:443?sni=a.com&type=grpc&serviceName=grpc-svc#grpc",
+        );
+        let trojan = Trojan::from_link(&link).unwrap();
+        assert_eq!(trojan.server, "2001:db8::1");
+        assert_eq!(
+            trojan
+                .grpc_opts
+                .as_ref()
+                .unwrap()
+                .grpc_service_name
+                .as_deref(),
+            Some("grpc-svc")
+        );
+        assert_eq!(trojan.to_link(), link);
     }
 }
@@ -0,0 +1,172 @@
+use std::fmt;
+
+/// 节点字段校验失败，这类节点写入配置后会导致 clash 内核加载失败
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    field: &'static str,
+    message: String,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid {}: {}", self.field, self.message)
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+impl ValidationError {
+    pub(crate) fn new(field: &'static str, message: impl Into<String>) -> Self {
+        ValidationError {
+            field,
+            message: message.into(),
+        }
+    }
+
+    /// 校验失败的字段名
+    pub fn field(&self) -> &str {
+        self.field
+    }
+}
+
+/// mihomo 支持的 ss 加密方式
+pub(crate) const SS_CIPHERS: &[&str] = &[
+    "none",
+    "aes-128-gcm",
+    "aes-192-gcm",
+    "aes-256-gcm",
+    "aes-128-cfb",
+    "aes-192-cfb",
+    "aes-256-cfb",
+    "aes-128-ctr",
+    "aes-192-ctr",
+    "aes-256-ctr",
+    "rc4-md5",
+    "chacha20",
+    "chacha20-ietf",
+    "xchacha20",
+    "chacha20-ietf-poly1305",
+    "xchacha20-ietf-poly1305",
+    "2022-blake3-aes-128-gcm",
+    "2022-blake3-aes-256-gcm",
+    "2022-blake3-chacha20-poly1305",
+];
+
+/// ssr 仅支持流加密
+pub(crate) const SSR_CIPHERS: &[&str] = &[
+    "none",
+    "dummy",
+    "aes-128-cfb",
+    "aes-192-cfb",
+    "aes-256-cfb",
+    "aes-128-ctr",
+    "aes-192-ctr",
+    "aes-256-ctr",
+    "rc4-md5",
+    "chacha20",
+    "chacha20-ietf",
+    "xchacha20",
+];
+
+pub(crate) const SSR_PROTOCOLS: &[&str] = &[
+    "origin",
+    "auth_sha1_v4",
+    "auth_aes128_md5",
+    "auth_aes128_sha1",
+    "auth_chain_a",
+    "auth_chain_b",
+];
+
+pub(crate) const SSR_OBFS: &[&str] = &[
+    "plain",
+    "http_simple",
+    "http_post",
+    "random_head",
+    "tls1.2_ticket_auth",
+    "tls1.2_ticket_fastauth",
+];
+
+pub(crate) const VMESS_CIPHERS: &[&str] =
+    &["auto", "none", "zero", "aes-128-gcm", "chacha20-poly1305"];
+
+pub(crate) const VLESS_FLOWS: &[&str] = &["xtls-rprx-vision"];
+
+pub(crate) const TUIC_CONGESTION_CONTROLLERS: &[&str] = &["cubic", "new_reno", "bbr"];
+
+pub(crate) const TUIC_UDP_RELAY_MODES: &[&str] = &["native", "quic"];
+
+pub(crate) fn check_server(server: &str) -> Result<(), ValidationError> {
+    if server.is_empty() || server.contains(char::is_whitespace) {
+        return Err(ValidationError::new("server", format!("{:?}", server)));
+    }
+    Ok(())
+}
+
+pub(crate) fn check_port(port: u16) -> Result<(), ValidationError> {
+    if port == 0 {
+        return Err(ValidationError::new("port", "port must be in 1-65535"));
+    }
+    Ok(())
+}
+
+pub(crate) fn check_not_empty(field: &'static str, value: &str) -> Result<(), ValidationError> {
+    if value.is_empty() {
+        return Err(ValidationError::new(field, "must not be empty"));
+    }
+    Ok(())
+}
+
+pub(crate) fn check_one_of(
+    field: &'static str,
+    value: &str,
+    allowed: &[&str],
+) -> Result<(), ValidationError> {
+    if !allowed.contains(&value) {
+        return Err(ValidationError::new(
+            field,
+            format!("unsupported value {:?}", value),
+        ));
+    }
+    Ok(())
+}
+
+/// 标准 UUID 格式，xray/mihomo 也允许 1-30 字节的自定义字符串，会映射为 UUIDv5
+/// 包含 - 的自定义字符串一般是被截断或写错的 UUID，同样视为不合法
+pub(crate) fn check_uuid(uuid: &str) -> Result<(), ValidationError> {
+    if is_uuid(uuid) || (!uuid.is_empty() && uuid.len() <= 30 && !uuid.contains('-')) {
+        return Ok(());
+    }
+    Err(ValidationError::new("uuid", format!("{:?}", uuid)))
+}
+
+fn is_uuid(uuid: &str) -> bool {
+    let parts = uuid.split('-').collect::<Vec<_>>();
+    parts.len() == 5
+        && parts
+            .iter()
+            .zip([8, 4, 4, 4, 12])
+            .all(|(part, len)| part.len() == len && part.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_check_uuid() {
+        assert!(check_uuid("2cd6ed0f-636e-4e6c-9449-5a263d7a0fa5").is_ok());
+        assert!(check_uuid("custom-id").is_err());
+        assert!(check_uuid("myid").is_ok());
+        assert!(check_uuid("").is_err());
+        assert!(check_uuid("2cd6ed0f-636e-4e6c-9449-5a263d7a0fz5").is_err());
+        assert!(check_uuid("2cd6ed0f-636e-4e6c-9449").is_err());
+    }
+
+    #[test]
+    fn test_check_server() {
+        assert!(check_server("example.com").is_ok());
+        assert!(check_server("").is_err());
+        assert!(check_server("exa mple.com").is_err());
+        assert_eq!(check_port(0).unwrap_err().field(), "port");
+    }
+}
@@ -1,4 +1,3 @@
-use std::any::Any;
 use std::collections::HashMap;
 use std::hash::Hash;
 use std::hash::Hasher;
@@ -7,15 +6,31 @@ use serde::Deserialize;
 use serde::Serialize;
 use serde_json::Error;
 
+use crate::protocol::deserialize_bool_or_string;
+use crate::protocol::deserialize_string;
 use crate::protocol::deserialize_u16_or_string;
+use crate::protocol::format_server_port;
+use crate::protocol::parse_server_port;
+use crate::protocol::strip_scheme;
+use crate::protocol::url_decode;
+use crate::protocol::validate::check_not_empty;
+use crate::protocol::validate::check_one_of;
+use crate::protocol::validate::check_port;
+use crate::protocol::validate::check_server;
+use crate::protocol::validate::check_uuid;
+use crate::protocol::validate::VLESS_FLOWS;
 use crate::protocol::GrpcOptions;
+use crate::protocol::H2Options;
 use crate::protocol::ProxyAdapter;
 use crate::protocol::RealtyOptions;
+use crate::protocol::Transport;
 use crate::protocol::UnsupportedLinkError;
+use crate::protocol::ValidationError;
 use crate::protocol::WSOptions;
 
 #[derive(Deserialize, Debug, Serialize, Eq, Clone)]
 pub struct Vless {
+    #[serde(deserialize_with = "deserialize_string")]
     pub name: String,
     pub server: String,
     #[serde(deserialize_with = "deserialize_u16_or_string")]
@@ -23,11 +38,24 @@ pub struct Vless {
     pub uuid: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub flow: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "deserialize_bool_or_string"
+    )]
     pub tls: Option<bool>,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "deserialize_bool_or_string"
+    )]
     pub udp: Option<bool>,
-    #[serde(skip_serializing_if = "Option::is_none", rename = "skip-cert-verify")]
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "skip-cert-verify",
+        deserialize_with = "deserialize_bool_or_string"
+    )]
     pub skip_cert_verify: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub fingerprint: Option<String>,
@@ -37,6 +65,8 @@ pub struct Vless {
     pub network: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none", rename = "ws-opts")]
     pub ws_opts: Option<WSOptions>,
+    #[serde(skip_serializing_if = "Option::is_none", rename = "h2-opts")]
+    pub h2_opts: Option<H2Options>,
     #[serde(skip_serializing_if = "Option::is_none", rename = "reality-opts")]
     pub reality_opts: Option<RealtyOptions>,
     #[serde(skip_serializing_if = "Option::is_none", rename = "grpc-opts")]
@@ -63,18 +93,64 @@ impl ProxyAdapter for Vless {
     }
 
     fn to_link(&self) -> String {
-        todo!()
+        let security = if self.reality_opts.is_some() {
+            "reality"
+        } else if self.tls.unwrap_or(false) {
+            "tls"
+        } else {
+            "none"
+        };
+        let mut params = vec![
+            "encryption=none".to_string(),
+            format!("security={}", security),
+        ];
+        if let Some(servername) = &self.servername {
+            params.push(format!("sni={}", urlencoding::encode(servername)));
+        }
+        if let Some(fingerprint) = &self.fingerprint {
+            params.push(format!("fp={}", fingerprint));
+        } else if self.reality_opts.is_some() {
+            // reality 必须指定 uTLS 指纹，v2rayN 等客户端缺省时无法连接
+            params.push("fp=chrome".to_string());
+        }
+        if self.skip_cert_verify.unwrap_or(false) {
+            params.push("allowInsecure=1".to_string());
+        }
+        if let Some(flow) = self.flow.as_ref().filter(|f| !f.is_empty()) {
+            params.push(format!("flow={}", flow));
+        }
+        params.extend(Transport::link_params(
+            self.network.as_deref(),
+            self.ws_opts.as_ref(),
+            self.h2_opts.as_ref(),
+            self.grpc_opts.as_ref(),
+        ));
+        if let Some(reality_opts) = &self.reality_opts {
+            if let Some(public_key) = &reality_opts.public_key {
+                params.push(format!("pbk={}", public_key));
+            }
+            if let Some(short_id) = &reality_opts.short_id {
+                params.push(format!("sid={}", short_id));
+            }
+        }
+        format!(
+            "vless://{}@{}?{}#{}",
+            self.uuid,
+            format_server_port(&self.server, self.port),
+            params.join("&"),
+            urlencoding::encode(&self.name)
+        )
     }
 
-    fn from_link(link: String) -> Result<Self, UnsupportedLinkError>
+    fn from_link(link: &str) -> Result<Self, UnsupportedLinkError>
     where
         Self: Sized,
     {
-        let url = &link[8..];
+        let url = strip_scheme(link, "vless://")?;
         let parts = url.split("#").collect::<Vec<_>>();
         let mut name = "".to_string();
         if parts.len() > 1 {
-            name = urlencoding::decode(parts[1]).unwrap().to_string();
+            name = url_decode(parts[1]);
         }
 
         let url = parts[0];
@@ -88,45 +164,47 @@ impl ProxyAdapter for Vless {
             let params = parts[1];
             for param in params.split("&") {
                 if let Some((key, value)) = param.split_once('=') {
-                    let value = value.parse::<String>().unwrap();
-                    params_map.insert(key, value);
+                    params_map.insert(key, value.to_string());
                 }
             }
         }
 
-        let tls = params_map.get("security").is_some_and(|s| s == "tls");
-        let network = params_map.get("type").cloned();
-        let servername = params_map.get("sni").cloned();
+        let security = params_map.get("security").map(|s| s.as_str());
+        // reality 同样需要开启 tls
+        let tls = matches!(security, Some("tls") | Some("reality"));
+        let servername = params_map.get("sni").map(|s| url_decode(s));
         let flow = params_map.get("flow").cloned();
         let fingerprint = params_map.get("fp").cloned();
-        let mut ws_opts = None;
+        // 链接未声明时不跳过证书校验
+        let skip_cert_verify = params_map
+            .get("allowInsecure")
+            .or(params_map.get("insecure"))
+            .filter(|s| *s == "1" || *s == "true")
+            .map(|_| true);
+        let transport = Transport::from_params(
+            params_map.get("type").map(|s| s.as_str()),
+            params_map.get("host").map(|s| url_decode(s)).as_deref(),
+            params_map.get("path").map(|s| url_decode(s)).as_deref(),
+            params_map
+                .get("serviceName")
+                .map(|s| url_decode(s))
+                .as_deref(),
+        );
 
-        if network.as_deref().is_some_and(|s| s == "ws") {
-            let mut headers = HashMap::new();
-            if let Some(host) = params_map.get("host") {
-                headers.insert(String::from("host"), host.to_string());
-            }
-            ws_opts = Some(WSOptions {
-                path: params_map
-                    .get("path")
-                    .map(|s| urlencoding::decode(s).unwrap().to_string()),
-                headers: Some(headers),
+        let mut reality_opts = None;
+        if security == Some("reality") {
+            reality_opts = Some(RealtyOptions {
+                public_key: params_map.get("pbk").cloned(),
+                short_id: params_map.get("sid").cloned(),
             })
         }
 
-        let url = parts[0];
-        let parts: Vec<&str> = url.split("@").collect();
-        let uuid = String::from(parts[0]);
-
-        let addr = parts[1];
-        let (server, port) = if addr.starts_with('[') {
-            // IPv6 format: [2001:bc8:1d90:d4e::]:9999
-            let (ip, port) = addr.rsplit_once(':').unwrap_or((addr, ""));
-            (ip.trim_matches('[').trim_matches(']'), port)
-        } else {
-            // IPv4 format: 146.56.43.3:443
-            addr.split_once(':').unwrap_or((addr, ""))
-        };
+        let (uuid, addr) = parts[0]
+            .rsplit_once("@")
+            .ok_or_else(|| UnsupportedLinkError::invalid(link, "server"))?;
+        // IPv6 format: [2001:bc8:1d90:d4e::]:9999
+        // IPv4 format: 146.56.43.3:443
+        let (server, port) = parse_server_port(addr, link)?;
 
         if name.is_empty() {
             name = server.to_owned() + port.to_string().as_str();
@@ -134,19 +212,20 @@ impl ProxyAdapter for Vless {
 
         Ok(Vless {
             name,
-            server: server.to_owned(),
-            port: port.parse::<u16>().unwrap(),
-            uuid,
+            server,
+            port,
+            uuid: uuid.to_string(),
             flow,
             udp: Some(true),
             tls: Some(tls),
-            skip_cert_verify: Some(true),
+            skip_cert_verify,
             fingerprint,
             servername,
-            ws_opts,
-            reality_opts: None,
-            network,
-            grpc_opts: None,
+            ws_opts: transport.ws_opts,
+            h2_opts: transport.h2_opts,
+            reality_opts,
+            network: transport.network,
+            grpc_opts: transport.grpc_opts,
         })
     }
 
@@ -154,23 +233,34 @@ impl ProxyAdapter for Vless {
         serde_json::to_string(self)
     }
 
-    fn as_any(&self) -> &dyn Any {
-        self
-    }
-
-    fn eq(&self, other: &dyn ProxyAdapter) -> bool {
-        if let Some(other) = other.as_any().downcast_ref::<Vless>() {
-            self == other
-        } else {
-            false
+    fn validate(&self) -> Result<(), ValidationError> {
+        check_server(&self.server)?;
+        check_port(self.port)?;
+        check_uuid(&self.uuid)?;
+        if let Some(flow) = self.flow.as_ref().filter(|f| !f.is_empty()) {
+            check_one_of("flow", flow, VLESS_FLOWS)?;
+        }
+        // reality 必须开启 tls，并提供 sni 及公钥
+        if let Some(reality_opts) = &self.reality_opts {
+            if !self.tls.unwrap_or(false) {
+                return Err(ValidationError::new("tls", "reality requires tls"));
+            }
+            check_not_empty("servername", self.servername.as_deref().unwrap_or_default())?;
+            check_not_empty(
+                "public-key",
+                reality_opts.public_key.as_deref().unwrap_or_default(),
+            )?;
         }
+        Ok(())
     }
+}
 
-    fn hash(&self, mut state: &mut dyn Hasher) {
-        self.server.hash(&mut state);
-        self.port.hash(&mut state);
-        self.uuid.hash(&mut state);
-        self.network.hash(&mut state);
+impl Hash for Vless {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.server.hash(state);
+        self.port.hash(state);
+        self.uuid.hash(state);
+        self.network.hash(state);
     }
 }
 
@@ -185,13 +275,13 @@ mod test {
         // 40ZDYZ2%3Fed%3D2560#TG%40ZDYZ2%20-%E6%BE%B3%E5%A4%A7%E5%88%A9%E4%BA%9A%F0%9F%87%A6%F0%9F%
         // 87%BA
         let link = String::from("vless://2cd6ed0f-636e-4e6c-9449-5a263d7a0fa5@192.9.165.253:20001?encryption=none&security=tls&sni=cfed.tgzdyz2.top&fp=random&type=ws&host=cfed.tgzdyz2.top&path=%2FTG%40ZDYZ2%3Fed%3D2560#TG%40ZDYZ2%20-%E6%BE%B3%E5%A4%A7%E5%88%A9%E4%BA%9A%F0%9F%87%A6%F0%9F%87%BA");
-        let vless = Vless::from_link(link).unwrap();
+        let vless = Vless::from_link(&link).unwrap();
         assert_eq!(vless.server, "192.9.165.253");
         assert_eq!(vless.port, 20001);
         assert_eq!(vless.tls, Some(true));
         assert_eq!(vless.uuid, "2cd6ed0f-636e-4e6c-9449-5a263d7a0fa5");
         assert_eq!(vless.servername, Some("cfed.tgzdyz2.top".to_string()));
-        assert_eq!(vless.skip_cert_verify, Some(true));
+        assert_eq!(vless.skip_cert_verify, None);
         assert_eq!(vless.network, Some("ws".to_string()));
         let mut headers = HashMap::new();
         headers.insert("host".to_string(), "cfed.tgzdyz2.top".to_string());
@@ -200,6 +290,7 @@ mod test {
             Some(WSOptions {
                 path: Some("/TG@ZDYZ2?ed=2560".to_string()),
                 headers: Some(headers),
+                ..Default::default()
             })
         );
         assert_eq!(vless.fingerprint, Some("random".to_string()));
@@ -218,16 +309,28 @@ mod test {
             servername: None,
             network: None,
             ws_opts: None,
+            h2_opts: None,
             reality_opts: None,
             grpc_opts: None,
         };
         assert_eq!(new, vless);
     }
 
+    #[test]
+    fn test_vless_to_link() {
+        let link = String::from("vless://2cd6ed0f-636e-4e6c-9449-5a263d7a0fa5@192.9.165.253:20001?encryption=none&security=tls&sni=cfed.tgzdyz2.top&fp=random&type=ws&host=cfed.tgzdyz2.top&path=%2FTG%40ZDYZ2%3Fed%3D2560#TG");
+        let vless = Vless::from_link(&link).unwrap();
+        assert_eq!(vless.to_link(), link);
+
+        let link = String::from("vless://b7c0a9b4-0b85-4e93-921e-63bef702172b@[2001:bc8:1d90:d4e::]:9999?encryption=none&security=none#v6");
+        let vless = Vless::from_link(&link).unwrap();
+        assert_eq!(vless.to_link(), link);
+    }
+
     #[test]
     fn test_parse_vless1() {
         let link = String::from("vless://bfbe4deb-07c8-450b-945e-e3c7676ba5ed@146.56.43.3:443?type=tcp&encryption=none&host=&path=&headerType=none&quicSecurity=none&serviceName=&mode=gun&security=tls&flow=xtls-rprx-vision&fp=safari&sni=djdownloadkr1.xn--4gq62f52gopi49k.com&pbk=&sid=#%F0%9F%87%B0%F0%9F%87%B7%E9%9F%A9%E5%9B%BD%E9%A6%96%E5%B0%942");
-        let vless = Vless::from_link(link).unwrap();
+        let vless = Vless::from_link(&link).unwrap();
         assert_eq!(vless.server, "146.56.43.3");
         assert_eq!(vless.port, 443);
         assert_eq!(vless.tls, Some(true));
@@ -245,17 +348,51 @@ mod test {
     #[test]
     fn test_parse_vless2() {
         let link = String::from("vless://eb3b564b-4b6e-4733-8d03-c6130b858562@[2001:bc8:1d90:d4e::]:9999?encryption=none&security=reality&sni=swdist.apple.com&fp=chrome&pbk=UK7qxWWGfRQcQfwaGpHnqmmqqJBut4jxve8AeDDJ2UI&sid=aaa666&type=grpc&authority=&serviceName=applestore&mode=gun#%E6%B3%A2%E5%85%B0v6");
-        let vless = Vless::from_link(link).unwrap();
+        let vless = Vless::from_link(&link).unwrap();
         assert_eq!("2001:bc8:1d90:d4e::", vless.server);
     }
 
     #[test]
     fn test_parse_vless3() {
         let link = "vless://fa3129d0-5d5c-4bdf-99d7-708b25e92241@[2603:c022:8013:f300:2859:298e:1387:7c28]:35803?encryption=none&security=reality&sni=sega.com&fp=firefox&pbk=euJOlEl0IAbuX8rsStBPM_DVHBtWF0e5uinEhHCzYxw&sid=32ae7737&spx=%2F&type=tcp&headerType=none#yx9mzoya".to_string();
-        let vless = Vless::from_link(link).unwrap();
+        let vless = Vless::from_link(&link).unwrap();
         assert_eq!(vless.server, "2603:c022:8013:f300:2859:298e:1387:7c28");
     }
 
+    #[test]
+    fn test_vless_transport_link() {
+        let link = "vless://2cd6ed0f-636e-4e6c-9449-5a263d7a0fa5@1.2.3.4:443?encryption=none&security=tls&sni=a.example.com&type=httpupgrade&host=a.example.com&path=%2Fup#up".to_string();
+        let vless = Vless::from_link(&link).unwrap();
+        assert_eq!(vless.network, Some("ws".to_string()));
+        let ws_opts = vless.ws_opts.as_ref().unwrap();
+        assert!(ws_opts.is_http_upgrade());
+        assert_eq!(ws_opts.host(), Some("a.example.com"));
+        assert!(vless
+            .to_link()
+            .contains("type=httpupgrade&host=a.example.com&path=%2Fup"));
+        assert_eq!(
+            Vless::from_link(&vless.to_link()).unwrap().ws_opts,
+            vless.ws_opts
+        );
+
+        let link = "vless://2cd6ed0f-636e-4e6c-9449-5a263d7a0fa5@1.2.3.4:443?encryption=none&security=tls&type=http&host=a.example.com%2Cb.example.com&path=%2Fh2#h2".to_string();
+        let vless = Vless::from_link(&link).unwrap();
+        assert_eq!(vless.network, Some("h2".to_string()));
+        assert_eq!(
+            vless.h2_opts,
+            Some(H2Options {
+                host: Some(vec![
+                    "a.example.com".to_string(),
+                    "b.example.com".to_string()
+                ]),
+                path: Some("/h2".to_string()),
+            })
+        );
+        assert!(vless
+            .to_link()
+            .contains("type=h2&host=a.example.com%2Cb.example.com&path=%2Fh2"));
+    }
+
     // vless://b3524347-d27b-4d4a-8371-6cf837dea4d2@us1.helloco.xyz:60001?mode=multi&
     // security=reality&encryption=none&type=tcp&flow=xtls-rprx-vision&
     // pbk=Kyrdn7OhtL66JwSRScElBxoFSZLr5beafP4njt_Y_G0&sid=a3ffb25d&sni=python.org&
@@ -1,4 +1,3 @@
-use std::any::Any;
 use std::collections::HashMap;
 use std::hash::Hash;
 use std::hash::Hasher;
@@ -9,15 +8,30 @@ use serde_json::Error;
 
 use crate::base64::base64decode;
 use crate::base64::base64encode;
+use crate::protocol::deserialize_bool_or_string;
+use crate::protocol::deserialize_string;
 use crate::protocol::deserialize_u16_or_string;
+use crate::protocol::deserialize_vec_or_string;
+use crate::protocol::parse_server_port;
+use crate::protocol::strip_scheme;
+use crate::protocol::url_decode;
+use crate::protocol::validate::check_one_of;
+use crate::protocol::validate::check_port;
+use crate::protocol::validate::check_server;
+use crate::protocol::validate::check_uuid;
+use crate::protocol::validate::VMESS_CIPHERS;
 use crate::protocol::GrpcOptions;
+use crate::protocol::H2Options;
 use crate::protocol::ProxyAdapter;
 use crate::protocol::RealtyOptions;
+use crate::protocol::Transport;
 use crate::protocol::UnsupportedLinkError;
+use crate::protocol::ValidationError;
 use crate::protocol::WSOptions;
 
 #[derive(Deserialize, Debug, Serialize, Eq, Clone)]
 pub struct Vmess {
+    #[serde(deserialize_with = "deserialize_string")]
     pub name: String,
     pub server: String,
     #[serde(deserialize_with = "deserialize_u16_or_string")]
@@ -26,11 +40,23 @@ pub struct Vmess {
     #[serde(deserialize_with = "deserialize_u16_or_string", rename = "alterId")]
     pub alter_id: u16,
     pub cipher: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "deserialize_vec_or_string"
+    )]
     pub alpn: Option<Vec<String>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "deserialize_bool_or_string"
+    )]
     pub tls: Option<bool>,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "deserialize_bool_or_string"
+    )]
     pub udp: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub servername: Option<String>,
@@ -38,10 +64,17 @@ pub struct Vmess {
     pub fingerprint: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub network: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none", rename = "skip-cert-verify")]
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "skip-cert-verify",
+        deserialize_with = "deserialize_bool_or_string"
+    )]
     pub skip_cert_verify: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none", rename = "ws-opts")]
     pub ws_opts: Option<WSOptions>,
+    #[serde(skip_serializing_if = "Option::is_none", rename = "h2-opts")]
+    pub h2_opts: Option<H2Options>,
     #[serde(skip_serializing_if = "Option::is_none", rename = "grpc-opts")]
     pub grpc_opts: Option<GrpcOptions>,
     #[serde(skip_serializing_if = "Option::is_none", rename = "reality-opts")]
@@ -70,13 +103,15 @@ pub struct VmessProtocol {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub path: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub tls: Option<bool>,
+    pub tls: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub alpn: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub sni: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub fp: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", rename = "allowInsecure")]
+    pub allow_insecure: Option<String>,
 }
 
 impl ProxyAdapter for Vmess {
@@ -95,16 +130,32 @@ impl ProxyAdapter for Vmess {
     fn to_link(&self) -> String {
         let mut host = None;
         let mut path = None;
-        let net = self.network.clone();
-
-        if net.is_some_and(|s| s == "ws") {
-            let ws_opts = self.ws_opts.clone();
-            if let Some(opts) = ws_opts {
-                path = opts.path.clone();
-                if let Some(headers) = opts.headers {
-                    host = headers.get("host").cloned();
+        let mut net = self.network.clone();
+
+        match net.as_deref() {
+            Some("ws") => {
+                if let Some(opts) = &self.ws_opts {
+                    path = opts.path.clone();
+                    host = opts.host().map(|s| s.to_string());
+                    if opts.is_http_upgrade() {
+                        net = Some("httpupgrade".to_string());
+                    }
+                }
+            }
+            Some("h2") => {
+                if let Some(opts) = &self.h2_opts {
+                    path = opts.path.clone();
+                    host = opts.host.as_ref().map(|h| h.join(","));
                 }
             }
+            // v2rayN 中 grpc 的 serviceName 保存在 path 中
+            Some("grpc") => {
+                path = self
+                    .grpc_opts
+                    .as_ref()
+                    .and_then(|o| o.grpc_service_name.clone());
+            }
+            _ => {}
         }
 
         let mut alpn = None;
@@ -121,147 +172,167 @@ impl ProxyAdapter for Vmess {
             id: self.uuid.clone(),
             aid: self.alter_id,
             scy: self.cipher.clone(),
-            net: self.network.clone(),
+            net,
             alpn,
             host,
             path,
-            tls: self.tls,
+            tls: self.tls.filter(|tls| *tls).map(|_| "tls".to_string()),
             sni: self.servername.clone(),
             fp: self.fingerprint.clone(),
+            allow_insecure: self
+                .skip_cert_verify
+                .filter(|v| *v)
+                .map(|_| "1".to_string()),
         };
         "vmess://".to_string() + &*base64encode(serde_json::to_string(&vmess).unwrap())
     }
 
-    fn from_link(link: String) -> Result<Self, UnsupportedLinkError>
+    fn from_link(link: &str) -> Result<Self, UnsupportedLinkError>
     where
         Self: Sized,
     {
-        let url = base64decode(&link[8..]);
+        let url = base64decode(strip_scheme(link, "vmess://")?);
         match serde_json::from_str::<serde_json::Value>(&url) {
             Ok(parsed) => {
+                // 数字字段可能是字符串也可能是数字
+                let number = |key: &str| -> Option<u16> {
+                    match &parsed[key] {
+                        serde_json::Value::String(s) => s.trim().parse().ok(),
+                        value => value.as_u64().and_then(|n| u16::try_from(n).ok()),
+                    }
+                };
                 let name = String::from(parsed["ps"].as_str().unwrap_or_default());
-                let server = parsed["add"].as_str().unwrap().to_string();
-                let alter_id = parsed["aid"].as_str().map_or_else(
-                    || parsed["aid"].as_u64().unwrap() as u16,
-                    |s| s.parse::<u16>().unwrap(),
-                );
-                let uuid = parsed["id"].as_str().unwrap().to_string();
-                let port = parsed["port"].as_str().map_or_else(
-                    || parsed["port"].as_u64().unwrap() as u16,
-                    |s| s.parse::<u16>().unwrap(),
-                );
+                let server = parsed["add"]
+                    .as_str()
+                    .filter(|s| !s.is_empty())
+                    .ok_or_else(|| UnsupportedLinkError::invalid(link, "add"))?
+                    .to_string();
+                let alter_id = number("aid").unwrap_or(0);
+                let uuid = parsed["id"]
+                    .as_str()
+                    .ok_or_else(|| UnsupportedLinkError::invalid(link, "id"))?
+                    .to_string();
+                let port =
+                    number("port").ok_or_else(|| UnsupportedLinkError::invalid(link, "port"))?;
 
                 let mut alpn = None;
                 if let Some(p) = parsed["alpn"].as_str() {
                     alpn = Some(p.split(",").map(|s| s.to_string()).collect());
                 }
 
-                let mut network = parsed["net"].as_str().map(|s| s.to_string());
-                let mut ws_opts = None;
-
-                // parse ws-opts
-                if network.as_deref().is_some_and(|s| s == "ws") {
-                    let path = parsed["path"].as_str().map(|s| s.to_string());
-                    let mut headers = HashMap::new();
-                    if let Some(host) = parsed["host"].as_str() {
-                        headers.insert(String::from("host"), host.to_string());
-                    }
-                    ws_opts = Some(WSOptions {
-                        path,
-                        headers: Some(headers),
-                    });
-                }
-
-                let mut grpc_opts = None;
-
-                // parse grpc sni
-                if network.as_deref().is_some_and(|s| s == "grpc") {
-                    let sni = parsed["sni"].as_str().map(|s| s.to_string());
-                    grpc_opts = Some(GrpcOptions {
-                        grpc_service_name: sni,
-                    })
+                let net = parsed["net"].as_str().filter(|s| !s.is_empty());
+                // vmess 链接中的 http 为 tcp 的 http 伪装，暂不支持
+                if let Some(net) = net.filter(|s| *s == "quic" || *s == "http") {
+                    return Err(UnsupportedLinkError::new(format!(
+                        "vmess not suitable for network type {}",
+                        net
+                    )));
                 }
 
-                if let Some(net) = network.as_deref() {
-                    if net == "quic" || net == "http" {
-                        return Err(UnsupportedLinkError {
-                            message: format!("vmess not suitable for network type {}", net),
-                        });
-                    }
-
-                    if net.is_empty() {
-                        network = None;
-                    }
-                }
+                // grpc 的 serviceName 取自 sni
+                let transport = Transport::from_params(
+                    net,
+                    parsed["host"].as_str(),
+                    parsed["path"].as_str(),
+                    parsed["sni"].as_str(),
+                );
 
                 let servername = parsed["sni"].as_str().map(|s| s.to_string());
                 let udp = parsed["udp"]
                     .as_str()
                     .map(|s| s.parse::<bool>().unwrap_or(true));
-                let tls = parsed["tls"]
+                // tls 字段一般为 "tls" 或空字符串，也兼容布尔值
+                let tls = match &parsed["tls"] {
+                    serde_json::Value::Bool(true) => Some(true),
+                    serde_json::Value::String(s) if s == "tls" || s == "true" => Some(true),
+                    _ => None,
+                };
+                let cipher = parsed["scy"]
                     .as_str()
-                    .map(|s| s.parse::<bool>().unwrap_or(false));
+                    .filter(|s| !s.is_empty())
+                    .unwrap_or("auto")
+                    .to_string();
+                let fingerprint = parsed["fp"]
+                    .as_str()
+                    .filter(|s| !s.is_empty())
+                    .map(|s| s.to_string());
+                // 链接未声明时不跳过证书校验
+                let skip_cert_verify = match &parsed["allowInsecure"] {
+                    serde_json::Value::Bool(true) => Some(true),
+                    serde_json::Value::Number(n) if n.as_u64() == Some(1) => Some(true),
+                    serde_json::Value::String(s) if s == "1" || s == "true" => Some(true),
+                    _ => None,
+                };
                 Ok(Vmess {
                     name,
                     server,
                     port,
                     uuid,
                     alter_id,
-                    cipher: "auto".to_string(),
+                    cipher,
                     tls,
                     udp,
                     alpn,
                     servername,
-                    fingerprint: Some(String::from("chrome")),
-                    network,
-                    skip_cert_verify: Some(true),
-                    ws_opts,
-                    grpc_opts,
+                    fingerprint,
+                    network: transport.network,
+                    skip_cert_verify,
+                    ws_opts: transport.ws_opts,
+                    h2_opts: transport.h2_opts,
+                    grpc_opts: transport.grpc_opts,
                     realty_opts: None,
                 })
             }
             Err(_) => {
                 // parse params
-                let parts: Vec<&str> = url.split("?").collect();
-                let params = parts[1];
+                let (url, params) = url
+                    .split_once("?")
+                    .ok_or_else(|| UnsupportedLinkError::invalid(link, "params"))?;
                 let mut params_map: HashMap<&str, String> = HashMap::new();
                 for param in params.split("&") {
                     if let Some((key, value)) = param.split_once('=') {
-                        let value = value.parse::<String>().unwrap();
-                        params_map.insert(key, value);
+                        params_map.insert(key, value.to_string());
                     }
                 }
-                let alter_id = params_map.get("alterId").unwrap().parse::<u16>().unwrap();
-                let name = urlencoding::decode(params_map.get("remarks").unwrap()).unwrap().to_string();
+                let alter_id = params_map
+                    .get("alterId")
+                    .and_then(|s| s.parse::<u16>().ok())
+                    .unwrap_or(0);
+                let name = params_map
+                    .get("remarks")
+                    .map(|s| url_decode(s))
+                    .unwrap_or_default();
 
                 // parse server port
-                let url = base64decode(parts[0]);
-                let secret_server_port_parts: Vec<&str> = url.split("@").collect();
-
-                let secret = base64decode(secret_server_port_parts[0]);
-                let cipher_pwd_parts: Vec<&str> = secret.splitn(2, ":").collect();
-                let cipher = cipher_pwd_parts[0].parse().unwrap();
-                let uuid = cipher_pwd_parts[1].parse().unwrap();
-
-                let server_port = secret_server_port_parts[1];
-                let server_port_parts: Vec<&str> = server_port.split(":").collect();
-                let server = server_port_parts[0].parse::<String>().unwrap();
-                let port = server_port_parts[1].parse::<u16>().unwrap();
+                let url = base64decode(url);
+                let (secret, server_port) = url
+                    .rsplit_once("@")
+                    .ok_or_else(|| UnsupportedLinkError::invalid(link, "server"))?;
+
+                let secret = base64decode(secret);
+                let (cipher, uuid) = secret
+                    .split_once(":")
+                    .ok_or_else(|| UnsupportedLinkError::invalid(link, "uuid"))?;
+                let (server, port) = parse_server_port(server_port, link)?;
                 Ok(Vmess{
                     name,
                     server,
                     port,
-                    uuid,
+                    uuid: uuid.to_string(),
                     alter_id,
-                    cipher,
+                    cipher: cipher.to_string(),
                     alpn: None,
                     tls: None,
                     udp: None,
                     servername: None,
                     fingerprint: None,
                     network: None,
-                    skip_cert_verify: Some(true),
+                    skip_cert_verify: params_map
+                        .get("allowInsecure")
+                        .filter(|s| *s == "1" || *s == "true")
+                        .map(|_| true),
                     ws_opts: None,
+                    h2_opts: None,
                     grpc_opts: None,
                     realty_opts: None,
                 })
@@ -273,23 +344,20 @@ impl ProxyAdapter for Vmess {
         serde_json::to_string(self)
     }
 
-    fn as_any(&self) -> &dyn Any {
-        self
-    }
-
-    fn eq(&self, other: &dyn ProxyAdapter) -> bool {
-        if let Some(other) = other.as_any().downcast_ref::<Vmess>() {
-            self == other
-        } else {
-            false
-        }
+    fn validate(&self) -> Result<(), ValidationError> {
+        check_server(&self.server)?;
+        check_port(self.port)?;
+        check_uuid(&self.uuid)?;
+        check_one_of("cipher", &self.cipher, VMESS_CIPHERS)
     }
+}
 
-    fn hash(&self, mut state: &mut dyn Hasher) {
-        self.server.hash(&mut state);
-        self.port.hash(&mut state);
-        self.uuid.hash(&mut state);
-        self.network.hash(&mut state);
+impl Hash for Vmess {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.server.hash(state);
+        self.port.hash(state);
+        self.uuid.hash(state);
+        self.network.hash(state);
     }
 }
 
@@ -300,7 +368,7 @@ mod test {
     #[test]
     fn test_parse_vmess() {
         let link = String::from("vmess://eyJ2IjoiMiIsInBzIjoiQHZwbnBvb2wiLCJhZGQiOiJrci5haWt1bmFwcC5jb20iLCJwb3J0IjoyMDAwNiwiaWQiOiIyMTM2ZGM2Yy01ZmQ0LTRiZmQtODhhMS0yYWVlYTk4ODhmOGIiLCJhaWQiOjAsInNjeSI6ImF1dG8iLCJuZXQiOiIiLCJ0bHMiOiIifQ==");
-        let vmess = Vmess::from_link(link).unwrap();
+        let vmess = Vmess::from_link(&link).unwrap();
         assert_eq!(vmess.server, "kr.aikunapp.com");
         assert_eq!(vmess.port, 20006);
         assert_eq!(vmess.uuid, "2136dc6c-5fd4-4bfd-88a1-2aeea9888f8b");
@@ -312,7 +380,7 @@ mod test {
     #[test]
     fn test_parse_ss_vmess() {
         let link = String::from("vmess://YXV0bzoyMTc5ZjE3OS01MzYwLTRiYzAtOWJjYS1mZmQ1NDAzNjNlNGFAMjAzLjY2LjE0LjQ4OjQ1MTE2?remarks=%F0%9F%87%B9%F0%9F%87%BC%20%E6%B8%AF%E4%BB%94&obfs=none&alterId=0");
-        let vmess = Vmess::from_link(link).unwrap();
+        let vmess = Vmess::from_link(&link).unwrap();
         assert_eq!(vmess.server, "203.66.14.48");
         assert_eq!(vmess.port, 45116);
         assert_eq!(vmess.uuid, "2179f179-5360-4bc0-9bca-ffd540363e4a");
@@ -325,7 +393,7 @@ mod test {
     #[test]
     fn test_parse_ws_vmess() {
         let link = String::from("vmess://eyJ2IjoiMiIsInBzIjoiXHU1MmEwXHU2MmZmXHU1OTI3IDAzIFx1OWFkOFx1OTAxZlx1ZmYwODAuMVx1NTAwZFx1NmQ0MVx1OTFjZlx1NmQ4OFx1ODAxN1x1ZmYwOSIsImFkZCI6ImNkbmNkbmNkbmNkbi43ODQ2NTQueHl6IiwicG9ydCI6IjIwNTIiLCJpZCI6IjNlYTU3OGM2LTFlYWEtNGUxNS1iZmUxLTlmNzU3YjU4ZThmMiIsImFpZCI6IjAiLCJuZXQiOiJ3cyIsInR5cGUiOiJub25lIiwiaG9zdCI6ImNhLWNmY2RuLmFpa3VuYXBwLmNvbSIsInBhdGgiOiJcL2luZGV4P2VkPTIwNDgiLCJ0bHMiOiIifQ==");
-        let vmess = Vmess::from_link(link).unwrap();
+        let vmess = Vmess::from_link(&link).unwrap();
         assert_eq!(vmess.server, "cdncdncdncdn.784654.xyz");
         assert_eq!(vmess.port, 2052);
         assert_eq!(vmess.uuid, "3ea578c6-1eaa-4e15-bfe1-9f757b58e8f2");
@@ -337,7 +405,7 @@ mod test {
     #[test]
     fn test_parse_grpc_vmess() {
         let link = String::from("vmess://eyJ2IjoiMiIsInBzIjoiXHU5MDgwXHU4YmY3XHU2NWIwXHU3NTI4XHU2MjM3NjAlXHU4ZmQ0XHU1MjI5IiwiYWRkIjoiZGVmYXVsdC42NTNlYmVlYi01ZjYwLTRiZTUtOTU4ZC03YmY0ODM5Y2RjY2QuZWY2NjE2ZmQtNWIwNi00ODJmLTlkNjQtMTgzNzQ1NjU5Y2JmLmJ5dGVwcml2YXRlbGluay5jb20iLCJwb3J0IjoiNDQzIiwiaWQiOiJhNDQzMDZkNS0zMzQzLTQ0MDUtYTA4Yy0yZDU0NmE1N2QzYjgiLCJhaWQiOiIwIiwibmV0IjoiZ3JwYyIsInR5cGUiOiJub25lIiwiaG9zdCI6IiIsInBhdGgiOiIxMjMwNiIsInRscyI6InRscyIsInNuaSI6ImNkbjEuMTAzOTIub25saW5lIn0=");
-        let vmess = Vmess::from_link(link).unwrap();
+        let vmess = Vmess::from_link(&link).unwrap();
         assert_eq!(Some("grpc".to_string()), vmess.network);
         assert_eq!(
             Some(GrpcOptions {
@@ -1,28 +1,181 @@
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::collections::HashSet;
-use std::fs;
+use std::fmt;
 use std::fs::File;
 use std::hash::DefaultHasher;
 use std::hash::Hash;
 use std::hash::Hasher;
 use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
 use std::io::Read;
 use std::io::Write;
 use std::path::Path;
 use std::time::Duration;
+use std::time::Instant;
 
+use rayon::prelude::*;
 use regex::Regex;
 use reqwest::Client;
-use serde_yaml::Mapping;
-use serde_yaml::Value;
 use tokio::time::sleep;
+use tracing::info;
+use tracing::warn;
 
 use crate::base64::base64decode;
+use crate::config::parse_yaml;
+use crate::config::ClashConfig;
+use crate::config::ConfigError;
 use crate::protocol::Proxy;
+use crate::protocol::ProxyType;
+use crate::protocol::UnsupportedLinkError;
 
 #[derive(Debug)]
 pub struct SubManager {}
 
+/// 订阅响应
+#[derive(Debug, Clone)]
+pub struct SubResponse {
+    pub content: String,
+    pub userinfo: Option<SubUserInfo>,
+}
+
+/// 订阅响应头 subscription-userinfo 中的流量信息，单位 byte
+/// upload=455727941; download=6174315083; total=1073741824000; expire=1671815872
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SubUserInfo {
+    pub upload: u64,
+    pub download: u64,
+    pub total: u64,
+    /// 过期时间戳，单位秒，0 或缺失表示不过期
+    pub expire: Option<i64>,
+}
+
+impl SubUserInfo {
+    pub fn parse(header: &str) -> Option<Self> {
+        let mut info = SubUserInfo::default();
+        let mut found = false;
+        for pair in header.split(';') {
+            let Some((key, value)) = pair.split_once('=') else {
+                continue;
+            };
+            let value = value.trim();
+            match key.trim() {
+                "upload" => info.upload = value.parse().ok()?,
+                "download" => info.download = value.parse().ok()?,
+                "total" => info.total = value.parse().ok()?,
+                "expire" => info.expire = value.parse().ok().filter(|e| *e > 0),
+                _ => continue,
+            }
+            found = true;
+        }
+        found.then_some(info)
+    }
+
+    /// 剩余流量
+    pub fn remaining(&self) -> u64 {
+        self.total.saturating_sub(self.upload + self.download)
+    }
+}
+
+/// 流式解析时每批并行解码的行数
+const PARSE_BATCH_SIZE: usize = 4096;
+
+/// 解析结果，包含无法解析或校验失败的节点及原因
+#[derive(Debug, Default)]
+pub struct ParseResult {
+    pub proxies: Vec<Proxy>,
+    pub errors: Vec<String>,
+    /// 解析耗时
+    pub elapsed: Duration,
+}
+
+impl ParseResult {
+    /// 校验节点字段，不合法的节点会导致 clash 内核加载配置失败，直接跳过
+    fn push(&mut self, proxy: Proxy) {
+        match proxy.validate() {
+            Ok(()) => self.proxies.push(proxy),
+            Err(e) => self
+                .errors
+                .push(format!("{} in proxy: {}", e, proxy.get_name())),
+        }
+    }
+
+    /// 被跳过的节点数
+    pub fn skipped(&self) -> usize {
+        self.errors.len()
+    }
+
+    /// 解析速度，单位 节点/秒
+    pub fn throughput(&self) -> f64 {
+        let total = (self.proxies.len() + self.skipped()) as f64;
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 {
+            total / secs
+        } else {
+            total
+        }
+    }
+
+    fn report(&self) {
+        for error in &self.errors {
+            warn!("{}", error);
+        }
+        if self.skipped() > 0 {
+            warn!(
+                "解析到 {} 个节点，跳过 {} 个无法解析或校验失败的节点",
+                self.proxies.len(),
+                self.skipped()
+            );
+        }
+        info!(
+            "解析 {} 个节点耗时 {:.2?}，{:.0} 个/秒",
+            self.proxies.len() + self.skipped(),
+            self.elapsed,
+            self.throughput()
+        );
+    }
+}
+
+/// 单个订阅的拉取结果，用于统计各订阅实际贡献的节点
+#[derive(Debug, Default)]
+pub struct SubFetch {
+    /// 订阅内容的字节数
+    pub bytes: usize,
+    /// 解析结果，包含跳过的节点及原因
+    pub parsed: ParseResult,
+    /// 无法获取订阅内容时的原因
+    pub error: Option<String>,
+}
+
+/// 节点去重统计
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct DedupStats {
+    /// 去重前的节点数
+    pub total: usize,
+    /// 去重后的节点数
+    pub unique: usize,
+    /// 去重后各协议的节点数
+    pub protocols: BTreeMap<ProxyType, usize>,
+}
+
+impl fmt::Display for DedupStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "节点总数：{}，去重后：{}，重复：{}",
+            self.total,
+            self.unique,
+            self.total - self.unique
+        )?;
+        for (proxy_type, count) in &self.protocols {
+            let name = serde_json::to_value(proxy_type).unwrap_or_default();
+            write!(f, "，{}：{}", name.as_str().unwrap_or_default(), count)?;
+        }
+        Ok(())
+    }
+}
+
 impl SubManager {
     /// 从链接中获取代理信息支持以下四种结构
     /// 1. http://订阅链接，传入代理地址
@@ -30,17 +183,41 @@ impl SubManager {
     /// 3. ss://xxxx，传入单个节点链接
     /// 4. edhxxx, 传入 base64 的节点信息
     pub async fn get_proxies_from_url(url: String) -> Vec<Proxy> {
-        let mut proxies: Vec<Proxy> = Vec::new();
+        Self::fetch_proxies(&url).await.parsed.proxies
+    }
+
+    /// 同 get_proxies_from_url，同时返回内容大小、跳过的节点及原因，获取失败时 error 为失败原因
+    pub async fn fetch_proxies(url: &str) -> SubFetch {
+        let mut fetch = SubFetch::default();
         if url.starts_with("http") {
-            if let Ok(file_path) = Self::get_content_from_sub_url(&url).await {
-                proxies = Self::parse_content(file_path).unwrap();
+            match Self::get_content_from_sub_url(url).await {
+                Ok(content) => {
+                    fetch.bytes = content.len();
+                    fetch.parsed = Self::parse_content_with_errors(&content);
+                }
+                Err(e) => {
+                    warn!("{}", e);
+                    fetch.error = Some(e.to_string());
+                }
             }
-        } else if Path::new(&url).is_file() {
-            proxies = Self::parse_from_path(&url).unwrap();
-        } else if let Ok(p) = Self::parse_content(url.to_string()) {
-            proxies.extend(p);
+        } else if Path::new(url).is_file() {
+            let parsed = File::open(url).and_then(|file| {
+                let bytes = file.metadata()?.len() as usize;
+                Ok((bytes, Self::parse_reader(BufReader::new(file))?))
+            });
+            match parsed {
+                Ok((bytes, parsed)) => {
+                    fetch.bytes = bytes;
+                    fetch.parsed = parsed;
+                }
+                Err(e) => fetch.error = Some(format!("Error reading file: {}", e)),
+            }
+        } else {
+            fetch.bytes = url.len();
+            fetch.parsed = Self::parse_content_with_errors(url);
         }
-        proxies
+        fetch.parsed.report();
+        fetch
     }
 
     /// 传入 urls 列表解析代理
@@ -59,6 +236,11 @@ impl SubManager {
     }
 
     async fn get_content_from_sub_url(sub_url: &str) -> Result<String, Box<dyn std::error::Error>> {
+        Ok(Self::fetch_sub_url(sub_url).await?.content)
+    }
+
+    /// 获取订阅内容及响应头中的流量信息
+    pub async fn fetch_sub_url(sub_url: &str) -> Result<SubResponse, Box<dyn std::error::Error>> {
         let client = Client::new();
         let mut attempts = 0;
         let retries = 3;
@@ -72,6 +254,11 @@ impl SubManager {
             match result {
                 Ok(resp) => {
                     let status = resp.status();
+                    let userinfo = resp
+                        .headers()
+                        .get("subscription-userinfo")
+                        .and_then(|v| v.to_str().ok())
+                        .and_then(SubUserInfo::parse);
                     return if status.is_success() {
                         // 获取 UUID 作为文件名
                         // let re = Regex::new(r"files/(.*?)/raw").unwrap();
@@ -90,7 +277,7 @@ impl SubManager {
                                 // file.write_all(content.as_bytes()).unwrap();
                                 // Ok(env::current_dir().unwrap().join(file_path).to_string_lossy().
                                 // to_string())
-                                Ok(content)
+                                Ok(SubResponse { content, userinfo })
                             }
                             Err(e) => {
                                 if e.is_timeout() {
@@ -127,91 +314,145 @@ impl SubManager {
     pub fn parse_from_path<P: AsRef<Path>>(
         file_path: P,
     ) -> Result<Vec<Proxy>, Box<dyn std::error::Error>> {
-        match fs::read_to_string(file_path) {
-            Ok(contents) => Ok(Self::parse_content(contents)?),
+        match File::open(file_path) {
+            Ok(file) => {
+                let result = Self::parse_reader(BufReader::new(file))?;
+                result.report();
+                Ok(result.proxies)
+            }
             Err(e) => Err(format!("Error reading file: {}", e).into()),
         }
     }
 
+    /// 流式解析，逐行读取并分批并行解码，内存中只保留一批原始内容
+    /// 首个非空行不是分享链接时（yaml、base64）回退为整体解析
+    pub fn parse_reader<R: BufRead>(mut reader: R) -> io::Result<ParseResult> {
+        let start = Instant::now();
+        let mut first = String::new();
+        while reader.read_line(&mut first)? > 0 && first.trim().is_empty() {}
+        let is_link = first.trim().split_once("://").is_some_and(|(scheme, _)| {
+            !scheme.is_empty() && scheme.chars().all(|c| c.is_ascii_alphanumeric())
+        });
+        if !is_link {
+            reader.read_to_string(&mut first)?;
+            return Ok(Self::parse_content_with_errors(&first));
+        }
+
+        let mut result = ParseResult::default();
+        let mut batch = vec![first.trim().to_string()];
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            batch.push(line);
+            if batch.len() >= PARSE_BATCH_SIZE {
+                Self::parse_links_batch(&batch, &mut result);
+                batch.clear();
+            }
+        }
+        Self::parse_links_batch(&batch, &mut result);
+        result.elapsed = start.elapsed();
+        Ok(result)
+    }
+
     /// 从字符串中解析代理
     /// 1. 先尝试使用 yaml 格式解析
     /// 2. 尝试解析 base64 格式
     /// 3. 尝试使用纯链接格式解析
     pub fn parse_content(content: String) -> Result<Vec<Proxy>, Box<dyn std::error::Error>> {
-        let mut conf_proxies: Vec<Proxy> = Vec::new();
-        match Self::parse_yaml_content(&content) {
-            Ok(proxies) => {
-                conf_proxies = proxies;
-            }
-            Err(_) => match Self::parse_base64_content(&content) {
-                Ok(proxies) => {
-                    conf_proxies = proxies;
-                }
-                Err(_) => {
-                    if let Ok(proxies) = Self::parse_links_content(&content) {
-                        conf_proxies = proxies;
-                    }
-                }
-            },
+        let result = Self::parse_content_with_errors(&content);
+        result.report();
+        Ok(result.proxies)
+    }
+
+    /// 同 parse_content，同时返回无法解析或校验失败的节点及原因
+    pub fn parse_content_with_errors(content: &str) -> ParseResult {
+        let start = Instant::now();
+        let mut result = ParseResult::default();
+        if Self::parse_yaml_content(content, &mut result).is_err()
+            && Self::parse_base64_content(content, &mut result).is_err()
+        {
+            Self::parse_links_content(content, &mut result);
         }
-        Ok(conf_proxies)
+        result.elapsed = start.elapsed();
+        result
     }
 
-    fn parse_yaml_content(content: &str) -> Result<Vec<Proxy>, Box<dyn std::error::Error>> {
-        let mut conf_proxies: Vec<Proxy> = Vec::new();
-        let yaml = serde_yaml::from_str::<serde_json::Value>(content)?;
+    fn parse_yaml_content(
+        content: &str,
+        result: &mut ParseResult,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let yaml = parse_yaml(content)?;
         let proxies = yaml.get("proxies").or_else(|| yaml.get("Proxies"));
         match proxies {
             None => {
                 return Err(format!("Proxy not found: {}", content).into());
             }
             Some(proxies) => {
-                if let Some(proxies_arr) = proxies.as_array() {
-                    for proxy in proxies_arr {
-                        let result = Proxy::from_json(&proxy.to_string());
-                        match result {
+                if let Some(proxies_arr) = proxies.as_sequence() {
+                    // 逐个转换，单个节点字段不合法时只跳过该节点
+                    for (index, proxy) in proxies_arr.iter().enumerate() {
+                        match Self::parse_yaml_proxy(proxy) {
                             Ok(p) => {
-                                conf_proxies.push(p);
+                                result.push(p);
                             }
                             Err(e) => {
-                                println!("{} {:?}", e, proxy);
+                                let name = proxy.get("name").and_then(|n| n.as_str());
+                                result.errors.push(format!(
+                                    "{} in proxy #{} {}",
+                                    e,
+                                    index + 1,
+                                    name.unwrap_or_default()
+                                ));
                             }
                         }
                     }
                 }
             }
         }
-        Ok(conf_proxies)
+        Ok(())
+    }
+
+    fn parse_yaml_proxy(proxy: &serde_yaml::Value) -> Result<Proxy, UnsupportedLinkError> {
+        let value = serde_yaml::from_value::<serde_json::Value>(proxy.clone())
+            .map_err(|e| UnsupportedLinkError::new(e.to_string()))?;
+        Proxy::from_clash_value(&value)
     }
 
-    fn parse_base64_content(content: &str) -> Result<Vec<Proxy>, Box<dyn std::error::Error>> {
-        let mut conf_proxies: Vec<Proxy> = Vec::new();
+    fn parse_base64_content(
+        content: &str,
+        result: &mut ParseResult,
+    ) -> Result<(), Box<dyn std::error::Error>> {
         let base64 = base64decode(content.trim());
-        base64
+        let links = base64
             .split("\n")
             .filter(|line| !line.is_empty())
-            .for_each(|line| match Proxy::from_link(line.trim().to_string()) {
-                Ok(proxy) => conf_proxies.push(proxy),
-                Err(e) => {
-                    println!("{}", e);
-                }
-            });
-        Ok(conf_proxies)
+            .collect::<Vec<_>>();
+        Self::parse_links_batch(&links, result);
+        Ok(())
     }
 
-    fn parse_links_content(content: &str) -> Result<Vec<Proxy>, Box<dyn std::error::Error>> {
-        let mut conf_proxies: Vec<Proxy> = Vec::new();
+    fn parse_links_content(content: &str, result: &mut ParseResult) {
         let links = content
             .split("\n")
             .filter(|line| !line.is_empty())
-            .map(|link| link.trim())
             .collect::<Vec<&str>>();
-        for link in links {
-            if let Ok(proxy) = Proxy::from_link(link.trim().to_string()) {
-                conf_proxies.push(proxy)
+        Self::parse_links_batch(&links, result);
+    }
+
+    /// 并行解码一批链接，结果保持原有顺序
+    fn parse_links_batch<S: AsRef<str> + Sync>(links: &[S], result: &mut ParseResult) {
+        let parsed = links
+            .par_iter()
+            .map(|link| Proxy::from_link(link.as_ref().trim()))
+            .collect::<Vec<_>>();
+        for proxy in parsed {
+            match proxy {
+                Ok(proxy) => result.push(proxy),
+                Err(e) => result.errors.push(e.to_string()),
             }
         }
-        Ok(conf_proxies)
     }
 
     /// 移除重复节点
@@ -220,11 +461,27 @@ impl SubManager {
         if !proxies.is_empty() {
             let set: HashSet<Proxy> = HashSet::from_iter(proxies);
             new_proxies = set.into_iter().collect();
-            new_proxies.sort_by(|a, b| a.proxy_type.cmp(&b.proxy_type));
+            new_proxies.sort_by(|a, b| a.proxy_type().cmp(&b.proxy_type()));
         }
         new_proxies
     }
 
+    /// 移除重复节点并统计各协议数量
+    pub fn dedupe_with_stats(proxies: Vec<Proxy>) -> (Vec<Proxy>, DedupStats) {
+        let total = proxies.len();
+        let proxies = Self::exclude_dup_proxies(proxies);
+        let mut protocols = BTreeMap::new();
+        for proxy in &proxies {
+            *protocols.entry(proxy.proxy_type()).or_insert(0) += 1;
+        }
+        let stats = DedupStats {
+            total,
+            unique: proxies.len(),
+            protocols,
+        };
+        (proxies, stats)
+    }
+
     /// 重置节点名称
     #[allow(dead_code)]
     pub fn unset_proxies_name(proxies: &mut Vec<Proxy>) {
@@ -276,60 +533,11 @@ impl SubManager {
         config_path: String,
         new_proxies: &Vec<Proxy>,
     ) -> io::Result<String> {
-        let mut file = File::open(config_path)?;
-        let mut contents = String::new();
-        file.read_to_string(&mut contents)?;
-        let mut yaml: Value = serde_yaml::from_str(&contents).expect("Failed to parse YAML");
-
-        // 插入 proxies
-        if let Some(proxies) = yaml.get_mut("proxies").and_then(Value::as_sequence_mut) {
-            for proxy in new_proxies {
-                proxies.push(Value::Mapping(
-                    serde_yaml::from_str::<Mapping>(&proxy.to_json()?).unwrap(),
-                ));
-            }
-        } else {
-            println!("Failed to find 'proxies' in the YAML file");
-        }
-
-        // 处理 proxy-groups 逻辑
-        if let Some(groups) = yaml
-            .get_mut("proxy-groups")
-            .and_then(Value::as_sequence_mut)
-        {
-            for group in groups.iter_mut() {
-                if let Some(group_map) = group.as_mapping_mut() {
-                    if let Some(Value::String(filter)) =
-                        group_map.get(Value::String("filter".to_string()))
-                    {
-                        let regex = Regex::new(filter).expect("Invalid regex");
-                        if let Some(proxies) = group_map
-                            .get_mut(Value::String("proxies".to_string()))
-                            .and_then(Value::as_sequence_mut)
-                        {
-                            let mut removed_default = false;
-                            for proxy in new_proxies {
-                                if regex.is_match(proxy.get_name()) {
-                                    if !removed_default
-                                        && proxies
-                                        .first()
-                                        .is_some_and(|p| p.as_str().unwrap().eq("PROXY"))
-                                    {
-                                        proxies.remove(0);
-                                        removed_default = true;
-                                    }
-                                    proxies.push(Value::String(proxy.get_name().to_string()));
-                                }
-                            }
-                            if proxies.is_empty() {
-                                proxies.push(Value::String("DIRECT".to_string()));
-                            }
-                        }
-                    }
-                }
-            }
-        }
-        Ok(serde_yaml::to_string(&yaml).expect("Failed to serialize YAML"))
+        let to_io_error = |e: ConfigError| io::Error::new(io::ErrorKind::InvalidData, e);
+        let mut config = ClashConfig::from_path(config_path).map_err(to_io_error)?;
+        config.add_proxies(new_proxies);
+        config.prune_groups();
+        config.to_yaml().map_err(to_io_error)
     }
 
     pub fn save_proxies_into_clash_file(
@@ -338,16 +546,32 @@ impl SubManager {
         save_path: String,
     ) {
         let content = SubManager::get_clash_config_content(config_path, proxies).unwrap();
-        let mut file = File::create(&save_path).unwrap();
-        file.write_all(content.as_bytes()).unwrap();
+        write_atomic(&save_path, content.as_bytes()).unwrap();
     }
 }
 
+/// 先写入同目录下的临时文件再重命名，写入中途崩溃或同时读取时不会得到不完整的文件
+pub fn write_atomic(path: impl AsRef<Path>, content: &[u8]) -> io::Result<()> {
+    let path = path.as_ref();
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid file path"))?;
+    let tmp_path = path.with_file_name(format!(".{}.tmp", file_name.to_string_lossy()));
+    let result = File::create(&tmp_path)
+        .and_then(|mut file| {
+            file.write_all(content)?;
+            file.sync_all()
+        })
+        .and_then(|_| std::fs::rename(&tmp_path, path));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp_path);
+    }
+    result
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::protocol;
-    use crate::protocol::ProxyType::{Vless, Vmess};
     use std::path::PathBuf;
 
     #[test]
@@ -362,6 +586,137 @@ mod test {
         println!("{}", content);
     }
 
+    #[test]
+    fn test_write_atomic() {
+        let dir = std::env::temp_dir().join("proxrs_test_write_atomic");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("clash.yaml");
+        write_atomic(&path, b"proxies: []").unwrap();
+        write_atomic(&path, b"proxies: [1]").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "proxies: [1]");
+        assert!(!dir.join(".clash.yaml.tmp").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_userinfo() {
+        let info = SubUserInfo::parse(
+            "upload=455727941; download=6174315083; total=1073741824000; expire=1671815872",
+        )
+        .unwrap();
+        assert_eq!(info.upload, 455727941);
+        assert_eq!(info.download, 6174315083);
+        assert_eq!(info.remaining(), 1073741824000 - 455727941 - 6174315083);
+        assert_eq!(info.expire, Some(1671815872));
+
+        let info = SubUserInfo::parse("upload=0; download=0; total=0; expire=0").unwrap();
+        assert_eq!(info.expire, None);
+        assert_eq!(SubUserInfo::parse("text/plain"), None);
+    }
+
+    #[test]
+    fn test_parse_content_with_errors() {
+        let content = "ss://YWVzLTEyOC1nY206ZDljNTc3MzI4ZmIzNDlmZQ==@120.232.73.68:40676#HK\nunknown://xxx\n";
+        let result = SubManager::parse_content_with_errors(content);
+        assert_eq!(result.proxies.len(), 1);
+        assert_eq!(result.errors.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_reader() {
+        let mut content = String::from("\n");
+        for port in 1..=10000 {
+            content += &format!("trojan://pwd@1.1.1.1:{}#node{}\r\n", port, port);
+        }
+        content += "unknown://xxx\n";
+        let result = SubManager::parse_reader(content.as_bytes()).unwrap();
+        assert_eq!(result.proxies.len(), 10000);
+        assert_eq!(result.skipped(), 1);
+        assert_eq!(result.proxies[0].get_name(), "node1");
+        assert_eq!(result.proxies[9999].get_name(), "node10000");
+        assert!(result.throughput() > 0.0);
+
+        // 非逐行链接格式回退为整体解析
+        let yaml = "# https://example.com\nproxies:\n  - {name: ok, type: ss, server: 1.1.1.1, port: 443, cipher: aes-128-gcm, password: pwd}\n";
+        let result = SubManager::parse_reader(yaml.as_bytes()).unwrap();
+        assert_eq!(result.proxies.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_yaml_merge_keys() {
+        let content = r#"
+tls-opts: &tls
+  tls: true
+  skip-cert-verify: false
+  servername: a.com
+proxies:
+  - &base {name: base, type: vmess, server: 1.1.1.1, port: 443, uuid: 2cd6ed0f-636e-4e6c-9449-5a263d7a0fa5, alterId: 0, cipher: auto}
+  - <<: [*base, *tls]
+    name: merged
+    port: 8443
+  - *base
+"#;
+        let result = SubManager::parse_content_with_errors(content);
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+        assert_eq!(result.proxies.len(), 3);
+        let merged = result.proxies[1].to_clash_value().unwrap();
+        assert_eq!(merged["name"], "merged");
+        assert_eq!(merged["port"], 8443);
+        assert_eq!(merged["tls"], true);
+        assert_eq!(merged["servername"], "a.com");
+        assert_eq!(result.proxies[2].get_name(), "base");
+
+        let config = content.parse::<ClashConfig>().unwrap();
+        assert_eq!(config.proxies.len(), 3);
+        assert!(config.proxies[1].uses_tls());
+    }
+
+    #[test]
+    fn test_parse_yaml_isolates_invalid_proxy() {
+        let content = "proxies:\n  - {name: ok, type: ss, server: 1.1.1.1, port: 443, cipher: aes-128-gcm, password: pwd}\n  - {name: bad-port, type: ss, server: 1.1.1.1, port: 443a, cipher: aes-128-gcm, password: pwd}\n  - {name: bad-tag, type: ss, server: 1.1.1.1, port: 443, cipher: aes-128-gcm, password: !secret pwd}\n  - plain-string\n  - {name: ok2, type: trojan, server: 1.1.1.2, port: 443, password: pwd}\n";
+        let result = SubManager::parse_content_with_errors(content);
+        assert_eq!(result.proxies.len(), 2);
+        assert_eq!(result.proxies[1].get_name(), "ok2");
+        assert_eq!(result.skipped(), 3);
+        assert!(result.errors[0].contains("#2 bad-port"));
+        assert!(result.errors[1].contains("#3 bad-tag"));
+    }
+
+    #[test]
+    fn test_parse_content_skip_invalid() {
+        let content = "proxies:\n  - {name: ok, type: ss, server: 1.1.1.1, port: 443, cipher: aes-128-gcm, password: pwd}\n  - {name: bad-cipher, type: ss, server: 1.1.1.1, port: 443, cipher: aes-128-xxx, password: pwd}\n  - {name: bad-uuid, type: vless, server: 1.1.1.1, port: 443, uuid: not-a-valid-uuid}\n  - {name: bad-reality, type: vless, server: 1.1.1.1, port: 443, uuid: 2cd6ed0f-636e-4e6c-9449-5a263d7a0fa5, reality-opts: {short-id: aa}}\n";
+        let result = SubManager::parse_content_with_errors(content);
+        assert_eq!(result.proxies.len(), 1);
+        assert_eq!(result.proxies[0].get_name(), "ok");
+        assert_eq!(result.skipped(), 3);
+        assert!(result.errors[0].contains("cipher"));
+        assert!(result.errors[1].contains("uuid"));
+        assert!(result.errors[2].contains("tls"));
+    }
+
+    #[test]
+    fn test_dedupe_with_stats() {
+        let links = vec![
+            "ss://YWVzLTEyOC1nY206ZDljNTc3MzI4ZmIzNDlmZQ==@120.232.73.68:40676#HK",
+            "ss://YWVzLTEyOC1nY206ZDljNTc3MzI4ZmIzNDlmZQ==@120.232.73.68:40676#HK_dup",
+            "trojan://4fee57cc-ee15-4800-888f-3493f7b261f2@hk1.example.com:443?sni=example.com#TR",
+        ];
+        let proxies = links
+            .into_iter()
+            .map(|link| Proxy::from_link(link).unwrap())
+            .collect();
+        let (proxies, stats) = SubManager::dedupe_with_stats(proxies);
+        assert_eq!(proxies.len(), 2);
+        assert_eq!(stats.total, 3);
+        assert_eq!(stats.unique, 2);
+        assert_eq!(stats.protocols.get(&ProxyType::SS), Some(&1));
+        assert_eq!(stats.protocols.get(&ProxyType::Trojan), Some(&1));
+        assert_eq!(
+            stats.to_string(),
+            "节点总数：3，去重后：2，重复：1，ss：1，trojan：1"
+        );
+    }
+
     #[test]
     fn test_urls_type() {
         let link = "ss://YWVzLTEyOC1nY206ZDljNTc3MzI4ZmIzNDlmZQ==@120.232.73.68:40676#%F0%9F%87%AD%F0%9F%87%B0HK";
@@ -417,6 +772,20 @@ mod test {
         assert_eq!(proxies.get(4).unwrap().get_name(), "xixi");
     }
 
+    #[tokio::test]
+    async fn test_fetch_proxies() {
+        let content = "ss://YWVzLTEyOC1nY206ZDljNTc3MzI4ZmIzNDlmZQ==@1.1.1.1:1001#a\nfoo://bar";
+        let fetch = SubManager::fetch_proxies(content).await;
+        assert_eq!(fetch.bytes, content.len());
+        assert_eq!(fetch.parsed.proxies.len(), 1);
+        assert_eq!(fetch.parsed.skipped(), 1);
+        assert!(fetch.error.is_none());
+
+        let fetch = SubManager::fetch_proxies("http://127.0.0.1:1/sub").await;
+        assert!(fetch.error.is_some());
+        assert!(fetch.parsed.proxies.is_empty());
+    }
+
     #[tokio::test]
     async fn test_merge_config() {
         let urls = vec![
@@ -490,21 +859,12 @@ mod test {
         for uuid in uuids {
             for proxy in &mut proxies {
                 println!("{:?}", proxy);
-                if proxy.proxy_type.eq(&Vless) {
-                    if let Some(vless) = proxy.adapter.as_any().downcast_ref::<protocol::vless::Vless>() {
-                        let mut p = vless.clone();
-                        p.uuid = uuid.to_string();
-                        proxy.adapter = Box::new(p);
-                        result.push(proxy.clone());
-                    } else {}
-                } else if proxy.proxy_type.eq(&Vmess) {
-                    if let Some(vmess) = proxy.adapter.as_any().downcast_ref::<protocol::vmess::Vmess>() {
-                        let mut p = vmess.clone();
-                        p.uuid = uuid.to_string();
-                        proxy.adapter = Box::new(p);
-                        result.push(proxy.clone());
-                    }
+                match proxy {
+                    Proxy::Vless(vless) => vless.uuid = uuid.to_string(),
+                    Proxy::Vmess(vmess) => vmess.uuid = uuid.to_string(),
+                    _ => continue,
                 }
+                result.push(proxy.clone());
             }
         }
 
@@ -0,0 +1,338 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use chrono::DateTime;
+use chrono::Duration;
+use chrono::Local;
+use chrono::NaiveDate;
+use rusqlite::params;
+use rusqlite::Connection;
+use serde::Serialize;
+
+use crate::report::NodeReport;
+
+/// 按天汇总的统计数据库，只保存汇总结果，页面及报告查询趋势时无需扫描原始记录；
+/// 另外保存最近 ROUNDS_KEEP_DAYS 天导出节点每轮的原始延迟，供离线分析
+pub const ANALYTICS_DB_PATH: &str = "subs/analytics.db";
+/// 每轮原始延迟的保留天数
+const ROUNDS_KEEP_DAYS: i64 = 30;
+const RUN_AT_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS daily_runs (
+    day TEXT PRIMARY KEY,
+    runs INTEGER NOT NULL,
+    fetched INTEGER NOT NULL,
+    alive INTEGER NOT NULL,
+    exported INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS daily_countries (
+    day TEXT NOT NULL,
+    country TEXT NOT NULL,
+    nodes INTEGER NOT NULL,
+    latency_sum INTEGER NOT NULL,
+    latency_samples INTEGER NOT NULL,
+    PRIMARY KEY (day, country)
+);
+CREATE TABLE IF NOT EXISTS latency_rounds (
+    run_at TEXT NOT NULL,
+    node TEXT NOT NULL,
+    round INTEGER NOT NULL,
+    latency INTEGER,
+    PRIMARY KEY (run_at, node, round)
+);
+";
+
+/// 单次完整运行的节点数
+#[derive(Debug, Clone, Copy)]
+pub struct RunCounts {
+    pub fetched: usize,
+    pub alive: usize,
+    pub exported: usize,
+}
+
+/// 某一天所有运行的平均节点数
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DailyRollup {
+    pub day: String,
+    pub runs: u32,
+    pub fetched: f64,
+    pub alive: f64,
+    pub exported: f64,
+}
+
+/// 某一天某个国家的节点数及平均延迟
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CountryRollup {
+    pub day: String,
+    pub country: String,
+    /// 当天所有运行中该国家可用节点数之和
+    pub nodes: u32,
+    /// 平均延迟，单位 ms，没有延迟数据时为空
+    pub latency: Option<f64>,
+}
+
+/// 最近若干天的趋势，通过 /api/trends 查询
+#[derive(Debug, Clone, Serialize)]
+pub struct Trends {
+    pub daily: Vec<DailyRollup>,
+    pub countries: Vec<CountryRollup>,
+}
+
+/// 某次运行中某个节点某一轮的延迟，通过 /api/rounds 查询
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RoundSample {
+    pub run_at: String,
+    pub node: String,
+    /// 从 0 开始的轮次
+    pub round: u32,
+    /// 单位 ms，该轮失败时为空
+    pub latency: Option<i64>,
+}
+
+pub struct Analytics {
+    conn: Connection,
+}
+
+impl Analytics {
+    pub fn open(path: &Path) -> Result<Self, Box<dyn Error>> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        Ok(Self::init(Connection::open(path)?)?)
+    }
+
+    fn init(conn: Connection) -> rusqlite::Result<Self> {
+        conn.execute_batch(SCHEMA)?;
+        Ok(Analytics { conn })
+    }
+
+    /// 将一次完整运行累加到当天的汇总中，reports 为通过检测的节点
+    pub fn record_run(
+        &mut self,
+        day: NaiveDate,
+        counts: RunCounts,
+        reports: &[NodeReport],
+    ) -> rusqlite::Result<()> {
+        let day = day.format("%Y-%m-%d").to_string();
+        // 国家 -> (节点数, 延迟之和, 有延迟的节点数)
+        let mut countries: BTreeMap<&str, (i64, i64, i64)> = BTreeMap::new();
+        for report in reports {
+            let Some(country) = report.country.as_deref() else {
+                continue;
+            };
+            let entry = countries.entry(country).or_default();
+            entry.0 += 1;
+            if let Some(latency) = report.latency {
+                entry.1 += latency;
+                entry.2 += 1;
+            }
+        }
+
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "INSERT INTO daily_runs (day, runs, fetched, alive, exported) VALUES (?1, 1, ?2, ?3, ?4)
+             ON CONFLICT(day) DO UPDATE SET runs = runs + 1, fetched = fetched + excluded.fetched,
+             alive = alive + excluded.alive, exported = exported + excluded.exported",
+            params![
+                day,
+                counts.fetched as i64,
+                counts.alive as i64,
+                counts.exported as i64
+            ],
+        )?;
+        for (country, (nodes, latency_sum, latency_samples)) in countries {
+            tx.execute(
+                "INSERT INTO daily_countries (day, country, nodes, latency_sum, latency_samples)
+                 VALUES (?1, ?2, ?3, ?4, ?5)
+                 ON CONFLICT(day, country) DO UPDATE SET nodes = nodes + excluded.nodes,
+                 latency_sum = latency_sum + excluded.latency_sum,
+                 latency_samples = latency_samples + excluded.latency_samples",
+                params![day, country, nodes, latency_sum, latency_samples],
+            )?;
+        }
+        tx.commit()
+    }
+
+    /// 保存 reports 中每个节点每轮的原始延迟，同时清除超过保留天数的记录
+    pub fn record_rounds(
+        &mut self,
+        run_at: DateTime<Local>,
+        reports: &[NodeReport],
+    ) -> rusqlite::Result<()> {
+        let cutoff = (run_at - Duration::days(ROUNDS_KEEP_DAYS))
+            .format(RUN_AT_FORMAT)
+            .to_string();
+        let run_at = run_at.format(RUN_AT_FORMAT).to_string();
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "DELETE FROM latency_rounds WHERE run_at < ?1",
+            params![cutoff],
+        )?;
+        {
+            let mut insert = tx.prepare(
+                "INSERT OR REPLACE INTO latency_rounds (run_at, node, round, latency)
+                 VALUES (?1, ?2, ?3, ?4)",
+            )?;
+            for report in reports {
+                for (round, latency) in report.rounds.iter().enumerate() {
+                    insert.execute(params![run_at, report.name, round as i64, latency])?;
+                }
+            }
+        }
+        tx.commit()
+    }
+
+    /// 截至 today 最近 days 天每轮的原始延迟，按运行时间、节点及轮次排列
+    pub fn rounds(&self, today: NaiveDate, days: u32) -> rusqlite::Result<Vec<RoundSample>> {
+        let since = (today - Duration::days(days.saturating_sub(1) as i64))
+            .format("%Y-%m-%d")
+            .to_string();
+        self.conn
+            .prepare(
+                "SELECT run_at, node, round, latency FROM latency_rounds
+                 WHERE run_at >= ?1 ORDER BY run_at, node, round",
+            )?
+            .query_map(params![since], |row| {
+                Ok(RoundSample {
+                    run_at: row.get(0)?,
+                    node: row.get(1)?,
+                    round: row.get(2)?,
+                    latency: row.get(3)?,
+                })
+            })?
+            .collect()
+    }
+
+    /// 截至 today 最近 days 天的趋势，按日期升序排列
+    pub fn trends(&self, today: NaiveDate, days: u32) -> rusqlite::Result<Trends> {
+        let since = (today - Duration::days(days.saturating_sub(1) as i64))
+            .format("%Y-%m-%d")
+            .to_string();
+        let daily = self
+            .conn
+            .prepare(
+                "SELECT day, runs, fetched, alive, exported FROM daily_runs
+                 WHERE day >= ?1 ORDER BY day",
+            )?
+            .query_map(params![since], |row| {
+                let runs: u32 = row.get(1)?;
+                let mean = |sum: i64| sum as f64 / runs.max(1) as f64;
+                Ok(DailyRollup {
+                    day: row.get(0)?,
+                    runs,
+                    fetched: mean(row.get(2)?),
+                    alive: mean(row.get(3)?),
+                    exported: mean(row.get(4)?),
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        let countries = self
+            .conn
+            .prepare(
+                "SELECT day, country, nodes, latency_sum, latency_samples FROM daily_countries
+                 WHERE day >= ?1 ORDER BY day, nodes DESC, country",
+            )?
+            .query_map(params![since], |row| {
+                let latency_sum: i64 = row.get(3)?;
+                let latency_samples: i64 = row.get(4)?;
+                Ok(CountryRollup {
+                    day: row.get(0)?,
+                    country: row.get(1)?,
+                    nodes: row.get(2)?,
+                    latency: (latency_samples > 0)
+                        .then(|| latency_sum as f64 / latency_samples as f64),
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(Trends { daily, countries })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn report(country: Option<&str>, latency: Option<i64>) -> NodeReport {
+        let mut report = NodeReport::new("node", latency);
+        report.country = country.map(str::to_string);
+        report
+    }
+
+    #[test]
+    fn test_record_and_trends() {
+        let mut analytics = Analytics::init(Connection::open_in_memory().unwrap()).unwrap();
+        let day = NaiveDate::from_ymd_opt(2024, 11, 20).unwrap();
+        let counts = |alive| RunCounts {
+            fetched: 100,
+            alive,
+            exported: 10,
+        };
+        analytics
+            .record_run(
+                day,
+                counts(20),
+                &[
+                    report(Some("US"), Some(100)),
+                    report(Some("US"), None),
+                    report(Some("JP"), Some(50)),
+                    report(None, Some(10)),
+                ],
+            )
+            .unwrap();
+        analytics
+            .record_run(day, counts(30), &[report(Some("US"), Some(200))])
+            .unwrap();
+        let earlier = day - Duration::days(10);
+        analytics.record_run(earlier, counts(5), &[]).unwrap();
+
+        let trends = analytics.trends(day, 7).unwrap();
+        assert_eq!(
+            trends.daily,
+            vec![DailyRollup {
+                day: "2024-11-20".to_string(),
+                runs: 2,
+                fetched: 100.0,
+                alive: 25.0,
+                exported: 10.0,
+            }]
+        );
+        assert_eq!(trends.countries.len(), 2);
+        assert_eq!(trends.countries[0].country, "US");
+        assert_eq!(trends.countries[0].nodes, 3);
+        assert_eq!(trends.countries[0].latency, Some(150.0));
+        assert_eq!(trends.countries[1].latency, Some(50.0));
+
+        assert_eq!(analytics.trends(day, 30).unwrap().daily.len(), 2);
+    }
+
+    #[test]
+    fn test_record_rounds() {
+        let mut analytics = Analytics::init(Connection::open_in_memory().unwrap()).unwrap();
+        let mut hk = report(Some("HK"), Some(150));
+        hk.name = "HK".to_string();
+        hk.rounds = vec![Some(100), None, Some(200)];
+        let old = Local.with_ymd_and_hms(2024, 10, 1, 8, 0, 0).unwrap();
+        analytics.record_rounds(old, &[hk.clone()]).unwrap();
+        let now = Local.with_ymd_and_hms(2024, 11, 20, 8, 0, 0).unwrap();
+        analytics.record_rounds(now, &[hk]).unwrap();
+
+        // 超过保留天数的记录已被清除
+        let rounds = analytics.rounds(now.date_naive(), 365).unwrap();
+        assert_eq!(rounds.len(), 3);
+        assert_eq!(
+            rounds[1],
+            RoundSample {
+                run_at: "2024-11-20 08:00:00".to_string(),
+                node: "HK".to_string(),
+                round: 1,
+                latency: None,
+            }
+        );
+        assert_eq!(rounds[2].latency, Some(200));
+    }
+}
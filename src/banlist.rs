@@ -0,0 +1,109 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use chrono::DateTime;
+use chrono::Local;
+use proxrs::protocol::Proxy;
+use proxrs::sub::write_atomic;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::history::fingerprint;
+
+/// 手动禁用的节点，以 history::fingerprint 区分，拉取及导出时剔除，serve 模式下通过 /api/nodes 管理
+pub const BAN_LIST_PATH: &str = "subs/banned.json";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BannedNode {
+    /// 禁用时的节点名称，仅用于展示
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub banned_at: DateTime<Local>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(transparent)]
+pub struct BanList {
+    nodes: BTreeMap<String, BannedNode>,
+}
+
+impl BanList {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(BanList::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let content = serde_json::to_string_pretty(self).map_err(io::Error::from)?;
+        write_atomic(path, content.as_bytes())
+    }
+
+    /// 已禁用时不更新禁用时间，返回是否新增
+    pub fn ban(&mut self, fingerprint: &str, name: Option<&str>, now: DateTime<Local>) -> bool {
+        if self.nodes.contains_key(fingerprint) {
+            return false;
+        }
+        self.nodes.insert(
+            fingerprint.to_string(),
+            BannedNode {
+                name: name.map(str::to_string),
+                banned_at: now,
+            },
+        );
+        true
+    }
+
+    /// 返回节点之前是否被禁用
+    pub fn unban(&mut self, fingerprint: &str) -> bool {
+        self.nodes.remove(fingerprint).is_some()
+    }
+
+    pub fn contains(&self, proxy: &Proxy) -> bool {
+        !self.nodes.is_empty() && self.nodes.contains_key(&fingerprint(proxy))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn nodes(&self) -> &BTreeMap<String, BannedNode> {
+        &self.nodes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ban_list() {
+        let proxy =
+            Proxy::from_link("ss://YWVzLTI1Ni1nY206cGFzc3dvcmQ@1.2.3.4:8388#HK_01").unwrap();
+        let mut bans = BanList::default();
+        assert!(!bans.contains(&proxy));
+
+        let now = Local::now();
+        assert!(bans.ban(&fingerprint(&proxy), Some("HK_01"), now));
+        assert!(!bans.ban(&fingerprint(&proxy), None, now));
+        assert!(bans.contains(&proxy));
+
+        let path = std::env::temp_dir().join(format!("banned-{}.json", std::process::id()));
+        bans.save(&path).unwrap();
+        let loaded = BanList::load(&path).unwrap();
+        assert_eq!(loaded.nodes(), bans.nodes());
+        fs::remove_file(&path).unwrap();
+
+        assert!(bans.unban(&fingerprint(&proxy)));
+        assert!(!bans.unban(&fingerprint(&proxy)));
+        assert!(!bans.contains(&proxy));
+    }
+}
@@ -0,0 +1,193 @@
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+use chrono::DateTime;
+use chrono::Local;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use proxrs::sub::write_atomic;
+use serde::Deserialize;
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
+
+/// 打包文件的保存目录
+pub const BUNDLE_DIR: &str = "subs/release/bundle";
+const BUNDLE_PREFIX: &str = "clash-butler-";
+const LATEST: &str = "latest";
+
+/// 每次导出后将所有产物打包为一个压缩文件，同时保存固定名称的 latest 及带时间戳的副本，并随其他文件上传
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct BundleConfig {
+    pub enabled: bool,
+    pub format: BundleFormat,
+    /// 保留的带时间戳的打包文件数，更早的会被删除
+    pub keep: usize,
+}
+
+impl Default for BundleConfig {
+    fn default() -> Self {
+        BundleConfig {
+            enabled: false,
+            format: BundleFormat::TarGz,
+            keep: 10,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BundleFormat {
+    #[serde(rename = "zip")]
+    Zip,
+    #[serde(rename = "tar.gz")]
+    TarGz,
+}
+
+impl BundleFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            BundleFormat::Zip => "zip",
+            BundleFormat::TarGz => "tar.gz",
+        }
+    }
+}
+
+/// 将 entries（文件名及内容）打包压缩
+pub fn archive(format: BundleFormat, entries: &[(String, Vec<u8>)]) -> io::Result<Vec<u8>> {
+    match format {
+        BundleFormat::Zip => {
+            let mut zip = ZipWriter::new(io::Cursor::new(vec![]));
+            for (name, content) in entries {
+                zip.start_file(name.as_str(), SimpleFileOptions::default())?;
+                zip.write_all(content)?;
+            }
+            Ok(zip.finish()?.into_inner())
+        }
+        BundleFormat::TarGz => {
+            let mut tar = tar::Builder::new(GzEncoder::new(vec![], Compression::default()));
+            let mtime = Local::now().timestamp().max(0) as u64;
+            for (name, content) in entries {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(content.len() as u64);
+                header.set_mode(0o644);
+                header.set_mtime(mtime);
+                tar.append_data(&mut header, name, content.as_slice())?;
+            }
+            tar.into_inner()?.finish()
+        }
+    }
+}
+
+/// 在 dir 下保存 latest 及 now 时间戳两个打包文件并清理旧文件，返回两个文件的路径
+pub fn save(
+    config: &BundleConfig,
+    dir: &Path,
+    entries: &[(String, Vec<u8>)],
+    now: DateTime<Local>,
+) -> io::Result<Vec<PathBuf>> {
+    fs::create_dir_all(dir)?;
+    let content = archive(config.format, entries)?;
+    let extension = config.format.extension();
+    let latest = dir.join(format!("{}{}.{}", BUNDLE_PREFIX, LATEST, extension));
+    let stamped = dir.join(format!(
+        "{}{}.{}",
+        BUNDLE_PREFIX,
+        now.format("%Y%m%d-%H%M%S"),
+        extension
+    ));
+    write_atomic(&latest, &content)?;
+    write_atomic(&stamped, &content)?;
+    prune(dir, extension, config.keep)?;
+    Ok(vec![latest, stamped])
+}
+
+/// 按文件名中的时间戳只保留最新的 keep 个带时间戳的打包文件
+fn prune(dir: &Path, extension: &str, keep: usize) -> io::Result<()> {
+    let latest = format!("{}{}.{}", BUNDLE_PREFIX, LATEST, extension);
+    let mut stamped = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .filter(|name| {
+            name.starts_with(BUNDLE_PREFIX)
+                && name.ends_with(&format!(".{}", extension))
+                && *name != latest
+        })
+        .collect::<Vec<_>>();
+    stamped.sort();
+    let excess = stamped.len().saturating_sub(keep);
+    for name in &stamped[..excess] {
+        fs::remove_file(dir.join(name))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use chrono::TimeZone;
+    use flate2::read::GzDecoder;
+
+    use super::*;
+
+    fn entries() -> Vec<(String, Vec<u8>)> {
+        vec![
+            ("clash.yaml".to_string(), b"proxies: []\n".to_vec()),
+            ("report.json".to_string(), b"[]".to_vec()),
+        ]
+    }
+
+    #[test]
+    fn test_archive() {
+        let content = archive(BundleFormat::Zip, &entries()).unwrap();
+        let mut zip = zip::ZipArchive::new(io::Cursor::new(content)).unwrap();
+        assert_eq!(zip.len(), 2);
+        let mut yaml = String::new();
+        zip.by_name("clash.yaml")
+            .unwrap()
+            .read_to_string(&mut yaml)
+            .unwrap();
+        assert_eq!(yaml, "proxies: []\n");
+
+        let content = archive(BundleFormat::TarGz, &entries()).unwrap();
+        let mut tar = tar::Archive::new(GzDecoder::new(content.as_slice()));
+        let names = tar
+            .entries()
+            .unwrap()
+            .map(|entry| entry.unwrap().path().unwrap().display().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["clash.yaml", "report.json"]);
+    }
+
+    #[test]
+    fn test_save() {
+        let dir = std::env::temp_dir().join(format!("bundle-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let config = BundleConfig {
+            keep: 2,
+            ..Default::default()
+        };
+        for hour in 1..=3 {
+            let now = Local.with_ymd_and_hms(2024, 1, 1, hour, 0, 0).unwrap();
+            let files = save(&config, &dir, &entries(), now).unwrap();
+            assert!(files[0].ends_with("clash-butler-latest.tar.gz"));
+        }
+        let mut names = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(
+            names,
+            vec![
+                "clash-butler-20240101-020000.tar.gz",
+                "clash-butler-20240101-030000.tar.gz",
+                "clash-butler-latest.tar.gz",
+            ]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
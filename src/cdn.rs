@@ -0,0 +1,139 @@
+use std::net::IpAddr;
+
+use tokio::net::lookup_host;
+
+/// 常见 CDN 的任播地址段，节点服务器位于其中时测得的延迟只反映到 CDN 边缘节点的距离
+const CDN_RANGES: &[(&str, &[&str])] = &[
+    (
+        "Cloudflare",
+        &[
+            "173.245.48.0/20",
+            "103.21.244.0/22",
+            "103.22.200.0/22",
+            "103.31.4.0/22",
+            "141.101.64.0/18",
+            "108.162.192.0/18",
+            "190.93.240.0/20",
+            "188.114.96.0/20",
+            "197.234.240.0/22",
+            "198.41.128.0/17",
+            "162.158.0.0/15",
+            "104.16.0.0/13",
+            "104.24.0.0/14",
+            "172.64.0.0/13",
+            "131.0.72.0/22",
+            "2400:cb00::/32",
+            "2606:4700::/32",
+            "2803:f800::/32",
+            "2405:b500::/32",
+            "2405:8100::/32",
+            "2a06:98c0::/29",
+            "2c0f:f248::/32",
+        ],
+    ),
+    (
+        "Fastly",
+        &[
+            "23.235.32.0/20",
+            "43.249.72.0/22",
+            "103.244.50.0/24",
+            "103.245.222.0/23",
+            "103.245.224.0/24",
+            "104.156.80.0/20",
+            "140.248.64.0/18",
+            "140.248.128.0/17",
+            "146.75.0.0/17",
+            "151.101.0.0/16",
+            "157.52.64.0/18",
+            "167.82.0.0/17",
+            "167.82.128.0/20",
+            "167.82.160.0/20",
+            "167.82.224.0/20",
+            "172.111.64.0/18",
+            "185.31.16.0/22",
+            "199.27.72.0/21",
+            "199.232.0.0/16",
+            "2a04:4e40::/32",
+            "2a04:4e42::/32",
+        ],
+    ),
+];
+
+/// IP 所属的 CDN，不属于任何已知 CDN 时返回 None
+pub fn provider(ip: IpAddr) -> Option<&'static str> {
+    CDN_RANGES
+        .iter()
+        .find(|(_, ranges)| ranges.iter().any(|range| in_range(ip, range)))
+        .map(|(name, _)| *name)
+}
+
+/// 节点服务器地址是否位于 CDN 中，域名会先解析，解析出的任一地址位于 CDN 中即视为 CDN 节点
+pub async fn detect(server: &str) -> Option<&'static str> {
+    if let Ok(ip) = server.trim_matches(['[', ']']).parse::<IpAddr>() {
+        return provider(ip);
+    }
+    lookup_host((server, 0))
+        .await
+        .ok()?
+        .find_map(|addr| provider(addr.ip()))
+}
+
+fn in_range(ip: IpAddr, range: &str) -> bool {
+    let Some((network, prefix)) = range.split_once('/') else {
+        return false;
+    };
+    let (Ok(network), Ok(prefix)) = (network.parse::<IpAddr>(), prefix.parse::<u32>()) else {
+        return false;
+    };
+    match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(network)) => {
+            let Some(shift) = 32u32.checked_sub(prefix) else {
+                return false;
+            };
+            let mask = u32::MAX.checked_shl(shift).unwrap_or(0);
+            u32::from(ip) & mask == u32::from(network) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(network)) => {
+            let Some(shift) = 128u32.checked_sub(prefix) else {
+                return false;
+            };
+            let mask = u128::MAX.checked_shl(shift).unwrap_or(0);
+            u128::from(ip) & mask == u128::from(network) & mask
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_provider() {
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+        assert_eq!(provider(ip("104.16.123.96")), Some("Cloudflare"));
+        assert_eq!(provider(ip("172.67.1.1")), Some("Cloudflare"));
+        assert_eq!(provider(ip("2606:4700::6810:7b60")), Some("Cloudflare"));
+        assert_eq!(provider(ip("151.101.1.69")), Some("Fastly"));
+        assert_eq!(provider(ip("8.8.8.8")), None);
+        assert_eq!(provider(ip("2001:db8::1")), None);
+    }
+
+    #[test]
+    fn test_in_range() {
+        let ip = "10.0.0.1".parse().unwrap();
+        assert!(in_range(ip, "0.0.0.0/0"));
+        assert!(in_range(ip, "10.0.0.1/32"));
+        assert!(!in_range(ip, "10.0.0.2/32"));
+        assert!(!in_range(ip, "::/0"));
+        assert!(!in_range(ip, "10.0.0.0/33"));
+        assert!(!in_range(ip, "invalid"));
+    }
+
+    #[tokio::test]
+    async fn test_detect_ip() {
+        assert_eq!(detect("104.16.0.1").await, Some("Cloudflare"));
+        assert_eq!(detect("[2a04:4e42::1]").await, Some("Fastly"));
+        assert_eq!(detect("127.0.0.1").await, None);
+    }
+}
@@ -11,6 +11,8 @@ use serde_json::Value;
 use tokio::time::sleep;
 use tracing::log::error;
 
+use crate::tr;
+
 const OPENAI_TRACE_URL: &str = "https://chat.openai.com/cdn-cgi/trace";
 const CF_TRACE_URL: &str = "https://1.0.0.1/cdn-cgi/trace";
 
@@ -27,7 +29,14 @@ pub async fn get_ip(proxy_url: &str) -> Result<(IpAddr, &str), Box<dyn std::erro
         match get_trace_info_with_proxy(proxy_url, CF_TRACE_URL).await {
             Ok(trace) => Ok((trace.ip, "cf")),
             Err(e) => {
-                error!("从 Cloudflare 获取 IP 失败, {e}");
+                error!(
+                    "{}",
+                    tr!(
+                        "从 Cloudflare 获取 IP 失败, {}",
+                        "Failed to get IP from Cloudflare, {}",
+                        e
+                    )
+                );
                 Err(e)
             }
         }
@@ -38,7 +47,14 @@ pub async fn get_ip(proxy_url: &str) -> Result<(IpAddr, &str), Box<dyn std::erro
         match get_ip_by_ipify(proxy_url).await {
             Ok(ip) => Ok((ip, "ipify")),
             Err(e) => {
-                error!("从 ipify 获取 IP 失败, {e}");
+                error!(
+                    "{}",
+                    tr!(
+                        "从 ipify 获取 IP 失败, {}",
+                        "Failed to get IP from ipify, {}",
+                        e
+                    )
+                );
                 Err(e)
             }
         }
@@ -49,7 +65,14 @@ pub async fn get_ip(proxy_url: &str) -> Result<(IpAddr, &str), Box<dyn std::erro
         match get_trace_info_with_proxy(proxy_url, OPENAI_TRACE_URL).await {
             Ok(trace) => Ok((trace.ip, "openai")),
             Err(e) => {
-                error!("从 OpenAI 获取 IP 失败, {e}");
+                error!(
+                    "{}",
+                    tr!(
+                        "从 OpenAI 获取 IP 失败, {}",
+                        "Failed to get IP from OpenAI, {}",
+                        e
+                    )
+                );
                 Err(e)
             }
         }
@@ -59,7 +82,11 @@ pub async fn get_ip(proxy_url: &str) -> Result<(IpAddr, &str), Box<dyn std::erro
     let futures = vec![cf_future, ipify_future, openai_future];
     match select_ok(futures).await {
         Ok(((ip, from), _)) => Ok((ip, from)),
-        Err(_) => Err("获取不到 IP 地址，可能节点已失效，已过滤".into()),
+        Err(_) => Err(tr!(
+            "获取不到 IP 地址，可能节点已失效，已过滤",
+            "Failed to get the IP address, the node may be dead and is filtered out"
+        )
+        .into()),
     }
 }
 
@@ -0,0 +1,180 @@
+use std::path::Path;
+
+use chrono::DateTime;
+use chrono::Local;
+use proxrs::sub::DedupStats;
+use proxrs::sub::ParseResult;
+use proxrs::sub::SubManager;
+use proxrs::sub::SubUserInfo;
+use tracing::error;
+use tracing::info;
+use tracing::warn;
+
+use crate::tr;
+
+/// 单个订阅的检查结果
+#[derive(Debug)]
+pub struct SubCheck {
+    pub url: String,
+    /// 无法访问或读取时的原因
+    pub error: Option<String>,
+    pub stats: DedupStats,
+    /// 无法解析的节点及原因
+    pub parse_errors: Vec<String>,
+    pub userinfo: Option<SubUserInfo>,
+}
+
+impl SubCheck {
+    fn new(url: &str) -> Self {
+        SubCheck {
+            url: url.to_string(),
+            error: None,
+            stats: DedupStats::default(),
+            parse_errors: vec![],
+            userinfo: None,
+        }
+    }
+
+    fn with_parse_result(mut self, result: ParseResult) -> Self {
+        let (_, stats) = SubManager::dedupe_with_stats(result.proxies);
+        self.stats = stats;
+        self.parse_errors = result.errors;
+        self
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none() && self.stats.unique > 0
+    }
+
+    /// 输出检查结果
+    pub fn log(&self) {
+        if let Some(e) = &self.error {
+            error!(
+                "{}",
+                tr!("{} 无法访问：{}", "{} is unreachable: {}", self.url, e)
+            );
+            return;
+        }
+        info!("{} {}", self.url, self.stats);
+        if let Some(userinfo) = &self.userinfo {
+            let expire = match userinfo.expire.and_then(|e| DateTime::from_timestamp(e, 0)) {
+                Some(expire) => expire
+                    .with_timezone(&Local)
+                    .format("%Y-%m-%d %H:%M")
+                    .to_string(),
+                None => tr!("长期有效", "never"),
+            };
+            info!(
+                "{}",
+                tr!(
+                    "{} 已用流量：{}，剩余流量：{}，总流量：{}，到期时间：{}",
+                    "{} used: {}, remaining: {}, total: {}, expires: {}",
+                    self.url,
+                    format_bytes(userinfo.upload + userinfo.download),
+                    format_bytes(userinfo.remaining()),
+                    format_bytes(userinfo.total),
+                    expire
+                )
+            );
+        }
+        if self.stats.total == 0 {
+            warn!(
+                "{}",
+                tr!("{} 未解析到任何节点", "{} contains no nodes", self.url)
+            );
+        }
+        if !self.parse_errors.is_empty() {
+            warn!(
+                "{}",
+                tr!(
+                    "{} 解析失败节点个数：{}",
+                    "{} nodes failed to parse: {}",
+                    self.url,
+                    self.parse_errors.len()
+                )
+            );
+            for e in &self.parse_errors {
+                warn!("  {}", e);
+            }
+        }
+    }
+}
+
+/// 检查单个订阅，支持订阅链接、本地文件及节点链接
+pub async fn check_sub(url: &str) -> SubCheck {
+    let check = SubCheck::new(url);
+    if url.starts_with("http") {
+        match SubManager::fetch_sub_url(url).await {
+            Ok(response) => {
+                let mut check = check
+                    .with_parse_result(SubManager::parse_content_with_errors(&response.content));
+                check.userinfo = response.userinfo;
+                check
+            }
+            Err(e) => SubCheck {
+                error: Some(e.to_string()),
+                ..check
+            },
+        }
+    } else if Path::new(url).is_file() {
+        match std::fs::read_to_string(url) {
+            Ok(content) => check.with_parse_result(SubManager::parse_content_with_errors(&content)),
+            Err(e) => SubCheck {
+                error: Some(e.to_string()),
+                ..check
+            },
+        }
+    } else {
+        check.with_parse_result(SubManager::parse_content_with_errors(url))
+    }
+}
+
+/// 依次检查所有订阅并输出结果
+pub async fn check_subs(subs: &[String]) -> Vec<SubCheck> {
+    let mut checks = Vec::new();
+    for sub in subs {
+        let check = check_sub(sub).await;
+        check.log();
+        checks.push(check);
+    }
+    let ok = checks.iter().filter(|c| c.is_ok()).count();
+    info!(
+        "{}",
+        tr!(
+            "订阅检查完成，可用 {} 个，不可用 {} 个",
+            "Checked subscriptions, {} ok, {} failed",
+            ok,
+            checks.len() - ok
+        )
+    );
+    checks
+}
+
+fn format_bytes(bytes: u64) -> String {
+    let gb = bytes as f64 / 1024.0 / 1024.0 / 1024.0;
+    if gb >= 1.0 {
+        format!("{:.2} GB", gb)
+    } else {
+        format!("{:.2} MB", bytes as f64 / 1024.0 / 1024.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(1073741824000), "1000.00 GB");
+        assert_eq!(format_bytes(524288000), "500.00 MB");
+    }
+
+    #[tokio::test]
+    async fn test_check_inline_link() {
+        let check =
+            check_sub("ss://YWVzLTEyOC1nY206ZDljNTc3MzI4ZmIzNDlmZQ==@120.232.73.68:40676#HK").await;
+        assert!(check.is_ok());
+        assert_eq!(check.stats.unique, 1);
+        assert!(check.userinfo.is_none());
+    }
+}
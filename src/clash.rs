@@ -1,26 +1,56 @@
 #![allow(dead_code)]
 
 use std::collections::HashMap;
+use std::env;
+use std::fmt;
 use std::fs::File;
+use std::future::Future;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::path::Path;
+use std::path::PathBuf;
 use std::process::Child;
 use std::process::Command;
 use std::process::Stdio;
+use std::str::FromStr;
+use std::sync::Mutex;
 use std::time::Duration;
+use std::time::Instant;
 
+use futures_util::stream;
+use futures_util::StreamExt;
+use reqwest::header::HeaderMap;
+use reqwest::header::HeaderValue;
+use reqwest::header::AUTHORIZATION;
 use reqwest::Client;
+use reqwest::ClientBuilder;
+use reqwest::StatusCode;
+use serde::de;
+use serde::de::SeqAccess;
+use serde::de::Visitor;
 use serde::Deserialize;
+use serde::Deserializer;
 use serde::Serialize;
+use serde::Serializer;
 use serde_json::json;
 use serde_json::Value;
 use tokio::time::sleep;
+use tracing::debug;
 use tracing::info;
 
+use crate::netstack::host_port;
+use crate::tr;
+
 pub struct ClashMeta {
     pub external_port: u64,
     pub mixed_port: u64,
     pub proxy_url: String,
     pub external_url: String,
-    core_path: String,
+    /// 本机访问内核端口使用的地址
+    host: IpAddr,
+    /// 控制接口的密钥，与生成的内核配置中的 secret 一致
+    secret: Option<String>,
+    core_path: PathBuf,
     test_path: String,
     log_path: String,
     process: Option<Child>,
@@ -33,34 +63,85 @@ impl ClashMeta {
             mixed_port,
             external_url: format!("http://127.0.0.1:{}", external_port),
             proxy_url: format!("http://127.0.0.1:{}", mixed_port),
+            host: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            secret: None,
             process: None,
-            core_path: "clash-meta/mihomo".to_string(),
+            core_path: default_core_path(),
             test_path: "subs/test".to_string(),
             log_path: "logs/clash.log".to_string(),
         }
     }
 
+    /// 修改本机访问内核端口使用的地址，默认为 127.0.0.1，只有 IPv6 的机器上为 ::1
+    pub fn with_host(mut self, host: IpAddr) -> Self {
+        self.host = host;
+        self.external_url = format!("http://{}", host_port(host, self.external_port as u16));
+        self.proxy_url = format!("http://{}", host_port(host, self.mixed_port as u16));
+        self
+    }
+
+    /// 设置控制接口的密钥，之后的请求带上 Authorization 头
+    pub fn with_secret(mut self, secret: &str) -> Self {
+        self.secret = Some(secret.to_string());
+        self
+    }
+
+    /// 访问控制接口的客户端，设置了密钥时带上认证头
+    pub fn api_client(&self) -> ClientBuilder {
+        let mut headers = HeaderMap::new();
+        if let Some(value) = self
+            .secret
+            .as_ref()
+            .and_then(|secret| HeaderValue::from_str(&format!("Bearer {}", secret)).ok())
+        {
+            headers.insert(AUTHORIZATION, value);
+        }
+        Client::builder().default_headers(headers)
+    }
+
+    /// 修改内核可执行文件路径，默认为 clash-meta/mihomo，Windows 下可以省略 .exe 后缀
+    pub fn with_core_path(mut self, core_path: &Path) -> Self {
+        self.core_path = resolve_core_path(core_path);
+        self
+    }
+
+    /// 修改内核的工作目录及日志文件，默认为 subs/test 和 logs/clash.log
+    pub fn with_dir(mut self, dir: &str, log_path: &str) -> Self {
+        self.test_path = dir.to_string();
+        self.log_path = log_path.to_string();
+        self
+    }
+
+    pub fn log_path(&self) -> &str {
+        &self.log_path
+    }
+
     pub async fn start(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         let log_file = File::create(&self.log_path)?;
 
         let clash_process = Command::new(&self.core_path)
             .arg("-d")
             .arg(&self.test_path)
-            .stdout(Stdio::from(log_file.try_clone().unwrap()))
-            .stdout(Stdio::from(log_file))
+            .stdout(Stdio::from(log_file.try_clone()?))
+            .stderr(Stdio::from(log_file))
             .spawn()?;
-
-        sleep(Duration::from_secs(2)).await;
-
-        let response = reqwest::get(format!("{}/version", &self.external_url)).await?;
-        let res = response.json::<ClashVersion>().await?;
-        info!("原神启动！ 版本号：{}", res.version);
+        // 先保存进程，版本检查失败时 stop 或 drop 仍能关闭内核
         self.process = Some(clash_process);
+
+        let res = self.wait_ready().await?;
+        info!(
+            "{}",
+            tr!(
+                "原神启动！ 版本号：{}",
+                "Clash core started, version: {}",
+                res.version
+            )
+        );
         Ok(())
     }
 
-    pub async fn restart(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let client = Client::builder().timeout(Duration::from_secs(5)).build()?;
+    pub async fn restart(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let client = self.api_client().timeout(Duration::from_secs(5)).build()?;
         let response = client
             .post(format!("{}/restart", &self.external_url))
             .json(&json!({"path": self.test_path,"payload": ""}))
@@ -68,28 +149,92 @@ impl ClashMeta {
             .await?;
 
         if response.status().is_success() {
-            info!("内核重启成功");
-            sleep(Duration::from_secs(2)).await;
+            self.wait_ready().await?;
+            info!("{}", tr!("内核重启成功", "Core restarted"));
         } else {
-            info!("内核重启失败: {}", response.status());
+            info!(
+                "{}",
+                tr!(
+                    "内核重启失败: {}",
+                    "Failed to restart the core: {}",
+                    response.status()
+                )
+            );
         }
         Ok(())
     }
 
+    /// 轮询 /version 直到控制接口可用，内核提前退出或超时时返回错误
+    async fn wait_ready(&mut self) -> Result<ClashVersion, Box<dyn std::error::Error>> {
+        let client = self
+            .api_client()
+            .timeout(READY_POLL_INTERVAL * 10)
+            .build()?;
+        let url = format!("{}/version", &self.external_url);
+        let deadline = Instant::now() + READY_TIMEOUT;
+        loop {
+            if let Some(process) = self.process.as_mut() {
+                if let Some(status) = process.try_wait()? {
+                    return Err(Box::from(format!(
+                        "clash core exited with {}, see {}",
+                        status, self.log_path
+                    )));
+                }
+            }
+            let error = match client.get(&url).send().await {
+                Ok(response) => match response.json::<ClashVersion>().await {
+                    Ok(version) => return Ok(version),
+                    Err(e) => e.to_string(),
+                },
+                Err(e) => e.to_string(),
+            };
+            if Instant::now() >= deadline {
+                return Err(Box::from(format!(
+                    "clash controller is not ready after {:?}, {}",
+                    READY_TIMEOUT, error
+                )));
+            }
+            sleep(READY_POLL_INTERVAL).await;
+        }
+    }
+
+    /// 等待策略组加载完成，即包含至少一个节点，返回加载后的策略组
+    pub async fn wait_for_group(
+        &self,
+        group_name: &str,
+    ) -> Result<Group, Box<dyn std::error::Error>> {
+        let deadline = Instant::now() + READY_TIMEOUT;
+        loop {
+            let error = match self.get_group(group_name).await {
+                Ok(group) if !group.all.is_empty() => return Ok(group),
+                Ok(_) => format!("group {} has no proxies", group_name),
+                Err(e) => e.to_string(),
+            };
+            if Instant::now() >= deadline {
+                return Err(Box::from(format!(
+                    "group {} is not loaded after {:?}, {}",
+                    group_name, READY_TIMEOUT, error
+                )));
+            }
+            sleep(READY_POLL_INTERVAL).await;
+        }
+    }
+
     pub fn stop(mut self) -> std::io::Result<()> {
-        if let Some(mut process) = self.process.take() {
-            process.kill()?;
-            process.wait()?;
+        match self.process.take() {
+            Some(mut process) => kill(&mut process),
+            None => Ok(()),
         }
-        Ok(())
     }
 
     pub async fn get_group(&self, group_name: &str) -> Result<Group, Box<dyn std::error::Error>> {
         let url = format!("{}/group/{}", &self.external_url, group_name);
-        let client = Client::builder().timeout(Duration::from_secs(5)).build()?;
-        let response = client.get(url).send().await?;
-        let group = response.json::<Group>().await?;
-        Ok(group)
+        let client = self.api_client().timeout(Duration::from_secs(5)).build()?;
+        retry(|| async {
+            let response = client.get(&url).send().await?;
+            Ok::<_, Box<dyn std::error::Error>>(response.json::<Group>().await?)
+        })
+        .await
     }
 
     pub async fn test_group(
@@ -98,20 +243,18 @@ impl ClashMeta {
         delay_test_config: &DelayTestConfig,
     ) -> Result<HashMap<String, i64>, Box<dyn std::error::Error>> {
         let url = format!("{}/group/{}/delay", &self.external_url, group_name);
-        let client = Client::builder()
-            .timeout(Duration::from_secs(10))
-            .build()
-            .unwrap();
-        let response = client.get(&url).query(&delay_test_config).send().await?;
+        let client = self.api_client().timeout(Duration::from_secs(10)).build()?;
+        let response = retry(|| client.get(&url).query(&delay_test_config).send()).await?;
         if !response.status().is_success() {
-            return Err(Box::from("获取分组延迟失败".to_string()));
+            return Err(Box::from(tr!(
+                "获取分组延迟失败",
+                "Failed to get the group delay"
+            )));
         }
         let res: Value = response.json().await?;
         match res {
             Value::Object(map) => {
-                let msg = map.get("message");
-                if msg.is_some() {
-                    let msg = msg.unwrap();
+                if let Some(msg) = map.get("message") {
                     Err(Box::from(msg.to_string()))
                 } else {
                     let mut result = HashMap::new();
@@ -123,7 +266,10 @@ impl ClashMeta {
                     Ok(result)
                 }
             }
-            _ => Err(Box::from("所有节点无速度")),
+            _ => Err(Box::from(tr!(
+                "所有节点无速度",
+                "No delay results for any node"
+            ))),
         }
     }
 
@@ -133,40 +279,261 @@ impl ClashMeta {
         delay_test_config: &DelayTestConfig,
     ) -> Result<u64, Box<dyn std::error::Error>> {
         let url = format!("{}/proxies/{}/delay", &self.external_url, proxy_name);
-        let client = Client::builder().timeout(Duration::from_secs(60)).build()?;
-        let response = client.get(&url).query(delay_test_config).send().await?;
+        let client = self.api_client().timeout(Duration::from_secs(60)).build()?;
+        let response = retry(|| client.get(&url).query(delay_test_config).send()).await?;
         if !response.status().is_success() {
-            return Err(Box::from("获取分组延迟失败".to_string()));
+            return Err(Box::from(tr!(
+                "获取节点延迟失败",
+                "Failed to get the proxy delay"
+            )));
         }
         Ok(response.json::<ProxyDelay>().await?.delay)
     }
 
+    /// 单独测试节点延迟，失败时按控制接口返回的错误信息归类原因
+    pub async fn diagnose_proxy(
+        &self,
+        proxy_name: &str,
+        delay_test_config: &DelayTestConfig,
+    ) -> Result<u64, FailureReason> {
+        let url = format!(
+            "{}/proxies/{}/delay",
+            &self.external_url,
+            urlencoding::encode(proxy_name)
+        );
+        let timeout = Duration::from_millis(delay_test_config.timeout.into());
+        // 控制接口本身的错误不能反映节点的问题，统一归为其他
+        let client = self
+            .api_client()
+            .timeout(timeout + Duration::from_secs(5))
+            .build()
+            .map_err(|_| FailureReason::Other)?;
+        let response = retry(|| client.get(&url).query(delay_test_config).send())
+            .await
+            .map_err(|_| FailureReason::Other)?;
+        if response.status() == StatusCode::GATEWAY_TIMEOUT {
+            return Err(FailureReason::Timeout);
+        }
+        let res: Value = response.json().await.map_err(|_| FailureReason::Other)?;
+        match res.get("delay").and_then(Value::as_u64) {
+            Some(delay) if delay > 0 => Ok(delay),
+            _ => Err(FailureReason::classify(
+                res.get("message")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default(),
+            )),
+        }
+    }
+
     pub async fn test_direct_delay(&self) -> Result<u64, Box<dyn std::error::Error>> {
         self.test_proxy(
             "DIRECT",
-            &DelayTestConfig {
-                url: "http://www.gstatic.com/generate_204".to_string(),
-                expected: Some(204),
-                timeout: 200,
-            },
+            &DelayTestConfig::new("http://www.gstatic.com/generate_204", 204, 200),
         )
         .await
     }
 
+    /// 内核启动以来经由代理的累计流量
+    pub async fn traffic(&self) -> Result<Traffic, Box<dyn std::error::Error>> {
+        let url = format!("{}/connections", &self.external_url);
+        let client = self.api_client().timeout(Duration::from_secs(5)).build()?;
+        let response = retry(|| client.get(&url).send()).await?;
+        if !response.status().is_success() {
+            return Err(Box::from(format!(
+                "failed to get connections: {}",
+                response.status()
+            )));
+        }
+        let connections = response.json::<Connections>().await?;
+        Ok(Traffic {
+            upload: connections.upload_total,
+            download: connections.download_total,
+        })
+    }
+
     pub async fn set_group_proxy(
         &self,
         group_name: &str,
         proxy_name: &str,
     ) -> Result<bool, Box<dyn std::error::Error>> {
         let url = format!("{}/proxies/{}", &self.external_url, group_name);
-        let client = Client::builder().timeout(Duration::from_secs(5)).build()?;
-        let response = client
-            .put(url)
-            .json(&json!({"name": proxy_name}))
-            .send()
-            .await?;
+        let client = self.api_client().timeout(Duration::from_secs(5)).build()?;
+        let body = json!({"name": proxy_name});
+        let response = retry(|| client.put(&url).json(&body).send()).await?;
         Ok(response.status().is_success())
     }
+
+    /// 经 port 入站、由策略组 group 选择节点的检测通道，port 为 mixed-port 或配置中 listeners 的端口
+    pub fn lane(&self, group: &str, port: u16) -> InspectLane {
+        InspectLane {
+            group: group.to_string(),
+            proxy_url: format!("http://{}", host_port(self.host, port)),
+        }
+    }
+
+    /// 将空闲通道的策略组切换到 nodes 中的节点后执行 inspect，最多同时检测 lanes.len() 个节点
+    ///
+    /// nodes 为节点名称及传给 inspect 的数据，所有切换共用一个控制接口客户端，返回结果的顺序与 nodes 一致
+    pub async fn inspect_nodes<'a, P, T, F, Fut>(
+        &self,
+        lanes: Vec<InspectLane>,
+        nodes: impl IntoIterator<Item = (&'a str, P)>,
+        timeout: Duration,
+        inspect: F,
+    ) -> Result<Vec<Result<T, InspectError>>, Box<dyn std::error::Error>>
+    where
+        F: Fn(P, InspectLane) -> Fut,
+        Fut: Future<Output = T>,
+    {
+        let client = self.api_client().timeout(Duration::from_secs(5)).build()?;
+        let concurrency = lanes.len().max(1);
+        let idle = Mutex::new(lanes);
+        let (client, idle, inspect) = (&client, &idle, &inspect);
+        let mut results = stream::iter(nodes.into_iter().enumerate())
+            .map(|(index, (node, data))| async move {
+                // 同时运行的检测不超过通道数，总有空闲通道
+                let lane = idle
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .pop()
+                    .expect("idle lane");
+                let result = match self.select(client, &lane.group, node).await {
+                    Ok(()) => tokio::time::timeout(timeout, inspect(data, lane.clone()))
+                        .await
+                        .map_err(|_| InspectError::Timeout(timeout)),
+                    Err(e) => Err(InspectError::Select(e)),
+                };
+                idle.lock().unwrap_or_else(|e| e.into_inner()).push(lane);
+                (index, result)
+            })
+            .buffer_unordered(concurrency)
+            .collect::<Vec<_>>()
+            .await;
+        results.sort_by_key(|(index, _)| *index);
+        Ok(results.into_iter().map(|(_, result)| result).collect())
+    }
+
+    async fn select(&self, client: &Client, group: &str, node: &str) -> Result<(), String> {
+        let url = format!("{}/proxies/{}", &self.external_url, group);
+        let body = json!({"name": node});
+        match retry(|| client.put(&url).json(&body).send()).await {
+            Ok(response) if response.status().is_success() => Ok(()),
+            Ok(response) => Err(format!("failed to select node: {}", response.status())),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+}
+
+/// 节点检测的一路，策略组切换到待检测节点后经 proxy_url 访问
+#[derive(Debug, Clone)]
+pub struct InspectLane {
+    pub group: String,
+    pub proxy_url: String,
+}
+
+/// 检测通道未能完成节点检测的原因
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InspectError {
+    /// 策略组切换到节点失败
+    Select(String),
+    /// 检测超过单个节点的超时时间
+    Timeout(Duration),
+}
+
+impl fmt::Display for InspectError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InspectError::Select(e) => write!(f, "{}", e),
+            InspectError::Timeout(timeout) => write!(f, "timed out after {:?}", timeout),
+        }
+    }
+}
+
+/// 等待内核控制接口可用及策略组加载的超时时间，节点很多或机器较慢时加载需要数秒
+const READY_TIMEOUT: Duration = Duration::from_secs(30);
+const READY_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// 控制接口调用失败后的重试次数，首次重试等待 API_RETRY_DELAY，之后每次翻倍
+const API_RETRIES: u32 = 3;
+const API_RETRY_DELAY: Duration = Duration::from_millis(200);
+
+/// 调用控制接口，连接被拒绝、超时等暂时性错误按指数退避重试，其他错误直接返回
+async fn retry<T, E, F, Fut>(mut call: F) -> Result<T, Box<dyn std::error::Error>>
+where
+    E: Into<Box<dyn std::error::Error>>,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut delay = API_RETRY_DELAY;
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let message = match call().await.map_err(Into::into) {
+            Ok(value) => return Ok(value),
+            Err(e) if attempt <= API_RETRIES && is_transient(e.as_ref()) => e.to_string(),
+            Err(e) => return Err(e),
+        };
+        debug!(
+            "{}",
+            tr!(
+                "控制接口调用失败，{:?} 后第 {} 次重试: {}",
+                "Controller API call failed, retry {1} in {0:?}: {2}",
+                delay,
+                attempt,
+                message
+            )
+        );
+        sleep(delay).await;
+        delay *= 2;
+    }
+}
+
+fn is_transient(e: &(dyn std::error::Error + 'static)) -> bool {
+    e.downcast_ref::<reqwest::Error>()
+        .is_some_and(|e| e.is_connect() || e.is_timeout())
+}
+
+/// 流程中途出错提前返回时也要关闭内核，避免占用端口影响下一次启动
+impl Drop for ClashMeta {
+    fn drop(&mut self) {
+        if let Some(mut process) = self.process.take() {
+            let _ = kill(&mut process);
+        }
+    }
+}
+
+/// 默认的内核路径，Windows 下为 clash-meta\mihomo.exe
+pub fn default_core_path() -> PathBuf {
+    Path::new("clash-meta").join(format!("mihomo{}", env::consts::EXE_SUFFIX))
+}
+
+/// 没有扩展名且文件不存在时补上可执行文件后缀，Unix 下原样返回
+fn resolve_core_path(path: &Path) -> PathBuf {
+    let suffix = env::consts::EXE_EXTENSION;
+    if !suffix.is_empty() && path.extension().is_none() && !path.exists() {
+        return path.with_extension(suffix);
+    }
+    path.to_path_buf()
+}
+
+/// 关闭内核进程并等待退出，Windows 下先使用 taskkill /T 结束整个进程树
+fn kill(process: &mut Child) -> std::io::Result<()> {
+    #[cfg(windows)]
+    {
+        let status = Command::new("taskkill")
+            .args(["/PID", &process.id().to_string(), "/T", "/F"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+        if status.is_ok_and(|status| status.success()) {
+            process.wait()?;
+            return Ok(());
+        }
+    }
+    // 进程已退出时 kill 同样返回 Ok
+    process.kill()?;
+    process.wait()?;
+    Ok(())
 }
 
 #[derive(Deserialize, Debug)]
@@ -176,17 +543,269 @@ struct ClashVersion {
     version: String,
 }
 
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Connections {
+    upload_total: u64,
+    download_total: u64,
+}
+
+/// 经由代理的流量，单位字节
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Traffic {
+    pub upload: u64,
+    pub download: u64,
+}
+
+impl Traffic {
+    pub fn total(&self) -> u64 {
+        self.upload.saturating_add(self.download)
+    }
+
+    /// 两次累计流量之差，内核重启导致计数变小时返回 0
+    pub fn since(&self, earlier: &Traffic) -> Traffic {
+        Traffic {
+            upload: self.upload.saturating_sub(earlier.upload),
+            download: self.download.saturating_sub(earlier.download),
+        }
+    }
+}
+
+impl std::ops::AddAssign for Traffic {
+    fn add_assign(&mut self, other: Traffic) {
+        self.upload = self.upload.saturating_add(other.upload);
+        self.download = self.download.saturating_add(other.download);
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ProxyDelay {
     pub delay: u64,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// 节点延迟测试失败的原因，用于区分被封锁和配置错误的节点
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureReason {
+    Timeout,
+    ConnectionRefused,
+    Dns,
+    Tls,
+    Other,
+}
+
+impl FailureReason {
+    /// 按错误信息归类，DNS 及 TLS 握手超时归为对应的原因而不是超时
+    pub fn classify(message: &str) -> Self {
+        let message = message.to_lowercase();
+        let contains_any = |keys: &[&str]| keys.iter().any(|key| message.contains(key));
+        if contains_any(&["no such host", "lookup ", "dns", "resolve"]) {
+            FailureReason::Dns
+        } else if contains_any(&["tls", "x509", "certificate", "handshake"]) {
+            FailureReason::Tls
+        } else if contains_any(&["connection refused", "connection reset", "refused"]) {
+            FailureReason::ConnectionRefused
+        } else if contains_any(&["timeout", "timed out", "deadline exceeded"]) {
+            FailureReason::Timeout
+        } else {
+            FailureReason::Other
+        }
+    }
+}
+
+impl fmt::Display for FailureReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&match self {
+            FailureReason::Timeout => tr!("超时", "timeout"),
+            FailureReason::ConnectionRefused => tr!("连接被拒绝", "connection refused"),
+            FailureReason::Dns => tr!("DNS 解析失败", "DNS failure"),
+            FailureReason::Tls => tr!("TLS 错误", "TLS error"),
+            FailureReason::Other => tr!("其他错误", "other error"),
+        })
+    }
+}
+
+/// 延迟及网站测试配置，序列化后作为 clash 延迟测试接口的参数
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[allow(unused)]
 pub struct DelayTestConfig {
     pub url: String,
-    pub expected: Option<u16>,
+    /// 期望的状态码，支持 204、[200, 301] 或 "200-299/301"，未指定时 2xx 视为成功
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected: Option<ExpectedStatus>,
+    /// 超时时间，单位 ms
+    #[serde(default = "default_timeout")]
     pub timeout: u16,
+    /// 以下选项仅用于网站检测，clash 延迟测试接口不支持
+    /// 请求方法，默认为 GET
+    #[serde(default, skip_serializing)]
+    pub method: Option<String>,
+    #[serde(default, skip_serializing)]
+    pub headers: HashMap<String, String>,
+    /// 响应内容必须包含的字符串
+    #[serde(default, skip_serializing)]
+    pub contains: Option<String>,
+    /// 响应内容不能包含的字符串，如地区限制的提示
+    #[serde(default, skip_serializing)]
+    pub not_contains: Option<String>,
+    /// tcp://、udp:// 目标测延迟时发送的数据，十六进制
+    #[serde(default, skip_serializing)]
+    pub payload: Option<String>,
+    /// 请求时在该请求头中带上随机的 trace id，响应内容必须原样回显，确认响应来自自建的回显接口
+    #[serde(default, skip_serializing)]
+    pub trace_header: Option<String>,
+    /// 响应内容必须包含节点的出口 IP，识别请求实际走了 DIRECT 而测得虚假低延迟的节点
+    #[serde(default, skip_serializing)]
+    pub echo_ip: bool,
+}
+
+fn default_timeout() -> u16 {
+    5000
+}
+
+impl DelayTestConfig {
+    pub fn new(url: &str, expected: u16, timeout: u16) -> Self {
+        DelayTestConfig {
+            url: url.to_string(),
+            expected: Some(ExpectedStatus::from(expected)),
+            timeout,
+            ..Default::default()
+        }
+    }
+
+    /// 是否使用了仅网站检测支持的选项
+    pub fn has_http_options(&self) -> bool {
+        self.method.is_some()
+            || !self.headers.is_empty()
+            || self.contains.is_some()
+            || self.not_contains.is_some()
+            || self.payload.is_some()
+            || self.trace_header.is_some()
+            || self.echo_ip
+    }
+
+    /// 状态码是否符合预期
+    pub fn is_expected(&self, status: u16) -> bool {
+        match &self.expected {
+            Some(expected) => expected.matches(status),
+            None => (200..300).contains(&status),
+        }
+    }
+}
+
+/// 期望的 HTTP 状态码范围，格式与 mihomo 延迟测试的 expected 参数一致，如 "200-299/301"
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpectedStatus(Vec<(u16, u16)>);
+
+impl ExpectedStatus {
+    pub fn matches(&self, status: u16) -> bool {
+        self.0
+            .iter()
+            .any(|(start, end)| (*start..=*end).contains(&status))
+    }
+
+    /// 所有状态码范围，起止均包含在内
+    pub fn ranges(&self) -> &[(u16, u16)] {
+        &self.0
+    }
+}
+
+impl From<u16> for ExpectedStatus {
+    fn from(status: u16) -> Self {
+        ExpectedStatus(vec![(status, status)])
+    }
+}
+
+impl FromStr for ExpectedStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |s: &str| {
+            s.trim()
+                .parse::<u16>()
+                .map_err(|_| format!("invalid status code {:?}", s))
+        };
+        let ranges = s
+            .split(['/', ','])
+            .filter(|part| !part.trim().is_empty())
+            .map(|part| match part.split_once('-') {
+                Some((start, end)) => Ok((parse(start)?, parse(end)?)),
+                None => parse(part).map(|status| (status, status)),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if ranges.is_empty() {
+            return Err("empty expected status".to_string());
+        }
+        Ok(ExpectedStatus(ranges))
+    }
+}
+
+impl fmt::Display for ExpectedStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ranges = self
+            .0
+            .iter()
+            .map(|(start, end)| {
+                if start == end {
+                    start.to_string()
+                } else {
+                    format!("{}-{}", start, end)
+                }
+            })
+            .collect::<Vec<_>>();
+        write!(f, "{}", ranges.join("/"))
+    }
+}
+
+impl Serialize for ExpectedStatus {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ExpectedStatus {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ExpectedVisitor;
+
+        impl<'de> Visitor<'de> for ExpectedVisitor {
+            type Value = ExpectedStatus;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str(
+                    "a status code, a list of status codes or a string like \"200-299/301\"",
+                )
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<ExpectedStatus, E> {
+                u16::try_from(v)
+                    .map(ExpectedStatus::from)
+                    .map_err(|_| E::custom(format!("invalid status code {}", v)))
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<ExpectedStatus, E> {
+                u16::try_from(v)
+                    .map(ExpectedStatus::from)
+                    .map_err(|_| E::custom(format!("invalid status code {}", v)))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<ExpectedStatus, E> {
+                v.parse().map_err(E::custom)
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<ExpectedStatus, A::Error> {
+                let mut ranges = vec![];
+                while let Some(status) = seq.next_element::<ExpectedStatus>()? {
+                    ranges.extend(status.0);
+                }
+                if ranges.is_empty() {
+                    return Err(de::Error::custom("empty expected status"));
+                }
+                Ok(ExpectedStatus(ranges))
+            }
+        }
+
+        deserializer.deserialize_any(ExpectedVisitor)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -199,8 +818,140 @@ pub struct Group {
 
 #[cfg(test)]
 mod tests {
+    use std::path::Path;
+    use std::sync::atomic::AtomicU32;
+    use std::sync::atomic::Ordering;
+
+    use crate::clash::default_core_path;
+    use crate::clash::resolve_core_path;
+    use crate::clash::retry;
     use crate::clash::ClashMeta;
     use crate::clash::DelayTestConfig;
+    use crate::clash::ExpectedStatus;
+    use crate::clash::FailureReason;
+    use crate::clash::Traffic;
+    use crate::clash::API_RETRIES;
+
+    #[test]
+    fn test_traffic() {
+        let before = Traffic {
+            upload: 100,
+            download: 1000,
+        };
+        let after = Traffic {
+            upload: 150,
+            download: 5000,
+        };
+        let mut total = after.since(&before);
+        assert_eq!(
+            total,
+            Traffic {
+                upload: 50,
+                download: 4000
+            }
+        );
+        assert_eq!(before.since(&after), Traffic::default());
+        total += before;
+        assert_eq!(total.total(), 5150);
+    }
+
+    #[tokio::test]
+    async fn test_retry() {
+        let attempts = AtomicU32::new(0);
+        let result = retry(|| {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            // 没有服务监听的端口，连接被拒绝
+            reqwest::get("http://127.0.0.1:1/version")
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), API_RETRIES + 1);
+
+        let attempts = AtomicU32::new(0);
+        let result = retry(|| {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            async { Err::<(), _>("invalid response") }
+        })
+        .await;
+        assert_eq!(result.unwrap_err().to_string(), "invalid response");
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_wait_ready_exited() {
+        // 立即退出的进程，不需要等到超时
+        let mut clash_meta = ClashMeta::new(1, 1);
+        clash_meta.process = Some(std::process::Command::new("/bin/false").spawn().unwrap());
+        let start = std::time::Instant::now();
+        let result = clash_meta.wait_ready().await;
+        assert!(result.unwrap_err().to_string().contains("exited"));
+        assert!(start.elapsed() < std::time::Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_classify_failure() {
+        assert_eq!(FailureReason::classify("Timeout"), FailureReason::Timeout);
+        assert_eq!(
+            FailureReason::classify("dial tcp 1.2.3.4:443: connect: connection refused"),
+            FailureReason::ConnectionRefused
+        );
+        assert_eq!(
+            FailureReason::classify("lookup example.com on 8.8.8.8:53: i/o timeout"),
+            FailureReason::Dns
+        );
+        assert_eq!(
+            FailureReason::classify(
+                "tls: failed to verify certificate: x509: certificate has expired"
+            ),
+            FailureReason::Tls
+        );
+        assert_eq!(
+            FailureReason::classify("An error occurred in the delay test"),
+            FailureReason::Other
+        );
+    }
+
+    #[test]
+    fn test_core_path() {
+        let path = default_core_path();
+        assert!(path.starts_with("clash-meta"));
+        if cfg!(windows) {
+            assert_eq!(path.extension().unwrap(), "exe");
+            assert_eq!(
+                resolve_core_path(Path::new("bin/mihomo")),
+                Path::new("bin/mihomo.exe")
+            );
+        } else {
+            assert_eq!(path, Path::new("clash-meta/mihomo"));
+            assert_eq!(
+                resolve_core_path(Path::new("bin/mihomo")),
+                Path::new("bin/mihomo")
+            );
+        }
+    }
+
+    #[test]
+    fn test_expected_status() {
+        let expected = "200-299/301".parse::<ExpectedStatus>().unwrap();
+        assert!(expected.matches(204));
+        assert!(expected.matches(301));
+        assert!(!expected.matches(302));
+        assert_eq!(expected.to_string(), "200-299/301");
+        assert!("abc".parse::<ExpectedStatus>().is_err());
+
+        let config: DelayTestConfig =
+            serde_json::from_str(r#"{"url": "https://example.com", "expected": [204, "301-302"]}"#)
+                .unwrap();
+        assert!(config.is_expected(302));
+        assert!(!config.is_expected(200));
+        assert_eq!(config.timeout, 5000);
+
+        let config: DelayTestConfig =
+            serde_json::from_str(r#"{"url": "https://example.com", "timeout": 500}"#).unwrap();
+        assert!(config.is_expected(200));
+        assert!(!config.is_expected(301));
+    }
 
     #[tokio::test]
     async fn test_proxy_delay() {
@@ -208,11 +959,7 @@ mod tests {
         let delay = clash_meta
             .test_proxy(
                 "DIRECT",
-                &DelayTestConfig {
-                    url: "http://www.gstatic.com/generate_204".to_string(),
-                    expected: Some(204),
-                    timeout: 500,
-                },
+                &DelayTestConfig::new("http://www.gstatic.com/generate_204", 204, 500),
             )
             .await
             .unwrap();
@@ -244,11 +991,7 @@ mod tests {
         let result = clash_meta
             .test_group(
                 "PROXY",
-                &DelayTestConfig {
-                    url: "http://www.google.com/generate_204".to_string(),
-                    expected: Some(204),
-                    timeout: 1000,
-                },
+                &DelayTestConfig::new("http://www.google.com/generate_204", 204, 1000),
             )
             .await;
 
@@ -0,0 +1,143 @@
+use reqwest::Url;
+use serde::Serialize;
+
+use crate::report;
+
+/// 订阅对比中单个订阅的测试统计
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SubStats {
+    pub sub: String,
+    pub total: usize,
+    pub alive: usize,
+    /// 可用节点平均延迟的中位数，单位 ms
+    pub median_latency: Option<i64>,
+    /// 测速节点下载速度的中位数，单位 KB/s，未开启测速时为 None
+    pub median_speed: Option<f64>,
+}
+
+impl SubStats {
+    pub fn new(sub: &str, total: usize, alive: usize, latencies: &[i64], speeds: &[f64]) -> Self {
+        let latencies = latencies.iter().map(|l| *l as f64).collect::<Vec<_>>();
+        SubStats {
+            sub: sub.to_string(),
+            total,
+            alive,
+            median_latency: median(&latencies).map(|l| l.round() as i64),
+            median_speed: median(speeds),
+        }
+    }
+
+    /// 可用节点占比，订阅没有节点时为 0
+    pub fn alive_rate(&self) -> f64 {
+        if self.total == 0 {
+            0.0
+        } else {
+            self.alive as f64 / self.total as f64
+        }
+    }
+}
+
+/// 两个订阅在相同条件下的测试结果
+#[derive(Debug, Clone, Serialize)]
+pub struct Comparison {
+    pub a: SubStats,
+    pub b: SubStats,
+}
+
+impl Comparison {
+    /// 生成并排对比的 Markdown 表格，订阅链接只显示域名，避免泄露 token
+    pub fn render_markdown(&self) -> String {
+        let (a, b) = (&self.a, &self.b);
+        let rows = [
+            ("订阅", sub_label(&a.sub), sub_label(&b.sub)),
+            ("节点数", a.total.to_string(), b.total.to_string()),
+            ("可用节点数", a.alive.to_string(), b.alive.to_string()),
+            (
+                "可用率",
+                format!("{:.1}%", a.alive_rate() * 100.0),
+                format!("{:.1}%", b.alive_rate() * 100.0),
+            ),
+            (
+                "延迟中位数",
+                a.median_latency
+                    .map_or("-".to_string(), |l| format!("{} ms", l)),
+                b.median_latency
+                    .map_or("-".to_string(), |l| format!("{} ms", l)),
+            ),
+            (
+                "速度中位数",
+                a.median_speed.map_or("-".to_string(), report::format_speed),
+                b.median_speed.map_or("-".to_string(), report::format_speed),
+            ),
+        ];
+
+        let mut content = String::new();
+        content.push_str("| 指标 | A | B |\n");
+        content.push_str("|---|---|---|\n");
+        for (name, a, b) in rows {
+            content.push_str(&format!(
+                "| {} | {} | {} |\n",
+                name,
+                report::cell(Some(&a)),
+                report::cell(Some(&b))
+            ));
+        }
+        content
+    }
+}
+
+/// 订阅链接只保留域名，避免泄露 token，本地文件及分享链接保持不变
+pub fn sub_label(sub: &str) -> String {
+    match Url::parse(sub) {
+        Ok(url) if url.scheme().starts_with("http") => url.host_str().unwrap_or(sub).to_string(),
+        _ => sub.to_string(),
+    }
+}
+
+/// 中位数，数量为偶数时取中间两个值的平均值
+fn median(values: &[f64]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let mid = sorted.len() / 2;
+    if sorted.len() % 2 == 0 {
+        Some((sorted[mid - 1] + sorted[mid]) / 2.0)
+    } else {
+        Some(sorted[mid])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_median() {
+        assert_eq!(median(&[]), None);
+        assert_eq!(median(&[300.0, 100.0, 200.0]), Some(200.0));
+        assert_eq!(median(&[400.0, 100.0, 200.0, 300.0]), Some(250.0));
+    }
+
+    #[test]
+    fn test_render_comparison() {
+        let comparison = Comparison {
+            a: SubStats::new(
+                "https://a.example.com/sub?token=secret",
+                10,
+                8,
+                &[100, 300, 200],
+                &[2048.0],
+            ),
+            b: SubStats::new("subs/b.yaml", 0, 0, &[], &[]),
+        };
+        let content = comparison.render_markdown();
+        let lines = content.lines().collect::<Vec<_>>();
+        assert_eq!(lines[2], "| 订阅 | a.example.com | subs/b.yaml |");
+        assert_eq!(lines[5], "| 可用率 | 80.0% | 0.0% |");
+        assert_eq!(lines[6], "| 延迟中位数 | 200 ms | - |");
+        assert_eq!(lines[7], "| 速度中位数 | 2.00 MB/s | - |");
+        assert!(!content.contains("secret"));
+    }
+}
@@ -0,0 +1,184 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use proxrs::protocol::Proxy;
+use serde::Serialize;
+
+use crate::tr;
+
+/// 端口分布中单独列出的端口数，其余合并为“其他”
+const TOP_PORTS: usize = 8;
+
+/// 候选节点按协议、端口及 TLS 的构成，用于判断哪些订阅及协议值得保留
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct Composition {
+    pub total: usize,
+    pub protocols: BTreeMap<String, usize>,
+    pub ports: BTreeMap<u16, usize>,
+    /// 使用 TLS 的节点数
+    pub tls: usize,
+}
+
+impl Composition {
+    pub fn of(proxies: &[Proxy]) -> Self {
+        let mut composition = Composition {
+            total: proxies.len(),
+            ..Default::default()
+        };
+        for proxy in proxies {
+            *composition
+                .protocols
+                .entry(protocol_name(proxy))
+                .or_insert(0) += 1;
+            *composition.ports.entry(proxy.get_port()).or_insert(0) += 1;
+            if proxy.uses_tls() {
+                composition.tls += 1;
+            }
+        }
+        composition
+    }
+
+    /// 节点数最多的端口，数量相同时端口号小的在前
+    fn top_ports(&self) -> Vec<(u16, usize)> {
+        let mut ports: Vec<_> = self.ports.iter().map(|(p, c)| (*p, *c)).collect();
+        ports.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        ports
+    }
+}
+
+impl fmt::Display for Composition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let protocols = self
+            .protocols
+            .iter()
+            .map(|(name, count)| format!("{} {} ({})", name, count, percent(*count, self.total)))
+            .collect::<Vec<_>>();
+        writeln!(
+            f,
+            "{}{}",
+            tr!("协议：", "Protocols: "),
+            protocols.join(", ")
+        )?;
+
+        let ports = self.top_ports();
+        let mut items = ports
+            .iter()
+            .take(TOP_PORTS)
+            .map(|(port, count)| format!("{} {}", port, count))
+            .collect::<Vec<_>>();
+        let others: usize = ports.iter().skip(TOP_PORTS).map(|(_, c)| c).sum();
+        if others > 0 {
+            items.push(format!("{} {}", tr!("其他", "others"), others));
+        }
+        writeln!(f, "{}{}", tr!("端口：", "Ports: "), items.join(", "))?;
+
+        write!(
+            f,
+            "TLS: {}/{} ({})",
+            self.tls,
+            self.total,
+            percent(self.tls, self.total)
+        )
+    }
+}
+
+/// 单个协议在连通性测试中的存活情况
+#[derive(Debug, Clone, Serialize)]
+pub struct ProtocolSurvival {
+    pub protocol: String,
+    pub candidates: usize,
+    pub alive: usize,
+}
+
+/// 按协议统计测试前后的节点数，按候选节点数从多到少排列
+pub fn survival(candidates: &[Proxy], alive: &[Proxy]) -> Vec<ProtocolSurvival> {
+    let before = Composition::of(candidates).protocols;
+    let after = Composition::of(alive).protocols;
+    let mut survival = before
+        .into_iter()
+        .map(|(protocol, candidates)| ProtocolSurvival {
+            alive: after.get(&protocol).copied().unwrap_or(0),
+            protocol,
+            candidates,
+        })
+        .collect::<Vec<_>>();
+    survival.sort_by(|a, b| b.candidates.cmp(&a.candidates));
+    survival
+}
+
+/// 各协议存活率的表格
+pub fn render_survival(survival: &[ProtocolSurvival]) -> String {
+    let mut content = String::new();
+    content.push_str(&tr!(
+        "| 协议 | 候选 | 存活 | 存活率 |\n",
+        "| Protocol | Candidates | Alive | Survival |\n"
+    ));
+    content.push_str("|---|---|---|---|\n");
+    for item in survival {
+        content.push_str(&format!(
+            "| {} | {} | {} | {} |\n",
+            item.protocol,
+            item.candidates,
+            item.alive,
+            percent(item.alive, item.candidates)
+        ));
+    }
+    content
+}
+
+fn protocol_name(proxy: &Proxy) -> String {
+    serde_json::to_value(proxy.proxy_type())
+        .ok()
+        .and_then(|v| v.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+fn percent(count: usize, total: usize) -> String {
+    if total == 0 {
+        return "-".to_string();
+    }
+    format!("{:.1}%", count as f64 * 100.0 / total as f64)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn proxies() -> Vec<Proxy> {
+        [
+            "ss://YWVzLTI1Ni1nY206cGFzc3dvcmQ@1.2.3.4:8388#a",
+            "ss://YWVzLTI1Ni1nY206cGFzc3dvcmQ@1.2.3.5:443#b",
+            "trojan://pwd@1.1.1.1:443?sni=a.com#c",
+        ]
+        .iter()
+        .map(|link| Proxy::from_link(link).unwrap())
+        .collect()
+    }
+
+    #[test]
+    fn test_composition() {
+        let composition = Composition::of(&proxies());
+        assert_eq!(composition.total, 3);
+        assert_eq!(composition.protocols["ss"], 2);
+        assert_eq!(composition.protocols["trojan"], 1);
+        assert_eq!(composition.tls, 1);
+        assert_eq!(composition.top_ports(), vec![(443, 2), (8388, 1)]);
+    }
+
+    #[test]
+    fn test_survival() {
+        let candidates = proxies();
+        let survival = survival(&candidates, &candidates[2..]);
+        assert_eq!(survival[0].protocol, "ss");
+        assert_eq!(survival[0].alive, 0);
+        assert_eq!(survival[1].alive, 1);
+
+        let table = render_survival(&survival);
+        assert!(table.lines().nth(2).unwrap().ends_with("| 2 | 0 | 0.0% |"));
+        assert!(table
+            .lines()
+            .nth(3)
+            .unwrap()
+            .ends_with("| 1 | 1 | 100.0% |"));
+    }
+}
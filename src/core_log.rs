@@ -0,0 +1,220 @@
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::path::PathBuf;
+
+use crate::tr;
+
+/// 内核日志中能识别出具体原因的错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CoreIssue {
+    /// 节点配置被内核拒绝，index 为 config.yaml 中 proxies 的下标
+    ProxyRejected { index: usize, reason: String },
+    /// geo 数据文件下载或加载失败
+    GeoData(String),
+    /// 端口被占用，内容为监听地址
+    PortInUse(String),
+}
+
+impl fmt::Display for CoreIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CoreIssue::ProxyRejected { index, reason } => f.write_str(&tr!(
+                "内核拒绝了第 {} 个节点的配置：{}",
+                "The clash core rejected proxy #{}: {}",
+                index,
+                reason
+            )),
+            CoreIssue::GeoData(e) => f.write_str(&tr!(
+                "geo 数据文件下载失败，请检查网络或手动下载到内核目录：{}",
+                "Failed to download geo data files, check the network or download them to the core directory manually: {}",
+                e
+            )),
+            CoreIssue::PortInUse(addr) => f.write_str(&tr!(
+                "端口 {} 已被占用，请关闭占用该端口的程序或修改端口配置",
+                "Port {} is already in use, stop the program using it or change the port settings",
+                addr
+            )),
+        }
+    }
+}
+
+/// 解析一行 mihomo 日志，格式为 time="..." level=error msg="..."，只处理 error 及以上级别
+pub fn parse_line(line: &str) -> Option<CoreIssue> {
+    let level = field(line, "level=")?;
+    if !matches!(level, "error" | "fatal") {
+        return None;
+    }
+    let msg = field(line, "msg=")?;
+
+    if msg.contains("address already in use")
+        || msg.contains("Only one usage of each socket address")
+    {
+        let addr = ["listen tcp ", "listen udp "]
+            .iter()
+            .find_map(|prefix| msg.split_once(prefix))
+            .and_then(|(_, rest)| rest.split_once(": bind"))
+            .map(|(addr, _)| addr)
+            .unwrap_or(msg);
+        return Some(CoreIssue::PortInUse(addr.to_string()));
+    }
+
+    // 节点解析失败：Parse config error: proxy 3: ...
+    if let Some((_, rest)) = msg.split_once("proxy ") {
+        if let Some((index, reason)) = rest.split_once(": ") {
+            if let Ok(index) = index.parse::<usize>() {
+                return Some(CoreIssue::ProxyRejected {
+                    index,
+                    reason: reason.to_string(),
+                });
+            }
+        }
+    }
+
+    let lower = msg.to_lowercase();
+    if ["geoip", "geosite", "mmdb", "geodata"]
+        .iter()
+        .any(|key| lower.contains(key))
+    {
+        return Some(CoreIssue::GeoData(msg.to_string()));
+    }
+    None
+}
+
+/// 取出 key 对应的值，带引号的值去掉引号
+fn field<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    let (_, rest) = line.split_once(key)?;
+    match rest.strip_prefix('"') {
+        Some(quoted) => {
+            let mut escaped = false;
+            for (i, c) in quoted.char_indices() {
+                match c {
+                    '\\' if !escaped => escaped = true,
+                    '"' if !escaped => return Some(&quoted[..i]),
+                    _ => escaped = false,
+                }
+            }
+            Some(quoted)
+        }
+        None => rest.split_whitespace().next(),
+    }
+}
+
+/// 跟踪内核日志文件，每次只读取上次之后新增的完整行
+pub struct LogTail {
+    path: PathBuf,
+    offset: u64,
+}
+
+impl LogTail {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        LogTail {
+            path: path.into(),
+            offset: 0,
+        }
+    }
+
+    /// 读取新增日志中识别出的错误，相同的错误只返回一次，日志不存在时返回空
+    pub fn issues(&mut self) -> Vec<CoreIssue> {
+        let Ok(mut file) = File::open(&self.path) else {
+            return vec![];
+        };
+        let mut content = vec![];
+        if file.seek(SeekFrom::Start(self.offset)).is_err()
+            || file.read_to_end(&mut content).is_err()
+        {
+            return vec![];
+        }
+        // 最后一行可能还没写完，留到下次读取
+        let Some(end) = content.iter().rposition(|b| *b == b'\n') else {
+            return vec![];
+        };
+        self.offset += end as u64 + 1;
+
+        let mut issues = vec![];
+        for line in String::from_utf8_lossy(&content[..end]).lines() {
+            if let Some(issue) = parse_line(line) {
+                if !issues.contains(&issue) {
+                    issues.push(issue);
+                }
+            }
+        }
+        issues
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_line() {
+        assert_eq!(
+            parse_line(
+                r#"time="2024-05-01T10:00:00+08:00" level=fatal msg="Parse config error: proxy 3: ss 3: unsupported method: rc4""#
+            ),
+            Some(CoreIssue::ProxyRejected {
+                index: 3,
+                reason: "ss 3: unsupported method: rc4".to_string()
+            })
+        );
+        assert_eq!(
+            parse_line(
+                r#"time="2024-05-01T10:00:00+08:00" level=error msg="Start Mixed(http+socks) server error: listen tcp 127.0.0.1:7890: bind: address already in use""#
+            ),
+            Some(CoreIssue::PortInUse("127.0.0.1:7890".to_string()))
+        );
+        assert_eq!(
+            parse_line(r#"time="2024-05-01T10:00:00+08:00" level=fatal msg="Parse config error: rules[0] [GEOIP,CN,DIRECT] error: can't download MMDB: context deadline exceeded""#),
+            Some(CoreIssue::GeoData(
+                "Parse config error: rules[0] [GEOIP,CN,DIRECT] error: can't download MMDB: context deadline exceeded".to_string()
+            ))
+        );
+        assert_eq!(
+            parse_line(
+                r#"time="2024-05-01T10:00:00+08:00" level=info msg="Can't find MMDB, start download""#
+            ),
+            None
+        );
+        assert_eq!(
+            parse_line(
+                r#"time="2024-05-01T10:00:00+08:00" level=warning msg="[TCP] dial PROXY error: i/o timeout""#
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_log_tail() {
+        let path = std::env::temp_dir().join(format!("core-log-{}.log", std::process::id()));
+        let mut file = File::create(&path).unwrap();
+        let mut tail = LogTail::new(&path);
+        assert!(tail.issues().is_empty());
+
+        let line = r#"level=fatal msg="Parse config error: proxy 0: missing type""#;
+        writeln!(file, "{}\n{}", line, line).unwrap();
+        write!(
+            file,
+            "level=error msg=\"listen tcp :9090: bind: address already in use"
+        )
+        .unwrap();
+        let issues = tail.issues();
+        assert_eq!(issues.len(), 1);
+        assert!(matches!(
+            issues[0],
+            CoreIssue::ProxyRejected { index: 0, .. }
+        ));
+
+        writeln!(file, "\"").unwrap();
+        assert_eq!(
+            tail.issues(),
+            vec![CoreIssue::PortInUse(":9090".to_string())]
+        );
+        assert!(tail.issues().is_empty());
+        std::fs::remove_file(path).unwrap();
+    }
+}
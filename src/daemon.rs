@@ -0,0 +1,230 @@
+use std::env;
+use std::fmt;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::fs::TryLockError;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use chrono::DateTime;
+use chrono::Local;
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+use crate::tr;
+
+/// 默认的 PID 文件，同一数据目录下只允许一个实例同时运行，避免两个进程争抢内核端口
+pub const DEFAULT_PID_FILE: &str = "subs/clash-butler.pid";
+
+/// 通过 NOTIFY_SOCKET 向 systemd 发送状态，未由 systemd 以 Type=notify 启动时不做任何事
+pub fn notify(state: &str) {
+    if let Err(e) = sd_notify(state) {
+        warn!(
+            "{}",
+            tr!(
+                "向 systemd 发送 {} 失败: {}",
+                "Failed to send {} to systemd: {}",
+                state,
+                e
+            )
+        );
+    }
+}
+
+/// 服务已就绪，systemd 在收到后才认为启动完成
+pub fn notify_ready() {
+    notify("READY=1");
+}
+
+pub fn notify_stopping() {
+    notify("STOPPING=1");
+}
+
+#[cfg(unix)]
+fn sd_notify(state: &str) -> io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::net::UnixDatagram;
+
+    let Some(path) = env::var_os("NOTIFY_SOCKET") else {
+        return Ok(());
+    };
+    let socket = UnixDatagram::unbound()?;
+    match path.as_bytes().strip_prefix(b"@") {
+        // @ 开头的是 Linux 的抽象命名空间地址
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            use std::os::unix::net::SocketAddr;
+
+            let addr = SocketAddr::from_abstract_name(name)?;
+            socket.send_to_addr(state.as_bytes(), &addr)?;
+        }
+        #[cfg(not(target_os = "linux"))]
+        Some(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "abstract socket is only supported on linux",
+            ))
+        }
+        None => {
+            socket.send_to(state.as_bytes(), &path)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn sd_notify(_state: &str) -> io::Result<()> {
+    Ok(())
+}
+
+/// systemd 配置了 WatchdogSec 时需要在该间隔内发送心跳，未配置或不是发给当前进程时返回 None
+pub fn watchdog_interval() -> Option<Duration> {
+    if let Ok(pid) = env::var("WATCHDOG_PID") {
+        if pid.parse::<u32>().ok()? != std::process::id() {
+            return None;
+        }
+    }
+    let usec = env::var("WATCHDOG_USEC").ok()?.parse::<u64>().ok()?;
+    (usec > 0).then_some(Duration::from_micros(usec))
+}
+
+/// 以看门狗间隔的一半定时发送心跳，长期运行的 serve、--watch 模式使用
+pub fn spawn_watchdog() -> Option<JoinHandle<()>> {
+    let interval = watchdog_interval()? / 2;
+    Some(tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            notify("WATCHDOG=1");
+        }
+    }))
+}
+
+/// 单实例锁，持有期间其他进程无法获取同一文件的锁，进程退出时由系统自动释放
+///
+/// 锁由系统维护而不是以文件是否存在判断，进程崩溃或被强制结束后残留的 PID 文件不会阻止下次运行
+#[derive(Debug)]
+pub struct InstanceLock {
+    _file: File,
+}
+
+#[derive(Debug)]
+pub enum LockError {
+    /// 已有实例持有锁，pid 为 PID 文件中记录的进程号，since 为该实例获得锁的时间
+    Locked {
+        path: PathBuf,
+        pid: Option<u32>,
+        since: Option<DateTime<Local>>,
+    },
+    Io(io::Error),
+}
+
+impl fmt::Display for LockError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LockError::Locked { path, pid, since } => {
+                let pid = pid.map_or("?".to_string(), |pid| pid.to_string());
+                let since = since.map_or("?".to_string(), |since| {
+                    since.format("%Y-%m-%d %H:%M:%S").to_string()
+                });
+                f.write_str(&tr!(
+                    "已有实例正在运行（pid {}，启动于 {}，锁文件 {}），请等待其结束后重试",
+                    "Another instance is running (pid {}, started at {}, lock file {}), retry after it exits",
+                    pid,
+                    since,
+                    path.display()
+                ))
+            }
+            LockError::Io(e) => f.write_str(&tr!(
+                "PID 文件读写失败, {}",
+                "Failed to access the PID file, {}",
+                e
+            )),
+        }
+    }
+}
+
+impl std::error::Error for LockError {}
+
+impl From<io::Error> for LockError {
+    fn from(e: io::Error) -> Self {
+        LockError::Io(e)
+    }
+}
+
+impl InstanceLock {
+    /// 锁定 PID 文件并写入当前进程号，文件不存在时创建
+    pub fn acquire(path: &Path) -> Result<Self, LockError> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                let mut content = String::new();
+                file.read_to_string(&mut content)?;
+                // 获得锁时会重写进程号，文件的修改时间即该实例获得锁的时间
+                let since = file.metadata().and_then(|m| m.modified()).ok();
+                return Err(LockError::Locked {
+                    path: path.to_path_buf(),
+                    pid: content.trim().parse().ok(),
+                    since: since.map(DateTime::from),
+                });
+            }
+            Err(TryLockError::Error(e)) => return Err(e.into()),
+        }
+        // 获得锁之后才覆盖旧的进程号
+        file.set_len(0)?;
+        writeln!(file, "{}", std::process::id())?;
+        file.sync_all()?;
+        Ok(InstanceLock { _file: file })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_instance_lock() {
+        let dir = env::temp_dir().join("clash_butler_test_instance_lock");
+        let _ = std::fs::remove_dir_all(&dir);
+        let path = dir.join("clash-butler.pid");
+
+        let lock = InstanceLock::acquire(&path).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap().trim(),
+            std::process::id().to_string()
+        );
+        match InstanceLock::acquire(&path) {
+            Err(LockError::Locked { pid, since, .. }) => {
+                assert_eq!(pid, Some(std::process::id()));
+                assert!(since.is_some());
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        drop(lock);
+        assert!(InstanceLock::acquire(&path).is_ok());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_watchdog_interval() {
+        // 测试进程不是由 systemd 启动的
+        if env::var_os("WATCHDOG_USEC").is_none() {
+            assert_eq!(watchdog_interval(), None);
+        }
+    }
+}
@@ -0,0 +1,329 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::fs;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::io;
+use std::path::Path;
+
+use chrono::DateTime;
+use chrono::Duration;
+use chrono::Local;
+use proxrs::protocol::Proxy;
+use proxrs::sub::write_atomic;
+use serde::Deserialize;
+use serde::Serialize;
+use sha2::Digest;
+use sha2::Sha256;
+
+/// 节点历史记录文件，跨运行保存每个节点首次出现及最近一次通过测试的时间，以及各订阅最近的表现
+pub const NODE_HISTORY_PATH: &str = "subs/history.json";
+/// 超过该天数没有再出现在订阅中的节点从历史记录中删除
+const RETENTION_DAYS: i64 = 30;
+/// 每个订阅保留的最近运行记录数
+const SOURCE_RUNS: usize = 20;
+
+/// 单个节点的历史记录
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NodeSeen {
+    pub first_seen: DateTime<Local>,
+    /// 最近一次出现在订阅中的时间
+    pub last_seen: DateTime<Local>,
+    /// 最近一次通过连通性测试的时间
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_passed: Option<DateTime<Local>>,
+    /// 最近连续通过连通性测试的完整运行次数，用于 stable 发布通道
+    #[serde(default)]
+    pub passed_runs: u32,
+}
+
+impl NodeSeen {
+    /// 首次出现至今的天数
+    pub fn age_days(&self, now: DateTime<Local>) -> i64 {
+        (now - self.first_seen).num_days().max(0)
+    }
+}
+
+/// 订阅在单次运行中的表现
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SourceRun {
+    pub at: DateTime<Local>,
+    /// 是否成功拉取
+    pub ok: bool,
+    /// 去重后新增的节点数
+    pub unique: usize,
+    /// 新增节点中通过连通性测试的节点数
+    pub alive: usize,
+}
+
+/// 订阅最近若干次运行的表现，用于对订阅排序及跳过长期无可用节点的订阅
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct SourceHealth {
+    pub runs: VecDeque<SourceRun>,
+}
+
+impl SourceHealth {
+    /// 拉取成功率，0 ~ 1
+    pub fn success_rate(&self) -> f64 {
+        self.mean(|run| if run.ok { 1.0 } else { 0.0 })
+    }
+
+    /// 平均每次新增的节点数
+    pub fn mean_unique(&self) -> f64 {
+        self.mean(|run| run.unique as f64)
+    }
+
+    /// 平均每次可用的节点数
+    pub fn mean_alive(&self) -> f64 {
+        self.mean(|run| run.alive as f64)
+    }
+
+    /// 最近连续没有可用节点的运行次数
+    pub fn idle_runs(&self) -> usize {
+        self.runs
+            .iter()
+            .rev()
+            .take_while(|run| run.alive == 0)
+            .count()
+    }
+
+    fn mean(&self, value: impl Fn(&SourceRun) -> f64) -> f64 {
+        if self.runs.is_empty() {
+            return 0.0;
+        }
+        self.runs.iter().map(value).sum::<f64>() / self.runs.len() as f64
+    }
+}
+
+/// 以节点指纹为键的历史记录，节点改名或换订阅后仍能对应上，同时记录各订阅的表现
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct NodeHistory {
+    nodes: HashMap<String, NodeSeen>,
+    /// 以订阅地址为键
+    #[serde(default)]
+    sources: HashMap<String, SourceHealth>,
+}
+
+impl NodeHistory {
+    /// 读取历史记录，文件不存在时返回空记录
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(NodeHistory::default()),
+            Err(e) => return Err(e),
+        };
+        // 旧版本只记录节点，整个文件就是节点的映射
+        if let Ok(nodes) = serde_json::from_str(&content) {
+            return Ok(NodeHistory {
+                nodes,
+                sources: HashMap::new(),
+            });
+        }
+        serde_json::from_str(&content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let content = serde_json::to_string_pretty(self).map_err(io::Error::from)?;
+        write_atomic(path, content.as_bytes())
+    }
+
+    /// 记录本次拉取到的节点，并删除长时间没有出现的节点
+    pub fn seen(&mut self, proxies: &[Proxy], now: DateTime<Local>) {
+        for proxy in proxies {
+            self.entry(proxy, now).last_seen = now;
+        }
+        let expire = now - Duration::days(RETENTION_DAYS);
+        self.nodes.retain(|_, node| node.last_seen > expire);
+    }
+
+    /// 记录一次完整运行的测试结果，通过的节点连续通过次数加一，未通过的清零
+    pub fn tested(&mut self, candidates: &[Proxy], alive: &[Proxy], now: DateTime<Local>) {
+        let alive = alive.iter().map(fingerprint).collect::<HashSet<_>>();
+        for proxy in candidates {
+            let passed = alive.contains(&fingerprint(proxy));
+            let node = self.entry(proxy, now);
+            node.last_seen = now;
+            if passed {
+                node.last_passed = Some(now);
+                node.passed_runs += 1;
+            } else {
+                node.passed_runs = 0;
+            }
+        }
+    }
+
+    /// 记录通过连通性测试的节点，不影响连续通过次数
+    pub fn passed(&mut self, proxies: &[Proxy], now: DateTime<Local>) {
+        for proxy in proxies {
+            let node = self.entry(proxy, now);
+            node.last_seen = now;
+            node.last_passed = Some(now);
+        }
+    }
+
+    pub fn get(&self, proxy: &Proxy) -> Option<&NodeSeen> {
+        self.nodes.get(&fingerprint(proxy))
+    }
+
+    /// 记录订阅本次运行的表现，只保留最近 SOURCE_RUNS 次
+    pub fn record_source(&mut self, url: &str, run: SourceRun) {
+        let runs = &mut self.sources.entry(url.to_string()).or_default().runs;
+        runs.push_back(run);
+        while runs.len() > SOURCE_RUNS {
+            runs.pop_front();
+        }
+    }
+
+    pub fn source(&self, url: &str) -> Option<&SourceHealth> {
+        self.sources.get(url)
+    }
+
+    fn entry(&mut self, proxy: &Proxy, now: DateTime<Local>) -> &mut NodeSeen {
+        self.nodes
+            .entry(fingerprint(proxy))
+            .or_insert_with(|| NodeSeen {
+                first_seen: now,
+                last_seen: now,
+                last_passed: None,
+                passed_runs: 0,
+            })
+    }
+}
+
+/// 节点指纹，由服务器、端口及凭据计算，不包含节点名称
+pub fn fingerprint(proxy: &Proxy) -> String {
+    // 标准库的哈希算法不保证跨版本稳定，持久化的键使用 SHA-256
+    struct Sha256Hasher(Sha256);
+
+    impl Hasher for Sha256Hasher {
+        fn finish(&self) -> u64 {
+            unreachable!("only used to feed bytes into sha256")
+        }
+
+        fn write(&mut self, bytes: &[u8]) {
+            self.0.update(bytes);
+        }
+    }
+
+    let mut hasher = Sha256Hasher(Sha256::new());
+    proxy.hash(&mut hasher);
+    hex::encode(&hasher.0.finalize()[..16])
+}
+
+/// 节点在线时长的简写，如 0d、12d，用于 ${AGE} 占位符
+pub fn format_age(days: i64) -> String {
+    format!("{}d", days)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn proxy(name: &str, port: u16) -> Proxy {
+        Proxy::from_link(&format!(
+            "ss://YWVzLTI1Ni1nY206cGFzc3dvcmQ@1.2.3.4:{}#{}",
+            port, name
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        assert_eq!(
+            fingerprint(&proxy("a", 8388)),
+            fingerprint(&proxy("b", 8388))
+        );
+        assert_ne!(
+            fingerprint(&proxy("a", 8388)),
+            fingerprint(&proxy("a", 8389))
+        );
+    }
+
+    #[test]
+    fn test_node_history() {
+        let start = Local::now() - Duration::days(40);
+        let mut history = NodeHistory::default();
+        history.seen(&[proxy("old", 1), proxy("gone", 2)], start);
+
+        let now = start + Duration::days(35);
+        history.seen(&[proxy("old_renamed", 1), proxy("new", 3)], now);
+        history.passed(&[proxy("new", 3)], now);
+
+        let old = history.get(&proxy("old", 1)).unwrap();
+        assert_eq!(old.first_seen, start);
+        assert_eq!(old.age_days(now), 35);
+        assert_eq!(old.last_passed, None);
+        assert!(history.get(&proxy("gone", 2)).is_none());
+        assert_eq!(
+            history.get(&proxy("new", 3)).unwrap().last_passed,
+            Some(now)
+        );
+
+        let path = std::env::temp_dir().join(format!("history-{}.json", std::process::id()));
+        history.save(&path).unwrap();
+        let loaded = NodeHistory::load(&path).unwrap();
+        assert_eq!(loaded.get(&proxy("old", 1)), Some(old));
+        fs::remove_file(&path).unwrap();
+        assert!(NodeHistory::load(&path)
+            .unwrap()
+            .get(&proxy("old", 1))
+            .is_none());
+    }
+
+    #[test]
+    fn test_passed_runs() {
+        let now = Local::now();
+        let mut history = NodeHistory::default();
+        let (a, b) = (proxy("a", 1), proxy("b", 2));
+        let candidates = [a.clone(), b.clone()];
+        history.tested(&candidates, &candidates, now);
+        history.tested(&candidates, &[a.clone()], now);
+        history.tested(&candidates, &[a.clone(), b.clone()], now);
+        history.passed(&[b.clone()], now);
+        assert_eq!(history.get(&a).unwrap().passed_runs, 3);
+        assert_eq!(history.get(&b).unwrap().passed_runs, 1);
+        assert_eq!(history.get(&b).unwrap().last_passed, Some(now));
+    }
+
+    #[test]
+    fn test_source_health() {
+        let now = Local::now();
+        let mut history = NodeHistory::default();
+        let run = |ok, unique, alive| SourceRun {
+            at: now,
+            ok,
+            unique,
+            alive,
+        };
+        history.record_source("a", run(true, 10, 4));
+        history.record_source("a", run(false, 0, 0));
+        history.record_source("a", run(true, 6, 0));
+        let health = history.source("a").unwrap();
+        assert!((health.success_rate() - 2.0 / 3.0).abs() < 1e-9);
+        assert_eq!(health.mean_unique(), 16.0 / 3.0);
+        assert_eq!(health.idle_runs(), 2);
+        assert!(history.source("b").is_none());
+
+        for _ in 0..SOURCE_RUNS {
+            history.record_source("a", run(true, 1, 1));
+        }
+        let health = history.source("a").unwrap();
+        assert_eq!(health.runs.len(), SOURCE_RUNS);
+        assert_eq!(health.idle_runs(), 0);
+
+        // 兼容旧版本只包含节点映射的文件
+        let path = std::env::temp_dir().join(format!("history-legacy-{}.json", std::process::id()));
+        history.seen(&[proxy("a", 1)], now);
+        fs::write(&path, serde_json::to_string(&history.nodes).unwrap()).unwrap();
+        assert!(NodeHistory::load(&path)
+            .unwrap()
+            .get(&proxy("a", 1))
+            .is_some());
+        history.save(&path).unwrap();
+        let loaded = NodeHistory::load(&path).unwrap();
+        assert_eq!(loaded.source("a"), history.source("a"));
+        assert!(loaded.get(&proxy("a", 1)).is_some());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,98 @@
+use std::str::FromStr;
+use std::sync::atomic::AtomicU8;
+use std::sync::atomic::Ordering;
+
+use serde::Deserialize;
+
+/// 指定语言的环境变量，与配置文件中的 lang 相同，未读取配置文件前即可生效
+pub const LANG_ENV: &str = "CLASH_BUTLER__LANG";
+
+/// 日志及错误信息使用的语言，默认中文
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(try_from = "String")]
+pub enum Lang {
+    #[default]
+    Zh,
+    En,
+}
+
+impl Lang {
+    /// 读取 CLASH_BUTLER__LANG 环境变量，未设置或无法识别时返回 None
+    pub fn from_env() -> Option<Lang> {
+        std::env::var(LANG_ENV).ok()?.parse().ok()
+    }
+}
+
+impl FromStr for Lang {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // 兼容 zh_CN.UTF-8、en-US 这类写法
+        let s = s.trim().to_ascii_lowercase();
+        match s.split(['_', '-', '.']).next() {
+            Some("zh") => Ok(Lang::Zh),
+            Some("en") => Ok(Lang::En),
+            _ => Err(format!("unsupported language: {}", s)),
+        }
+    }
+}
+
+impl TryFrom<String> for Lang {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+static LANG: AtomicU8 = AtomicU8::new(Lang::Zh as u8);
+
+/// 设置全局语言，之后输出的日志及错误信息使用该语言
+pub fn set_lang(lang: Lang) {
+    LANG.store(lang as u8, Ordering::Relaxed);
+}
+
+pub fn lang() -> Lang {
+    match LANG.load(Ordering::Relaxed) {
+        x if x == Lang::En as u8 => Lang::En,
+        _ => Lang::Zh,
+    }
+}
+
+/// 按当前语言选择中文或英文的格式化字符串，参数与 format! 相同
+///
+/// ```
+/// let version = "v1.18.0";
+/// let msg = clash_butler::tr!("原神启动！ 版本号：{}", "Core started, version: {}", version);
+/// ```
+#[macro_export]
+macro_rules! tr {
+    ($zh:literal, $en:literal $(, $arg:expr)* $(,)?) => {
+        match $crate::i18n::lang() {
+            $crate::i18n::Lang::Zh => format!($zh $(, $arg)*),
+            $crate::i18n::Lang::En => format!($en $(, $arg)*),
+        }
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_lang() {
+        assert_eq!("zh".parse::<Lang>(), Ok(Lang::Zh));
+        assert_eq!("zh_CN.UTF-8".parse::<Lang>(), Ok(Lang::Zh));
+        assert_eq!("EN".parse::<Lang>(), Ok(Lang::En));
+        assert_eq!("en-US".parse::<Lang>(), Ok(Lang::En));
+        assert!("ja".parse::<Lang>().is_err());
+    }
+
+    #[test]
+    fn test_tr() {
+        set_lang(Lang::En);
+        assert_eq!(tr!("节点个数：{}", "Nodes: {}", 3), "Nodes: 3");
+        set_lang(Lang::Zh);
+        assert_eq!(tr!("节点个数：{}", "Nodes: {}", 3), "节点个数：3");
+    }
+}
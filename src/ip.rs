@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::net::IpAddr;
 use std::time::Duration;
 
@@ -8,10 +9,17 @@ use reqwest::Client;
 use reqwest::Error;
 use serde::Deserialize;
 use serde::Serialize;
+use tokio::net::lookup_host;
 use tracing::log::error;
 
+use crate::pacing::RateLimiter;
+use crate::tr;
+
 // IP 详情查询超时时间
 const TIMEOUT: Duration = Duration::from_millis(1000);
+/// ip-api 批量查询接口每次最多查询 100 个 IP
+const BATCH_SIZE: usize = 100;
+const BATCH_TIMEOUT: Duration = Duration::from_secs(10);
 
 pub async fn get_ip_detail(
     ip_addr: &IpAddr,
@@ -21,7 +29,14 @@ pub async fn get_ip_detail(
         match get_ip_detail_from_ipsb(ip_addr, proxy_url).await {
             Ok(ip_detail) => Ok(ip_detail),
             Err(err) => {
-                error!("从 ipSb 获取 IP 详情失败, {err}");
+                error!(
+                    "{}",
+                    tr!(
+                        "从 ipSb 获取 IP 详情失败, {}",
+                        "Failed to get IP details from ipSb, {}",
+                        err
+                    )
+                );
                 Err(err)
             }
         }
@@ -32,7 +47,14 @@ pub async fn get_ip_detail(
         match get_ip_detail_from_ipapi(ip_addr, proxy_url).await {
             Ok(ip_detail) => Ok(ip_detail),
             Err(err) => {
-                error!("从 ipApi 获取 IP 详情失败, {err}");
+                error!(
+                    "{}",
+                    tr!(
+                        "从 ipApi 获取 IP 详情失败, {}",
+                        "Failed to get IP details from ipApi, {}",
+                        err
+                    )
+                );
                 Err(err)
             }
         }
@@ -42,7 +64,7 @@ pub async fn get_ip_detail(
     let futures = vec![ipsb_future, ipapi_future];
     match select_ok(futures).await {
         Ok((ip_detail, _)) => Ok(ip_detail),
-        Err(_) => Err("获取 IP 详情失败".into()),
+        Err(_) => Err(tr!("获取 IP 详情失败", "Failed to get the IP details").into()),
     }
 }
 
@@ -81,6 +103,61 @@ pub async fn get_ip_detail_from_ipapi(
     })
 }
 
+/// 解析节点服务器地址，域名取第一个解析结果，解析失败时返回 None
+pub async fn resolve(server: &str) -> Option<IpAddr> {
+    if let Ok(ip) = server.trim_matches(['[', ']']).parse::<IpAddr>() {
+        return Some(ip);
+    }
+    lookup_host((server, 0))
+        .await
+        .ok()?
+        .next()
+        .map(|addr| addr.ip())
+}
+
+/// 不经过代理批量查询 IP 所属的国家代码，查询失败的 IP 不在结果中
+pub async fn get_countries(ips: &[IpAddr], limiter: &RateLimiter) -> HashMap<IpAddr, String> {
+    let mut countries = HashMap::new();
+    let Ok(client) = Client::builder().timeout(BATCH_TIMEOUT).build() else {
+        return countries;
+    };
+    for chunk in ips.chunks(BATCH_SIZE) {
+        limiter.acquire().await;
+        let body = chunk.iter().map(IpAddr::to_string).collect::<Vec<_>>();
+        let response = client
+            .post("http://ip-api.com/batch?fields=status,countryCode,query")
+            .json(&body)
+            .send()
+            .await;
+        let results = match response {
+            Ok(response) => response.json::<Vec<IpApiBatchResult>>().await,
+            Err(e) => Err(e),
+        };
+        match results {
+            Ok(results) => {
+                for result in results {
+                    if let (Ok(ip), Some(country_code)) =
+                        (result.query.parse::<IpAddr>(), result.country_code)
+                    {
+                        if result.status == "success" {
+                            countries.insert(ip, country_code);
+                        }
+                    }
+                }
+            }
+            Err(err) => error!(
+                "{}",
+                tr!(
+                    "批量查询 IP 所属国家失败, {}",
+                    "Failed to look up IP countries in batch, {}",
+                    err
+                )
+            ),
+        }
+    }
+    countries
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct IpDetail {
     pub ip: String,
@@ -107,6 +184,14 @@ pub struct IpApiDetail {
     pub timezone: String,
 }
 
+#[derive(Debug, Deserialize)]
+struct IpApiBatchResult {
+    status: String,
+    #[serde(rename = "countryCode", default)]
+    country_code: Option<String>,
+    query: String,
+}
+
 #[cfg(test)]
 mod tests {
     use std::str::FromStr;
@@ -121,4 +206,16 @@ mod tests {
         let result = get_ip_detail(&IpAddr::from_str("223.160.128.89").unwrap(), PROXY_URL).await;
         println!("{:?}", result);
     }
+
+    #[tokio::test]
+    async fn test_resolve_ip() {
+        assert_eq!(
+            resolve("1.2.3.4").await,
+            Some(IpAddr::from_str("1.2.3.4").unwrap())
+        );
+        assert_eq!(
+            resolve("[2001:db8::1]").await,
+            Some(IpAddr::from_str("2001:db8::1").unwrap())
+        );
+    }
 }
@@ -0,0 +1,39 @@
+pub mod analytics;
+pub mod banlist;
+pub mod bundle;
+pub mod cdn;
+pub mod check;
+pub mod clash;
+pub mod compare;
+pub mod composition;
+pub mod daemon;
+pub mod history;
+pub mod i18n;
+pub mod ip;
+pub mod naming;
+pub mod netstack;
+pub mod node_check;
+pub mod pacing;
+pub mod pinlist;
+pub mod pipeline;
+pub mod reload;
+pub mod report;
+pub mod run_log;
+pub mod script;
+pub mod server;
+pub mod settings;
+pub mod speedtest;
+pub mod target;
+pub mod template;
+pub mod tui;
+pub mod upload;
+pub mod website;
+
+mod cgi_trace;
+mod core_log;
+mod progress;
+mod risk;
+mod routes;
+
+pub use pipeline::Pipeline;
+pub use settings::Settings;
@@ -1,487 +1,872 @@
-use std::collections::HashMap;
-use std::collections::HashSet;
-use std::env;
 use std::fs;
+use std::fs::OpenOptions;
+use std::io;
 use std::path::Path;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
 
+use chrono::Local;
 use clap::Parser;
-use proxrs::protocol::Proxy;
-use proxrs::sub::SubManager;
+use clap::Subcommand;
+use clap::ValueEnum;
+use clash_butler::check;
+use clash_butler::daemon;
+use clash_butler::daemon::InstanceLock;
+use clash_butler::i18n;
+use clash_butler::i18n::Lang;
+use clash_butler::pipeline;
+use clash_butler::pipeline::ExportFormat;
+use clash_butler::pipeline::FetchResult;
+use clash_butler::pipeline::PipelineError;
+use clash_butler::pipeline::RenameResult;
+use clash_butler::pipeline::RunSummary;
+use clash_butler::pipeline::TestResult;
+use clash_butler::reload::SettingsWatcher;
+use clash_butler::reload::SharedSettings;
+use clash_butler::report;
+use clash_butler::run_log::RunLog;
+use clash_butler::server;
+use clash_butler::server::LastRun;
+use clash_butler::server::ReleaseHook;
+use clash_butler::server::SharedStatus;
+use clash_butler::settings::SettingsError;
+use clash_butler::tr;
+use clash_butler::tui;
+use clash_butler::Pipeline;
+use clash_butler::Settings;
+use serde::Serialize;
 use tracing::error;
 use tracing::info;
-use tracing::Level;
-use tracing_subscriber::FmtSubscriber;
+use tracing_subscriber::fmt;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
 
-use crate::clash::ClashMeta;
-use crate::clash::DelayTestConfig;
-use crate::settings::Settings;
+const TUI_LOG_PATH: &str = "logs/clash-butler.log";
+const RUN_LOG_ROOT: &str = "logs";
 
-mod cgi_trace;
-mod clash;
-mod ip;
-mod risk;
-mod routes;
-mod server;
-mod settings;
-mod speedtest;
-mod website;
-
-#[derive(Parser)]
+#[derive(Parser, Clone)]
 #[command(version, about, long_about = None)]
 struct Cli {
-    // Starts the Axum server
+    /// 不指定子命令时完整执行一次筛选流程
+    #[command(subcommand)]
+    command: Option<Commands>,
+
+    /// 配置文件路径，默认为 conf/config.toml，在容器中可以通过环境变量指定挂载的配置文件
+    #[arg(long, value_name = "PATH", global = true, env = "CLASH_BUTLER_CONFIG")]
+    config: Option<PathBuf>,
+    /// 工作目录，subs、logs、conf 等相对路径均基于该目录，目录不存在时创建，便于在 systemd、cron 中运行
+    #[arg(long, value_name = "DIR", global = true)]
+    workdir: Option<PathBuf>,
+    /// 在配置文件之上叠加的 profile，如 home 对应 conf/profiles/home.toml，也可以是 toml 文件路径
+    #[arg(long, value_name = "NAME", global = true)]
+    profile: Option<String>,
+    /// 订阅链接，可重复指定，覆盖配置文件中的 subs
+    #[arg(long = "sub", value_name = "URL")]
+    subs: Vec<String>,
+    /// release 文件保存路径，默认为当前目录下的 clash.yaml
+    #[arg(long)]
+    output: Option<PathBuf>,
+    /// 开启快速模式，仅测试连通性
     #[arg(long)]
-    server: bool,
+    fast: bool,
+    /// 重命名节点
+    #[arg(long, overrides_with = "no_rename")]
+    rename: bool,
+    /// 不重命名节点
+    #[arg(long, overrides_with = "rename")]
+    no_rename: bool,
+    /// 最终保留的节点数上限
+    #[arg(long, value_name = "N")]
+    max_nodes: Option<usize>,
+    /// 只保留指定国家的节点，如 US,HK
+    #[arg(long, value_delimiter = ',')]
+    country: Vec<String>,
+
+    /// 日志级别，设置 RUST_LOG 环境变量时以环境变量为准，如 RUST_LOG=info,proxrs=error
+    #[arg(long, value_enum, default_value_t = LogLevel::Info, global = true)]
+    log_level: LogLevel,
+    /// 日志格式
+    #[arg(long, value_enum, default_value_t = LogFormat::Text, global = true)]
+    log_format: LogFormat,
+    /// 看门狗模式，每隔指定分钟数只测试已发布的 release 文件中的节点，剔除失效节点并重新排序
+    #[arg(long, value_name = "MINUTES", value_parser = clap::value_parser!(u64).range(1..))]
+    watch: Option<u64>,
+    /// 以终端界面运行，测试完成后可人工选择导出的节点
+    #[arg(long)]
+    tui: bool,
+    /// 结束时向标准输出打印一行 JSON 摘要，包含退出码、节点数及导出文件
+    #[arg(long, global = true)]
+    summary: bool,
+    /// 不显示进度条，输出不是终端时会自动关闭
+    #[arg(long, global = true)]
+    no_progress: bool,
+    /// 每次运行的日志及内核日志保存在 logs/run-{时间} 目录下，保留最近 N 次，0 表示不保存
+    #[arg(long, value_name = "N", default_value_t = 10, global = true)]
+    keep_runs: usize,
+    /// 日志输出文件，默认输出到终端
+    #[arg(long, value_name = "PATH", global = true)]
+    log_file: Option<PathBuf>,
+    /// PID 文件，同时作为单实例锁，已有实例运行时以退出码 7 退出，相对路径基于数据目录
+    #[arg(long, value_name = "PATH", default_value = daemon::DEFAULT_PID_FILE, global = true)]
+    pid_file: PathBuf,
+    /// 日志及错误信息的语言，优先于 CLASH_BUTLER__LANG 环境变量及配置文件中的 lang
+    #[arg(long, value_enum, global = true)]
+    lang: Option<Lang>,
+    /// 切换到数据目录后记录其绝对路径，重新加载配置时替换配置中的 data_dir
+    #[arg(skip)]
+    data_dir: Option<PathBuf>,
 }
 
-const TEST_PROXY_GROUP_NAME: &str = "PROXY";
+#[derive(Clone, Copy, ValueEnum)]
+enum LogLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
 
-#[tokio::main]
-async fn main() {
-    tracing::subscriber::set_global_default(
-        FmtSubscriber::builder()
-            .with_max_level(Level::INFO)
-            .finish(),
-    )
-    .expect("setting default subscriber failed");
-    let args = Cli::parse();
-    let config = Settings::new();
-    match config {
-        Ok(config) => {
-            // 创建订阅测试所用的目录结构
-            create_folder();
-            if args.server {
-                // 服务端
-                // server::start_server(config).await
-            } else {
-                // 本地生成
-                run(config).await
-            }
-        }
-        Err(e) => {
-            error!("配置文件读取失败: {}", e)
+impl LogLevel {
+    fn as_str(&self) -> &'static str {
+        match self {
+            LogLevel::Trace => "trace",
+            LogLevel::Debug => "debug",
+            LogLevel::Info => "info",
+            LogLevel::Warn => "warn",
+            LogLevel::Error => "error",
         }
     }
 }
 
-async fn run(config: Settings) {
-    let test_yaml_path = "subs/test/config.yaml";
-    let test_all_yaml_path = "subs/test/all.yaml";
-    let release_yaml_path = env::current_dir().unwrap().join("clash.yaml");
-    let test_clash_template_path = "conf/clash_test.yaml";
-    let release_clash_template_path = "conf/clash_release.yaml";
-    let mut urls = config.subs;
-    if config.need_add_pool {
-        urls.extend(config.pools)
-    }
-    let test_proxies = SubManager::get_proxies_from_urls(&urls).await;
-    info!("待测速节点个数：{}", &test_proxies.len());
-    if test_proxies.is_empty() {
-        error!("当前无可用的待测试订阅连接，请修改配置文件添加订阅链接或确保当前网络通顺");
-        return;
-    }
-
-    // 全部保存一下节点信息
-    SubManager::save_proxies_into_clash_file(
-        &test_proxies,
-        test_clash_template_path.to_string(),
-        test_all_yaml_path.to_string(),
-    );
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum LogFormat {
+    Text,
+    Json,
+}
 
-    let chunk_size = config.test_group_size;
-    let proxies_group: Vec<_> = test_proxies
-        .chunks(chunk_size)
-        .map(|p| p.to_vec())
-        .collect();
-    let group_size = proxies_group.len();
-    if group_size > 1 {
-        info!(
-            "为加速测试速度，以 {} 为限制分为 {} 组测试",
-            chunk_size,
-            proxies_group.len()
+impl Cli {
+    /// 初始化日志，需要保存运行日志时返回其写入目标，进入数据目录后再创建运行目录
+    fn init_tracing(&self) -> io::Result<Option<Arc<RunLog>>> {
+        // 终端界面模式下日志默认写入文件，避免打乱界面
+        let log_file = match &self.log_file {
+            None if self.tui => Some(PathBuf::from(TUI_LOG_PATH)),
+            log_file => log_file.clone(),
+        };
+        let filter = EnvFilter::try_from_default_env()
+            .unwrap_or_else(|_| EnvFilter::new(self.log_level.as_str()));
+        let writer = match &log_file {
+            Some(path) => {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                let file = OpenOptions::new().create(true).append(true).open(path)?;
+                BoxMakeWriter::new(Arc::new(file))
+            }
+            None => BoxMakeWriter::new(io::stdout),
+        };
+        // 写入文件时不输出终端颜色控制符
+        let console = fmt::layer()
+            .with_writer(writer)
+            .with_ansi(log_file.is_none());
+
+        // 离线命令不需要单独保存运行日志
+        let offline = matches!(
+            self.command,
+            Some(Commands::Convert { .. } | Commands::Merge { .. } | Commands::Dedupe { .. })
         );
+        let run_log = (self.keep_runs > 0 && !offline)
+            .then(|| Arc::new(RunLog::new(RUN_LOG_ROOT, self.keep_runs)));
+
+        let registry = tracing_subscriber::registry().with(filter);
+        match self.log_format {
+            LogFormat::Text => registry
+                .with(console)
+                .with(
+                    run_log
+                        .clone()
+                        .map(|writer| fmt::layer().with_writer(writer).with_ansi(false)),
+                )
+                .try_init(),
+            LogFormat::Json => registry
+                .with(console.json())
+                .with(
+                    run_log
+                        .clone()
+                        .map(|writer| fmt::layer().json().with_writer(writer)),
+                )
+                .try_init(),
+        }
+        .expect("setting default subscriber failed");
+        Ok(run_log)
     }
 
-    // 启动 Clash 内核
-    let external_port = 9091;
-    let mixed_port = 7999;
-    let mut useful_proxies = Vec::new();
-    for (index, proxies) in proxies_group.iter().enumerate() {
-        if group_size > 1 {
-            info!("正在测试第 {} 组", index + 1)
+    /// 将配置文件路径及数据目录转为绝对路径，切换到 data_dir 后仍能重新加载配置，
+    /// 脚本、本地订阅等相对路径每次加载时都基于同一个数据目录校验
+    fn pin_config_paths(&mut self, data_dir: &Path) -> io::Result<()> {
+        let paths = Settings::source_paths(self.config.as_deref(), self.profile.as_deref());
+        self.config = Some(std::path::absolute(&paths[0])?);
+        if let Some(profile) = paths.get(1) {
+            self.profile = Some(std::path::absolute(profile)?.to_string_lossy().to_string());
         }
+        self.data_dir = Some(std::path::absolute(data_dir)?);
+        Ok(())
+    }
 
-        SubManager::save_proxies_into_clash_file(
-            proxies,
-            test_clash_template_path.to_string(),
-            test_yaml_path.to_string(),
-        );
+    /// 读取配置文件并使用命令行参数覆盖
+    fn load_settings(&self) -> Result<Settings, SettingsError> {
+        let mut settings = Settings::load_in(
+            self.config.as_deref(),
+            self.profile.as_deref(),
+            self.data_dir.as_deref(),
+        )?;
+        self.override_settings(&mut settings);
+        Ok(settings)
+    }
 
-        let mut clash_meta = ClashMeta::new(external_port, mixed_port);
-        if let Err(e) = clash_meta.start().await {
-            error!("原神启动失败，第一次启动可能会下载 geo 相关的文件，重新启动即可，打开 logs/clash.log，查看具体错误原因，{}", e);
-            clash_meta.stop().unwrap();
-            continue;
+    /// 使用命令行参数覆盖配置文件中的值
+    fn override_settings(&self, settings: &mut Settings) {
+        if !self.subs.is_empty() {
+            settings.override_subs(&self.subs);
+        }
+        if self.fast {
+            settings.fast_mode = true;
+        }
+        if self.rename {
+            settings.rename_node = true;
         }
+        if self.no_rename {
+            settings.rename_node = false;
+        }
+        if self.max_nodes.is_some() {
+            settings.max_nodes = self.max_nodes;
+        }
+        if !self.country.is_empty() {
+            settings.countries = self.country.clone();
+        }
+    }
+}
+
+#[derive(Subcommand, Clone)]
+enum Commands {
+    /// 完整执行拉取、测试、重命名、导出流程
+    Run,
+    /// 拉取配置文件中的所有订阅节点
+    Fetch {
+        /// 节点保存路径
+        #[arg(short, long, default_value = pipeline::TEST_ALL_YAML_PATH)]
+        output: PathBuf,
+    },
+    /// 测试节点连通性，保存可用节点及延迟数据
+    Test {
+        /// 待测试节点文件，支持 clash 配置、base64 及分享链接
+        #[arg(short, long, default_value = pipeline::TEST_ALL_YAML_PATH)]
+        input: PathBuf,
+        /// 可用节点保存路径
+        #[arg(short, long, default_value = pipeline::TEST_USEFUL_YAML_PATH)]
+        output: PathBuf,
+    },
+    /// 查询节点落地 IP 并按规则重命名
+    Rename {
+        #[arg(short, long, default_value = pipeline::TEST_USEFUL_YAML_PATH)]
+        input: PathBuf,
+        #[arg(short, long, default_value = pipeline::RENAMED_YAML_PATH)]
+        output: PathBuf,
+        /// 只输出重命名前后的名称对照表，不保存结果，用于调整 rename_pattern
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// 导出节点，同时生成 Markdown 汇总并上传
+    Export {
+        #[arg(short, long, default_value = pipeline::RENAMED_YAML_PATH)]
+        input: PathBuf,
+        #[arg(short, long, value_enum, default_value_t = ExportFormat::Clash)]
+        format: ExportFormat,
+        /// 导出路径，默认保存在当前目录下
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// 不做测试，直接转换节点文件格式
+    Convert {
+        /// 输入文件，支持 clash 配置、base64 及分享链接
+        #[arg(long = "in")]
+        input: PathBuf,
+        /// 输出文件，未指定格式时根据扩展名推断：yaml/yml 为 clash，json 为
+        /// sing-box，其余为分享链接
+        #[arg(long = "out")]
+        output: PathBuf,
+        #[arg(short, long, value_enum)]
+        format: Option<ExportFormat>,
+    },
+    /// 检查配置文件中的订阅是否可用，输出节点数、协议分布及流量信息
+    Check,
+    /// 合并多个本地或远程订阅并去重
+    Merge {
+        /// 订阅链接或本地文件
+        #[arg(required = true)]
+        subs: Vec<String>,
+        #[arg(long = "out")]
+        output: PathBuf,
+        #[arg(short, long, value_enum)]
+        format: Option<ExportFormat>,
+    },
+    /// 按节点指纹去重
+    Dedupe {
+        #[arg(long = "in")]
+        input: PathBuf,
+        /// 默认覆盖输入文件
+        #[arg(long = "out")]
+        output: Option<PathBuf>,
+        #[arg(short, long, value_enum)]
+        format: Option<ExportFormat>,
+    },
+    /// 在相同条件下测试两个订阅，输出可用率、延迟及速度的对比
+    Compare {
+        /// 订阅链接或本地文件
+        #[arg(long)]
+        a: String,
+        #[arg(long)]
+        b: String,
+        /// Markdown 对比报告保存路径，默认输出到标准输出
+        #[arg(long = "out")]
+        output: Option<PathBuf>,
+    },
+    /// 只测试单个分享链接，执行连通性测试、测速及网站解锁检测并输出该节点的详细结果
+    Probe {
+        /// 节点分享链接，如 vmess://、trojan://
+        link: String,
+        /// 以 JSON 格式输出结果
+        #[arg(long)]
+        json: bool,
+    },
+    /// 启动 Web 服务
+    Serve {
+        #[arg(short, long, default_value_t = 3003)]
+        port: u16,
+        /// 每隔指定分钟数完整执行一次筛选流程，覆盖配置文件中的 schedule_interval
+        #[arg(long, value_name = "MINUTES", value_parser = clap::value_parser!(u64).range(1..))]
+        interval: Option<u64>,
+    },
+}
 
-        match clash_meta.get_group(TEST_PROXY_GROUP_NAME).await {
-            Ok(nodes) => {
+/// 不依赖配置文件的离线命令，其余命令返回 None
+async fn execute_offline(command: &Commands) -> Option<Result<(), PipelineError>> {
+    let result = match command {
+        Commands::Convert {
+            input,
+            output,
+            format,
+        } => pipeline::convert(input, output, *format).map(|count| {
+            info!(
+                "{}",
+                tr!(
+                    "转换完成，节点个数：{}，文件地址：{}",
+                    "Converted {} nodes, file: {}",
+                    count,
+                    output.display()
+                )
+            )
+        }),
+        Commands::Merge {
+            subs,
+            output,
+            format,
+        } => pipeline::merge(subs, output, *format).await.map(|stats| {
+            info!("{}", stats);
+            info!(
+                "{}",
+                tr!(
+                    "合并完成，文件地址：{}",
+                    "Merged, file: {}",
+                    output.display()
+                )
+            )
+        }),
+        Commands::Dedupe {
+            input,
+            output,
+            format,
+        } => {
+            let output = output.as_ref().unwrap_or(input);
+            pipeline::dedupe(input, output, *format).map(|stats| {
+                info!("{}", stats);
                 info!(
-                    "开始测试 subs/test/config.yaml 中节点的延迟速度，节点总数：{}",
-                    nodes.all.len()
+                    "{}",
+                    tr!(
+                        "去重完成，文件地址：{}",
+                        "Deduplicated, file: {}",
+                        output.display()
+                    )
+                )
+            })
+        }
+        _ => return None,
+    };
+    Some(result)
+}
+
+async fn execute(pipeline: &Pipeline, command: Commands) -> Result<(), PipelineError> {
+    match command {
+        Commands::Run => {
+            pipeline.run().await?;
+        }
+        Commands::Fetch { output } => {
+            let fetched = pipeline.fetch().await?;
+            fetched.save(&output)?;
+            info!(
+                "{}",
+                tr!("节点文件地址：{}", "Nodes file: {}", output.display())
+            );
+        }
+        Commands::Test { input, output } => {
+            let fetched = FetchResult::load(&input)?;
+            let tested = pipeline.test(fetched.proxies).await?;
+            tested.save(&output)?;
+            info!(
+                "{}",
+                tr!(
+                    "可用节点文件地址：{}",
+                    "Alive nodes file: {}",
+                    output.display()
                 )
+            );
+        }
+        Commands::Rename {
+            input,
+            output,
+            dry_run,
+        } => {
+            let tested = TestResult::load(&input)?;
+            let before = tested.proxies.clone();
+            let renamed = pipeline.rename(tested).await?;
+            if dry_run {
+                print!("{}", renamed.render_preview(&before));
+                return Ok(());
             }
-            Err(e) => {
-                error!("获取节点数失败，请检查 clash 日志文件和 subs/test/config.yaml 生成的节点是否正确, {}", e);
-                clash_meta.stop().unwrap();
-                continue;
+            renamed.save(&output)?;
+            info!(
+                "{}",
+                tr!(
+                    "重命名节点文件地址：{}",
+                    "Renamed nodes file: {}",
+                    output.display()
+                )
+            );
+        }
+        Commands::Export {
+            input,
+            format,
+            output,
+        } => {
+            let renamed = RenameResult::load(&input)?;
+            pipeline.export_as(&renamed, format, output).await?;
+        }
+        Commands::Check => {
+            check::check_subs(&pipeline.settings().sub_urls()).await;
+            pipeline::lint_templates()?;
+        }
+        Commands::Compare { a, b, output } => {
+            let comparison = pipeline.compare(&a, &b).await?;
+            let content = comparison.render_markdown();
+            match output {
+                Some(output) => {
+                    fs::write(&output, content)?;
+                    info!(
+                        "{}",
+                        tr!(
+                            "对比报告地址：{}",
+                            "Comparison report: {}",
+                            output.display()
+                        )
+                    );
+                }
+                None => print!("{}", content),
             }
         }
-
-        info!("开始测试连通性");
-        let delay_results = test_node_with_delay_config(&clash_meta, &config.connect_test).await;
-        let nodes = get_all_tested_nodes(&delay_results);
-        info!("连通性测试结果：{} 个节点可用", nodes.len());
-        if !nodes.is_empty() {
-            let cur_useful_proxies = proxies
-                .iter()
-                .filter(|&proxy| nodes.contains(&proxy.get_name().to_string()))
-                .cloned()
-                .collect::<Vec<Proxy>>();
-            info!("cur_useful_proxies len: {}", &cur_useful_proxies.len());
-            useful_proxies.extend(cur_useful_proxies);
-            info!("useful_proxies len: {}", useful_proxies.len());
-        }
-        clash_meta.stop().unwrap();
+        Commands::Probe { link, json } => {
+            let report = pipeline.probe(&link).await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report).unwrap());
+            } else {
+                print!("{}", report::render_node(&report));
+            }
+        }
+        Commands::Convert { .. } | Commands::Merge { .. } | Commands::Dedupe { .. } => {
+            execute_offline(&command).await.unwrap_or(Ok(()))?
+        }
+        Commands::Serve { .. } => unreachable!("serve 命令在 run_cli 中处理"),
     }
+    Ok(())
+}
 
-    if useful_proxies.is_empty() {
-        error!("当前无可用节点，请尝试更换订阅节点或重试");
-        return;
-    } else {
-        info!("当前总可用节点个数：{}", &useful_proxies.len());
+/// 启动 Web 服务，运行期间配置文件变化时自动重新加载，设置了定时间隔时同时定时执行筛选流程
+async fn serve(
+    args: &Cli,
+    settings: Settings,
+    port: u16,
+    interval: Option<u64>,
+    run_log: Option<Arc<RunLog>>,
+) -> Result<(), PipelineError> {
+    let paths = Settings::source_paths(args.config.as_deref(), args.profile.as_deref());
+    let reload_args = args.clone();
+    let watcher = SettingsWatcher::watch(settings, &paths, move || reload_args.load_settings())
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    let status = SharedStatus::default();
+    let release_args = args.clone();
+    let release: ReleaseHook =
+        Arc::new(move || tokio::runtime::Handle::current().block_on(reexport(&release_args)));
+    let server = server::start_server(watcher.settings(), status.clone(), port, release);
+    let schedule = schedule(args, &status, watcher.settings(), interval, run_log);
+    tokio::pin!(server);
+    // 定时任务只在收到退出信号时结束，此时等待 Web 服务退出；Web 服务启动失败时不必等待定时任务
+    tokio::select! {
+        result = &mut server => result?,
+        _ = schedule => server.await?,
     }
+    Ok(())
+}
 
-    if config.fast_mode {
-        SubManager::save_proxies_into_clash_file(
-            &useful_proxies,
-            release_clash_template_path.to_string(),
-            release_yaml_path.to_string_lossy().to_string(),
-        );
-        info!("release 文件地址：{}", release_yaml_path.to_string_lossy());
-    } else {
-        let mut clash_meta = ClashMeta::new(external_port, mixed_port);
-        SubManager::save_proxies_into_clash_file(
-            &useful_proxies,
-            test_clash_template_path.to_string(),
-            test_yaml_path.to_string(),
-        );
-
-        if let Err(e) = clash_meta.start().await {
-            error!("原神启动失败，第一次启动可能会下载 geo 相关的文件，重新启动即可，打开 logs/clash.log，查看具体错误原因，{}", e);
-            clash_meta.stop().unwrap();
-            return;
-        }
-        info!("当前节点个数为：{}", useful_proxies.len());
-
-        let nodes = &mut useful_proxies
-            .iter()
-            .map(|p| p.get_name().to_string())
-            .collect::<Vec<String>>();
-        let mut node_rename_map: HashMap<String, String> = HashMap::new();
-        if config.rename_node {
-            if nodes.is_empty() {
-                error!("当前无可用节点，请尝试更换订阅节点或重试");
-                clash_meta.stop().unwrap();
-                return;
-            }
-            let mut i = 0;
-            while i < nodes.len() {
-                let node = &nodes[i];
-                let ip_result = clash_meta
-                    .set_group_proxy(TEST_PROXY_GROUP_NAME, node)
-                    .await;
-                if ip_result.is_ok() {
-                    let ip_result = cgi_trace::get_ip(&clash_meta.proxy_url).await;
-                    if ip_result.is_ok() {
-                        let (proxy_ip, from) = ip_result.unwrap();
-                        info!("「{}」ip: {} from: {}", node, proxy_ip, from);
-                        let mut openai_is_ok = false;
-                        match website::openai_is_ok(&clash_meta.proxy_url).await {
-                            Ok(_) => {
-                                info!("「{}」 openai is ok", node);
-                                openai_is_ok = true;
-                            }
-                            Err(err) => {
-                                error!("「{}」 openai is not ok, {:#}", node, err)
-                            }
-                        }
-
-                        let mut claude_is_ok = false;
-                        match website::claude_is_ok(&clash_meta.proxy_url).await {
-                            Ok(_) => {
-                                info!("「{}」 claude is ok", node);
-                                claude_is_ok = true;
-                            }
-                            Err(err) => {
-                                error!("「{}」 claude is not ok, {:#}", node, err)
-                            }
-                        }
+/// 禁用或解除禁用节点后，按最新配置重新导出最近一次的重命名结果，返回导出的节点数
+async fn reexport(args: &Cli) -> Result<usize, String> {
+    let settings = args.load_settings().map_err(|e| e.to_string())?;
+    let renamed =
+        RenameResult::load(Path::new(pipeline::RENAMED_YAML_PATH)).map_err(|e| e.to_string())?;
+    let mut pipeline = Pipeline::new(settings);
+    if let Some(output) = &args.output {
+        pipeline = pipeline.with_release_path(output.clone());
+    }
+    pipeline.export(&renamed).await.map_err(|e| e.to_string())?;
+    Ok(pipeline.summary().exported)
+}
 
-                        let ip_detail_result =
-                            ip::get_ip_detail(&proxy_ip, &clash_meta.proxy_url).await;
-                        match ip_detail_result {
-                            Ok(ip_detail) => {
-                                info!("{:?}", ip_detail);
-                                if config.rename_node {
-                                    let mut new_name = config
-                                        .rename_pattern
-                                        .replace("${IP}", &proxy_ip.to_string())
-                                        .replace("${COUNTRYCODE}", &ip_detail.country_code)
-                                        .replace("${ISP}", &ip_detail.isp)
-                                        .replace("${CITY}", &ip_detail.city);
-                                    if openai_is_ok {
-                                        new_name += "_OpenAI";
-                                    }
-                                    if claude_is_ok {
-                                        new_name += "_Claude";
-                                    }
-                                    node_rename_map.insert(node.clone(), new_name);
-                                }
-                            }
-                            Err(e) => {
-                                error!("获取节点 {node} 的 IP 信息失败, {e}");
-                                if !openai_is_ok && !claude_is_ok {
-                                    nodes.remove(i);
-                                } else {
-                                    let mut new_name = proxy_ip.to_string();
-                                    if openai_is_ok {
-                                        new_name += "_OpenAI";
-                                    }
-                                    if claude_is_ok {
-                                        new_name += "_Claude";
-                                    }
-                                    node_rename_map.insert(node.clone(), new_name);
-                                }
-                            }
-                        }
-                    } else {
-                        let err_msg = ip_result.err().unwrap();
-                        error!("获取节点 {} 的 IP 失败, {}", node, err_msg);
-                        nodes.remove(i);
-                    }
-                } else {
-                    let err_msg = ip_result.err().unwrap();
-                    error!("设置节点 {} 失败, {}", node, err_msg);
+/// 定时任务检查间隔是否变化的周期
+const SCHEDULE_POLL: Duration = Duration::from_secs(5);
+
+/// serve 模式下的定时任务，每次执行前重新读取配置，收到 Ctrl+C 或 SIGTERM 时退出
+///
+/// 命令行的 interval 优先，否则使用当前配置中的 schedule_interval，配置重新加载后按新的间隔计算下一次执行时间，
+/// 间隔为空时暂停，之后重新设置时立即执行一次
+async fn schedule(
+    args: &Cli,
+    status: &SharedStatus,
+    settings: SharedSettings,
+    interval: Option<u64>,
+    run_log: Option<Arc<RunLog>>,
+) {
+    let mut current = None;
+    let mut last_run: Option<Instant> = None;
+    loop {
+        let minutes = interval
+            .or(settings.read().unwrap().schedule_interval)
+            .filter(|minutes| *minutes > 0);
+        if minutes != current {
+            match minutes {
+                Some(minutes) => info!(
+                    "{}",
+                    tr!(
+                        "每 {} 分钟执行一次筛选流程",
+                        "Running the pipeline every {} minutes",
+                        minutes
+                    )
+                ),
+                None => {
+                    info!(
+                        "{}",
+                        tr!("已停止定时执行筛选流程", "Scheduled runs stopped")
+                    );
+                    last_run = None;
                 }
-                i += 1;
             }
+            current = minutes;
         }
 
-        let mut release_proxies = useful_proxies
-            .into_iter()
-            .filter(|proxy: &Proxy| nodes.contains(&proxy.get_name().to_string()))
-            .collect::<Vec<Proxy>>();
-
-        if !node_rename_map.is_empty() {
-            for proxy in &mut release_proxies {
-                let name = if let Some(new_name) = node_rename_map.get(proxy.get_name()) {
-                    new_name.clone()
-                } else {
-                    proxy.get_name().to_string()
-                };
-                proxy.set_name(&name);
+        let now = Instant::now();
+        let due = minutes.map(|minutes| match last_run {
+            Some(last) => last + Duration::from_secs(minutes * 60),
+            None => now,
+        });
+        let wait = match due {
+            Some(due) if due <= now => None,
+            Some(due) => Some((due - now).min(SCHEDULE_POLL)),
+            None => Some(SCHEDULE_POLL),
+        };
+        if wait.is_none() {
+            last_run = Some(now);
+        }
+        let step = async {
+            match wait {
+                Some(wait) => tokio::time::sleep(wait).await,
+                None => scheduled_run(args, status, run_log.as_deref()).await,
             }
+        };
+        // 中途退出时 drop 流程，内核进程随之关闭
+        tokio::select! {
+            _ = step => {}
+            _ = server::shutdown_signal() => return,
         }
-
-        SubManager::rename_dup_proxies_name(&mut release_proxies);
-        SubManager::save_proxies_into_clash_file(
-            &release_proxies,
-            release_clash_template_path.to_string(),
-            release_yaml_path.to_string_lossy().to_string(),
-        );
-        info!("release 文件地址：{}", release_yaml_path.to_string_lossy());
-        clash_meta.stop().unwrap();
     }
 }
 
-#[allow(dead_code)]
-fn get_top_node(test_results: &Vec<HashMap<String, i64>>) -> (String, i64) {
-    let mut combined_data: HashMap<String, Vec<i64>> = HashMap::new();
-    for test in test_results {
-        for (node, latency) in test {
-            combined_data
-                .entry(node.clone())
-                .or_default()
-                .push(*latency);
+/// 每次执行使用单独的运行日志目录，内核日志编号随新的 Pipeline 从 1 开始
+async fn scheduled_run(args: &Cli, status: &SharedStatus, run_log: Option<&RunLog>) {
+    status.write().unwrap().running = true;
+    let log_dir = run_log.and_then(|run_log| start_run_log(run_log).ok());
+    let (exit_code, error, summary) = match args.load_settings() {
+        Ok(settings) => {
+            let mut pipeline = Pipeline::new(settings);
+            if let Some(output) = &args.output {
+                pipeline = pipeline.with_release_path(output.clone());
+            }
+            if let Some(dir) = log_dir {
+                pipeline = pipeline.with_log_dir(dir);
+            }
+            let result = pipeline.run().await.map(|_| ());
+            finish(result, pipeline.summary())
+        }
+        Err(e) => {
+            error!(
+                "{}",
+                tr!("配置文件读取失败", "Failed to load the config file")
+            );
+            for issue in e.issues() {
+                error!("  {}", issue);
+            }
+            (
+                EXIT_CONFIG_ERROR,
+                Some(e.to_string()),
+                RunSummary::default(),
+            )
         }
+    };
+    if let Some(run_log) = run_log {
+        run_log.finish();
     }
-    let node_stats: Vec<(String, i64)> = combined_data
-        .clone()
-        .into_iter()
-        .map(|(node, latencies)| {
-            let sum: i64 = latencies.iter().sum();
-            let count = latencies.len() as i64;
-            let mean = sum / count;
-            (node, mean)
-        })
-        .collect();
-    node_stats
-        .into_iter()
-        .min_by_key(|(_, mean)| *mean)
-        .unwrap()
+    let mut status = status.write().unwrap();
+    status.running = false;
+    status.last_run = Some(LastRun {
+        finished_at: Local::now(),
+        exit_code,
+        error,
+        summary,
+    });
 }
 
-async fn test_node_with_delay_config(
-    clash_meta: &ClashMeta,
-    delay_test_config: &DelayTestConfig,
-) -> Vec<HashMap<String, i64>> {
-    const ROUND: i32 = 5;
-    info!("测试配置：{:?}", delay_test_config);
-    let mut delay_results = vec![];
+/// 配置文件读取失败的退出码，其余见 PipelineError::exit_code
+const EXIT_CONFIG_ERROR: u8 = 3;
+const EXIT_ERROR: u8 = 1;
+/// 已有实例正在运行
+const EXIT_LOCKED: u8 = 7;
+
+/// 运行结束后输出的 JSON 摘要
+#[derive(Serialize)]
+struct Summary {
+    status: &'static str,
+    exit_code: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(flatten)]
+    run: RunSummary,
+    elapsed_secs: u64,
+}
 
-    // 预热 2 轮，DNS lookup
-    for _ in 0..2 {
-        let _ = clash_meta
-            .test_group(TEST_PROXY_GROUP_NAME, delay_test_config)
-            .await;
+/// 执行命令，返回退出码、错误信息及运行统计
+async fn run_cli(mut args: Cli, run_log: Option<Arc<RunLog>>) -> (u8, Option<String>, RunSummary) {
+    // 格式转换、合并、去重不依赖配置文件
+    if let Some(command) = &args.command {
+        if let Some(result) = execute_offline(command).await {
+            return match result {
+                Ok(_) => (0, None, RunSummary::default()),
+                Err(e) => {
+                    error!("{}", e);
+                    (e.exit_code(), Some(e.to_string()), RunSummary::default())
+                }
+            };
+        }
     }
 
-    for n in 0..ROUND {
-        info!("测试第 {} 轮", n + 1);
-        let result = clash_meta
-            .test_group(TEST_PROXY_GROUP_NAME, delay_test_config)
-            .await;
-
-        match result {
-            Ok(delay) => {
-                delay_results.push(delay.clone());
-                info!("有速度节点个数为：{}", delay.len())
+    let config = match args.load_settings() {
+        Ok(config) => config,
+        Err(e) => {
+            error!(
+                "{}",
+                tr!("配置文件读取失败", "Failed to load the config file")
+            );
+            for issue in e.issues() {
+                error!("  {}", issue);
             }
+            return (
+                EXIT_CONFIG_ERROR,
+                Some(e.to_string()),
+                RunSummary::default(),
+            );
+        }
+    };
+    // 命令行及环境变量均未指定语言时使用配置文件中的 lang
+    if args.lang.is_none() && Lang::from_env().is_none() {
+        i18n::set_lang(config.lang);
+    }
+    if let Some(dir) = &config.data_dir {
+        if let Err(e) = args
+            .pin_config_paths(dir)
+            .and_then(|_| pipeline::enter_data_dir(dir))
+        {
+            error!(
+                "{}",
+                tr!(
+                    "切换数据目录 {} 失败: {}",
+                    "Failed to enter data dir {}: {}",
+                    dir.display(),
+                    e
+                )
+            );
+            return (EXIT_ERROR, Some(e.to_string()), RunSummary::default());
+        }
+        info!("{}", tr!("数据目录：{}", "Data dir: {}", dir.display()));
+    }
+    // 创建订阅测试所用的目录结构
+    if let Err(e) = pipeline::create_folder() {
+        error!(
+            "{}",
+            tr!("创建目录失败: {}", "Failed to create directories: {}", e)
+        );
+        return (EXIT_ERROR, Some(e.to_string()), RunSummary::default());
+    }
+    // 检查订阅不启动内核，可以与其他实例同时运行
+    let _lock = if matches!(args.command, Some(Commands::Check)) {
+        None
+    } else {
+        match InstanceLock::acquire(&args.pid_file) {
+            Ok(lock) => Some(lock),
             Err(e) => {
-                info!("当前测试轮完全没有速度, {}", e)
+                error!("{}", e);
+                return (EXIT_LOCKED, Some(e.to_string()), RunSummary::default());
             }
         }
+    };
+    // 长期运行的模式需要定时向 systemd 发送心跳
+    if args.watch.is_some() || matches!(args.command, Some(Commands::Serve { .. })) {
+        daemon::spawn_watchdog();
     }
-    delay_results
-}
-
-/*
-获取所有已测速有过一次速度的节点
- */
-fn get_all_tested_nodes(test_results: &Vec<HashMap<String, i64>>) -> Vec<String> {
-    let mut keys_set = HashSet::new();
-    for result in test_results {
-        for key in result.keys() {
-            keys_set.insert(key.clone());
-        }
+    if let Some(Commands::Serve { port, interval }) = args.command {
+        return finish(
+            serve(&args, config, port, interval, run_log).await,
+            RunSummary::default(),
+        );
     }
-    keys_set.into_iter().collect()
-}
-
-/*
-获取测速稳定的节点
- */
-#[allow(dead_code)]
-fn get_stable_tested_nodes(test_results: &Vec<HashMap<String, i64>>) -> Vec<String> {
-    // 合并所有测试数据
-    let mut combined_data: HashMap<String, Vec<i64>> = HashMap::new();
-    for test in test_results {
-        for (node, latency) in test {
-            combined_data
-                .entry(node.clone())
-                .or_default()
-                .push(*latency);
+    let mut pipeline = Pipeline::new(config).with_progress(!args.no_progress && !args.tui);
+    if let Some(output) = args.output {
+        pipeline = pipeline.with_release_path(output);
+    }
+    if let Some(run_log) = &run_log {
+        match start_run_log(run_log) {
+            Ok(dir) => pipeline = pipeline.with_log_dir(dir),
+            Err(e) => return (EXIT_ERROR, Some(e.to_string()), RunSummary::default()),
         }
     }
-
-    // 计算每个节点的平均延迟和标准差
-    let mut node_stats: Vec<(String, f64)> = combined_data
-        .clone()
-        .into_iter()
-        .filter_map(|(node, latencies)| {
-            let sum: i64 = latencies.iter().sum();
-            let count = latencies.len();
-            if count <= combined_data.len() / 2 {
-                None
-            } else {
-                let mean = sum as f64 / count as f64;
-                Some((node, mean))
+    if let Some(minutes) = args.watch {
+        let interval = Duration::from_secs(minutes * 60);
+        return finish(pipeline.watch_release(interval).await, pipeline.summary());
+    }
+    let command = args.command.unwrap_or(Commands::Run);
+    let result = if args.tui && matches!(command, Commands::Run) {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        pipeline = pipeline.with_events(tx);
+        tui::run(&pipeline, rx).await.map(|exported| {
+            if exported.is_none() {
+                info!("{}", tr!("已放弃导出", "Export cancelled"))
             }
         })
-        .collect();
-
-    // 根据平均延迟对稳定的节点进行排序
-    node_stats.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
-
-    node_stats.into_iter().map(|(node, _)| node).collect()
+    } else {
+        execute(&pipeline, command).await
+    };
+    finish(result, pipeline.summary())
 }
 
-// 创建目录
-fn create_folder() {
-    let logs_path = "logs";
-    if !Path::new(logs_path).exists() {
-        fs::create_dir(logs_path).unwrap()
-    }
-
-    let subs_path = "subs";
-    if !Path::new(subs_path).exists() {
-        fs::create_dir(subs_path).unwrap();
-    }
-
-    let test_path = "subs/test";
-    if !Path::new(test_path).exists() {
-        fs::create_dir(test_path).unwrap();
+/// 在数据目录下创建本次运行的日志目录，之后的日志同时写入其中的 run.log
+fn start_run_log(run_log: &RunLog) -> io::Result<PathBuf> {
+    match run_log.start() {
+        Ok(dir) => {
+            info!(
+                "{}",
+                tr!(
+                    "本次运行的日志目录：{}",
+                    "Logs of this run: {}",
+                    dir.display()
+                )
+            );
+            Ok(dir)
+        }
+        Err(e) => {
+            error!(
+                "{}",
+                tr!(
+                    "创建运行日志目录失败: {}",
+                    "Failed to create the run log dir: {}",
+                    e
+                )
+            );
+            Err(e)
+        }
     }
+}
 
-    let release_path = "subs/release";
-    if !Path::new(release_path).exists() {
-        fs::create_dir(release_path).unwrap();
+fn finish(
+    result: Result<(), PipelineError>,
+    summary: RunSummary,
+) -> (u8, Option<String>, RunSummary) {
+    match result {
+        Ok(_) => (0, None, summary),
+        Err(e) => {
+            error!("{}", e);
+            (e.exit_code(), Some(e.to_string()), summary)
+        }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_get_stable_nodes() {
-        // [
-        //     { "免费节点2": 829 },
-        //     { "免费节点3": 815, "免费节点2": 945, "免费节点1": 838 },
-        //     { "免费节点4": 835, "免费节点1": 850, "免费节点3": 819 },
-        //     { "免费节点1": 844, "免费节点3": 830, "免费节点2": 856 },
-        //     { "免费节点3": 857, "免费节点4": 796, "2": 911, "免费节点4": 816 },
-        //     { "免费节点1": 895, "免费节点3": 863, "免费节点4": 829 },
-        //     { "免费节点3": 837, "免费节点1": 809, "免费节点4": 849 },
-        //     { "免费节点3": 849, "免费节点2": 904, "免费节点4": 892 }
-        // ];
-
-        // 假设这是从十组测试中收集的数据
-        let test_data = vec![
-            HashMap::from([
-                ("node1".to_string(), 100),
-                ("node2".to_string(), 200),
-                ("node3".to_string(), 150),
-            ]),
-            HashMap::from([
-                ("node1".to_string(), 110),
-                ("node2".to_string(), 190),
-                ("node3".to_string(), 160),
-            ]),
-            HashMap::from([("node1".to_string(), 120), ("node3".to_string(), 10000)]),
-        ];
-
-        println!("{:?}", get_top_node(&test_data));
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = Cli::parse();
+    i18n::set_lang(args.lang.or_else(Lang::from_env).unwrap_or_default());
+    if let Some(dir) = &args.workdir {
+        if let Err(e) = pipeline::enter_data_dir(dir) {
+            eprintln!(
+                "{}",
+                tr!(
+                    "切换工作目录 {} 失败: {}",
+                    "Failed to enter workdir {}: {}",
+                    dir.display(),
+                    e
+                )
+            );
+            return ExitCode::from(EXIT_ERROR);
+        }
     }
-
-    #[test]
-    fn test_rename_pattern() {
-        let count = "${COUNTRYCODE}_${CITY}_${ISP}".matches('_').count();
-        println!("{count}");
-        let count = "HongKong_Jordan_VertexConnectivityLLC62"
-            .matches('_')
-            .count();
-        println!("{count}")
+    let run_log = match args.init_tracing() {
+        Ok(run_log) => run_log,
+        Err(e) => {
+            eprintln!(
+                "{}",
+                tr!("日志文件打开失败: {}", "Failed to open the log file: {}", e)
+            );
+            return ExitCode::from(EXIT_ERROR);
+        }
+    };
+    let print_summary = args.summary;
+    let started = Instant::now();
+    let (exit_code, error, run) = run_cli(args, run_log).await;
+    if print_summary {
+        let summary = Summary {
+            status: if exit_code == 0 { "ok" } else { "error" },
+            exit_code,
+            error,
+            run,
+            elapsed_secs: started.elapsed().as_secs(),
+        };
+        println!("{}", serde_json::to_string(&summary).unwrap());
     }
+    ExitCode::from(exit_code)
 }
@@ -0,0 +1,107 @@
+use regex::Regex;
+
+/// 按 rename_pattern 识别之前运行输出的节点名称
+///
+/// 输出的名称由模板生成，之后依次加上通过的检测后缀（如 _OpenAI）、动态 IP 后缀及去重时的数字后缀，
+/// 整个名称符合这一结构才视为已重命名，名称本身含有下划线不会误判
+pub struct ManagedName {
+    regex: Regex,
+}
+
+impl ManagedName {
+    /// suffixes 为重命名时可能追加的后缀，如 _OpenAI、_动态
+    pub fn new(pattern: &str, suffixes: &[String]) -> Self {
+        let mut expr = String::from("^(?P<base>");
+        let mut rest = pattern;
+        while let Some(start) = rest.find("${") {
+            // 未闭合的占位符按原文匹配，rename_node 关闭时不校验模板
+            let Some(len) = rest[start..].find('}') else {
+                break;
+            };
+            expr.push_str(&regex::escape(&rest[..start]));
+            expr.push_str(placeholder_expr(&rest[start + 2..start + len]));
+            rest = &rest[start + len + 1..];
+        }
+        expr.push_str(&regex::escape(rest));
+        expr.push(')');
+        if !suffixes.is_empty() {
+            let suffixes = suffixes
+                .iter()
+                .map(|s| regex::escape(s))
+                .collect::<Vec<_>>()
+                .join("|");
+            expr.push_str(&format!("(?:{})*", suffixes));
+        }
+        expr.push_str(r"\d*$");
+        ManagedName {
+            regex: Regex::new(&expr).expect("rename pattern is escaped"),
+        }
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        self.regex.is_match(name)
+    }
+
+    /// 去掉检测、动态 IP 及去重后缀，返回模板生成的部分，不是之前输出的名称时返回 None
+    pub fn base<'a>(&self, name: &'a str) -> Option<&'a str> {
+        self.regex
+            .captures(name)
+            .and_then(|captures| captures.name("base"))
+            .map(|base| base.as_str())
+            .filter(|base| !base.is_empty())
+    }
+}
+
+/// 占位符可能替换成的内容，ISP、城市名称不固定，按任意内容匹配
+fn placeholder_expr(name: &str) -> &'static str {
+    match name {
+        "IP" => r"[0-9A-Fa-f.:]+",
+        "COUNTRYCODE" => r"[A-Za-z]{2}",
+        "AGE" => r"\d+d",
+        "CDN" => r"[A-Za-z]*",
+        _ => r".*?",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn suffixes() -> Vec<String> {
+        vec![
+            "_OpenAI".to_string(),
+            "_H2".to_string(),
+            "_动态".to_string(),
+        ]
+    }
+
+    #[test]
+    fn test_matches() {
+        let managed = ManagedName::new("${COUNTRYCODE}_${CITY}_${ISP}", &suffixes());
+        assert!(managed.matches("HK_HongKong_VertexConnectivityLLC"));
+        assert!(managed.matches("HK_Hong_Kong_Vertex_Connectivity_LLC_OpenAI2"));
+        // 下划线数量与模板相同但国家代码不符合
+        assert!(!managed.matches("香港_01_IPLC"));
+        assert!(!managed.matches("node_1"));
+
+        let managed = ManagedName::new("${IP}-${AGE}", &[]);
+        assert!(managed.matches("1.2.3.4-12d"));
+        assert!(managed.matches("2001:db8::1-3d"));
+        assert!(!managed.matches("my_home-server"));
+    }
+
+    #[test]
+    fn test_base() {
+        let managed = ManagedName::new("${COUNTRYCODE}_${ISP}", &suffixes());
+        assert_eq!(
+            managed.base("US_Cloudflare_OpenAI_H2_动态3"),
+            Some("US_Cloudflare")
+        );
+        assert_eq!(managed.base("US_Cloudflare"), Some("US_Cloudflare"));
+        assert_eq!(managed.base("美国 01"), None);
+
+        let managed = ManagedName::new("home_${COUNTRYCODE}", &suffixes());
+        assert_eq!(managed.base("home_JP_OpenAI"), Some("home_JP"));
+        assert_eq!(managed.base("home_JPN"), None);
+    }
+}
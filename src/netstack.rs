@@ -0,0 +1,103 @@
+use std::fmt;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
+use std::net::SocketAddr;
+use std::net::UdpSocket;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+/// 探测出站路由时 connect 的公共 DNS 地址，UDP connect 只查路由表，不发送数据
+const PROBE_V4: &str = "8.8.8.8:53";
+const PROBE_V6: &str = "[2001:4860:4860::8888]:53";
+
+/// 本机的网络协议栈，决定内核监听及本机访问内核时使用的地址
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NetStack {
+    Ipv4,
+    Ipv6,
+    Dual,
+}
+
+impl NetStack {
+    /// 按本机是否有 IPv4、IPv6 出站路由判断，都没有时按 IPv4 处理
+    pub fn detect() -> Self {
+        Self::from_routes(
+            has_route("0.0.0.0:0", PROBE_V4),
+            has_route("[::]:0", PROBE_V6),
+        )
+    }
+
+    fn from_routes(v4: bool, v6: bool) -> Self {
+        match (v4, v6) {
+            (true, true) => NetStack::Dual,
+            (false, true) => NetStack::Ipv6,
+            _ => NetStack::Ipv4,
+        }
+    }
+
+    pub fn has_ipv6(&self) -> bool {
+        matches!(self, NetStack::Ipv6 | NetStack::Dual)
+    }
+
+    /// 本机访问内核端口使用的回环地址
+    pub fn loopback(&self) -> IpAddr {
+        match self {
+            NetStack::Ipv6 => IpAddr::V6(Ipv6Addr::LOCALHOST),
+            _ => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        }
+    }
+
+    /// 监听所有网卡的地址，只有 IPv6 时监听 [::]
+    pub fn unspecified(&self) -> IpAddr {
+        match self {
+            NetStack::Ipv6 => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+            _ => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        }
+    }
+}
+
+impl fmt::Display for NetStack {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NetStack::Ipv4 => write!(f, "IPv4"),
+            NetStack::Ipv6 => write!(f, "IPv6"),
+            NetStack::Dual => write!(f, "IPv4 + IPv6"),
+        }
+    }
+}
+
+fn has_route(bind: &str, target: &str) -> bool {
+    UdpSocket::bind(bind)
+        .and_then(|socket| socket.connect(target))
+        .is_ok()
+}
+
+/// 拼接 ip 与端口，IPv6 地址带方括号，如 [::1]:7999
+pub fn host_port(ip: IpAddr, port: u16) -> String {
+    SocketAddr::new(ip, port).to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_from_routes() {
+        assert_eq!(NetStack::from_routes(true, true), NetStack::Dual);
+        assert_eq!(NetStack::from_routes(true, false), NetStack::Ipv4);
+        assert_eq!(NetStack::from_routes(false, true), NetStack::Ipv6);
+        assert_eq!(NetStack::from_routes(false, false), NetStack::Ipv4);
+    }
+
+    #[test]
+    fn test_addresses() {
+        assert_eq!(host_port(NetStack::Ipv6.loopback(), 7999), "[::1]:7999");
+        assert_eq!(host_port(NetStack::Dual.loopback(), 7999), "127.0.0.1:7999");
+        assert_eq!(host_port(NetStack::Ipv6.unspecified(), 1053), "[::]:1053");
+        assert!(NetStack::Dual.has_ipv6());
+        assert!(!NetStack::Ipv4.has_ipv6());
+    }
+}
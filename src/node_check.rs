@@ -0,0 +1,315 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use futures_util::future::BoxFuture;
+use futures_util::FutureExt;
+
+use crate::target;
+use crate::target::Target;
+use crate::website;
+use crate::website::WebsiteTest;
+
+/// 单项检测的结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheckResult {
+    Pass,
+    /// 检测通过并测得到目标的延迟，单位 ms，节点评分时代替连通性测试的延迟
+    Latency(i64),
+    /// 检测未通过及原因
+    Fail(String),
+}
+
+impl CheckResult {
+    pub fn is_pass(&self) -> bool {
+        matches!(self, CheckResult::Pass | CheckResult::Latency(_))
+    }
+}
+
+impl<E: fmt::Display> From<Result<(), E>> for CheckResult {
+    fn from(result: Result<(), E>) -> Self {
+        match result {
+            Ok(_) => CheckResult::Pass,
+            Err(e) => CheckResult::Fail(format!("{:#}", e)),
+        }
+    }
+}
+
+/// 节点检测阶段对每个节点执行的检测，检测通过时节点名称加上 _{name} 后缀
+///
+/// 自定义检测实现该 trait 后通过 `Pipeline::with_check` 加入，或注册到 `CheckRegistry`
+/// 后在配置文件的 checks 中启用：
+///
+/// ```
+/// use clash_butler::node_check::CheckResult;
+/// use clash_butler::node_check::NodeCheck;
+/// use futures_util::future::BoxFuture;
+/// use futures_util::FutureExt;
+///
+/// struct Intranet;
+///
+/// impl NodeCheck for Intranet {
+///     fn name(&self) -> &str {
+///         "Intranet"
+///     }
+///
+///     fn check<'a>(&'a self, _node: &'a str, proxy_url: &'a str) -> BoxFuture<'a, CheckResult> {
+///         async move {
+///             let client = reqwest::Client::builder()
+///                 .proxy(reqwest::Proxy::all(proxy_url).unwrap())
+///                 .build()
+///                 .unwrap();
+///             client.get("https://git.example.com").send().await.map(|_| ()).into()
+///         }
+///         .boxed()
+///     }
+/// }
+/// ```
+pub trait NodeCheck: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// 通过 proxy_url 访问目标，请求会经由正在检测的节点 node 发出
+    ///
+    /// 同一节点的各项检测同时执行，检测之间不应依赖执行顺序
+    fn check<'a>(&'a self, node: &'a str, proxy_url: &'a str) -> BoxFuture<'a, CheckResult>;
+
+    /// 节点检测阶段已查询到节点的出口 IP exit_ip，需要比对出口 IP 的检测覆盖该方法，默认调用 check
+    fn check_exit<'a>(
+        &'a self,
+        node: &'a str,
+        proxy_url: &'a str,
+        _exit_ip: IpAddr,
+    ) -> BoxFuture<'a, CheckResult> {
+        self.check(node, proxy_url)
+    }
+}
+
+/// 配置文件 websites 中的网站检测
+pub struct WebsiteCheck(pub WebsiteTest);
+
+impl NodeCheck for WebsiteCheck {
+    fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    fn check<'a>(&'a self, _node: &'a str, proxy_url: &'a str) -> BoxFuture<'a, CheckResult> {
+        async move { website::check(proxy_url, &self.0.test, None).await.into() }.boxed()
+    }
+
+    fn check_exit<'a>(
+        &'a self,
+        _node: &'a str,
+        proxy_url: &'a str,
+        exit_ip: IpAddr,
+    ) -> BoxFuture<'a, CheckResult> {
+        async move {
+            website::check(proxy_url, &self.0.test, Some(exit_ip))
+                .await
+                .into()
+        }
+        .boxed()
+    }
+}
+
+/// 配置文件 websites 中 tcp://、udp:// 目标的延迟检测，用于按到游戏服务器、区域接入点的延迟排序节点
+pub struct TargetCheck {
+    name: String,
+    target: Target,
+    payload: Vec<u8>,
+    timeout: Duration,
+}
+
+impl TargetCheck {
+    pub fn new(name: &str, target: Target, payload: Vec<u8>, timeout: Duration) -> Self {
+        TargetCheck {
+            name: name.to_string(),
+            target,
+            payload,
+            timeout,
+        }
+    }
+}
+
+impl NodeCheck for TargetCheck {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn check<'a>(&'a self, _node: &'a str, proxy_url: &'a str) -> BoxFuture<'a, CheckResult> {
+        async move {
+            match target::measure(proxy_url, &self.target, &self.payload, self.timeout).await {
+                Ok(rtt) => CheckResult::Latency(rtt),
+                Err(e) => CheckResult::Fail(format!("{}: {}", self.target, e)),
+            }
+        }
+        .boxed()
+    }
+}
+
+/// websites 中的一项，tcp://、udp:// 目标测延迟，其余按网站检测
+pub fn website_check(site: &WebsiteTest) -> Box<dyn NodeCheck> {
+    match Target::parse(&site.test.url) {
+        Ok(target) => {
+            // 配置已校验过 payload
+            let payload = site
+                .test
+                .payload
+                .as_deref()
+                .and_then(|p| hex::decode(p).ok())
+                .unwrap_or_default();
+            Box::new(TargetCheck::new(
+                &site.name,
+                target,
+                payload,
+                Duration::from_millis(site.test.timeout as u64),
+            ))
+        }
+        Err(_) => Box::new(WebsiteCheck(site.clone())),
+    }
+}
+
+/// YouTube Music 是否可用
+pub struct YoutubeMusicCheck;
+
+impl NodeCheck for YoutubeMusicCheck {
+    fn name(&self) -> &str {
+        "YouTubeMusic"
+    }
+
+    fn check<'a>(&'a self, _node: &'a str, proxy_url: &'a str) -> BoxFuture<'a, CheckResult> {
+        async move {
+            website::youtube_music_is_ok(proxy_url)
+                .await
+                .map(|_| ())
+                .into()
+        }
+        .boxed()
+    }
+}
+
+/// 能否完成 HTTP/2 请求
+pub struct Http2Check;
+
+impl NodeCheck for Http2Check {
+    fn name(&self) -> &str {
+        "H2"
+    }
+
+    fn check<'a>(&'a self, _node: &'a str, proxy_url: &'a str) -> BoxFuture<'a, CheckResult> {
+        async move { website::http2_is_ok(proxy_url).await.into() }.boxed()
+    }
+}
+
+/// 能否承载 HTTP/3，部分传输协议或出口不转发 UDP，QUIC 无法使用
+pub struct Http3Check;
+
+impl NodeCheck for Http3Check {
+    fn name(&self) -> &str {
+        "H3"
+    }
+
+    fn check<'a>(&'a self, _node: &'a str, proxy_url: &'a str) -> BoxFuture<'a, CheckResult> {
+        async move { website::http3_is_ok(proxy_url).await.into() }.boxed()
+    }
+}
+
+type CheckFactory = Box<dyn Fn() -> Box<dyn NodeCheck> + Send + Sync>;
+
+/// 按名称创建检测，配置文件的 checks 中列出的名称从这里查找
+pub struct CheckRegistry {
+    factories: BTreeMap<String, CheckFactory>,
+}
+
+impl Default for CheckRegistry {
+    /// 包含所有内置检测
+    fn default() -> Self {
+        let mut registry = CheckRegistry::empty();
+        registry.register("youtube_music", || Box::new(YoutubeMusicCheck));
+        registry.register("http2", || Box::new(Http2Check));
+        registry.register("http3", || Box::new(Http3Check));
+        registry
+    }
+}
+
+impl CheckRegistry {
+    pub fn empty() -> Self {
+        CheckRegistry {
+            factories: BTreeMap::new(),
+        }
+    }
+
+    /// 注册检测，名称相同时覆盖之前的检测
+    pub fn register<F>(&mut self, name: &str, factory: F)
+    where
+        F: Fn() -> Box<dyn NodeCheck> + Send + Sync + 'static,
+    {
+        self.factories.insert(name.to_string(), Box::new(factory));
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.factories.keys().map(String::as_str).collect()
+    }
+
+    /// 按名称创建检测，返回创建的检测及未注册的名称
+    pub fn build(&self, names: &[String]) -> (Vec<Box<dyn NodeCheck>>, Vec<String>) {
+        let mut checks = vec![];
+        let mut unknown = vec![];
+        for name in names {
+            match self.factories.get(name) {
+                Some(factory) => checks.push(factory()),
+                None => unknown.push(name.clone()),
+            }
+        }
+        (checks, unknown)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Always(bool);
+
+    impl NodeCheck for Always {
+        fn name(&self) -> &str {
+            "Always"
+        }
+
+        fn check<'a>(&'a self, _node: &'a str, _proxy_url: &'a str) -> BoxFuture<'a, CheckResult> {
+            let result = if self.0 {
+                CheckResult::Pass
+            } else {
+                CheckResult::Fail("always fails".to_string())
+            };
+            async move { result }.boxed()
+        }
+    }
+
+    #[test]
+    fn test_registry() {
+        let mut registry = CheckRegistry::default();
+        assert_eq!(registry.names(), vec!["http2", "http3", "youtube_music"]);
+        registry.register("always", || Box::new(Always(true)));
+
+        let (checks, unknown) = registry.build(&[
+            "always".to_string(),
+            "youtube_music".to_string(),
+            "netflix".to_string(),
+        ]);
+        let names = checks.iter().map(|c| c.name()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["Always", "YouTubeMusic"]);
+        assert_eq!(unknown, vec!["netflix"]);
+    }
+
+    #[tokio::test]
+    async fn test_check_result() {
+        assert!(Always(true).check("node", "").await.is_pass());
+        assert_eq!(
+            Always(false).check("node", "").await,
+            CheckResult::Fail("always fails".to_string())
+        );
+        let result: CheckResult = Err::<(), _>(anyhow::anyhow!("timeout")).into();
+        assert_eq!(result, CheckResult::Fail("timeout".to_string()));
+    }
+}
@@ -0,0 +1,98 @@
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::hash::Hasher;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::time::sleep;
+
+/// 请求节奏控制，节点很多时避免被订阅服务商及 IP 查询服务限流或封禁 IP，默认不限制
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PacingConfig {
+    /// 每分钟最多拉取的订阅数
+    #[serde(default)]
+    pub fetches_per_minute: Option<u32>,
+    /// 每秒最多的 IP 地理位置查询次数
+    #[serde(default)]
+    pub geo_lookups_per_second: Option<u32>,
+    /// 两次测速之间随机等待 0 ~ speed_test_jitter_ms 毫秒，0 表示不等待
+    #[serde(default)]
+    pub speed_test_jitter_ms: u64,
+}
+
+/// 按固定间隔放行请求，多个任务共用时依次排队
+#[derive(Debug)]
+pub struct RateLimiter {
+    interval: Option<Duration>,
+    next: Mutex<Option<Instant>>,
+}
+
+impl RateLimiter {
+    /// 每个 period 最多放行 limit 次，limit 为 None 或 0 时不限制
+    pub fn new(limit: Option<u32>, period: Duration) -> Self {
+        RateLimiter {
+            interval: limit.filter(|n| *n > 0).map(|n| period / n),
+            next: Mutex::new(None),
+        }
+    }
+
+    pub async fn acquire(&self) {
+        let Some(interval) = self.interval else {
+            return;
+        };
+        let wait = {
+            let mut next = self.next.lock().unwrap_or_else(|e| e.into_inner());
+            let now = Instant::now();
+            let start = next.map_or(now, |at| at.max(now));
+            *next = Some(start + interval);
+            start - now
+        };
+        if !wait.is_zero() {
+            sleep(wait).await;
+        }
+    }
+}
+
+/// 0 ~ max 之间的随机时长
+pub fn jitter(max: Duration) -> Duration {
+    if max.is_zero() {
+        return Duration::ZERO;
+    }
+    let random = RandomState::new().build_hasher().finish();
+    Duration::from_nanos(random % (max.as_nanos() as u64 + 1))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_rate_limiter() {
+        let unlimited = RateLimiter::new(Some(0), Duration::from_secs(60));
+        let start = Instant::now();
+        for _ in 0..10 {
+            unlimited.acquire().await;
+        }
+        assert!(start.elapsed() < Duration::from_millis(50));
+
+        // 每秒 20 次，即间隔 50ms，第一次立即放行
+        let limiter = RateLimiter::new(Some(20), Duration::from_secs(1));
+        let start = Instant::now();
+        for _ in 0..3 {
+            limiter.acquire().await;
+        }
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_jitter() {
+        assert_eq!(jitter(Duration::ZERO), Duration::ZERO);
+        let max = Duration::from_millis(500);
+        for _ in 0..100 {
+            assert!(jitter(max) <= max);
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use chrono::DateTime;
+use chrono::Local;
+use proxrs::protocol::Proxy;
+use proxrs::sub::write_atomic;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::history::fingerprint;
+use crate::report::NodeReport;
+
+/// 手动固定的节点，以 history::fingerprint 区分，之后的运行中即使测试失败或未被选中也保留在导出结果中，
+/// serve 模式下通过 /api/nodes 管理
+pub const PIN_LIST_PATH: &str = "subs/pinned.json";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PinnedNode {
+    /// 固定时的节点配置（Clash 格式），节点之后未通过测试时以此恢复
+    pub proxy: Value,
+    /// 固定时的测试结果，恢复的节点沿用该结果参与排序及分组
+    pub report: NodeReport,
+    pub pinned_at: DateTime<Local>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(transparent)]
+pub struct PinList {
+    nodes: BTreeMap<String, PinnedNode>,
+}
+
+impl PinList {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(PinList::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let content = serde_json::to_string_pretty(self).map_err(io::Error::from)?;
+        write_atomic(path, content.as_bytes())
+    }
+
+    /// 已固定时不更新保存的配置，返回是否新增
+    pub fn pin(
+        &mut self,
+        proxy: &Proxy,
+        report: &NodeReport,
+        now: DateTime<Local>,
+    ) -> io::Result<bool> {
+        let id = fingerprint(proxy);
+        if self.nodes.contains_key(&id) {
+            return Ok(false);
+        }
+        let value = proxy.to_clash_value().map_err(io::Error::from)?;
+        self.nodes.insert(
+            id,
+            PinnedNode {
+                proxy: value,
+                report: report.clone(),
+                pinned_at: now,
+            },
+        );
+        Ok(true)
+    }
+
+    /// 返回节点之前是否被固定
+    pub fn unpin(&mut self, fingerprint: &str) -> bool {
+        self.nodes.remove(fingerprint).is_some()
+    }
+
+    pub fn contains(&self, proxy: &Proxy) -> bool {
+        !self.nodes.is_empty() && self.nodes.contains_key(&fingerprint(proxy))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// 固定的节点及固定时的测试结果，配置无法解析的节点跳过
+    pub fn entries(&self) -> Vec<(Proxy, NodeReport)> {
+        self.nodes
+            .values()
+            .filter_map(|node| {
+                let proxy = Proxy::from_clash_value(&node.proxy).ok()?;
+                Some((proxy, node.report.clone()))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pin_list() {
+        let proxy =
+            Proxy::from_link("ss://YWVzLTI1Ni1nY206cGFzc3dvcmQ@1.2.3.4:8388#HK_01").unwrap();
+        let report = NodeReport::new("HK_01", Some(120));
+        let mut pins = PinList::default();
+        assert!(!pins.contains(&proxy));
+
+        let now = Local::now();
+        assert!(pins.pin(&proxy, &report, now).unwrap());
+        assert!(!pins.pin(&proxy, &report, now).unwrap());
+        assert!(pins.contains(&proxy));
+
+        let path = std::env::temp_dir().join(format!("pinned-{}.json", std::process::id()));
+        pins.save(&path).unwrap();
+        let loaded = PinList::load(&path).unwrap();
+        let entries = loaded.entries();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].0, proxy);
+        assert_eq!(entries[0].0.get_name(), "HK_01");
+        assert_eq!(entries[0].1.latency, Some(120));
+        fs::remove_file(&path).unwrap();
+
+        assert!(pins.unpin(&fingerprint(&proxy)));
+        assert!(!pins.unpin(&fingerprint(&proxy)));
+        assert!(!pins.contains(&proxy));
+    }
+}
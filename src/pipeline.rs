@@ -0,0 +1,5047 @@
+use std::cmp::Reverse;
+use std::collections::hash_map::Entry;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::net::IpAddr;
+use std::net::TcpListener;
+use std::net::UdpSocket;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering as AtomicOrdering;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::time::Duration;
+
+use chrono::Local;
+use futures_util::future::join_all;
+use futures_util::stream;
+use futures_util::StreamExt;
+use indicatif::ProgressBar;
+use proxrs::base64::base64encode;
+use proxrs::config::ClashConfig;
+use proxrs::config::ConfigError;
+use proxrs::config::ProxyGroup;
+use proxrs::config::ProxyGroupType;
+use proxrs::protocol::Proxy;
+use proxrs::sub::write_atomic;
+use proxrs::sub::DedupStats;
+use proxrs::sub::SubFetch;
+use proxrs::sub::SubManager;
+use rand::seq::SliceRandom;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_yaml::Mapping;
+use sha2::Digest;
+use sha2::Sha256;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::time::sleep;
+use tracing::debug;
+use tracing::error;
+use tracing::info;
+use tracing::warn;
+
+use crate::analytics::Analytics;
+use crate::analytics::RunCounts;
+use crate::analytics::ANALYTICS_DB_PATH;
+use crate::banlist::BanList;
+use crate::banlist::BAN_LIST_PATH;
+use crate::bundle;
+use crate::cdn;
+use crate::cgi_trace;
+use crate::clash::ClashMeta;
+use crate::clash::DelayTestConfig;
+use crate::clash::FailureReason;
+use crate::clash::InspectError;
+use crate::clash::InspectLane;
+use crate::clash::Traffic;
+use crate::compare;
+use crate::compare::Comparison;
+use crate::compare::SubStats;
+use crate::composition;
+use crate::composition::Composition;
+use crate::composition::ProtocolSurvival;
+use crate::core_log::CoreIssue;
+use crate::core_log::LogTail;
+use crate::daemon;
+use crate::history;
+use crate::history::NodeHistory;
+use crate::history::SourceRun;
+use crate::history::NODE_HISTORY_PATH;
+use crate::ip;
+use crate::naming::ManagedName;
+use crate::netstack::host_port;
+use crate::netstack::NetStack;
+use crate::node_check;
+use crate::node_check::CheckRegistry;
+use crate::node_check::CheckResult;
+use crate::node_check::NodeCheck;
+use crate::pacing;
+use crate::pacing::RateLimiter;
+use crate::pinlist::PinList;
+use crate::pinlist::PIN_LIST_PATH;
+use crate::progress;
+use crate::report;
+use crate::report::Baseline;
+use crate::report::NodeReport;
+use crate::report::ScoreConfig;
+use crate::script::NodeScript;
+use crate::script::ScriptDecision;
+use crate::server;
+use crate::settings::BalanceConfig;
+use crate::settings::EarlyStopConfig;
+use crate::settings::SampleMode;
+use crate::settings::Settings;
+use crate::settings::SubConfig;
+use crate::settings::TagRule;
+use crate::settings::TestDnsConfig;
+use crate::speedtest;
+use crate::speedtest::DownloadBudget;
+use crate::speedtest::SpeedTestBackend;
+use crate::template;
+use crate::template::TemplateKind;
+use crate::tr;
+use crate::upload;
+use crate::website::WebsiteTest;
+
+pub const TEST_PROXY_GROUP_NAME: &str = "PROXY";
+
+/// 拉取阶段保存的全部节点
+pub const TEST_ALL_YAML_PATH: &str = "subs/test/all.yaml";
+/// 测试阶段保存的可用节点，延迟数据保存在同名 json 文件中
+pub const TEST_USEFUL_YAML_PATH: &str = "subs/test/useful.yaml";
+/// 重命名阶段保存的节点，检测结果保存在同名 json 文件中
+pub const RENAMED_YAML_PATH: &str = "subs/release/renamed.yaml";
+
+/// release 文件的历史备份目录
+pub const RELEASE_HISTORY_PATH: &str = "subs/release/history";
+/// testing 与 stable 发布通道的输出目录
+pub const CHANNELS_PATH: &str = "subs/release/channels";
+
+const TEST_CLASH_TEMPLATE_PATH: &str = "conf/clash_test.yaml";
+const RELEASE_CLASH_TEMPLATE_PATH: &str = "conf/clash_release.yaml";
+/// 连通性测试轮数及预热轮数
+const ROUND: usize = 5;
+const WARMUP_ROUND: usize = 2;
+/// 不可用节点单独诊断的轮数及并发数
+const DIAGNOSE_ROUND: usize = 3;
+const DIAGNOSE_CONCURRENCY: usize = 32;
+/// 预先按国家筛选时同时解析的服务器地址数
+const RESOLVE_CONCURRENCY: usize = 64;
+/// 订阅对比时每个订阅测速的节点数
+const COMPARE_SPEED_NODES: usize = 10;
+/// 直连基准测量延迟的次数，取成功结果的平均值
+const BASELINE_ROUNDS: usize = 3;
+/// 查询出口 IP 失败后重试前的等待时间
+const TRACE_RETRY_DELAY: Duration = Duration::from_secs(1);
+/// 检测期间出口 IP 发生变化的节点名称后缀
+const DYNAMIC_IP_TAG: &str = "_动态";
+
+/// 内核实例的工作目录及端口，流水线执行时连通性测试与节点检测各使用一个内核
+struct CoreSlot {
+    name: &'static str,
+    dir: &'static str,
+    log_path: &'static str,
+    external_port: u16,
+    mixed_port: u16,
+    dns_port: u16,
+}
+
+/// 内核实际使用的端口及控制接口密钥
+struct CorePorts {
+    external: u16,
+    mixed: u16,
+    dns: u16,
+    secret: String,
+    /// 并发检测时额外检测通道的入站端口，见 lane_group
+    lanes: Vec<u16>,
+}
+
+/// 预设端口，生成内核配置时写入，模板中的端口不起作用
+const TEST_CORE: CoreSlot = CoreSlot {
+    name: "test",
+    dir: "subs/test",
+    log_path: "logs/clash.log",
+    external_port: 9091,
+    mixed_port: 7999,
+    dns_port: 1053,
+};
+const INSPECT_CORE: CoreSlot = CoreSlot {
+    name: "inspect",
+    dir: "subs/inspect",
+    log_path: "logs/clash-inspect.log",
+    external_port: 9092,
+    mixed_port: 7998,
+    dns_port: 1054,
+};
+
+impl CoreSlot {
+    /// 预设端口被占用或不可用时（如 Windows 中被 Hyper-V 保留的端口段）改用系统分配的空闲端口
+    ///
+    /// lanes 为 mixed-port 之外额外的检测通道数，通道端口由系统分配
+    fn ports(&self, stack: NetStack, lanes: usize) -> CorePorts {
+        let ip = stack.unspecified();
+        let external = free_port(ip, self.external_port, false);
+        CorePorts {
+            external,
+            mixed: free_port(ip, self.mixed_port, false),
+            dns: free_port(ip, self.dns_port, true),
+            secret: control_secret(external),
+            lanes: free_ports(ip, lanes),
+        }
+    }
+
+    /// 生成包含指定节点的内核配置，端口替换为该实例的端口
+    fn save_config(
+        &self,
+        proxies: &[Proxy],
+        ports: &CorePorts,
+        overrides: &CoreOverrides,
+    ) -> Result<(), PipelineError> {
+        let to_io_error = |e: ConfigError| std::io::Error::new(std::io::ErrorKind::InvalidData, e);
+        let mut config = ClashConfig::from_path(TEST_CLASH_TEMPLATE_PATH).map_err(to_io_error)?;
+        config.add_proxies(proxies);
+        config.prune_groups();
+        add_lanes(&mut config, &ports.lanes, overrides.stack);
+        merge_hosts(&mut config.extra, &overrides.hosts);
+        apply_dns(&mut config.extra, overrides.dns);
+        // 内核只监听分配的端口，模板中的其他入站端口可能与本机正在使用的 Clash 冲突
+        for key in LISTENER_KEYS {
+            config.extra.remove(key);
+        }
+        config.extra.insert("mixed-port".into(), ports.mixed.into());
+        config.extra.insert(
+            "external-controller".into(),
+            format!(":{}", ports.external).into(),
+        );
+        config
+            .extra
+            .insert("secret".into(), ports.secret.as_str().into());
+        if let Some(interface) = overrides.interface {
+            config
+                .extra
+                .insert("interface-name".into(), interface.into());
+        }
+        if let Some(mark) = overrides.routing_mark {
+            config.extra.insert("routing-mark".into(), mark.into());
+        }
+        // 有 IPv6 时确保内核解析并连接 IPv6 地址，只有 IPv6 的机器上 IPv4 节点需经 NAT64 访问
+        if overrides.stack.has_ipv6() {
+            config.extra.insert("ipv6".into(), true.into());
+        }
+        if let Some(dns) = config.extra.get_mut("dns").and_then(|v| v.as_mapping_mut()) {
+            if dns.contains_key("listen") {
+                let listen = host_port(overrides.stack.unspecified(), ports.dns);
+                dns.insert("listen".into(), listen.into());
+            }
+            if overrides.stack.has_ipv6() {
+                dns.insert("ipv6".into(), true.into());
+            }
+        }
+
+        fs::create_dir_all(self.dir)?;
+        if self.dir != TEST_CORE.dir {
+            copy_geodata(Path::new(TEST_CORE.dir), Path::new(self.dir));
+        }
+        let content = config.to_yaml().map_err(to_io_error)?;
+        write_atomic(Path::new(self.dir).join("config.yaml"), content.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// 生成测试内核配置时对模板的覆盖
+struct CoreOverrides<'a> {
+    /// 预先解析的节点域名，见 Settings::warm_dns
+    hosts: HashMap<String, IpAddr>,
+    dns: &'a TestDnsConfig,
+    stack: NetStack,
+    /// 出站网卡及 fwmark，见 Settings::interface
+    interface: Option<&'a str>,
+    routing_mark: Option<u32>,
+}
+
+/// 第 index 个额外检测通道的策略组名称，从 1 开始，第 0 路为 PROXY
+fn lane_group(index: usize) -> String {
+    format!("{}-{}", TEST_PROXY_GROUP_NAME, index)
+}
+
+/// 为每个额外的检测通道复制一份 PROXY 策略组，并添加直接交给该策略组的入站端口，
+/// 各通道可以同时选择不同的节点
+fn add_lanes(config: &mut ClashConfig, ports: &[u16], stack: NetStack) {
+    let Some(group) = config.group(TEST_PROXY_GROUP_NAME).cloned() else {
+        return;
+    };
+    let mut listeners = vec![];
+    for (i, port) in ports.iter().enumerate() {
+        let name = lane_group(i + 1);
+        let mut lane = group.clone();
+        lane.name = name.clone();
+        config.proxy_groups.push(lane);
+
+        let mut listener = Mapping::new();
+        listener.insert("name".into(), format!("inspect-{}", i + 1).into());
+        listener.insert("type".into(), "mixed".into());
+        listener.insert("port".into(), (*port).into());
+        listener.insert("listen".into(), stack.unspecified().to_string().into());
+        listener.insert("proxy".into(), name.into());
+        listeners.push(serde_yaml::Value::Mapping(listener));
+    }
+    if !listeners.is_empty() {
+        config.extra.insert("listeners".into(), listeners.into());
+    }
+}
+
+/// 用配置中的 test_dns 覆盖模板的 dns，未填写的项保持不变
+fn apply_dns(extra: &mut Mapping, dns: &TestDnsConfig) {
+    if dns.enable.is_none()
+        && dns.nameservers.is_empty()
+        && dns.fallback.is_empty()
+        && dns.enhanced_mode.is_none()
+    {
+        return;
+    }
+    let mut mapping = extra
+        .get("dns")
+        .and_then(|v| v.as_mapping())
+        .cloned()
+        .unwrap_or_default();
+    if let Some(enable) = dns.enable {
+        mapping.insert("enable".into(), enable.into());
+    }
+    if !dns.nameservers.is_empty() {
+        mapping.insert("nameserver".into(), dns.nameservers.clone().into());
+    }
+    if !dns.fallback.is_empty() {
+        mapping.insert("fallback".into(), dns.fallback.clone().into());
+    }
+    if let Some(mode) = dns.enhanced_mode {
+        mapping.insert("enhanced-mode".into(), mode.as_str().into());
+    }
+    extra.insert("dns".into(), mapping.into());
+}
+
+/// 将预先解析的域名加入配置的 hosts，模板中已有的条目优先
+fn merge_hosts(extra: &mut Mapping, hosts: &HashMap<String, IpAddr>) {
+    if hosts.is_empty() {
+        return;
+    }
+    let mut mapping = extra
+        .get("hosts")
+        .and_then(|v| v.as_mapping())
+        .cloned()
+        .unwrap_or_default();
+    let mut hosts = hosts.iter().collect::<Vec<_>>();
+    hosts.sort();
+    for (host, ip) in hosts {
+        let host = serde_yaml::Value::from(host.as_str());
+        if !mapping.contains_key(&host) {
+            mapping.insert(host, ip.to_string().into());
+        }
+    }
+    extra.insert("hosts".into(), mapping.into());
+}
+
+/// 配置文件 websites 中的网站检测及 checks 中启用的检测，未注册的检测名称会被忽略
+fn build_checks(settings: &Settings, registry: &CheckRegistry) -> Vec<Box<dyn NodeCheck>> {
+    let mut checks = settings
+        .websites
+        .iter()
+        .map(node_check::website_check)
+        .collect::<Vec<_>>();
+    let (enabled, unknown) = registry.build(&settings.checks);
+    if !unknown.is_empty() {
+        warn!(
+            "{}",
+            tr!(
+                "未知的节点检测 {:?}，可用的检测：{:?}",
+                "Unknown node checks {:?}, available: {:?}",
+                unknown,
+                registry.names()
+            )
+        );
+    }
+    checks.extend(enabled);
+    checks
+}
+
+/// 生成测试配置时移除的模板入站端口及控制接口配置
+const LISTENER_KEYS: [&str; 7] = [
+    "listeners",
+    "port",
+    "socks-port",
+    "redir-port",
+    "tproxy-port",
+    "external-controller-tls",
+    "external-controller-unix",
+];
+
+/// 每个内核实例的控制接口密钥，避免本机其他程序操作测试内核，也不依赖模板中的 secret
+fn control_secret(port: u16) -> String {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let seed = format!("{}-{}-{}", std::process::id(), port, nanos);
+    hex::encode(&Sha256::digest(seed.as_bytes())[..16])
+}
+
+/// 由系统分配 count 个不同的空闲 TCP 端口，分配失败时返回的端口数少于 count
+fn free_ports(ip: IpAddr, count: usize) -> Vec<u16> {
+    // 全部分配完之前保持监听，避免分到相同的端口
+    let listeners = (0..count)
+        .filter_map(|_| TcpListener::bind((ip, 0)).ok())
+        .collect::<Vec<_>>();
+    listeners
+        .iter()
+        .filter_map(|listener| listener.local_addr().ok())
+        .map(|addr| addr.port())
+        .collect()
+}
+
+/// 检查端口能否在 ip 上监听，不能时返回系统分配的空闲端口
+fn free_port(ip: IpAddr, preferred: u16, udp: bool) -> u16 {
+    let bind = |port: u16| -> std::io::Result<u16> {
+        let addr = if udp {
+            UdpSocket::bind((ip, port))?.local_addr()?
+        } else {
+            TcpListener::bind((ip, port))?.local_addr()?
+        };
+        Ok(addr.port())
+    };
+    if bind(preferred).is_ok() {
+        return preferred;
+    }
+    match bind(0) {
+        Ok(port) => {
+            warn!(
+                "{}",
+                tr!(
+                    "端口 {} 不可用，改用 {}",
+                    "Port {} is unavailable, using {} instead",
+                    preferred,
+                    port
+                )
+            );
+            port
+        }
+        Err(_) => preferred,
+    }
+}
+
+/// 复制已下载的 geoip、geosite 等数据文件，避免新内核启动时重新下载
+fn copy_geodata(from: &Path, to: &Path) {
+    let Ok(entries) = fs::read_dir(from) else {
+        return;
+    };
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        let is_geodata = path
+            .extension()
+            .is_some_and(|ext| ext == "dat" || ext == "mmdb");
+        let target = to.join(entry.file_name());
+        if is_geodata && !target.exists() {
+            if let Err(e) = fs::copy(&path, &target) {
+                warn!(
+                    "{}",
+                    tr!(
+                        "复制 {} 失败, {}",
+                        "Failed to copy {}, {}",
+                        path.display(),
+                        e
+                    )
+                );
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PipelineError {
+    /// 订阅中没有解析到任何节点
+    NoProxies,
+    /// 连通性测试后没有可用节点
+    NoAliveProxies,
+    /// Clash 内核启动失败
+    Core(String),
+    /// 读取上一阶段保存的结果失败
+    Load(String),
+    /// 内核配置模板有误，包含模板路径及问题
+    Template(Vec<String>),
+    /// probe 命令的分享链接无法解析或校验失败
+    InvalidLink(String),
+    Io(std::io::Error),
+}
+
+impl fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PipelineError::NoProxies => f.write_str(&tr!(
+                "当前无可用的待测试订阅连接，请修改配置文件添加订阅链接或确保当前网络通顺",
+                "No proxies to test, add subscription links to the config file or check the network"
+            )),
+            PipelineError::NoAliveProxies => f.write_str(&tr!(
+                "当前无可用节点，请尝试更换订阅节点或重试",
+                "No alive proxies, try other subscriptions or retry"
+            )),
+            PipelineError::Core(e) => f.write_str(&tr!(
+                "原神启动失败，第一次启动可能会下载 geo 相关的文件，重新启动即可，打开 logs/clash.log，查看具体错误原因，{}",
+                "Failed to start the clash core, the first start may download geo data files, just retry; see logs/clash.log for details, {}",
+                e
+            )),
+            PipelineError::Load(e) => f.write_str(&tr!(
+                "读取上一阶段结果失败，{}",
+                "Failed to load the result of the previous stage, {}",
+                e
+            )),
+            PipelineError::Template(issues) => f.write_str(&tr!(
+                "内核配置模板有误：\n{}",
+                "Invalid clash config templates:\n{}",
+                issues.join("\n")
+            )),
+            PipelineError::InvalidLink(e) => f.write_str(&tr!(
+                "无法解析分享链接，{}",
+                "Invalid share link, {}",
+                e
+            )),
+            PipelineError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for PipelineError {}
+
+impl PipelineError {
+    /// 进程退出码，便于脚本区分失败原因，2 为命令行参数错误，3 为配置文件错误
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            PipelineError::NoProxies | PipelineError::Load(_) => 4,
+            PipelineError::Core(_) => 5,
+            PipelineError::NoAliveProxies => 6,
+            PipelineError::Template(_) => 3,
+            PipelineError::InvalidLink(_) => 2,
+            PipelineError::Io(_) => 1,
+        }
+    }
+}
+
+impl From<std::io::Error> for PipelineError {
+    fn from(e: std::io::Error) -> Self {
+        PipelineError::Io(e)
+    }
+}
+
+/// countries 为空时不限制
+fn in_countries(report: &NodeReport, countries: &[String]) -> bool {
+    countries.is_empty()
+        || report
+            .country
+            .as_ref()
+            .is_some_and(|c| countries.iter().any(|x| x.eq_ignore_ascii_case(c)))
+}
+
+/// 节点是否满足标签规则中所有已填写的条件
+fn tag_matches(rule: &TagRule, proxy: &Proxy, report: &NodeReport) -> bool {
+    let contains_any = |value: Option<&str>, items: &[String]| {
+        items.is_empty()
+            || value.is_some_and(|value| {
+                let value = value.to_lowercase();
+                items.iter().any(|x| value.contains(&x.to_lowercase()))
+            })
+    };
+    rule.checks
+        .iter()
+        .all(|check| report.checks.contains(check))
+        && in_countries(report, &rule.countries)
+        && contains_any(report.isp.as_deref(), &rule.isps)
+        && (rule.servers.is_empty()
+            || rule
+                .servers
+                .iter()
+                .any(|server| server.eq_ignore_ascii_case(proxy.get_server())))
+        && contains_any(Some(proxy.get_name()), &rule.names)
+}
+
+/// 导出格式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// 基于 conf/clash_release.yaml 模板的 clash 配置
+    #[default]
+    Clash,
+    /// sing-box 的 outbounds 配置
+    Singbox,
+    /// 每行一个分享链接
+    Links,
+    /// base64 编码的分享链接，可直接作为订阅使用
+    Base64,
+}
+
+impl ExportFormat {
+    /// 根据文件扩展名推断格式，无法识别时返回 None
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "yaml" | "yml" => Some(ExportFormat::Clash),
+            "json" => Some(ExportFormat::Singbox),
+            "txt" => Some(ExportFormat::Links),
+            "b64" | "base64" => Some(ExportFormat::Base64),
+            _ => None,
+        }
+    }
+
+    fn default_file_name(&self) -> &'static str {
+        match self {
+            ExportFormat::Clash => "clash.yaml",
+            ExportFormat::Singbox => "singbox.json",
+            ExportFormat::Links => "links.txt",
+            ExportFormat::Base64 => "base64.txt",
+        }
+    }
+
+    /// 生成对应格式的文件内容
+    pub fn render(&self, proxies: &Vec<Proxy>) -> Result<String, PipelineError> {
+        self.render_with_groups(proxies, vec![])
+    }
+
+    /// 生成对应格式的文件内容，groups 为 Clash 配置中额外添加的策略组，会同时加入 PROXY 策略组
+    pub fn render_with_groups(
+        &self,
+        proxies: &Vec<Proxy>,
+        groups: Vec<ProxyGroup>,
+    ) -> Result<String, PipelineError> {
+        let content = match self {
+            ExportFormat::Clash if groups.is_empty() => SubManager::get_clash_config_content(
+                RELEASE_CLASH_TEMPLATE_PATH.to_string(),
+                proxies,
+            )?,
+            ExportFormat::Clash => {
+                let to_io_error =
+                    |e: ConfigError| std::io::Error::new(std::io::ErrorKind::InvalidData, e);
+                let mut config =
+                    ClashConfig::from_path(RELEASE_CLASH_TEMPLATE_PATH).map_err(to_io_error)?;
+                config.add_proxies(proxies);
+                if let Some(proxy_group) = config
+                    .proxy_groups
+                    .iter_mut()
+                    .find(|g| g.name == TEST_PROXY_GROUP_NAME)
+                {
+                    proxy_group
+                        .proxies
+                        .extend(groups.iter().map(|g| g.name.clone()));
+                }
+                config.proxy_groups.extend(groups);
+                config.prune_groups();
+                config.to_yaml().map_err(to_io_error)?
+            }
+            ExportFormat::Singbox => {
+                serde_json::to_string_pretty(&proxrs::singbox::to_config(proxies))
+                    .map_err(std::io::Error::from)?
+            }
+            ExportFormat::Links => links(proxies),
+            ExportFormat::Base64 => base64encode(links(proxies)),
+        };
+        Ok(content)
+    }
+}
+
+fn links(proxies: &[Proxy]) -> String {
+    proxies
+        .iter()
+        .map(|p| p.to_link())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// 拉取订阅的结果，已去重并重命名重复名称
+#[derive(Debug, Clone)]
+pub struct FetchResult {
+    pub proxies: Vec<Proxy>,
+}
+
+impl FetchResult {
+    pub fn load(path: &Path) -> Result<Self, PipelineError> {
+        Ok(FetchResult {
+            proxies: load_proxies(path)?,
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), PipelineError> {
+        save_proxies(&self.proxies, TEST_CLASH_TEMPLATE_PATH, path)
+    }
+}
+
+/// 连通性测试的结果
+#[derive(Debug, Clone, Default)]
+pub struct TestResult {
+    /// 至少有一轮测试通过的节点
+    pub proxies: Vec<Proxy>,
+    /// 每个节点每轮测试的延迟，单位 ms
+    pub latencies: HashMap<String, Vec<i64>>,
+    /// 每个节点按轮次排列的原始延迟，失败的轮次为 None，用于离线分析
+    pub rounds: HashMap<String, Vec<Option<i64>>>,
+}
+
+/// 连通性测试结果的附属文件，旧版本只保存 latencies
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum LatencySidecar {
+    Rounds {
+        latencies: HashMap<String, Vec<i64>>,
+        rounds: HashMap<String, Vec<Option<i64>>>,
+    },
+    Latencies(HashMap<String, Vec<i64>>),
+}
+
+impl TestResult {
+    fn extend(&mut self, other: TestResult) {
+        self.proxies.extend(other.proxies);
+        self.latencies.extend(other.latencies);
+        self.rounds.extend(other.rounds);
+    }
+
+    pub fn average_latency(&self, name: &str) -> Option<i64> {
+        report::average_latency(self.latencies.get(name))
+    }
+
+    /// 节点的延迟、抖动、丢包率及每轮的原始延迟
+    pub fn report(&self, name: &str) -> NodeReport {
+        // 提前结束时实际轮数少于 ROUND，旧的延迟数据没有每轮记录时按 ROUND 计算
+        let rounds = self
+            .rounds
+            .get(name)
+            .map(Vec::len)
+            .filter(|n| *n > 0)
+            .unwrap_or(ROUND);
+        let mut report = NodeReport::from_samples(name, self.latencies.get(name), rounds);
+        report.rounds = self.rounds.get(name).cloned().unwrap_or_default();
+        report
+    }
+
+    pub fn load(path: &Path) -> Result<Self, PipelineError> {
+        let proxies = load_proxies(path)?;
+        // 延迟数据缺失时不影响后续阶段
+        let (latencies, rounds) = match load_sidecar(path)? {
+            Some(LatencySidecar::Rounds { latencies, rounds }) => (latencies, rounds),
+            Some(LatencySidecar::Latencies(latencies)) => (latencies, HashMap::new()),
+            None => Default::default(),
+        };
+        Ok(TestResult {
+            proxies,
+            latencies,
+            rounds,
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), PipelineError> {
+        save_proxies(&self.proxies, TEST_CLASH_TEMPLATE_PATH, path)?;
+        save_sidecar(
+            path,
+            &LatencySidecar::Rounds {
+                latencies: self.latencies.clone(),
+                rounds: self.rounds.clone(),
+            },
+        )
+    }
+}
+
+/// 已测试节点的结果，alive 为是否通过连通性测试及延迟、成功率要求
+#[derive(Debug, Clone)]
+struct TestedNode {
+    alive: bool,
+    latencies: Vec<i64>,
+    rounds: Vec<Option<i64>>,
+}
+
+/// 已测试过的节点及结果，以节点指纹为键，名称不同的重复节点沿用同一结果
+#[derive(Default)]
+struct TestedCache {
+    nodes: Mutex<HashMap<Proxy, TestedNode>>,
+}
+
+impl TestedCache {
+    fn clear(&self) {
+        self.lock().clear();
+    }
+
+    /// 返回沿用的结果及需要测试的节点，沿用的结果使用 proxies 中的节点名称
+    fn reuse(&self, proxies: &[Proxy]) -> (TestResult, Vec<Proxy>) {
+        let nodes = self.lock();
+        let mut reused = TestResult::default();
+        let mut untested = vec![];
+        for proxy in proxies {
+            let Some(node) = nodes.get(proxy) else {
+                untested.push(proxy.clone());
+                continue;
+            };
+            let name = proxy.get_name();
+            if node.alive {
+                reused.proxies.push(proxy.clone());
+            }
+            if !node.latencies.is_empty() {
+                reused
+                    .latencies
+                    .insert(name.to_string(), node.latencies.clone());
+            }
+            if !node.rounds.is_empty() {
+                reused.rounds.insert(name.to_string(), node.rounds.clone());
+            }
+        }
+        (reused, untested)
+    }
+
+    fn remember(&self, proxies: &[Proxy], result: &TestResult) {
+        let alive = result
+            .proxies
+            .iter()
+            .map(Proxy::get_name)
+            .collect::<HashSet<_>>();
+        let mut nodes = self.lock();
+        for proxy in proxies {
+            let name = proxy.get_name();
+            nodes.entry(proxy.clone()).or_insert_with(|| TestedNode {
+                alive: alive.contains(name),
+                latencies: result.latencies.get(name).cloned().unwrap_or_default(),
+                rounds: result.rounds.get(name).cloned().unwrap_or_default(),
+            });
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<Proxy, TestedNode>> {
+        self.nodes.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// 重命名后的最终节点及其测试结果
+#[derive(Debug, Clone, Default)]
+pub struct RenameResult {
+    pub proxies: Vec<Proxy>,
+    pub reports: Vec<NodeReport>,
+}
+
+impl From<TestResult> for RenameResult {
+    /// 不进行重命名，仅保留连通性测试的结果
+    fn from(tested: TestResult) -> Self {
+        let reports = tested
+            .proxies
+            .iter()
+            .map(|p| tested.report(p.get_name()))
+            .collect();
+        RenameResult {
+            proxies: tested.proxies,
+            reports,
+        }
+    }
+}
+
+impl RenameResult {
+    /// 重命名相同名称的节点，同时更新对应测试结果中的名称
+    ///
+    /// 以节点本身（不含名称）关联测试结果，保证去重名、重新排序后仍能对应
+    fn rename_dup_proxies_name(&mut self) {
+        let mut reports_by_proxy = self
+            .proxies
+            .iter()
+            .cloned()
+            .zip(self.reports.drain(..))
+            .collect::<HashMap<Proxy, NodeReport>>();
+        SubManager::rename_dup_proxies_name(&mut self.proxies);
+        self.reports = self
+            .proxies
+            .iter()
+            .filter_map(|proxy| {
+                reports_by_proxy.remove(proxy).map(|mut node_report| {
+                    node_report.name = proxy.get_name().to_string();
+                    node_report
+                })
+            })
+            .collect();
+    }
+
+    /// 生成重命名前后名称对照的 Markdown 表格，附上命名用到的节点信息
+    ///
+    /// before 为重命名前的节点，以节点本身（不含名称）对应，检测后被丢弃的节点新名称为 -
+    pub fn render_preview(&self, before: &[Proxy]) -> String {
+        let renamed = self
+            .proxies
+            .iter()
+            .zip(&self.reports)
+            .collect::<HashMap<&Proxy, &NodeReport>>();
+        let mut content = String::new();
+        content.push_str("| 原名称 | 新名称 | 国家 | 城市 | ISP | CDN | 检测 | 首次出现 |\n");
+        content.push_str("|---|---|---|---|---|---|---|---|\n");
+        for proxy in before {
+            let report = renamed.get(proxy).copied();
+            let field = |f: fn(&NodeReport) -> Option<&String>| {
+                report::cell(report.and_then(f).map(String::as_str))
+            };
+            let first_seen = report
+                .and_then(|r| r.first_seen)
+                .map(|t| t.format("%Y-%m-%d").to_string());
+            content.push_str(&format!(
+                "| {} | {} | {} | {} | {} | {} | {} | {} |\n",
+                report::cell(Some(proxy.get_name())),
+                report::cell(report.map(|r| r.name.as_str())),
+                field(|r| r.country.as_ref()),
+                field(|r| r.city.as_ref()),
+                field(|r| r.isp.as_ref()),
+                field(|r| r.cdn.as_ref()),
+                report::cell(report.map(|r| r.checks.join(",")).as_deref()),
+                report::cell(first_seen.as_deref()),
+            ));
+        }
+        content
+    }
+
+    fn filter(&self, predicate: impl Fn(&Proxy, &NodeReport) -> bool) -> RenameResult {
+        let (proxies, reports) = self
+            .proxies
+            .iter()
+            .zip(&self.reports)
+            .filter(|(p, r)| predicate(p, r))
+            .map(|(p, r)| (p.clone(), r.clone()))
+            .unzip();
+        RenameResult { proxies, reports }
+    }
+
+    /// 追加 other 中尚未包含的节点（以节点本身区分，不含名称），同名节点重命名，返回追加的个数
+    fn merge(&mut self, other: &RenameResult) -> usize {
+        let existing = self.proxies.iter().cloned().collect::<HashSet<_>>();
+        let before = self.proxies.len();
+        for (proxy, report) in other.proxies.iter().zip(&other.reports) {
+            if !existing.contains(proxy) {
+                self.proxies.push(proxy.clone());
+                self.reports.push(report.clone());
+            }
+        }
+        let added = self.proxies.len() - before;
+        if added > 0 {
+            self.rename_dup_proxies_name();
+        }
+        added
+    }
+
+    /// 按节点所属订阅的 countries、max_nodes 筛选，并加上订阅的名称前缀
+    ///
+    /// origins 为节点到 sources 下标的映射，找不到来源的节点保持不变
+    pub fn select_by_source(
+        &self,
+        sources: &[SubConfig],
+        origins: &HashMap<Proxy, usize>,
+        score: &ScoreConfig,
+    ) -> RenameResult {
+        let mut selected = HashSet::new();
+        for (index, source) in sources.iter().enumerate() {
+            let part = self.filter(|p, _| origins.get(p) == Some(&index));
+            selected.extend(
+                part.select(&source.countries, source.max_nodes, score)
+                    .reports
+                    .into_iter()
+                    .map(|r| r.name),
+            );
+        }
+        let mut result = self.filter(|p, r| !origins.contains_key(p) || selected.contains(&r.name));
+        for (proxy, report) in result.proxies.iter_mut().zip(result.reports.iter_mut()) {
+            let prefix = origins
+                .get(proxy)
+                .and_then(|index| sources[*index].prefix.as_deref());
+            if let Some(prefix) = prefix {
+                let name = format!("{}{}", prefix, proxy.get_name());
+                proxy.set_name(&name);
+                report.name = name;
+            }
+        }
+        result
+    }
+
+    /// 剔除 ISP 名称包含 isps 中任一项的节点，不区分大小写，没有 ISP 信息的节点保留
+    pub fn exclude_isps(&self, isps: &[String]) -> RenameResult {
+        self.filter(|_, r| {
+            !r.isp.as_ref().is_some_and(|isp| {
+                let isp = isp.to_lowercase();
+                isps.iter().any(|x| isp.contains(&x.to_lowercase()))
+            })
+        })
+    }
+
+    /// 按规则重新计算所有节点的标签，规则顺序即标签顺序
+    pub fn apply_tags(&mut self, rules: &[TagRule]) {
+        for (proxy, report) in self.proxies.iter().zip(self.reports.iter_mut()) {
+            report.tags = rules
+                .iter()
+                .filter(|rule| tag_matches(rule, proxy, report))
+                .map(|rule| rule.name.clone())
+                .collect();
+        }
+    }
+
+    /// 只保留带有 tags 中任一标签的节点，tags 为空时不筛选
+    pub fn with_tags(&self, tags: &[String]) -> RenameResult {
+        if tags.is_empty() {
+            return self.clone();
+        }
+        self.filter(|_, r| r.tags.iter().any(|tag| tags.contains(tag)))
+    }
+
+    /// 为开启 group 的标签生成 url-test 策略组，名称为 Tag-{name}，没有节点的标签不生成
+    pub fn tag_groups(&self, rules: &[TagRule], test_url: &str) -> Vec<ProxyGroup> {
+        rules
+            .iter()
+            .filter(|rule| rule.group)
+            .filter_map(|rule| {
+                let proxies = self
+                    .proxies
+                    .iter()
+                    .zip(&self.reports)
+                    .filter(|(_, r)| r.tags.contains(&rule.name))
+                    .map(|(p, _)| p.get_name().to_string())
+                    .collect::<Vec<_>>();
+                if proxies.is_empty() {
+                    return None;
+                }
+                Some(ProxyGroup {
+                    name: format!("Tag-{}", rule.name),
+                    group_type: ProxyGroupType::UrlTest,
+                    proxies,
+                    providers: vec![],
+                    url: Some(test_url.to_string()),
+                    interval: Some(600),
+                    tolerance: Some(500),
+                    filter: None,
+                    extra: Default::default(),
+                })
+            })
+            .collect()
+    }
+
+    /// 按 ISP 生成 url-test 策略组，名称为 ISP-{isp}，没有 ISP 信息的节点不分组
+    pub fn isp_groups(&self, test_url: &str) -> Vec<ProxyGroup> {
+        let mut isps: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+        for (proxy, report) in self.proxies.iter().zip(&self.reports) {
+            if let Some(isp) = report.isp.as_deref().filter(|isp| !isp.is_empty()) {
+                isps.entry(isp)
+                    .or_default()
+                    .push(proxy.get_name().to_string());
+            }
+        }
+        isps.into_iter()
+            .map(|(isp, proxies)| ProxyGroup {
+                name: format!("ISP-{}", isp),
+                group_type: ProxyGroupType::UrlTest,
+                proxies,
+                providers: vec![],
+                url: Some(test_url.to_string()),
+                interval: Some(600),
+                tolerance: Some(500),
+                filter: None,
+                extra: Default::default(),
+            })
+            .collect()
+    }
+
+    /// 为配置了 top_n 的网站生成 Top-{name} url-test 策略组，包含通过该网站检测且评分最高的节点
+    pub fn top_groups(
+        &self,
+        websites: &[WebsiteTest],
+        score: &ScoreConfig,
+        test_url: &str,
+    ) -> Vec<ProxyGroup> {
+        websites
+            .iter()
+            .filter_map(|site| {
+                let top_n = site.top_n?;
+                let mut candidates = self
+                    .proxies
+                    .iter()
+                    .zip(&self.reports)
+                    .filter(|(_, r)| r.checks.contains(&site.name))
+                    .collect::<Vec<_>>();
+                if candidates.is_empty() {
+                    return None;
+                }
+                candidates.sort_by(|(_, a), (_, b)| score.compare(a, b));
+                Some(ProxyGroup {
+                    name: format!("Top-{}", site.name),
+                    group_type: ProxyGroupType::UrlTest,
+                    proxies: candidates
+                        .into_iter()
+                        .take(top_n)
+                        .map(|(p, _)| p.get_name().to_string())
+                        .collect(),
+                    providers: vec![],
+                    url: Some(test_url.to_string()),
+                    interval: Some(600),
+                    tolerance: Some(500),
+                    filter: None,
+                    extra: Default::default(),
+                })
+            })
+            .collect()
+    }
+
+    /// 只保留指定国家的节点，再按速度、延迟保留前 max_nodes 个，节点顺序不变
+    pub fn select(
+        &self,
+        countries: &[String],
+        max_nodes: Option<usize>,
+        score: &ScoreConfig,
+    ) -> RenameResult {
+        let mut candidates = self
+            .reports
+            .iter()
+            .filter(|r| in_countries(r, countries))
+            .collect::<Vec<_>>();
+        candidates.sort_by(|a, b| score.compare(a, b));
+        let selected = candidates
+            .into_iter()
+            .take(max_nodes.unwrap_or(usize::MAX))
+            .map(|r| r.name.as_str())
+            .collect::<HashSet<_>>();
+        self.filter(|_, r| selected.contains(r.name.as_str()))
+    }
+
+    /// 与 select 相同，但按 balance 限制单个国家的占比，并为每个国家保留最少的节点数
+    ///
+    /// 设置了 max_country_share 时，在满足占比的前提下尽量多保留节点，节点集中在少数国家时
+    /// 最终数量会少于 max_nodes。min_per_country 作用于 countries 中的国家，countries 为空时作用于所有国家，
+    /// 优先于占比限制。没有国家信息的节点视为同一个国家
+    pub fn balance(
+        &self,
+        countries: &[String],
+        max_nodes: Option<usize>,
+        balance: &BalanceConfig,
+        score: &ScoreConfig,
+    ) -> RenameResult {
+        let mut candidates = self
+            .reports
+            .iter()
+            .filter(|r| in_countries(r, countries))
+            .collect::<Vec<_>>();
+        candidates.sort_by(|a, b| score.compare(a, b));
+        let country = |r: &NodeReport| r.country.as_ref().map(|c| c.to_uppercase());
+
+        let mut available: HashMap<Option<String>, usize> = HashMap::new();
+        for report in &candidates {
+            *available.entry(country(report)).or_default() += 1;
+        }
+        let cap = |total: usize| match balance.max_country_share {
+            Some(share) => ((share * total as f64) as usize).max(1),
+            None => usize::MAX,
+        };
+        // 每个国家最多取 cap 个节点时能凑够的最大总数
+        let limit = max_nodes.unwrap_or(usize::MAX).min(candidates.len());
+        let total = (1..=limit)
+            .rev()
+            .find(|total| {
+                available
+                    .values()
+                    .map(|n| (*n).min(cap(*total)))
+                    .sum::<usize>()
+                    >= *total
+            })
+            .unwrap_or(0);
+        let cap = cap(total);
+
+        let mut selected = HashSet::new();
+        let mut counts: HashMap<Option<String>, usize> = HashMap::new();
+        for report in &candidates {
+            if selected.len() >= total {
+                break;
+            }
+            let key = country(report);
+            let required = key.as_ref().is_some_and(|c| {
+                countries.is_empty() || countries.iter().any(|x| x.eq_ignore_ascii_case(c))
+            });
+            let count = counts.entry(key).or_default();
+            if required && *count < balance.min_per_country {
+                *count += 1;
+                selected.insert(report.name.as_str());
+            }
+        }
+        for report in &candidates {
+            if selected.len() >= total {
+                break;
+            }
+            let count = counts.entry(country(report)).or_default();
+            if *count < cap && !selected.contains(report.name.as_str()) {
+                *count += 1;
+                selected.insert(report.name.as_str());
+            }
+        }
+        self.filter(|_, r| selected.contains(r.name.as_str()))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, PipelineError> {
+        let proxies = load_proxies(path)?;
+        let reports = match load_sidecar(path)? {
+            Some(reports) => reports,
+            None => proxies
+                .iter()
+                .map(|p| NodeReport::new(p.get_name(), None))
+                .collect(),
+        };
+        Ok(RenameResult { proxies, reports })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), PipelineError> {
+        save_proxies(&self.proxies, TEST_CLASH_TEMPLATE_PATH, path)?;
+        save_sidecar(path, &self.reports)
+    }
+}
+
+/// 本次运行的统计，各阶段执行后更新
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct RunSummary {
+    /// 拉取到的节点数
+    pub fetched: usize,
+    /// 解析订阅时跳过的无法解析或校验失败的节点数
+    pub skipped: usize,
+    /// 连通性测试通过的节点数
+    pub alive: usize,
+    /// 最终导出的节点数
+    pub exported: usize,
+    pub files: Vec<PathBuf>,
+    /// 节点检测阶段经由节点消耗的流量，连通性测试由内核直接发起，不计入
+    pub traffic: Traffic,
+    /// 所有轮次都失败的节点及每轮诊断的失败原因
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub failures: BTreeMap<String, Vec<FailureReason>>,
+    /// 各订阅的拉取结果，分阶段执行时为空
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<SourceStats>,
+    /// 待测试节点的协议、端口及 TLS 构成
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub composition: Option<Composition>,
+    /// 各协议在连通性测试中的存活情况
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub survival: Vec<ProtocolSurvival>,
+    /// 运行开始时直连测得的基准，开启 baseline 时才有
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub baseline: Option<Baseline>,
+}
+
+/// 单个订阅的拉取统计，用于判断各订阅实际贡献了多少节点
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct SourceStats {
+    /// 订阅地址，只保留域名
+    pub sub: String,
+    /// 订阅内容的字节数
+    pub bytes: usize,
+    /// 解析成功的节点数
+    pub parsed: usize,
+    /// 无法解析或校验失败而跳过的节点数
+    pub skipped: usize,
+    /// 与之前的订阅或本订阅中重复的节点数
+    pub duplicates: usize,
+    /// 超过 max_candidates 而没有参与测试的节点数
+    pub sampled_out: usize,
+    /// 无法获取订阅时的原因
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl SourceStats {
+    fn new(url: &str, fetch: &SubFetch) -> Self {
+        SourceStats {
+            sub: compare::sub_label(url),
+            bytes: fetch.bytes,
+            parsed: fetch.parsed.proxies.len(),
+            skipped: fetch.parsed.skipped(),
+            duplicates: 0,
+            sampled_out: 0,
+            error: fetch.error.clone(),
+        }
+    }
+
+    /// 去重后新增的节点数
+    pub fn contributed(&self) -> usize {
+        self.parsed - self.duplicates
+    }
+}
+
+/// 各订阅拉取结果的表格，便于在日志中对比
+fn render_sources(sources: &[SourceStats]) -> String {
+    let mut content = String::new();
+    content.push_str(&tr!(
+        "| 订阅 | 大小 | 解析 | 跳过 | 重复 | 新增 | 未抽中 |\n",
+        "| Subscription | Size | Parsed | Skipped | Duplicates | Contributed | Sampled out |\n"
+    ));
+    content.push_str("|---|---|---|---|---|---|---|\n");
+    for source in sources {
+        match &source.error {
+            Some(error) => content.push_str(&format!(
+                "| {} | {} | - | - | - | - | - |\n",
+                report::cell(Some(&source.sub)),
+                report::cell(Some(error))
+            )),
+            None => content.push_str(&format!(
+                "| {} | {} | {} | {} | {} | {} | {} |\n",
+                report::cell(Some(&source.sub)),
+                report::format_bytes(source.bytes as u64),
+                source.parsed,
+                source.skipped,
+                source.duplicates,
+                source.contributed(),
+                source.sampled_out
+            )),
+        }
+    }
+    content
+}
+
+/// 订阅去重后的节点超过 limit 时按 mode 抽取，返回抽中及未抽中的节点
+/// newest 时从未出现过的节点最优先，其余按首次出现时间从新到旧，相同时保持订阅中的顺序
+fn sample_candidates(
+    mut proxies: Vec<Proxy>,
+    limit: usize,
+    mode: SampleMode,
+    history: &NodeHistory,
+) -> (Vec<Proxy>, Vec<Proxy>) {
+    if proxies.len() <= limit {
+        return (proxies, vec![]);
+    }
+    match mode {
+        SampleMode::Random => proxies.shuffle(&mut rand::thread_rng()),
+        SampleMode::Newest => {
+            proxies.sort_by_key(|proxy| history.get(proxy).map(|node| Reverse(node.first_seen)))
+        }
+    }
+    let dropped = proxies.split_off(limit);
+    (proxies, dropped)
+}
+
+/// 各订阅历史表现的排名，按平均可用节点数从多到少排列
+fn render_source_health(sources: &[SubConfig], history: &NodeHistory) -> String {
+    let mut ranked = sources
+        .iter()
+        .filter_map(|source| history.source(&source.url).map(|health| (source, health)))
+        .collect::<Vec<_>>();
+    ranked.sort_by(|a, b| b.1.mean_alive().total_cmp(&a.1.mean_alive()));
+
+    let mut content = String::new();
+    content.push_str(&tr!(
+        "| 订阅 | 运行次数 | 拉取成功率 | 平均新增 | 平均可用 | 连续无可用 |\n",
+        "| Subscription | Runs | Fetch success | Avg contributed | Avg alive | Idle runs |\n"
+    ));
+    content.push_str("|---|---|---|---|---|---|\n");
+    for (source, health) in ranked {
+        content.push_str(&format!(
+            "| {} | {} | {:.0}% | {:.1} | {:.1} | {} |\n",
+            report::cell(Some(&compare::sub_label(&source.url))),
+            health.runs.len(),
+            health.success_rate() * 100.0,
+            health.mean_unique(),
+            health.mean_alive(),
+            health.idle_runs()
+        ));
+    }
+    content
+}
+
+/// 跳过连续 idle_runs 次运行没有可用节点的订阅，全部都需要跳过时仍然全部拉取
+fn active_sources(
+    sources: Vec<SubConfig>,
+    history: &NodeHistory,
+    idle_runs: Option<usize>,
+) -> Vec<SubConfig> {
+    let Some(idle_runs) = idle_runs.filter(|n| *n > 0) else {
+        return sources;
+    };
+    let (idle, active): (Vec<_>, Vec<_>) = sources.into_iter().partition(|source| {
+        history
+            .source(&source.url)
+            .is_some_and(|health| health.idle_runs() >= idle_runs)
+    });
+    if active.is_empty() && !idle.is_empty() {
+        warn!(
+            "{}",
+            tr!(
+                "所有订阅都已连续 {} 次运行没有可用节点，本次仍全部拉取",
+                "All subscriptions yielded no usable node in the last {} runs, fetching them anyway",
+                idle_runs
+            )
+        );
+        return idle;
+    }
+    for source in &idle {
+        warn!(
+            "{}",
+            tr!(
+                "订阅 {} 已连续 {} 次运行没有可用节点，跳过",
+                "Skipping subscription {}, no usable node in the last {} runs",
+                compare::sub_label(&source.url),
+                idle_runs
+            )
+        );
+    }
+    active
+}
+
+/// 导出的文件
+#[derive(Debug, Clone)]
+pub struct ExportResult {
+    pub files: Vec<PathBuf>,
+}
+
+/// 流程事件
+#[derive(Debug, Clone)]
+pub enum PipelineEvent {
+    /// 进入新的阶段
+    Stage(String),
+    /// 拉取到的所有节点名称
+    Fetched(Vec<String>),
+    /// 节点完成连通性测试，latency 为 None 表示不可用
+    Tested { name: String, latency: Option<i64> },
+    /// 节点完成检测，report 为 None 表示已剔除
+    Inspected {
+        name: String,
+        new_name: Option<String>,
+        report: Option<NodeReport>,
+    },
+}
+
+/// 单个节点的检测结果
+struct NodeInspection {
+    new_name: Option<String>,
+    report: Option<NodeReport>,
+}
+
+/// 节点筛选流程：拉取订阅 -> 连通性测试 -> 重命名 -> 导出
+///
+/// ```no_run
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// let pipeline = clash_butler::Pipeline::new(clash_butler::Settings::new()?);
+/// let fetched = pipeline.fetch().await?;
+/// let tested = pipeline.test(fetched.proxies).await?;
+/// let renamed = pipeline.rename(tested).await?;
+/// let exported = pipeline.export(&renamed).await?;
+/// println!("{:?}", exported.files);
+/// # Ok(())
+/// # }
+/// ```
+pub struct Pipeline {
+    settings: Settings,
+    /// 需要拉取的订阅，见 Settings::sources
+    sources: Vec<SubConfig>,
+    /// 拉取阶段记录的节点来源，值为 sources 的下标，分阶段执行时为空
+    origins: Mutex<HashMap<Proxy, usize>>,
+    release_path: PathBuf,
+    progress: bool,
+    events: Option<UnboundedSender<PipelineEvent>>,
+    summary: Mutex<RunSummary>,
+    /// 本次运行的日志目录，每次启动的内核日志单独保存在该目录下
+    log_dir: Option<PathBuf>,
+    core_runs: AtomicUsize,
+    /// 节点检测阶段执行的检测，检测通过时节点名称加上对应后缀
+    checks: Vec<Box<dyn NodeCheck>>,
+    /// 节点检测后决定是否保留节点及新名称的脚本
+    script: Option<NodeScript>,
+    /// 本次运行中已测试过的节点及结果，分组之间重复的节点不再测试
+    tested: TestedCache,
+    /// 订阅拉取及 IP 地理位置查询的限速，见 Settings::pacing
+    fetch_limiter: RateLimiter,
+    geo_limiter: RateLimiter,
+    /// 各节点首次出现及最近一次通过测试的时间，见 history::NODE_HISTORY_PATH
+    history: Mutex<NodeHistory>,
+    /// 开启 warm_dns 时预先解析的节点服务器域名，生成测试内核配置时写入 hosts
+    hosts: Mutex<HashMap<String, IpAddr>>,
+    /// 本机的网络协议栈，见 Settings::ip_stack
+    stack: NetStack,
+}
+
+impl Pipeline {
+    pub fn new(settings: Settings) -> Self {
+        let checks = build_checks(&settings, &CheckRegistry::default());
+        // 配置加载时已校验过脚本，这里失败只可能是文件在之后被修改
+        let script = settings
+            .script
+            .as_deref()
+            .and_then(|path| match NodeScript::load(path) {
+                Ok(script) => Some(script),
+                Err(e) => {
+                    error!(
+                        "{}",
+                        tr!(
+                            "加载脚本 {} 失败，跳过脚本, {}",
+                            "Failed to load script {}, skipped, {}",
+                            path.display(),
+                            e
+                        )
+                    );
+                    None
+                }
+            });
+        let release_path = env::current_dir()
+            .unwrap_or_default()
+            .join(ExportFormat::Clash.default_file_name());
+        let fetch_limiter =
+            RateLimiter::new(settings.pacing.fetches_per_minute, Duration::from_secs(60));
+        let geo_limiter = RateLimiter::new(
+            settings.pacing.geo_lookups_per_second,
+            Duration::from_secs(1),
+        );
+        let stack = settings.ip_stack.unwrap_or_else(NetStack::detect);
+        if stack != NetStack::Ipv4 {
+            info!(
+                "{}",
+                tr!("本机网络协议栈：{}", "Host network stack: {}", stack)
+            );
+        }
+        let history = NodeHistory::load(Path::new(NODE_HISTORY_PATH)).unwrap_or_else(|e| {
+            warn!(
+                "{}",
+                tr!(
+                    "读取节点历史记录失败，重新开始记录, {}",
+                    "Failed to load node history, starting over, {}",
+                    e
+                )
+            );
+            NodeHistory::default()
+        });
+        Pipeline {
+            sources: active_sources(settings.sources(), &history, settings.disable_idle_sources),
+            origins: Mutex::new(HashMap::new()),
+            settings,
+            release_path,
+            progress: false,
+            events: None,
+            summary: Mutex::new(RunSummary::default()),
+            tested: TestedCache::default(),
+            log_dir: None,
+            core_runs: AtomicUsize::new(0),
+            checks,
+            script,
+            fetch_limiter,
+            geo_limiter,
+            history: Mutex::new(history),
+            hosts: Mutex::new(HashMap::new()),
+            stack,
+        }
+    }
+
+    /// 当前的运行统计
+    pub fn summary(&self) -> RunSummary {
+        self.summary
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    fn update_summary(&self, update: impl FnOnce(&mut RunSummary)) {
+        update(&mut self.summary.lock().unwrap_or_else(|e| e.into_inner()));
+    }
+
+    /// 更新节点历史记录并保存，保存失败不影响筛选流程
+    fn update_history(&self, update: impl FnOnce(&mut NodeHistory)) {
+        let mut history = self.history.lock().unwrap_or_else(|e| e.into_inner());
+        update(&mut history);
+        if let Err(e) = history.save(Path::new(NODE_HISTORY_PATH)) {
+            warn!(
+                "{}",
+                tr!(
+                    "保存节点历史记录失败, {}",
+                    "Failed to save node history, {}",
+                    e
+                )
+            );
+        }
+    }
+
+    /// 节点首次出现至今的天数，没有记录时为 0
+    fn node_age(&self, proxy: &Proxy) -> i64 {
+        self.history
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(proxy)
+            .map_or(0, |node| node.age_days(Local::now()))
+    }
+
+    /// 节点所属订阅的名称，未配置 label 时为订阅地址的域名，分阶段执行时没有来源记录
+    fn source_label(&self, proxy: &Proxy) -> Option<String> {
+        let origins = self.origins.lock().unwrap_or_else(|e| e.into_inner());
+        let source = self.sources.get(*origins.get(proxy)?)?;
+        Some(
+            source
+                .label
+                .clone()
+                .unwrap_or_else(|| compare::sub_label(&source.url)),
+        )
+    }
+
+    /// 在测试结果中填入节点首次出现的时间、所属订阅及标签
+    fn annotate(&self, mut renamed: RenameResult) -> RenameResult {
+        let history = self.history.lock().unwrap_or_else(|e| e.into_inner());
+        for (proxy, report) in renamed.proxies.iter().zip(renamed.reports.iter_mut()) {
+            report.first_seen = history.get(proxy).map(|node| node.first_seen);
+            if report.source.is_none() {
+                report.source = self.source_label(proxy);
+            }
+        }
+        renamed.apply_tags(&self.settings.tags);
+        renamed
+    }
+
+    /// 识别之前运行按 rename_pattern 输出的节点名称，后缀包括所有检测名称及动态 IP 标记
+    fn managed_name(&self) -> ManagedName {
+        let suffixes = self
+            .checks
+            .iter()
+            .map(|check| format!("_{}", check.name()))
+            .chain([DYNAMIC_IP_TAG.to_string()])
+            .collect::<Vec<_>>();
+        ManagedName::new(&self.settings.rename_pattern, &suffixes)
+    }
+
+    /// 订阅流程事件，用于实时展示各节点的测试结果
+    pub fn with_events(mut self, events: UnboundedSender<PipelineEvent>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    fn emit(&self, event: PipelineEvent) {
+        if let Some(events) = &self.events {
+            // 接收方已关闭时忽略
+            let _ = events.send(event);
+        }
+    }
+
+    /// 在终端显示各阶段的进度条，默认关闭
+    pub fn with_progress(mut self, progress: bool) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// 修改 release 文件的保存路径，默认为当前目录下的 clash.yaml
+    pub fn with_release_path(mut self, release_path: PathBuf) -> Self {
+        self.release_path = release_path;
+        self
+    }
+
+    /// 使用自定义的检测注册表重新创建配置文件 checks 中启用的检测
+    pub fn with_check_registry(mut self, registry: &CheckRegistry) -> Self {
+        self.checks = build_checks(&self.settings, registry);
+        self
+    }
+
+    /// 追加一项节点检测，无需在配置文件中启用
+    pub fn with_check(mut self, check: Box<dyn NodeCheck>) -> Self {
+        self.checks.push(check);
+        self
+    }
+
+    /// 将内核日志保存到指定目录，文件名为 clash-{test|inspect}-{n}.log
+    pub fn with_log_dir(mut self, log_dir: PathBuf) -> Self {
+        self.log_dir = Some(log_dir);
+        self
+    }
+
+    fn clash_meta(&self, core: &CoreSlot, ports: &CorePorts) -> ClashMeta {
+        let log_path = match &self.log_dir {
+            Some(dir) => {
+                let n = self.core_runs.fetch_add(1, AtomicOrdering::Relaxed) + 1;
+                dir.join(format!("clash-{}-{}.log", core.name, n))
+                    .to_string_lossy()
+                    .to_string()
+            }
+            None => core.log_path.to_string(),
+        };
+        let clash_meta = ClashMeta::new(ports.external.into(), ports.mixed.into())
+            .with_dir(core.dir, &log_path)
+            .with_host(self.stack.loopback())
+            .with_secret(&ports.secret);
+        match &self.settings.core_path {
+            Some(path) => clash_meta.with_core_path(path),
+            None => clash_meta,
+        }
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// 完整执行一次筛选流程
+    pub async fn run(&self) -> Result<ExportResult, PipelineError> {
+        let renamed = self.prepare().await?;
+        // serve 模式下 /api/nodes 及订阅接口读取最近一次的重命名结果，保存失败不影响导出
+        if let Err(e) = renamed.save(Path::new(RENAMED_YAML_PATH)) {
+            warn!(
+                "{}",
+                tr!(
+                    "保存重命名结果 {} 失败: {}",
+                    "Failed to save the renamed result {}: {}",
+                    RENAMED_YAML_PATH,
+                    e
+                )
+            );
+        }
+        let exported = self.export(&renamed).await?;
+        if self.settings.channels.enabled {
+            self.export_channels(&renamed).await;
+        }
+        if self.settings.analytics {
+            self.record_analytics(&renamed);
+        }
+        Ok(exported)
+    }
+
+    /// 将本次运行累加到按天汇总的统计中，失败时只记录日志
+    fn record_analytics(&self, renamed: &RenameResult) {
+        let summary = self.summary();
+        let counts = RunCounts {
+            fetched: summary.fetched,
+            alive: summary.alive,
+            exported: summary.exported,
+        };
+        let now = Local::now();
+        let result = Analytics::open(Path::new(ANALYTICS_DB_PATH)).and_then(|mut analytics| {
+            analytics.record_run(now.date_naive(), counts, &renamed.reports)?;
+            Ok(analytics.record_rounds(now, &renamed.reports)?)
+        });
+        if let Err(e) = result {
+            warn!(
+                "{}",
+                tr!(
+                    "保存统计汇总失败, {}",
+                    "Failed to save analytics rollups, {}",
+                    e
+                )
+            );
+        }
+    }
+
+    /// 导出 testing 及 stable 两个发布通道，testing 包含本次运行的所有可用节点，
+    /// stable 只包含连续多次运行都可用的节点，通道为空时保留原文件
+    async fn export_channels(&self, renamed: &RenameResult) {
+        let stable_runs = self.settings.channels.stable_runs;
+        let stable = {
+            let history = self.history.lock().unwrap_or_else(|e| e.into_inner());
+            renamed.filter(|p, _| {
+                history
+                    .get(p)
+                    .is_some_and(|node| node.passed_runs >= stable_runs)
+            })
+        };
+        let mut files = vec![];
+        for (name, proxies) in [("testing", &renamed.proxies), ("stable", &stable.proxies)] {
+            if proxies.is_empty() {
+                warn!(
+                    "{}",
+                    tr!(
+                        "{} 通道没有节点，保留原文件",
+                        "Channel {} has no nodes, keeping the previous file",
+                        name
+                    )
+                );
+                continue;
+            }
+            let path = Path::new(CHANNELS_PATH).join(format!("{}.yaml", name));
+            let result = ExportFormat::Clash.render(proxies).and_then(|content| {
+                fs::create_dir_all(CHANNELS_PATH)?;
+                Ok(write_atomic(&path, content.as_bytes())?)
+            });
+            match result {
+                Ok(()) => {
+                    info!(
+                        "{}",
+                        tr!(
+                            "{} 通道文件地址：{}，节点数：{}",
+                            "Channel {} file: {}, nodes: {}",
+                            name,
+                            path.display(),
+                            proxies.len()
+                        )
+                    );
+                    files.push(path);
+                }
+                Err(e) => warn!(
+                    "{}",
+                    tr!(
+                        "导出 {} 通道失败, {}",
+                        "Failed to export channel {}, {}",
+                        name,
+                        e
+                    )
+                ),
+            }
+        }
+        upload::upload_files(&self.settings.uploads, &files).await;
+    }
+
+    /// 执行导出前的所有阶段，快速模式下不进行重命名
+    pub async fn prepare(&self) -> Result<RenameResult, PipelineError> {
+        if self.settings.baseline {
+            self.measure_baseline().await;
+        }
+        let fetched = self.fetch().await?;
+        if self.settings.fast_mode {
+            let tested = self.test(fetched.proxies).await?;
+            return Ok(self.annotate(RenameResult::from(tested)));
+        }
+        if self.settings.overlap_stages
+            && self.settings.rename_node
+            && fetched.proxies.len() > self.settings.test_group_size
+        {
+            return self.test_and_rename(fetched.proxies).await;
+        }
+        let tested = self.test(fetched.proxies).await?;
+        self.rename(tested).await
+    }
+
+    /// 不经过代理直连测量延迟及速度，作为节点结果的参照，失败的项为空
+    pub async fn measure_baseline(&self) -> Baseline {
+        let connect_test = &self.settings.connect_test;
+        let timeout = Duration::from_millis(connect_test.timeout as u64);
+        let mut latencies = vec![];
+        for _ in 0..BASELINE_ROUNDS {
+            match speedtest::test_download(
+                &connect_test.url,
+                timeout,
+                None,
+                DownloadBudget::default(),
+            )
+            .await
+            {
+                Ok((elapsed, _, _)) => latencies.push(elapsed.as_millis() as i64),
+                Err(e) => warn!(
+                    "{}",
+                    tr!(
+                        "直连访问 {} 失败, {}",
+                        "Direct request to {} failed, {}",
+                        connect_test.url,
+                        e
+                    )
+                ),
+            }
+        }
+
+        let speed_test = &self.settings.speed_test;
+        let speed = if speed_test.enabled {
+            match self.download_speed(None).await {
+                Ok(bandwidth) => Some(bandwidth),
+                Err(e) => {
+                    warn!(
+                        "{}",
+                        tr!("直连测速失败, {}", "Direct speed test failed, {}", e)
+                    );
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let baseline = Baseline {
+            latency: report::average_latency(Some(&latencies)),
+            speed,
+        };
+        info!(
+            "{}",
+            tr!(
+                "本机直连基准：{}",
+                "Direct connection baseline: {}",
+                baseline.describe()
+            )
+        );
+        self.update_summary(|s| s.baseline = Some(baseline.clone()));
+        baseline
+    }
+
+    /// 拉取所有订阅中的节点
+    pub async fn fetch(&self) -> Result<FetchResult, PipelineError> {
+        let stage = tr!("拉取订阅", "Fetching subscriptions");
+        self.emit(PipelineEvent::Stage(stage.clone()));
+        let bar = progress::new_bar(self.progress, self.sources.len() as u64, &stage);
+        let mut proxies = Vec::new();
+        // 重复节点以先拉取的订阅为准，与去重时保留的节点一致
+        let mut origins = HashMap::new();
+        let mut source_stats = vec![];
+        for (index, source) in self.sources.iter().enumerate() {
+            self.fetch_limiter.acquire().await;
+            let fetch = SubManager::fetch_proxies(&source.url).await;
+            let mut stats = SourceStats::new(&source.url, &fetch);
+            let mut fresh = vec![];
+            for proxy in fetch.parsed.proxies {
+                match origins.entry(proxy.clone()) {
+                    Entry::Occupied(_) => stats.duplicates += 1,
+                    Entry::Vacant(entry) => {
+                        entry.insert(index);
+                        fresh.push(proxy);
+                    }
+                }
+            }
+            // 去重后再限制数量，与之前订阅重复的节点不占用名额
+            if let Some(limit) = source.max_candidates {
+                let history = self.history.lock().unwrap_or_else(|e| e.into_inner());
+                let (kept, dropped) = sample_candidates(fresh, limit, source.sample, &history);
+                // 未抽中的节点可以由之后的订阅提供
+                for proxy in &dropped {
+                    origins.remove(proxy);
+                }
+                stats.sampled_out = dropped.len();
+                fresh = kept;
+            }
+            proxies.extend(fresh);
+            source_stats.push(stats);
+            bar.inc(1);
+        }
+        bar.finish_and_clear();
+        info!(
+            "{}",
+            tr!(
+                "各订阅拉取结果：\n{}",
+                "Subscription fetch results:\n{}",
+                render_sources(&source_stats)
+            )
+        );
+        self.update_summary(|s| {
+            s.skipped = source_stats.iter().map(|stats| stats.skipped).sum();
+            s.sources = source_stats;
+        });
+        *self.origins.lock().unwrap_or_else(|e| e.into_inner()) = origins;
+        if !proxies.is_empty() {
+            proxies = SubManager::exclude_dup_proxies(proxies);
+            SubManager::rename_dup_proxies_name(&mut proxies);
+        }
+        let bans = self.ban_list();
+        if !bans.is_empty() {
+            let total = proxies.len();
+            proxies.retain(|proxy| !bans.contains(proxy));
+            if proxies.len() < total {
+                info!(
+                    "{}",
+                    tr!(
+                        "剔除 {} 个已禁用的节点",
+                        "Excluded {} banned nodes",
+                        total - proxies.len()
+                    )
+                );
+            }
+        }
+        if self.settings.insecure_default {
+            proxies.iter_mut().for_each(Proxy::apply_insecure_default);
+        }
+        if self.settings.prefilter_countries {
+            proxies = self.prefilter_countries(proxies).await;
+        }
+        info!(
+            "{}",
+            tr!("待测速节点个数：{}", "Nodes to test: {}", proxies.len())
+        );
+        let composition = Composition::of(&proxies);
+        info!(
+            "{}",
+            tr!("节点构成：\n{}", "Node composition:\n{}", composition)
+        );
+        self.update_summary(|s| {
+            s.fetched = proxies.len();
+            s.composition = Some(composition);
+        });
+        self.update_history(|h| h.seen(&proxies, Local::now()));
+        self.emit(PipelineEvent::Fetched(
+            proxies.iter().map(|p| p.get_name().to_string()).collect(),
+        ));
+        if proxies.is_empty() {
+            return Err(PipelineError::NoProxies);
+        }
+
+        // 全部保存一下节点信息
+        SubManager::save_proxies_into_clash_file(
+            &proxies,
+            TEST_CLASH_TEMPLATE_PATH.to_string(),
+            TEST_ALL_YAML_PATH.to_string(),
+        );
+        Ok(FetchResult { proxies })
+    }
+
+    /// 按服务器地址所在国家剔除不符合全局及所属订阅 countries 的节点，解析或查询失败的节点保留
+    async fn prefilter_countries(&self, proxies: Vec<Proxy>) -> Vec<Proxy> {
+        // 每个节点需要满足的国家列表，先取出来避免跨 await 持有锁
+        let allowed = {
+            let origins = self.origins.lock().unwrap_or_else(|e| e.into_inner());
+            proxies
+                .iter()
+                .map(|proxy| {
+                    let mut lists = vec![];
+                    if !self.settings.countries.is_empty() {
+                        lists.push(self.settings.countries.clone());
+                    }
+                    if let Some(source) = origins.get(proxy).and_then(|i| self.sources.get(*i)) {
+                        if !source.countries.is_empty() {
+                            lists.push(source.countries.clone());
+                        }
+                    }
+                    lists
+                })
+                .collect::<Vec<_>>()
+        };
+        if allowed.iter().all(Vec::is_empty) {
+            return proxies;
+        }
+
+        let servers = stream::iter(&proxies)
+            .map(|proxy| ip::resolve(proxy.get_server()))
+            .buffered(RESOLVE_CONCURRENCY)
+            .collect::<Vec<_>>()
+            .await;
+        let ips = servers
+            .iter()
+            .flatten()
+            .copied()
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+        let countries = ip::get_countries(&ips, &self.geo_limiter).await;
+
+        let total = proxies.len();
+        let proxies = proxies
+            .into_iter()
+            .zip(servers)
+            .zip(allowed)
+            .filter(|((_, server), lists)| {
+                let country = server.and_then(|ip| countries.get(&ip));
+                entry_country_allowed(country.map(String::as_str), lists)
+            })
+            .map(|((proxy, _), _)| proxy)
+            .collect::<Vec<_>>();
+        info!(
+            "{}",
+            tr!(
+                "按服务器地址所在国家预先剔除 {} 个节点",
+                "{} nodes removed by the country of their server address",
+                total - proxies.len()
+            )
+        );
+        proxies
+    }
+
+    /// 并发解析所有节点服务器的域名，解析失败的域名仍交由内核解析
+    async fn warm_dns(&self, proxies: &[Proxy]) {
+        let domains = proxies
+            .iter()
+            .map(|proxy| proxy.get_server().to_string())
+            .filter(|server| server.trim_matches(['[', ']']).parse::<IpAddr>().is_err())
+            .collect::<HashSet<_>>();
+        let resolved = stream::iter(domains.iter())
+            .map(|domain| async move { (domain.clone(), ip::resolve(domain).await) })
+            .buffer_unordered(RESOLVE_CONCURRENCY)
+            .filter_map(|(domain, ip)| async move { ip.map(|ip| (domain, ip)) })
+            .collect::<HashMap<_, _>>()
+            .await;
+        info!(
+            "{}",
+            tr!(
+                "预先解析节点域名 {} 个，失败 {} 个",
+                "Pre-resolved {} node domains, {} failed",
+                resolved.len(),
+                domains.len() - resolved.len()
+            )
+        );
+        *self.hosts.lock().unwrap_or_else(|e| e.into_inner()) = resolved;
+    }
+
+    /// 测试这些节点时对内核配置模板的覆盖，hosts 只包含这些节点的域名
+    fn core_overrides(&self, proxies: &[Proxy]) -> CoreOverrides<'_> {
+        let hosts = self.hosts.lock().unwrap_or_else(|e| e.into_inner());
+        let hosts = proxies
+            .iter()
+            .filter_map(|proxy| {
+                let server = proxy.get_server();
+                hosts.get(server).map(|ip| (server.to_string(), *ip))
+            })
+            .collect();
+        CoreOverrides {
+            hosts,
+            dns: &self.settings.test_dns,
+            stack: self.stack,
+            interface: self.settings.interface.as_deref(),
+            routing_mark: self.settings.routing_mark,
+        }
+    }
+
+    /// 分组启动内核测试节点连通性
+    pub async fn test(&self, proxies: Vec<Proxy>) -> Result<TestResult, PipelineError> {
+        lint_templates()?;
+        self.reset_tested();
+        if self.settings.warm_dns {
+            self.warm_dns(&proxies).await;
+        }
+        let groups = self.split_groups(&proxies);
+        let bar = self.test_bar(groups.len());
+        let mut tested = TestResult::default();
+        for (index, group) in groups.iter().enumerate() {
+            tested.extend(
+                self.test_group(&TEST_CORE, index, groups.len(), group, &bar)
+                    .await,
+            );
+        }
+        bar.finish_and_clear();
+        self.finish_test(&proxies, tested)
+    }
+
+    /// 流水线执行连通性测试与节点检测，检测上一组可用节点的同时测试下一组
+    async fn test_and_rename(&self, proxies: Vec<Proxy>) -> Result<RenameResult, PipelineError> {
+        lint_templates()?;
+        self.reset_tested();
+        if self.settings.warm_dns {
+            self.warm_dns(&proxies).await;
+        }
+        let groups = self.split_groups(&proxies);
+        let bar = self.test_bar(groups.len());
+        // 检测较慢时测试最多领先一组，避免积压过多待检测的节点
+        let (tx, mut rx) = tokio::sync::mpsc::channel::<TestResult>(1);
+        let testing = async move {
+            let mut tested = TestResult::default();
+            for (index, group) in groups.iter().enumerate() {
+                let chunk = self
+                    .test_group(&TEST_CORE, index, groups.len(), group, &bar)
+                    .await;
+                if !chunk.proxies.is_empty() && tx.send(chunk.clone()).await.is_err() {
+                    break;
+                }
+                tested.extend(chunk);
+            }
+            bar.finish_and_clear();
+            tested
+        };
+        let inspecting = async {
+            let mut parts = vec![];
+            let mut failed = vec![];
+            while let Some(chunk) = rx.recv().await {
+                // 两个内核同时运行时不显示检测进度条，避免与测试进度条互相覆盖
+                match self.rename_with(chunk.clone(), &INSPECT_CORE, false).await {
+                    Ok(part) => parts.push(part),
+                    Err(e) => {
+                        warn!(
+                            "{}",
+                            tr!(
+                                "检测节点失败，将在连通性测试结束后重试, {}",
+                                "Failed to inspect nodes, will retry after the connectivity test, {}",
+                                e
+                            )
+                        );
+                        failed.push(chunk);
+                    }
+                }
+            }
+            (parts, failed)
+        };
+        let (tested, (mut parts, failed)) = tokio::join!(testing, inspecting);
+        self.finish_test(&proxies, tested)?;
+        for chunk in failed {
+            parts.push(self.rename_with(chunk, &TEST_CORE, self.progress).await?);
+        }
+
+        let mut renamed = RenameResult::default();
+        for part in parts {
+            renamed.proxies.extend(part.proxies);
+            renamed.reports.extend(part.reports);
+        }
+        renamed.rename_dup_proxies_name();
+        Ok(renamed)
+    }
+
+    /// 只测试单个分享链接中的节点，依次执行连通性测试及节点检测（忽略 rename_node，始终检测），
+    /// 不拉取订阅也不记录节点历史，节点未通过检测时返回连通性测试的结果
+    pub async fn probe(&self, link: &str) -> Result<NodeReport, PipelineError> {
+        lint_templates()?;
+        let proxy =
+            Proxy::from_link(link.trim()).map_err(|e| PipelineError::InvalidLink(e.to_string()))?;
+        proxy
+            .validate()
+            .map_err(|e| PipelineError::InvalidLink(e.to_string()))?;
+        let name = proxy.get_name().to_string();
+        info!("{}", tr!("测试节点「{}」", "Probing 「{}」", name));
+
+        let proxies = vec![proxy];
+        let bar = self.test_bar(1);
+        let tested = self.test_group(&TEST_CORE, 0, 1, &proxies, &bar).await;
+        bar.finish_and_clear();
+        if tested.proxies.is_empty() {
+            return Err(PipelineError::NoAliveProxies);
+        }
+        let fallback = tested.report(&name);
+        let inspected = self.inspect(tested, &TEST_CORE, self.progress).await?;
+        match inspected.reports.into_iter().next() {
+            Some(report) => Ok(report),
+            None => {
+                warn!(
+                    "{}",
+                    tr!(
+                        "「{}」未通过节点检测，只输出连通性测试结果",
+                        "「{}」 failed the inspection, showing the connectivity test result only",
+                        name
+                    )
+                );
+                Ok(fallback)
+            }
+        }
+    }
+
+    /// 使用相同的测试配置依次测试两个订阅，对比可用率、延迟及速度
+    pub async fn compare(&self, a: &str, b: &str) -> Result<Comparison, PipelineError> {
+        lint_templates()?;
+        Ok(Comparison {
+            a: self.sub_stats(a).await?,
+            b: self.sub_stats(b).await?,
+        })
+    }
+
+    async fn sub_stats(&self, sub: &str) -> Result<SubStats, PipelineError> {
+        self.fetch_limiter.acquire().await;
+        let mut proxies = SubManager::get_proxies_from_url(sub.to_string()).await;
+        info!(
+            "{}",
+            tr!("{} 节点个数：{}", "{} nodes: {}", sub, proxies.len())
+        );
+        if proxies.is_empty() {
+            warn!(
+                "{}",
+                tr!("{} 没有解析到节点", "No proxies parsed from {}", sub)
+            );
+            return Ok(SubStats::new(sub, 0, 0, &[], &[]));
+        }
+        SubManager::rename_dup_proxies_name(&mut proxies);
+
+        // 订阅内分在不同分组的重复节点沿用结果，但不能沿用另一个订阅中同一节点的结果
+        self.reset_tested();
+        let groups = self.split_groups(&proxies);
+        let bar = self.test_bar(groups.len());
+        let mut tested = TestResult::default();
+        for (index, group) in groups.iter().enumerate() {
+            tested.extend(
+                self.test_group(&TEST_CORE, index, groups.len(), group, &bar)
+                    .await,
+            );
+        }
+        bar.finish_and_clear();
+
+        let latencies = tested
+            .proxies
+            .iter()
+            .filter_map(|proxy| tested.average_latency(proxy.get_name()))
+            .collect::<Vec<_>>();
+        let speeds = if self.settings.speed_test.enabled {
+            self.sample_speeds(&tested).await?
+        } else {
+            vec![]
+        };
+        Ok(SubStats::new(
+            sub,
+            proxies.len(),
+            tested.proxies.len(),
+            &latencies,
+            &speeds,
+        ))
+    }
+
+    /// 对延迟最低的 COMPARE_SPEED_NODES 个节点测速，两个订阅的测速节点数相同
+    async fn sample_speeds(&self, tested: &TestResult) -> Result<Vec<f64>, PipelineError> {
+        let candidates = speed_candidates(tested, Some(COMPARE_SPEED_NODES));
+        let proxies = tested
+            .proxies
+            .iter()
+            .filter(|proxy| candidates.contains(proxy.get_name()))
+            .cloned()
+            .collect::<Vec<_>>();
+        if proxies.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let ports = TEST_CORE.ports(self.stack, 0);
+        TEST_CORE.save_config(&proxies, &ports, &self.core_overrides(&proxies))?;
+        let mut clash_meta = self.clash_meta(&TEST_CORE, &ports);
+        if let Err(e) = clash_meta.start().await {
+            stop_core(clash_meta);
+            return Err(PipelineError::Core(e.to_string()));
+        }
+        if let Err(e) = clash_meta.wait_for_group(TEST_PROXY_GROUP_NAME).await {
+            stop_core(clash_meta);
+            return Err(PipelineError::Core(e.to_string()));
+        }
+
+        let mut speeds = vec![];
+        for proxy in &proxies {
+            let node = proxy.get_name();
+            if !matches!(
+                clash_meta
+                    .set_group_proxy(TEST_PROXY_GROUP_NAME, node)
+                    .await,
+                Ok(true)
+            ) {
+                continue;
+            }
+            self.speed_test_jitter().await;
+            match self.measure_speed(&clash_meta, &clash_meta.proxy_url).await {
+                Ok(bandwidth) => {
+                    info!(
+                        "{}",
+                        tr!(
+                            "「{}」 速度：{:.2} KB/s",
+                            "「{}」 speed: {:.2} KB/s",
+                            node,
+                            bandwidth
+                        )
+                    );
+                    speeds.push(bandwidth);
+                }
+                Err(err) => error!(
+                    "{}",
+                    tr!(
+                        "「{}」 测速失败, {}",
+                        "「{}」 speed test failed, {}",
+                        node,
+                        err
+                    )
+                ),
+            }
+        }
+        stop_core(clash_meta);
+        Ok(speeds)
+    }
+
+    /// 经 proxy_url 测速，返回下载速度，单位 KB/s，sample_traffic 时从 clash_meta 的流量接口采样
+    async fn measure_speed(
+        &self,
+        clash_meta: &ClashMeta,
+        proxy_url: &str,
+    ) -> Result<f64, Box<dyn std::error::Error>> {
+        let speed_test = &self.settings.speed_test;
+        if speed_test.sample_traffic && speed_test.backend == SpeedTestBackend::Http {
+            return speedtest::sample_download(
+                &speed_test.url,
+                Duration::from_millis(speed_test.timeout as u64),
+                proxy_url,
+                &clash_meta.api_client().build()?,
+                &format!("{}/traffic", clash_meta.external_url),
+                speed_test.budget(),
+            )
+            .await;
+        }
+        self.download_speed(Some(proxy_url)).await
+    }
+
+    /// 按 speed_test.backend 下载测速，proxy_url 为 None 时直连，返回速度，单位 KB/s
+    async fn download_speed(
+        &self,
+        proxy_url: Option<&str>,
+    ) -> Result<f64, Box<dyn std::error::Error>> {
+        let speed_test = &self.settings.speed_test;
+        let timeout = Duration::from_millis(speed_test.timeout as u64);
+        match speed_test.backend {
+            SpeedTestBackend::Http => {
+                let (_, bandwidth, _) = speedtest::test_download(
+                    &speed_test.url,
+                    timeout,
+                    proxy_url,
+                    speed_test.budget(),
+                )
+                .await?;
+                Ok(bandwidth)
+            }
+            SpeedTestBackend::Speedtest => {
+                let result = speedtest::ookla_test(timeout, proxy_url, speed_test.budget()).await?;
+                info!(
+                    "{}",
+                    tr!(
+                        "测速服务器：{}，延迟 {} ms",
+                        "Speedtest server: {}, latency {} ms",
+                        result.server,
+                        result.latency.as_millis()
+                    )
+                );
+                Ok(result.speed)
+            }
+            SpeedTestBackend::Cloudflare => {
+                let result =
+                    speedtest::cloudflare_test(timeout, proxy_url, speed_test.budget()).await?;
+                info!(
+                    "{}",
+                    tr!(
+                        "Cloudflare 测速：延迟 {} ms，负载下延迟 {} ms，上传 {} KB/s",
+                        "Cloudflare speed test: latency {} ms, loaded latency {} ms, upload {} KB/s",
+                        result.latency.as_millis(),
+                        result
+                            .loaded_latency
+                            .map_or("-".to_string(), |d| d.as_millis().to_string()),
+                        result.upload.map_or("-".to_string(), |speed| format!("{:.2}", speed))
+                    )
+                );
+                Ok(result.download)
+            }
+        }
+    }
+
+    /// 经 proxy_url 查询出口 IP，失败时重试，共尝试 inspect.trace_attempts 次
+    async fn trace_ip<'a>(
+        &self,
+        proxy_url: &'a str,
+    ) -> Result<(IpAddr, &'a str), Box<dyn std::error::Error>> {
+        let mut attempt = 1;
+        loop {
+            let message = match cgi_trace::get_ip(proxy_url).await {
+                Ok(result) => return Ok(result),
+                Err(e) if attempt < self.settings.inspect.trace_attempts => e.to_string(),
+                Err(e) => return Err(e),
+            };
+            debug!(
+                "{}",
+                tr!(
+                    "查询出口 IP 失败，第 {} 次重试: {}",
+                    "Exit IP lookup failed, retry {}: {}",
+                    attempt,
+                    message
+                )
+            );
+            sleep(TRACE_RETRY_DELAY).await;
+            attempt += 1;
+        }
+    }
+
+    /// 测速前随机等待一段时间，避免连续测速被测速服务限流
+    async fn speed_test_jitter(&self) {
+        let max = Duration::from_millis(self.settings.pacing.speed_test_jitter_ms);
+        let wait = pacing::jitter(max);
+        if !wait.is_zero() {
+            sleep(wait).await;
+        }
+    }
+
+    /// 按 test_group_size 分组，每组启动一次内核
+    fn split_groups<'a>(&self, proxies: &'a [Proxy]) -> Vec<&'a [Proxy]> {
+        let chunk_size = self.settings.test_group_size;
+        let groups = proxies.chunks(chunk_size).collect::<Vec<_>>();
+        if groups.len() > 1 {
+            info!(
+                "{}",
+                tr!(
+                    "为加速测试速度，以 {} 为限制分为 {} 组测试",
+                    "Testing in {1} groups of up to {0} nodes",
+                    chunk_size,
+                    groups.len()
+                )
+            );
+        }
+        groups
+    }
+
+    fn test_bar(&self, group_size: usize) -> ProgressBar {
+        let stage = tr!("连通性测试", "Connectivity test");
+        self.emit(PipelineEvent::Stage(stage.clone()));
+        progress::new_bar(
+            self.progress,
+            (group_size * (WARMUP_ROUND + ROUND)) as u64,
+            &stage,
+        )
+    }
+
+    /// 看门狗模式下同一个 Pipeline 会执行多次，每次测试前清空上次的结果
+    fn reset_tested(&self) {
+        self.tested.clear();
+    }
+
+    /// 测试单组节点，与之前分组中指纹相同的节点直接沿用结果，内核启动失败时跳过该组，返回空结果
+    async fn test_group(
+        &self,
+        core: &CoreSlot,
+        index: usize,
+        group_size: usize,
+        proxies: &[Proxy],
+        bar: &ProgressBar,
+    ) -> TestResult {
+        let (reused, untested) = self.reuse_tested(proxies);
+        let mut result = if untested.is_empty() {
+            bar.inc((WARMUP_ROUND + ROUND) as u64);
+            TestResult::default()
+        } else {
+            match self
+                .test_chunk(core, index, group_size, &untested, bar)
+                .await
+            {
+                Some(result) => {
+                    self.tested.remember(&untested, &result);
+                    result
+                }
+                None => TestResult::default(),
+            }
+        };
+        result.extend(reused);
+        result
+    }
+
+    /// 取出本次运行中已测试过的节点的结果，返回沿用的结果及需要测试的节点
+    fn reuse_tested(&self, proxies: &[Proxy]) -> (TestResult, Vec<Proxy>) {
+        let (reused, untested) = self.tested.reuse(proxies);
+        let count = proxies.len() - untested.len();
+        if count == 0 {
+            return (reused, untested);
+        }
+        let untested_names = untested.iter().map(Proxy::get_name).collect::<HashSet<_>>();
+        for proxy in proxies {
+            let name = proxy.get_name();
+            if !untested_names.contains(name) {
+                self.emit(PipelineEvent::Tested {
+                    name: name.to_string(),
+                    latency: report::average_latency(reused.latencies.get(name)),
+                });
+            }
+        }
+        info!(
+            "{}",
+            tr!(
+                "{} 个节点已在之前的分组中测试过，沿用测试结果",
+                "{} nodes were already tested in previous groups, reusing the results",
+                count
+            )
+        );
+        (reused, untested)
+    }
+
+    /// 测试单组节点，生成配置或内核启动失败时跳过该组，返回 None
+    async fn test_chunk(
+        &self,
+        core: &CoreSlot,
+        index: usize,
+        group_size: usize,
+        proxies: &[Proxy],
+        bar: &ProgressBar,
+    ) -> Option<TestResult> {
+        if group_size > 1 {
+            info!("{}", tr!("正在测试第 {} 组", "Testing group {}", index + 1))
+        }
+
+        let ports = core.ports(self.stack, 0);
+        if let Err(e) = core.save_config(proxies, &ports, &self.core_overrides(proxies)) {
+            error!(
+                "{}",
+                tr!(
+                    "生成第 {} 组测试配置失败，跳过该组, {}",
+                    "Failed to generate the config of group {}, skipped, {}",
+                    index + 1,
+                    e
+                )
+            );
+            bar.inc((WARMUP_ROUND + ROUND) as u64);
+            return None;
+        }
+
+        // 启动 Clash 内核
+        let mut clash_meta = self.clash_meta(core, &ports);
+        let mut core_log = LogTail::new(clash_meta.log_path());
+        if let Err(e) = clash_meta.start().await {
+            error!("{}", PipelineError::Core(e.to_string()));
+            report_core_issues(&mut core_log, proxies);
+            stop_core(clash_meta);
+            bar.inc((WARMUP_ROUND + ROUND) as u64);
+            return None;
+        }
+
+        // 节点较多时内核加载策略组需要时间，等待加载完成后再测试
+        match clash_meta.wait_for_group(TEST_PROXY_GROUP_NAME).await {
+            Ok(nodes) => {
+                info!(
+                    "{}",
+                    tr!(
+                        "开始测试 {}/config.yaml 中节点的延迟速度，节点总数：{}",
+                        "Testing the delay of nodes in {}/config.yaml, total: {}",
+                        core.dir,
+                        nodes.all.len()
+                    )
+                )
+            }
+            Err(e) => {
+                error!(
+                    "{}",
+                    tr!(
+                        "获取节点数失败，请检查 clash 日志文件和 {}/config.yaml 生成的节点是否正确, {}",
+                        "Failed to get nodes, check the clash log and the nodes generated in {}/config.yaml, {}",
+                        core.dir,
+                        e
+                    )
+                );
+                report_core_issues(&mut core_log, proxies);
+                stop_core(clash_meta);
+                bar.inc((WARMUP_ROUND + ROUND) as u64);
+                return None;
+            }
+        }
+
+        info!("{}", tr!("开始测试连通性", "Testing connectivity"));
+        let delay_results = test_node_with_delay_config(
+            &clash_meta,
+            &self.settings.connect_test,
+            &self.settings.early_stop,
+            bar,
+        )
+        .await;
+        let mut nodes = get_all_tested_nodes(&delay_results);
+        let failed = proxies
+            .iter()
+            .map(Proxy::get_name)
+            .filter(|name| !nodes.iter().any(|node| node == name))
+            .collect::<Vec<_>>();
+        let failures = diagnose_failures(&clash_meta, &failed, &self.settings.connect_test).await;
+        self.update_summary(|s| s.failures.extend(failures));
+        report_core_issues(&mut core_log, proxies);
+        stop_core(clash_meta);
+        let mut latencies: HashMap<String, Vec<i64>> = HashMap::new();
+        for result in &delay_results {
+            for (node, latency) in result {
+                latencies.entry(node.clone()).or_default().push(*latency);
+            }
+        }
+        let rounds = latency_rounds(proxies, &delay_results);
+        let total = nodes.len();
+        nodes.retain(|node| {
+            meets_thresholds(
+                latencies.get(node),
+                delay_results.len(),
+                self.settings.max_latency_ms,
+                self.settings.min_success_rate,
+            )
+        });
+        if nodes.len() < total {
+            info!(
+                "{}",
+                tr!(
+                    "{} 个节点的平均延迟或成功率未达到要求，已剔除",
+                    "{} nodes removed for exceeding max_latency_ms or below min_success_rate",
+                    total - nodes.len()
+                )
+            );
+        }
+        info!(
+            "{}",
+            tr!(
+                "连通性测试结果：{} 个节点可用",
+                "Connectivity test: {} nodes alive",
+                nodes.len()
+            )
+        );
+        for proxy in proxies {
+            let name = proxy.get_name();
+            self.emit(PipelineEvent::Tested {
+                name: name.to_string(),
+                latency: report::average_latency(latencies.get(name)),
+            });
+        }
+        let proxies = proxies
+            .iter()
+            .filter(|&proxy| nodes.contains(&proxy.get_name().to_string()))
+            .cloned()
+            .collect::<Vec<Proxy>>();
+        Some(TestResult {
+            proxies,
+            latencies,
+            rounds,
+        })
+    }
+
+    fn finish_test(
+        &self,
+        candidates: &[Proxy],
+        tested: TestResult,
+    ) -> Result<TestResult, PipelineError> {
+        let survival = composition::survival(candidates, &tested.proxies);
+        info!(
+            "{}",
+            tr!(
+                "各协议存活率：\n{}",
+                "Survival by protocol:\n{}",
+                composition::render_survival(&survival)
+            )
+        );
+        self.update_summary(|s| s.survival = survival);
+        self.record_sources(&tested.proxies);
+        if tested.proxies.is_empty() {
+            return Err(PipelineError::NoAliveProxies);
+        }
+        info!(
+            "{}",
+            tr!(
+                "当前总可用节点个数：{}",
+                "Total alive nodes: {}",
+                tested.proxies.len()
+            )
+        );
+        self.update_summary(|s| s.alive = tested.proxies.len());
+        self.update_history(|h| h.tested(candidates, &tested.proxies, Local::now()));
+        Ok(tested)
+    }
+
+    /// 记录各订阅本次运行的表现并输出排名，分阶段执行时没有拉取统计，不记录
+    fn record_sources(&self, alive: &[Proxy]) {
+        let stats = self.summary().sources;
+        if stats.is_empty() {
+            return;
+        }
+        let mut alive_counts = vec![0; self.sources.len()];
+        {
+            let origins = self.origins.lock().unwrap_or_else(|e| e.into_inner());
+            for index in alive.iter().filter_map(|proxy| origins.get(proxy)) {
+                alive_counts[*index] += 1;
+            }
+        }
+        let now = Local::now();
+        self.update_history(|history| {
+            for ((source, stats), alive) in self.sources.iter().zip(&stats).zip(alive_counts) {
+                history.record_source(
+                    &source.url,
+                    SourceRun {
+                        at: now,
+                        ok: stats.error.is_none(),
+                        unique: stats.contributed(),
+                        alive,
+                    },
+                );
+            }
+        });
+        let history = self.history.lock().unwrap_or_else(|e| e.into_inner());
+        info!(
+            "{}",
+            tr!(
+                "订阅排名：\n{}",
+                "Subscription ranking:\n{}",
+                render_source_health(&self.sources, &history)
+            )
+        );
+    }
+
+    /// 查询节点落地 IP 信息并按规则重命名，无法获取 IP 的节点会被剔除
+    pub async fn rename(&self, tested: TestResult) -> Result<RenameResult, PipelineError> {
+        lint_templates()?;
+        self.rename_with(tested, &TEST_CORE, self.progress).await
+    }
+
+    async fn rename_with(
+        &self,
+        tested: TestResult,
+        core: &CoreSlot,
+        progress: bool,
+    ) -> Result<RenameResult, PipelineError> {
+        if !self.settings.rename_node {
+            let mut renamed = RenameResult::from(tested);
+            renamed.rename_dup_proxies_name();
+            return Ok(self.annotate(renamed));
+        }
+        self.inspect(tested, core, progress).await
+    }
+
+    /// 逐个检测节点的 IP 信息、速度及网站解锁，按结果重命名并剔除失效节点
+    async fn inspect(
+        &self,
+        tested: TestResult,
+        core: &CoreSlot,
+        progress: bool,
+    ) -> Result<RenameResult, PipelineError> {
+        if self.checks.is_empty() {
+            warn!(
+                "{}",
+                tr!(
+                    "未配置 websites 及 checks，节点检测只查询 IP 信息，查询失败的节点按连通性测试结果保留",
+                    "No websites or checks configured, node inspection only looks up IP info, nodes failing the lookup are kept by the connectivity test result"
+                )
+            );
+        }
+
+        let inspect = &self.settings.inspect;
+        let lane_count = inspect.concurrency.min(tested.proxies.len()).max(1);
+        let ports = core.ports(self.stack, lane_count - 1);
+        core.save_config(
+            &tested.proxies,
+            &ports,
+            &self.core_overrides(&tested.proxies),
+        )?;
+        let mut clash_meta = self.clash_meta(core, &ports);
+        let mut core_log = LogTail::new(clash_meta.log_path());
+        if let Err(e) = clash_meta.start().await {
+            report_core_issues(&mut core_log, &tested.proxies);
+            stop_core(clash_meta);
+            return Err(PipelineError::Core(e.to_string()));
+        }
+        if let Err(e) = clash_meta.wait_for_group(TEST_PROXY_GROUP_NAME).await {
+            report_core_issues(&mut core_log, &tested.proxies);
+            stop_core(clash_meta);
+            return Err(PipelineError::Core(e.to_string()));
+        }
+        info!(
+            "{}",
+            tr!("当前节点个数为：{}", "Nodes: {}", tested.proxies.len())
+        );
+
+        let speed_candidates = if self.settings.speed_test.enabled {
+            let limit = self
+                .settings
+                .max_nodes
+                .map(|n| n.saturating_mul(self.settings.speed_test.candidate_factor));
+            let candidates = speed_candidates(&tested, limit);
+            if candidates.len() < tested.proxies.len() {
+                info!(
+                    "{}",
+                    tr!(
+                        "仅对延迟最低的 {} 个节点测速",
+                        "Speed testing only the {} lowest-latency nodes",
+                        candidates.len()
+                    )
+                );
+            }
+            candidates
+        } else {
+            HashSet::new()
+        };
+
+        let mut removed_nodes: HashSet<String> = HashSet::new();
+        let mut node_rename_map: HashMap<String, String> = HashMap::new();
+        let mut node_reports: HashMap<String, NodeReport> = HashMap::new();
+        let stage = tr!("检测节点", "Inspecting nodes");
+        self.emit(PipelineEvent::Stage(stage.clone()));
+        let bar = progress::new_bar(progress, tested.proxies.len() as u64, &stage);
+        let lanes = std::iter::once(clash_meta.lane(TEST_PROXY_GROUP_NAME, ports.mixed))
+            .chain(
+                ports
+                    .lanes
+                    .iter()
+                    .enumerate()
+                    .map(|(i, port)| clash_meta.lane(&lane_group(i + 1), *port)),
+            )
+            .collect::<Vec<_>>();
+        if lanes.len() > 1 {
+            info!(
+                "{}",
+                tr!(
+                    "同时检测 {} 个节点",
+                    "Inspecting {} nodes concurrently",
+                    lanes.len()
+                )
+            );
+        }
+        // 并发检测时内核的流量统计包含其他通道的流量，只统计本次运行的总流量
+        let per_node_traffic = lanes.len() == 1;
+        let start_traffic = clash_meta.traffic().await.ok();
+        let speed_lock = tokio::sync::Mutex::new(());
+        let (clash, tested_ref, candidates, bar_ref, speed_lock) =
+            (&clash_meta, &tested, &speed_candidates, &bar, &speed_lock);
+        let inspections = clash_meta
+            .inspect_nodes(
+                lanes,
+                tested.proxies.iter().map(|proxy| (proxy.get_name(), proxy)),
+                Duration::from_secs(inspect.node_timeout_secs),
+                |proxy, lane| async move {
+                    let node = proxy.get_name();
+                    bar_ref.set_message(tr!("检测节点 {}", "Inspecting {}", node));
+                    let speed_test = candidates.contains(node);
+                    let before = if per_node_traffic {
+                        clash.traffic().await.ok()
+                    } else {
+                        None
+                    };
+                    let mut inspection = self
+                        .inspect_node(clash, &lane, proxy, tested_ref, speed_test, speed_lock)
+                        .await;
+                    if let (Some(before), Ok(after)) = (before, clash.traffic().await) {
+                        let used = after.since(&before);
+                        info!(
+                            "「{}」 traffic: {}",
+                            node,
+                            report::format_bytes(used.total())
+                        );
+                        if let Some(report) = inspection.as_mut().and_then(|i| i.report.as_mut()) {
+                            report.traffic = Some(used);
+                        }
+                    }
+                    bar_ref.inc(1);
+                    self.emit(PipelineEvent::Inspected {
+                        name: node.to_string(),
+                        new_name: inspection.as_ref().and_then(|i| i.new_name.clone()),
+                        report: inspection.as_ref().and_then(|i| i.report.clone()),
+                    });
+                    inspection
+                },
+            )
+            .await;
+        let inspections = match inspections {
+            Ok(inspections) => inspections,
+            Err(e) => {
+                bar.finish_and_clear();
+                stop_core(clash_meta);
+                return Err(PipelineError::Core(e.to_string()));
+            }
+        };
+        let run_traffic = match (start_traffic, clash_meta.traffic().await) {
+            (Some(start), Ok(end)) => end.since(&start),
+            _ => Traffic::default(),
+        };
+        for (proxy, inspection) in tested.proxies.iter().zip(inspections) {
+            let node = proxy.get_name();
+            let inspection = match inspection {
+                Ok(inspection) => inspection,
+                Err(e) => {
+                    bar.inc(1);
+                    self.emit(PipelineEvent::Inspected {
+                        name: node.to_string(),
+                        new_name: None,
+                        report: None,
+                    });
+                    match &e {
+                        // 未能切换到该节点，无法判断节点是否可用，保留节点
+                        InspectError::Select(reason) => {
+                            error!(
+                                "{}",
+                                tr!(
+                                    "设置节点 {} 失败, {}",
+                                    "Failed to select node {}, {}",
+                                    node,
+                                    reason
+                                )
+                            );
+                            Some(NodeInspection {
+                                new_name: None,
+                                report: None,
+                            })
+                        }
+                        InspectError::Timeout(_) => {
+                            error!(
+                                "{}",
+                                tr!(
+                                    "「{}」检测超时，{}",
+                                    "「{}」 inspection failed, {}",
+                                    node,
+                                    e
+                                )
+                            );
+                            None
+                        }
+                    }
+                }
+            };
+            match inspection {
+                Some(inspection) => {
+                    let new_name = match self.apply_script(proxy, &inspection) {
+                        ScriptDecision::Keep(Some(name)) => Some(name),
+                        ScriptDecision::Keep(None) => inspection.new_name,
+                        ScriptDecision::Drop => {
+                            removed_nodes.insert(node.to_string());
+                            continue;
+                        }
+                    };
+                    if let Some(new_name) = new_name {
+                        node_rename_map.insert(node.to_string(), new_name);
+                    }
+                    if let Some(node_report) = inspection.report {
+                        node_reports.insert(node.to_string(), node_report);
+                    }
+                }
+                None if self.is_trusted(proxy) => {
+                    info!(
+                        "{}",
+                        tr!(
+                            "「{}」来自受信任的订阅，保留节点",
+                            "「{}」 is from a trusted subscription, kept",
+                            node
+                        )
+                    );
+                }
+                // 没有网站检测时无法判断节点是否可用，以连通性测试结果为准
+                None if self.checks.is_empty() => {
+                    info!(
+                        "{}",
+                        tr!(
+                            "「{}」获取 IP 信息失败，按连通性测试结果保留节点",
+                            "「{}」 IP lookup failed, kept by the connectivity test result",
+                            node
+                        )
+                    );
+                }
+                None => {
+                    removed_nodes.insert(node.to_string());
+                }
+            }
+        }
+        bar.finish_and_clear();
+        report_core_issues(&mut core_log, &tested.proxies);
+        stop_core(clash_meta);
+        info!(
+            "{}",
+            tr!(
+                "节点检测消耗流量：上传 {}，下载 {}",
+                "Traffic used by node inspection: {} up, {} down",
+                report::format_bytes(run_traffic.upload),
+                report::format_bytes(run_traffic.download)
+            )
+        );
+        self.update_summary(|s| s.traffic += run_traffic);
+
+        let mut release_proxies = tested
+            .proxies
+            .iter()
+            .filter(|proxy| !removed_nodes.contains(proxy.get_name()))
+            .cloned()
+            .collect::<Vec<Proxy>>();
+
+        let reports = release_proxies
+            .iter()
+            .map(|proxy| {
+                node_reports
+                    .remove(proxy.get_name())
+                    .unwrap_or_else(|| tested.report(proxy.get_name()))
+            })
+            .collect::<Vec<_>>();
+        for proxy in &mut release_proxies {
+            if let Some(new_name) = node_rename_map.get(proxy.get_name()) {
+                proxy.set_name(new_name);
+            }
+        }
+
+        let mut renamed = RenameResult {
+            proxies: release_proxies,
+            reports,
+        };
+        renamed.rename_dup_proxies_name();
+        Ok(self.annotate(renamed))
+    }
+
+    /// 执行配置的脚本，脚本出错时保留节点
+    fn apply_script(&self, proxy: &Proxy, inspection: &NodeInspection) -> ScriptDecision {
+        let Some(script) = &self.script else {
+            return ScriptDecision::Keep(None);
+        };
+        let decision = script.eval(
+            proxy,
+            inspection.new_name.as_deref(),
+            inspection.report.as_ref(),
+        );
+        match decision {
+            Ok(decision) => {
+                if decision == ScriptDecision::Drop {
+                    info!(
+                        "{}",
+                        tr!(
+                            "「{}」被脚本丢弃",
+                            "「{}」 dropped by script",
+                            proxy.get_name()
+                        )
+                    );
+                }
+                decision
+            }
+            Err(e) => {
+                warn!(
+                    "{}",
+                    tr!(
+                        "「{}」执行脚本失败，保留节点, {}",
+                        "「{}」 script failed, node kept, {}",
+                        proxy.get_name(),
+                        e
+                    )
+                );
+                ScriptDecision::Keep(None)
+            }
+        }
+    }
+
+    /// 经检测通道 lane 检测单个节点，lane 的策略组已切换到该节点，返回 None 表示节点已失效需要剔除
+    ///
+    /// 测速时持有 speed_lock，并发检测时各通道依次测速，避免互相抢占带宽
+    async fn inspect_node(
+        &self,
+        clash_meta: &ClashMeta,
+        lane: &InspectLane,
+        proxy: &Proxy,
+        tested: &TestResult,
+        speed_test: bool,
+        speed_lock: &tokio::sync::Mutex<()>,
+    ) -> Option<NodeInspection> {
+        let node = proxy.get_name();
+        let proxy_url = lane.proxy_url.as_str();
+        let (proxy_ip, from) = match self.trace_ip(proxy_url).await {
+            Ok(result) => result,
+            Err(e) => {
+                error!(
+                    "{}",
+                    tr!(
+                        "获取节点 {} 的 IP 失败, {}",
+                        "Failed to get the IP of node {}, {}",
+                        node,
+                        e
+                    )
+                );
+                return None;
+            }
+        };
+        info!("「{}」ip: {} from: {}", node, proxy_ip, from);
+
+        let mut node_report = tested.report(node);
+        node_report.source = self.source_label(proxy);
+        if speed_test {
+            let _speed_guard = speed_lock.lock().await;
+            self.speed_test_jitter().await;
+            match self.measure_speed(clash_meta, proxy_url).await {
+                Ok(bandwidth) => {
+                    info!("「{}」 speed: {:.2} KB/s", node, bandwidth);
+                    node_report.speed = Some(bandwidth);
+                }
+                Err(err) => {
+                    error!("「{}」 speed test failed, {}", node, err)
+                }
+            }
+        }
+
+        node_report.cdn = cdn::detect(proxy.get_server()).await.map(str::to_string);
+        if let Some(cdn) = &node_report.cdn {
+            info!(
+                "{}",
+                tr!(
+                    "「{}」服务器位于 {} CDN，延迟只反映到 CDN 边缘节点的距离",
+                    "「{}」 server is behind {} CDN, latency only reflects the CDN edge",
+                    node,
+                    cdn
+                )
+            );
+        }
+
+        // 各项检测经由同一个节点访问不同的目标，互不影响，同时执行
+        let results = join_all(
+            self.checks
+                .iter()
+                .map(|check| check.check_exit(node, proxy_url, proxy_ip)),
+        )
+        .await;
+        let mut passed_checks = vec![];
+        let mut rtts = vec![];
+        for (check, result) in self.checks.iter().zip(results) {
+            match result {
+                CheckResult::Pass => {
+                    info!("「{}」 {} is ok", node, check.name());
+                    passed_checks.push(check.name());
+                }
+                CheckResult::Latency(rtt) => {
+                    info!("「{}」 {} rtt: {} ms", node, check.name(), rtt);
+                    passed_checks.push(check.name());
+                    rtts.push(rtt);
+                }
+                CheckResult::Fail(reason) => {
+                    error!("「{}」 {} is not ok, {}", node, check.name(), reason)
+                }
+            }
+        }
+
+        node_report.rtt = report::average_latency(Some(&rtts));
+
+        self.geo_limiter.acquire().await;
+        let mut new_name = match ip::get_ip_detail(&proxy_ip, proxy_url).await {
+            Ok(ip_detail) => {
+                info!("{:?}", ip_detail);
+                node_report = node_report.with_ip_detail(&ip_detail);
+                self.settings
+                    .rename_pattern
+                    .replace("${IP}", &proxy_ip.to_string())
+                    .replace("${COUNTRYCODE}", &ip_detail.country_code)
+                    .replace("${ISP}", &ip_detail.isp)
+                    .replace("${CITY}", &ip_detail.city)
+                    .replace("${CDN}", node_report.cdn.as_deref().unwrap_or_default())
+                    .replace("${AGE}", &history::format_age(self.node_age(proxy)))
+                    .replace(
+                        "${SOURCE}",
+                        node_report.source.as_deref().unwrap_or_default(),
+                    )
+            }
+            Err(e) => {
+                error!(
+                    "{}",
+                    tr!(
+                        "获取节点 {} 的 IP 信息失败, {}",
+                        "Failed to get the IP info of node {}, {}",
+                        node,
+                        e
+                    )
+                );
+                if passed_checks.is_empty() {
+                    return None;
+                }
+                // 之前运行输出的节点沿用模板生成的部分，避免名称退化为 IP
+                match self.managed_name().base(node) {
+                    Some(base) => base.to_string(),
+                    None => proxy_ip.to_string(),
+                }
+            }
+        };
+        node_report.checks = passed_checks.iter().map(|name| name.to_string()).collect();
+        for name in passed_checks {
+            new_name += &format!("_{}", name);
+        }
+        match cgi_trace::get_ip(proxy_url).await {
+            Ok((end_ip, _)) if end_ip != proxy_ip => {
+                warn!(
+                    "{}",
+                    tr!(
+                        "「{}」出口 IP 由 {} 变为 {}，标记为动态节点",
+                        "「{}」 exit IP changed from {} to {}, marked as dynamic",
+                        node,
+                        proxy_ip,
+                        end_ip
+                    )
+                );
+                node_report.dynamic_ip = true;
+                new_name += DYNAMIC_IP_TAG;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                warn!(
+                    "{}",
+                    tr!(
+                        "再次获取节点 {} 的 IP 失败, {}",
+                        "Failed to get the IP of node {} again, {}",
+                        node,
+                        e
+                    )
+                );
+            }
+        }
+        Some(NodeInspection {
+            new_name: Some(new_name),
+            report: Some(node_report),
+        })
+    }
+
+    /// 只测试已发布的 release 文件中的节点，剔除失效节点并按评分重新排序，返回剩余节点数
+    ///
+    /// 与导出时一致剔除已禁用的节点、保留固定的节点，没有剩余节点时保留原文件
+    pub async fn verify_release(&self) -> Result<usize, PipelineError> {
+        lint_templates()?;
+        self.reset_tested();
+        let proxies = load_proxies(&self.release_path)?;
+        let bar = progress::new_bar(false, 0, "");
+        let tested = self.test_group(&TEST_CORE, 0, 1, &proxies, &bar).await;
+        self.update_history(|h| h.passed(&tested.proxies, Local::now()));
+
+        // 分组用到的 ISP、解锁、速度等检测结果沿用最近一次的重命名结果，以节点本身（不含名称）对应
+        let previous = RenameResult::load(Path::new(RENAMED_YAML_PATH))
+            .map(|renamed| {
+                renamed
+                    .proxies
+                    .into_iter()
+                    .zip(renamed.reports)
+                    .collect::<HashMap<_, _>>()
+            })
+            .unwrap_or_default();
+        let mut alive = tested
+            .proxies
+            .iter()
+            .map(|p| {
+                let latest = tested.report(p.get_name());
+                let report = match previous.get(p) {
+                    Some(report) => NodeReport {
+                        name: latest.name,
+                        latency: latest.latency,
+                        jitter: latest.jitter,
+                        loss: latest.loss,
+                        rounds: latest.rounds,
+                        checked_at: latest.checked_at,
+                        ..report.clone()
+                    },
+                    None => latest,
+                };
+                (p.clone(), report)
+            })
+            .collect::<Vec<_>>();
+        alive.sort_by(|(_, a), (_, b)| self.settings.score.compare(a, b));
+        let (ranked, reports) = alive.into_iter().unzip();
+        let mut alive = RenameResult {
+            proxies: ranked,
+            reports,
+        };
+        alive.apply_tags(&self.settings.tags);
+        // 与导出时一致，剔除发布后才禁用的节点，固定的节点本次未通过测试时按固定时保存的配置及结果恢复
+        let bans = self.ban_list();
+        if !bans.is_empty() {
+            alive = alive.filter(|proxy, _| !bans.contains(proxy));
+        }
+        let pinned = self.pinned(&alive, &bans);
+        alive.merge(&pinned);
+        if alive.proxies.is_empty() {
+            return Err(PipelineError::NoAliveProxies);
+        }
+        let names = |proxies: &[Proxy]| {
+            proxies
+                .iter()
+                .map(|p| p.get_name().to_string())
+                .collect::<Vec<_>>()
+        };
+        if names(&alive.proxies) == names(&proxies) {
+            info!(
+                "{}",
+                tr!(
+                    "release 文件中的 {} 个节点均可用，无需更新",
+                    "All {} nodes in the release file are alive, nothing to update",
+                    alive.proxies.len()
+                )
+            );
+            return Ok(alive.proxies.len());
+        }
+
+        info!(
+            "{}",
+            tr!(
+                "release 文件中 {} 个节点失效，剩余 {} 个节点已按评分重新排序",
+                "{} nodes in the release file are dead, the remaining {} nodes are re-ranked by score",
+                proxies.len().saturating_sub(alive.proxies.len()),
+                alive.proxies.len()
+            )
+        );
+        let format = ExportFormat::from_path(&self.release_path).unwrap_or(ExportFormat::Clash);
+        let content = format.render_with_groups(&alive.proxies, self.release_groups(&alive))?;
+        let content = self.with_metadata(format, alive.proxies.len(), content);
+        write_atomic(&self.release_path, content.as_bytes())?;
+        self.archive_release(&self.release_path);
+        upload::upload_files(&self.settings.uploads, &[self.release_path.clone()]).await;
+        Ok(alive.proxies.len())
+    }
+
+    /// 看门狗模式，每隔 interval 校验一次已发布的 release 文件，收到 Ctrl+C 或 SIGTERM 时退出
+    pub async fn watch_release(&self, interval: Duration) -> Result<(), PipelineError> {
+        info!(
+            "{}",
+            tr!(
+                "看门狗模式，每 {} 分钟校验一次 {}",
+                "Watch mode, verifying {1} every {0} minutes",
+                interval.as_secs() / 60,
+                self.release_path.display()
+            )
+        );
+        daemon::notify_ready();
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {
+                    if let Err(e) = self.verify_release().await {
+                        error!(
+                            "{}",
+                            tr!("校验 release 文件失败, {}", "Failed to verify the release file, {}", e)
+                        );
+                    }
+                }
+                _ = server::shutdown_signal() => {
+                    info!("{}", tr!("退出看门狗模式", "Exiting watch mode"));
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// 保存 release 文件、Markdown 汇总并上传
+    pub async fn export(&self, renamed: &RenameResult) -> Result<ExportResult, PipelineError> {
+        self.export_as(renamed, ExportFormat::Clash, None).await
+    }
+
+    /// 将导出的文件及节点报告 report.json 打包到 bundle::BUNDLE_DIR
+    fn save_bundle(
+        &self,
+        files: &[PathBuf],
+        reports: &[NodeReport],
+    ) -> std::io::Result<Vec<PathBuf>> {
+        let mut entries = vec![];
+        for file in files {
+            let name = file.file_name().map_or_else(
+                || file.display().to_string(),
+                |n| n.to_string_lossy().to_string(),
+            );
+            entries.push((name, fs::read(file)?));
+        }
+        entries.push((
+            "report.json".to_string(),
+            serde_json::to_vec_pretty(reports).map_err(std::io::Error::from)?,
+        ));
+        bundle::save(
+            &self.settings.bundle,
+            Path::new(bundle::BUNDLE_DIR),
+            &entries,
+            Local::now(),
+        )
+    }
+
+    /// 读取手动禁用的节点，读取失败时不剔除任何节点
+    fn ban_list(&self) -> BanList {
+        BanList::load(Path::new(BAN_LIST_PATH)).unwrap_or_else(|e| {
+            warn!(
+                "{}",
+                tr!(
+                    "读取禁用节点列表 {} 失败: {}",
+                    "Failed to read the banned node list {}: {}",
+                    BAN_LIST_PATH,
+                    e
+                )
+            );
+            BanList::default()
+        })
+    }
+
+    /// 读取手动固定的节点，读取失败时不固定任何节点
+    fn pin_list(&self) -> PinList {
+        PinList::load(Path::new(PIN_LIST_PATH)).unwrap_or_else(|e| {
+            warn!(
+                "{}",
+                tr!(
+                    "读取固定节点列表 {} 失败: {}",
+                    "Failed to read the pinned node list {}: {}",
+                    PIN_LIST_PATH,
+                    e
+                )
+            );
+            PinList::default()
+        })
+    }
+
+    /// 本次结果中固定的节点使用最新的测试结果，其余按固定时保存的配置及结果恢复，已禁用的节点除外
+    fn pinned(&self, renamed: &RenameResult, bans: &BanList) -> RenameResult {
+        let pins = self.pin_list();
+        if pins.is_empty() {
+            return RenameResult::default();
+        }
+        let mut pinned = renamed.filter(|proxy, _| pins.contains(proxy));
+        let (proxies, reports) = pins
+            .entries()
+            .into_iter()
+            .filter(|(proxy, _)| !bans.contains(proxy))
+            .unzip();
+        let mut saved = RenameResult { proxies, reports };
+        saved.apply_tags(&self.settings.tags);
+        let restored = pinned.merge(&saved);
+        if restored > 0 {
+            info!(
+                "{}",
+                tr!(
+                    "恢复 {} 个本次未通过测试的固定节点",
+                    "Restored {} pinned nodes that failed this run",
+                    restored
+                )
+            );
+        }
+        pinned
+    }
+
+    /// 节点是否来自受信任的订阅
+    fn is_trusted(&self, proxy: &Proxy) -> bool {
+        self.origins
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(proxy)
+            .is_some_and(|index| self.sources[*index].trusted)
+    }
+
+    /// Clash 配置开头加上生成时间、节点数等注释，便于使用者判断订阅是否过期，其他格式不支持注释
+    fn with_metadata(&self, format: ExportFormat, nodes: usize, content: String) -> String {
+        match format {
+            ExportFormat::Clash => metadata_header(nodes, self.sources.len()) + &content,
+            _ => content,
+        }
+    }
+
+    /// 先按各订阅的配置筛选，再按全局的 countries、max_nodes 筛选最终导出的节点
+    fn select(&self, renamed: &RenameResult) -> Result<RenameResult, PipelineError> {
+        let mut renamed = renamed.select_by_source(
+            &self.sources,
+            &self.origins.lock().unwrap_or_else(|e| e.into_inner()),
+            &self.settings.score,
+        );
+        // 导出时按当前配置重新计算标签，修改规则后直接 export 即可生效
+        renamed.apply_tags(&self.settings.tags);
+        // 发布后才禁用的节点可能还在重命名结果中
+        let bans = self.ban_list();
+        if !bans.is_empty() {
+            renamed = renamed.filter(|proxy, _| !bans.contains(proxy));
+        }
+        // 固定的节点不受之后的筛选影响，本次未通过测试的按固定时保存的配置及结果恢复
+        let pinned = self.pinned(&renamed, &bans);
+        renamed.merge(&pinned);
+        if renamed.proxies.is_empty() {
+            return Err(PipelineError::NoAliveProxies);
+        }
+        let renamed = if self.settings.exclude_isps.is_empty() {
+            renamed
+        } else {
+            let mut excluded = renamed.exclude_isps(&self.settings.exclude_isps);
+            info!(
+                "{}",
+                tr!(
+                    "按 ISP {:?} 剔除 {} 个节点",
+                    "Excluded {1} nodes by ISPs {0:?}",
+                    self.settings.exclude_isps,
+                    renamed.proxies.len() - excluded.proxies.len()
+                )
+            );
+            excluded.merge(&pinned);
+            if excluded.proxies.is_empty() {
+                return Err(PipelineError::NoAliveProxies);
+            }
+            excluded
+        };
+        let countries = &self.settings.countries;
+        let max_nodes = self.settings.max_nodes;
+        let balance = &self.settings.balance;
+        if countries.is_empty() && max_nodes.is_none() && !balance.is_enabled() {
+            return Ok(renamed);
+        }
+        if !countries.is_empty() && renamed.reports.iter().all(|r| r.country.is_none()) {
+            warn!(
+                "{}",
+                tr!(
+                    "未获取到节点的国家信息，按国家筛选需要开启节点重命名且关闭快速模式",
+                    "No country info of nodes, filtering by country requires rename_node on and fast_mode off"
+                )
+            );
+        }
+        let mut selected = if balance.is_enabled() {
+            renamed.balance(countries, max_nodes, balance, &self.settings.score)
+        } else {
+            renamed.select(countries, max_nodes, &self.settings.score)
+        };
+        selected.merge(&pinned);
+        info!(
+            "{}",
+            tr!(
+                "按国家 {:?}、数量上限 {:?} 筛选后节点个数：{}",
+                "Nodes after filtering by countries {:?} and max nodes {:?}: {}",
+                countries,
+                max_nodes,
+                selected.proxies.len()
+            )
+        );
+        if selected.proxies.is_empty() {
+            return Err(PipelineError::NoAliveProxies);
+        }
+        Ok(selected)
+    }
+
+    /// release 文件中按网站、标签及 ISP 生成的代理组
+    fn release_groups(&self, renamed: &RenameResult) -> Vec<ProxyGroup> {
+        let test_url = &self.settings.connect_test.url;
+        let mut groups =
+            renamed.top_groups(&self.settings.websites, &self.settings.score, test_url);
+        groups.extend(renamed.tag_groups(&self.settings.tags, test_url));
+        if self.settings.group_by_isp {
+            groups.extend(renamed.isp_groups(test_url));
+        }
+        groups
+    }
+
+    /// 开启 release_history 时保存 release 文件的带时间戳副本，供 /api/releases 查询，失败时只记录日志
+    fn archive_release(&self, path: &Path) {
+        if self.settings.release_history == 0 {
+            return;
+        }
+        match backup_release(
+            path,
+            Path::new(RELEASE_HISTORY_PATH),
+            self.settings.release_history,
+        ) {
+            Ok(backup) => info!(
+                "{}",
+                tr!(
+                    "release 文件已备份至：{}",
+                    "Release file backed up to: {}",
+                    backup.display()
+                )
+            ),
+            Err(e) => warn!(
+                "{}",
+                tr!(
+                    "备份 release 文件失败: {}",
+                    "Failed to back up the release file: {}",
+                    e
+                )
+            ),
+        }
+    }
+
+    /// 按指定格式导出，未指定路径时保存在当前目录下
+    pub async fn export_as(
+        &self,
+        renamed: &RenameResult,
+        format: ExportFormat,
+        path: Option<PathBuf>,
+    ) -> Result<ExportResult, PipelineError> {
+        let renamed = self.select(renamed)?;
+        let path = path.unwrap_or_else(|| match format {
+            ExportFormat::Clash => self.release_path.clone(),
+            _ => self.release_path.with_file_name(format.default_file_name()),
+        });
+        let content = format.render_with_groups(&renamed.proxies, self.release_groups(&renamed))?;
+        let content = self.with_metadata(format, renamed.proxies.len(), content);
+        write_atomic(&path, content.as_bytes())?;
+        info!(
+            "{}",
+            tr!("release 文件地址：{}", "Release file: {}", path.display())
+        );
+        self.archive_release(&path);
+
+        let mut files = vec![path];
+        if self.settings.markdown.enabled {
+            let path = report::save_markdown(
+                &self.settings.markdown,
+                &renamed.reports,
+                &self.settings.score,
+                self.summary().baseline.as_ref(),
+            )?;
+            info!(
+                "{}",
+                tr!(
+                    "markdown 汇总文件地址：{}",
+                    "Markdown summary: {}",
+                    path.display()
+                )
+            );
+            files.push(path);
+        }
+        if self.settings.bundle.enabled {
+            match self.save_bundle(&files, &renamed.reports) {
+                Ok(bundles) => {
+                    info!(
+                        "{}",
+                        tr!(
+                            "打包文件地址：{}",
+                            "Bundle files: {}",
+                            bundles
+                                .iter()
+                                .map(|p| p.display().to_string())
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        )
+                    );
+                    files.extend(bundles);
+                }
+                Err(e) => warn!(
+                    "{}",
+                    tr!(
+                        "打包导出文件失败: {}",
+                        "Failed to bundle the exported files: {}",
+                        e
+                    )
+                ),
+            }
+        }
+        upload::upload_files(&self.settings.uploads, &files).await;
+        self.update_summary(|s| {
+            s.exported = renamed.proxies.len();
+            s.files = files.clone();
+        });
+        Ok(ExportResult { files })
+    }
+}
+
+/// 不做任何测试，直接转换节点文件格式，返回转换的节点数
+pub fn convert(
+    input: &Path,
+    output: &Path,
+    format: Option<ExportFormat>,
+) -> Result<usize, PipelineError> {
+    let mut proxies = load_proxies(input)?;
+    SubManager::rename_dup_proxies_name(&mut proxies);
+    write_proxies(&proxies, output, format)?;
+    Ok(proxies.len())
+}
+
+/// 合并多个本地或远程订阅，去重后保存到一个文件中
+pub async fn merge(
+    subs: &[String],
+    output: &Path,
+    format: Option<ExportFormat>,
+) -> Result<DedupStats, PipelineError> {
+    let mut proxies = Vec::new();
+    for sub in subs {
+        let sub_proxies = SubManager::get_proxies_from_url(sub.to_string()).await;
+        info!(
+            "{}",
+            tr!("{} 节点个数：{}", "{} nodes: {}", sub, sub_proxies.len())
+        );
+        proxies.extend(sub_proxies);
+    }
+    if proxies.is_empty() {
+        return Err(PipelineError::NoProxies);
+    }
+    let (mut proxies, stats) = SubManager::dedupe_with_stats(proxies);
+    SubManager::rename_dup_proxies_name(&mut proxies);
+    write_proxies(&proxies, output, format)?;
+    Ok(stats)
+}
+
+/// 按节点指纹（地址、端口及认证信息）去重
+pub fn dedupe(
+    input: &Path,
+    output: &Path,
+    format: Option<ExportFormat>,
+) -> Result<DedupStats, PipelineError> {
+    let (mut proxies, stats) = SubManager::dedupe_with_stats(load_proxies(input)?);
+    SubManager::rename_dup_proxies_name(&mut proxies);
+    write_proxies(&proxies, output, format)?;
+    Ok(stats)
+}
+
+/// 未指定格式时根据输出文件扩展名推断，无法推断时输出分享链接
+fn write_proxies(
+    proxies: &Vec<Proxy>,
+    output: &Path,
+    format: Option<ExportFormat>,
+) -> Result<(), PipelineError> {
+    let format = format
+        .or_else(|| ExportFormat::from_path(output))
+        .unwrap_or(ExportFormat::Links);
+    write_atomic(output, format.render(proxies)?.as_bytes())?;
+    Ok(())
+}
+
+fn load_proxies(path: &Path) -> Result<Vec<Proxy>, PipelineError> {
+    let proxies = SubManager::parse_from_path(path)
+        .map_err(|e| PipelineError::Load(format!("{}: {}", path.display(), e)))?;
+    if proxies.is_empty() {
+        return Err(PipelineError::NoProxies);
+    }
+    Ok(proxies)
+}
+
+fn metadata_header(nodes: usize, sources: usize) -> String {
+    format!(
+        "# clash-butler:\n#   generated_at: {}\n#   nodes: {}\n#   sources: {}\n#   version: {}\n",
+        Local::now().format("%Y-%m-%dT%H:%M:%S%:z"),
+        nodes,
+        sources,
+        env!("CARGO_PKG_VERSION")
+    )
+}
+
+fn save_proxies(proxies: &Vec<Proxy>, template: &str, path: &Path) -> Result<(), PipelineError> {
+    let content = SubManager::get_clash_config_content(template.to_string(), proxies)?;
+    write_atomic(path, content.as_bytes())?;
+    Ok(())
+}
+
+/// 按平均延迟选出需要测速的节点，limit 为 None 时全部测速
+fn speed_candidates(tested: &TestResult, limit: Option<usize>) -> HashSet<String> {
+    let mut candidates = tested
+        .proxies
+        .iter()
+        .map(|p| (p.get_name(), tested.average_latency(p.get_name())))
+        .collect::<Vec<_>>();
+    // 没有延迟数据的节点排在最后
+    candidates.sort_by_key(|(_, latency)| latency.unwrap_or(i64::MAX));
+    candidates
+        .into_iter()
+        .take(limit.unwrap_or(usize::MAX))
+        .map(|(name, _)| name.to_string())
+        .collect()
+}
+
+/// 将 release 文件复制到历史目录，文件名加上时间戳，只保留最近 keep 份
+fn backup_release(path: &Path, history_dir: &Path, keep: usize) -> std::io::Result<PathBuf> {
+    fs::create_dir_all(history_dir)?;
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = path
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+    let prefix = format!("{}-", stem);
+    let backup = history_dir.join(format!(
+        "{}{}{}",
+        prefix,
+        Local::now().format("%Y%m%d-%H%M%S"),
+        ext
+    ));
+    fs::copy(path, &backup)?;
+
+    let mut backups = list_backups(history_dir, &prefix, &ext)?;
+    backups.sort();
+    let expired = backups.len().saturating_sub(keep);
+    for (name, _) in &backups[..expired] {
+        fs::remove_file(history_dir.join(name))?;
+    }
+    Ok(backup)
+}
+
+/// 历史目录中 {prefix}{时间戳}{ext} 形式的备份，返回文件名及时间戳，
+/// 时间戳格式保证按文件名排序即按时间排序
+fn list_backups(
+    history_dir: &Path,
+    prefix: &str,
+    ext: &str,
+) -> std::io::Result<Vec<(String, String)>> {
+    let backups = fs::read_dir(history_dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .filter_map(|name| {
+            let ts = name.strip_prefix(prefix)?.strip_suffix(ext)?;
+            is_backup_timestamp(ts).then(|| (name.clone(), ts.to_string()))
+        })
+        .collect();
+    Ok(backups)
+}
+
+/// 备份文件名中的时间戳，如 20240102-000000
+fn is_backup_timestamp(ts: &str) -> bool {
+    ts.len() == 15
+        && ts.bytes().enumerate().all(|(i, b)| {
+            if i == 8 {
+                b == b'-'
+            } else {
+                b.is_ascii_digit()
+            }
+        })
+}
+
+/// release 文件的一份历史备份
+#[derive(Debug, Clone, Serialize)]
+pub struct ReleaseSnapshot {
+    /// 备份时间，如 20240102-000000，按此获取备份内容
+    pub timestamp: String,
+    pub file: String,
+    pub size: u64,
+    /// 节点数，解析失败时为空
+    pub nodes: Option<usize>,
+}
+
+/// 历史目录中 Clash 格式 release 文件的所有备份，按时间从新到旧排列
+pub fn list_releases(history_dir: &Path) -> std::io::Result<Vec<ReleaseSnapshot>> {
+    let (prefix, ext) = release_backup_affixes();
+    let mut backups = list_backups(history_dir, &prefix, &ext)?;
+    backups.sort_by(|a, b| b.cmp(a));
+    let snapshots = backups
+        .into_iter()
+        .map(|(file, timestamp)| {
+            let path = history_dir.join(&file);
+            ReleaseSnapshot {
+                size: fs::metadata(&path).map_or(0, |m| m.len()),
+                nodes: load_proxies(&path).ok().map(|proxies| proxies.len()),
+                timestamp,
+                file,
+            }
+        })
+        .collect();
+    Ok(snapshots)
+}
+
+/// 指定时间戳的 Clash 格式 release 备份，不存在时返回 None
+pub fn find_release(history_dir: &Path, timestamp: &str) -> Option<PathBuf> {
+    if !is_backup_timestamp(timestamp) {
+        return None;
+    }
+    let (prefix, ext) = release_backup_affixes();
+    let path = history_dir.join(format!("{}{}{}", prefix, timestamp, ext));
+    path.is_file().then_some(path)
+}
+
+/// 默认 release 文件 clash.yaml 备份的文件名前缀及后缀
+fn release_backup_affixes() -> (String, String) {
+    let name = Path::new(ExportFormat::Clash.default_file_name());
+    let stem = name.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = name.extension().unwrap_or_default().to_string_lossy();
+    (format!("{}-", stem), format!(".{}", ext))
+}
+
+/// 与节点文件同名的 json 文件，保存该阶段的附加数据
+fn sidecar_path(path: &Path) -> PathBuf {
+    path.with_extension("json")
+}
+
+fn load_sidecar<T: DeserializeOwned>(path: &Path) -> Result<Option<T>, PipelineError> {
+    let sidecar = sidecar_path(path);
+    if !sidecar.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(&sidecar)?;
+    serde_json::from_str(&content)
+        .map(Some)
+        .map_err(|e| PipelineError::Load(format!("{}: {}", sidecar.display(), e)))
+}
+
+fn save_sidecar<T: Serialize>(path: &Path, data: &T) -> Result<(), PipelineError> {
+    let content = serde_json::to_string_pretty(data).map_err(std::io::Error::from)?;
+    write_atomic(sidecar_path(path), content.as_bytes())?;
+    Ok(())
+}
+
+/// 输出内核日志中新增的可识别错误，节点配置被拒绝时给出节点名称，proxies 与 config.yaml 中的顺序一致
+fn report_core_issues(core_log: &mut LogTail, proxies: &[Proxy]) {
+    for issue in core_log.issues() {
+        match &issue {
+            CoreIssue::ProxyRejected { index, reason } if *index < proxies.len() => error!(
+                "{}",
+                tr!(
+                    "内核拒绝了节点 {} 的配置：{}",
+                    "The clash core rejected proxy {}: {}",
+                    proxies[*index].get_name(),
+                    reason
+                )
+            ),
+            _ => error!("{}", issue),
+        }
+    }
+}
+
+/// 关闭 Clash 内核，失败时仅记录日志，不影响后续流程
+fn stop_core(clash_meta: ClashMeta) {
+    if let Err(e) = clash_meta.stop() {
+        warn!(
+            "{}",
+            tr!(
+                "关闭 Clash 内核失败, {}",
+                "Failed to stop the clash core, {}",
+                e
+            )
+        );
+    }
+}
+
+#[allow(dead_code)]
+fn get_top_node(test_results: &Vec<HashMap<String, i64>>) -> (String, i64) {
+    let mut combined_data: HashMap<String, Vec<i64>> = HashMap::new();
+    for test in test_results {
+        for (node, latency) in test {
+            combined_data
+                .entry(node.clone())
+                .or_default()
+                .push(*latency);
+        }
+    }
+    let node_stats: Vec<(String, i64)> = combined_data
+        .clone()
+        .into_iter()
+        .map(|(node, latencies)| {
+            let sum: i64 = latencies.iter().sum();
+            let count = latencies.len() as i64;
+            let mean = sum / count;
+            (node, mean)
+        })
+        .collect();
+    node_stats
+        .into_iter()
+        .min_by_key(|(_, mean)| *mean)
+        .unwrap()
+}
+
+async fn test_node_with_delay_config(
+    clash_meta: &ClashMeta,
+    delay_test_config: &DelayTestConfig,
+    early_stop: &EarlyStopConfig,
+    bar: &ProgressBar,
+) -> Vec<HashMap<String, i64>> {
+    info!(
+        "{}",
+        tr!("测试配置：{:?}", "Test config: {:?}", delay_test_config)
+    );
+    let mut delay_results = vec![];
+    let mut top = vec![];
+    let mut stable = 0;
+
+    // 预热，DNS lookup
+    for _ in 0..WARMUP_ROUND {
+        let _ = clash_meta
+            .test_group(TEST_PROXY_GROUP_NAME, delay_test_config)
+            .await;
+        bar.inc(1);
+    }
+
+    for n in 0..ROUND {
+        info!("{}", tr!("测试第 {} 轮", "Round {}", n + 1));
+        let result = clash_meta
+            .test_group(TEST_PROXY_GROUP_NAME, delay_test_config)
+            .await;
+
+        match result {
+            Ok(delay) => {
+                delay_results.push(delay.clone());
+                info!(
+                    "{}",
+                    tr!("有速度节点个数为：{}", "Nodes with delay: {}", delay.len())
+                )
+            }
+            Err(e) => {
+                // 保留空的一轮，每轮的原始延迟按轮次对齐
+                delay_results.push(HashMap::new());
+                info!(
+                    "{}",
+                    tr!(
+                        "当前测试轮完全没有速度, {}",
+                        "No node responded in this round, {}",
+                        e
+                    )
+                )
+            }
+        }
+        bar.inc(1);
+
+        if early_stop.enabled {
+            let current = top_nodes(&delay_results, early_stop.top_k);
+            stable = match (current.is_empty(), current == top) {
+                (true, _) => 0,
+                (false, true) => stable + 1,
+                (false, false) => 1,
+            };
+            top = current;
+            let remaining = ROUND - n - 1;
+            if stable >= early_stop.stable_rounds && remaining > 0 {
+                info!(
+                    "{}",
+                    tr!(
+                        "前 {} 个节点已连续 {} 轮不变，跳过剩余 {} 轮",
+                        "Top {} nodes unchanged for {} rounds, skipping the remaining {} rounds",
+                        early_stop.top_k,
+                        stable,
+                        remaining
+                    )
+                );
+                bar.inc(remaining as u64);
+                break;
+            }
+        }
+    }
+    delay_results
+}
+
+/// 按已完成轮次的平均延迟取最快的 k 个节点，返回排序后的名称，只比较成员不比较先后
+fn top_nodes(delay_results: &[HashMap<String, i64>], k: usize) -> Vec<String> {
+    let mut latencies: HashMap<&str, Vec<i64>> = HashMap::new();
+    for result in delay_results {
+        for (node, latency) in result {
+            latencies.entry(node).or_default().push(*latency);
+        }
+    }
+    let mut averages = latencies
+        .into_iter()
+        .map(|(node, samples)| (samples.iter().sum::<i64>() / samples.len() as i64, node))
+        .collect::<Vec<_>>();
+    averages.sort();
+    let mut top = averages
+        .into_iter()
+        .take(k)
+        .map(|(_, node)| node.to_string())
+        .collect::<Vec<_>>();
+    top.sort();
+    top
+}
+
+/// 按轮次排列每个节点的延迟，节点在某一轮没有结果时为 None
+fn latency_rounds(
+    proxies: &[Proxy],
+    delay_results: &[HashMap<String, i64>],
+) -> HashMap<String, Vec<Option<i64>>> {
+    proxies
+        .iter()
+        .map(|proxy| {
+            let name = proxy.get_name();
+            let rounds = delay_results
+                .iter()
+                .map(|result| result.get(name).copied())
+                .collect();
+            (name.to_string(), rounds)
+        })
+        .collect()
+}
+
+/// 入口国家未知时保留，已知时需要在每个国家列表中
+fn entry_country_allowed(country: Option<&str>, lists: &[Vec<String>]) -> bool {
+    let Some(country) = country else {
+        return true;
+    };
+    lists
+        .iter()
+        .all(|list| list.iter().any(|c| c.eq_ignore_ascii_case(country)))
+}
+
+/// 对所有轮次都失败的节点单独测试 DIAGNOSE_ROUND 轮，记录每轮的失败原因
+async fn diagnose_failures(
+    clash_meta: &ClashMeta,
+    names: &[&str],
+    delay_test_config: &DelayTestConfig,
+) -> BTreeMap<String, Vec<FailureReason>> {
+    if names.is_empty() {
+        return BTreeMap::new();
+    }
+    info!(
+        "{}",
+        tr!(
+            "诊断 {} 个不可用节点的失败原因",
+            "Diagnosing {} failed nodes",
+            names.len()
+        )
+    );
+    let failures = stream::iter(names)
+        .map(|&name| async move {
+            let mut reasons = vec![];
+            for _ in 0..DIAGNOSE_ROUND {
+                // 单独测试时偶尔成功说明节点不稳定，该轮不记录原因
+                if let Err(reason) = clash_meta.diagnose_proxy(name, delay_test_config).await {
+                    reasons.push(reason);
+                }
+            }
+            (name.to_string(), reasons)
+        })
+        .buffer_unordered(DIAGNOSE_CONCURRENCY)
+        .collect::<BTreeMap<_, _>>()
+        .await;
+
+    let mut counts: BTreeMap<FailureReason, usize> = BTreeMap::new();
+    for reasons in failures.values() {
+        for reason in reasons {
+            *counts.entry(*reason).or_default() += 1;
+        }
+    }
+    let counts = counts
+        .iter()
+        .map(|(reason, count)| format!("{} {}", reason, count))
+        .collect::<Vec<_>>()
+        .join(", ");
+    info!(
+        "{}",
+        tr!(
+            "不可用节点的失败原因：{}",
+            "Failure reasons of failed nodes: {}",
+            counts
+        )
+    );
+    failures
+}
+
+/// 平均延迟不超过 max_latency_ms，且 rounds 轮中成功轮数占比不低于 min_success_rate，未配置的条件不检查
+fn meets_thresholds(
+    samples: Option<&Vec<i64>>,
+    rounds: usize,
+    max_latency_ms: Option<u64>,
+    min_success_rate: Option<f64>,
+) -> bool {
+    if let Some(max) = max_latency_ms {
+        match report::average_latency(samples) {
+            Some(latency) if latency <= max as i64 => {}
+            _ => return false,
+        }
+    }
+    if let Some(min) = min_success_rate {
+        let success = samples.map_or(0, |s| s.len().min(rounds));
+        if (success as f64) < min * rounds as f64 {
+            return false;
+        }
+    }
+    true
+}
+
+/*
+获取所有已测速有过一次速度的节点
+ */
+fn get_all_tested_nodes(test_results: &Vec<HashMap<String, i64>>) -> Vec<String> {
+    let mut keys_set = HashSet::new();
+    for result in test_results {
+        for key in result.keys() {
+            keys_set.insert(key.clone());
+        }
+    }
+    keys_set.into_iter().collect()
+}
+
+/*
+获取测速稳定的节点
+ */
+#[allow(dead_code)]
+fn get_stable_tested_nodes(test_results: &Vec<HashMap<String, i64>>) -> Vec<String> {
+    // 合并所有测试数据
+    let mut combined_data: HashMap<String, Vec<i64>> = HashMap::new();
+    for test in test_results {
+        for (node, latency) in test {
+            combined_data
+                .entry(node.clone())
+                .or_default()
+                .push(*latency);
+        }
+    }
+
+    // 计算每个节点的平均延迟和标准差
+    let mut node_stats: Vec<(String, f64)> = combined_data
+        .clone()
+        .into_iter()
+        .filter_map(|(node, latencies)| {
+            let sum: i64 = latencies.iter().sum();
+            let count = latencies.len();
+            if count <= combined_data.len() / 2 {
+                None
+            } else {
+                let mean = sum as f64 / count as f64;
+                Some((node, mean))
+            }
+        })
+        .collect();
+
+    // 根据平均延迟对稳定的节点进行排序
+    node_stats.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+    node_stats.into_iter().map(|(node, _)| node).collect()
+}
+
+/// 创建订阅测试所用的目录结构
+pub fn create_folder() -> std::io::Result<()> {
+    for path in ["logs", "subs/test", "subs/release"] {
+        fs::create_dir_all(path)?;
+    }
+    Ok(())
+}
+
+/// 切换到数据目录，目录不存在时创建，之后的相对路径均基于该目录
+pub fn enter_data_dir(dir: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(dir)?;
+    seed_templates(&env::current_dir()?, dir)?;
+    std::env::set_current_dir(dir)
+}
+
+/// 启动内核前检查测试及 release 模板，有问题时直接报告，不必从内核日志中排查
+pub fn lint_templates() -> Result<(), PipelineError> {
+    let issues = [
+        (TEST_CLASH_TEMPLATE_PATH, TemplateKind::Test),
+        (RELEASE_CLASH_TEMPLATE_PATH, TemplateKind::Release),
+    ]
+    .into_iter()
+    .flat_map(|(path, kind)| {
+        template::lint(Path::new(path), kind)
+            .into_iter()
+            .map(move |issue| format!("{}: {}", path, issue))
+    })
+    .collect::<Vec<_>>();
+    if issues.is_empty() {
+        Ok(())
+    } else {
+        Err(PipelineError::Template(issues))
+    }
+}
+
+/// 数据目录中缺少内核配置模板时从原工作目录复制，容器挂载空的数据卷也能直接运行
+fn seed_templates(from: &Path, to: &Path) -> std::io::Result<()> {
+    for template in [TEST_CLASH_TEMPLATE_PATH, RELEASE_CLASH_TEMPLATE_PATH] {
+        let source = from.join(template);
+        let target = to.join(template);
+        if target.exists() || !source.is_file() {
+            continue;
+        }
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(&source, &target)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::DnsMode;
+    use crate::website;
+
+    #[test]
+    fn test_merge_hosts() {
+        let mut extra: Mapping = serde_yaml::from_str("hosts:\n  a.com: 10.0.0.1\n").unwrap();
+        let hosts = HashMap::from([
+            ("a.com".to_string(), "1.1.1.1".parse().unwrap()),
+            ("b.com".to_string(), "2.2.2.2".parse().unwrap()),
+        ]);
+        merge_hosts(&mut extra, &hosts);
+        let merged = extra["hosts"].as_mapping().unwrap();
+        assert_eq!(merged["a.com"].as_str(), Some("10.0.0.1"));
+        assert_eq!(merged["b.com"].as_str(), Some("2.2.2.2"));
+
+        let mut empty = Mapping::new();
+        merge_hosts(&mut empty, &HashMap::new());
+        assert!(!empty.contains_key("hosts"));
+    }
+
+    #[test]
+    fn test_add_lanes() {
+        let template = "proxies: []\nproxy-groups:\n  - name: PROXY\n    type: select\n    proxies: [HK]\nrules:\n  - MATCH,PROXY\n";
+        let mut config = template.parse::<ClashConfig>().unwrap();
+        add_lanes(&mut config, &[], NetStack::Ipv4);
+        assert_eq!(config.proxy_groups.len(), 1);
+        assert!(!config.extra.contains_key("listeners"));
+
+        add_lanes(&mut config, &[20001, 20002], NetStack::Ipv6);
+        let names = config
+            .proxy_groups
+            .iter()
+            .map(|g| g.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["PROXY", "PROXY-1", "PROXY-2"]);
+        assert_eq!(config.proxy_groups[2].proxies, vec!["HK"]);
+        let listeners = config.extra["listeners"].as_sequence().unwrap();
+        assert_eq!(listeners.len(), 2);
+        assert_eq!(listeners[1]["port"].as_u64(), Some(20002));
+        assert_eq!(listeners[1]["listen"].as_str(), Some("::"));
+        assert_eq!(listeners[1]["proxy"].as_str(), Some("PROXY-2"));
+    }
+
+    #[test]
+    fn test_apply_dns() {
+        let template = "dns:\n  enable: true\n  listen: 0.0.0.0:1053\n  enhanced-mode: fake-ip\n  nameserver: [https://223.5.5.5/dns-query]\n";
+        let mut extra: Mapping = serde_yaml::from_str(template).unwrap();
+        apply_dns(&mut extra, &TestDnsConfig::default());
+        assert_eq!(extra, serde_yaml::from_str::<Mapping>(template).unwrap());
+
+        let dns = TestDnsConfig {
+            nameservers: vec!["https://1.1.1.1/dns-query".to_string()],
+            enhanced_mode: Some(DnsMode::RedirHost),
+            ..Default::default()
+        };
+        apply_dns(&mut extra, &dns);
+        let dns = extra["dns"].as_mapping().unwrap();
+        assert_eq!(dns["listen"].as_str(), Some("0.0.0.0:1053"));
+        assert_eq!(dns["enhanced-mode"].as_str(), Some("redir-host"));
+        assert_eq!(
+            dns["nameserver"][0].as_str(),
+            Some("https://1.1.1.1/dns-query")
+        );
+        assert!(!dns.contains_key("fallback"));
+    }
+
+    #[test]
+    fn test_get_stable_nodes() {
+        // [
+        //     { "免费节点2": 829 },
+        //     { "免费节点3": 815, "免费节点2": 945, "免费节点1": 838 },
+        //     { "免费节点4": 835, "免费节点1": 850, "免费节点3": 819 },
+        //     { "免费节点1": 844, "免费节点3": 830, "免费节点2": 856 },
+        //     { "免费节点3": 857, "免费节点4": 796, "2": 911, "免费节点4": 816 },
+        //     { "免费节点1": 895, "免费节点3": 863, "免费节点4": 829 },
+        //     { "免费节点3": 837, "免费节点1": 809, "免费节点4": 849 },
+        //     { "免费节点3": 849, "免费节点2": 904, "免费节点4": 892 }
+        // ];
+
+        // 假设这是从十组测试中收集的数据
+        let test_data = vec![
+            HashMap::from([
+                ("node1".to_string(), 100),
+                ("node2".to_string(), 200),
+                ("node3".to_string(), 150),
+            ]),
+            HashMap::from([
+                ("node1".to_string(), 110),
+                ("node2".to_string(), 190),
+                ("node3".to_string(), 160),
+            ]),
+            HashMap::from([("node1".to_string(), 120), ("node3".to_string(), 10000)]),
+        ];
+
+        println!("{:?}", get_top_node(&test_data));
+    }
+
+    #[test]
+    fn test_entry_country_allowed() {
+        let lists = vec![
+            vec!["US".to_string(), "JP".to_string()],
+            vec!["us".to_string()],
+        ];
+        assert!(entry_country_allowed(Some("US"), &lists));
+        assert!(!entry_country_allowed(Some("JP"), &lists));
+        assert!(entry_country_allowed(None, &lists));
+        assert!(entry_country_allowed(Some("HK"), &[]));
+    }
+
+    #[test]
+    fn test_metadata_header() {
+        let header = metadata_header(50, 3);
+        let lines = header.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "# clash-butler:");
+        assert!(lines[1].starts_with("#   generated_at: "));
+        assert_eq!(lines[2], "#   nodes: 50");
+        assert_eq!(lines[3], "#   sources: 3");
+        assert_eq!(
+            lines[4],
+            format!("#   version: {}", env!("CARGO_PKG_VERSION"))
+        );
+        // 注释不影响配置解析
+        let content = header + "proxies: []\n";
+        assert!(serde_yaml::from_str::<serde_yaml::Value>(&content).is_ok());
+    }
+
+    #[test]
+    fn test_meets_thresholds() {
+        let flaky = vec![4800];
+        let stable = vec![100, 120, 110, 90, 130];
+        assert!(meets_thresholds(Some(&flaky), ROUND, None, None));
+        assert!(!meets_thresholds(Some(&flaky), ROUND, Some(1000), None));
+        assert!(!meets_thresholds(Some(&flaky), ROUND, None, Some(0.6)));
+        assert!(meets_thresholds(
+            Some(&stable),
+            ROUND,
+            Some(1000),
+            Some(1.0)
+        ));
+        assert!(meets_thresholds(
+            Some(&vec![100, 100, 100]),
+            ROUND,
+            None,
+            Some(0.6)
+        ));
+        assert!(!meets_thresholds(None, ROUND, Some(1000), None));
+        // 提前结束时按实际轮数计算成功率
+        assert!(meets_thresholds(
+            Some(&vec![100, 100, 100]),
+            3,
+            None,
+            Some(1.0)
+        ));
+    }
+
+    #[test]
+    fn test_top_nodes() {
+        let round = |pairs: &[(&str, i64)]| {
+            pairs
+                .iter()
+                .map(|(name, latency)| (name.to_string(), *latency))
+                .collect::<HashMap<_, _>>()
+        };
+        let results = vec![
+            round(&[("a", 100), ("b", 300), ("c", 200)]),
+            round(&[("a", 120), ("c", 500), ("d", 150)]),
+        ];
+        assert_eq!(top_nodes(&results, 2), vec!["a", "d"]);
+        assert_eq!(top_nodes(&results, 10), vec!["a", "b", "c", "d"]);
+        assert!(top_nodes(&[HashMap::new()], 2).is_empty());
+    }
+
+    #[test]
+    fn test_export_format_from_path() {
+        assert_eq!(
+            ExportFormat::from_path(Path::new("clash.yml")),
+            Some(ExportFormat::Clash)
+        );
+        assert_eq!(
+            ExportFormat::from_path(Path::new("out/singbox.json")),
+            Some(ExportFormat::Singbox)
+        );
+        assert_eq!(
+            ExportFormat::from_path(Path::new("links.txt")),
+            Some(ExportFormat::Links)
+        );
+        assert_eq!(ExportFormat::from_path(Path::new("links")), None);
+    }
+
+    #[test]
+    fn test_render_links() {
+        let link = "ss://YWVzLTEyOC1nY206ZDljNTc3MzI4ZmIzNDlmZQ==@120.232.73.68:40676#HK";
+        let proxies = vec![Proxy::from_link(link).unwrap()];
+        let content = ExportFormat::Links.render(&proxies).unwrap();
+        assert_eq!(content, link);
+        let content = ExportFormat::Base64.render(&proxies).unwrap();
+        assert_eq!(SubManager::parse_content(content).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_rename_result_select() {
+        let links = [
+            "ss://YWVzLTEyOC1nY206ZDljNTc3MzI4ZmIzNDlmZQ==@1.1.1.1:1001#US_1",
+            "ss://YWVzLTEyOC1nY206ZDljNTc3MzI4ZmIzNDlmZQ==@1.1.1.1:1002#US_2",
+            "ss://YWVzLTEyOC1nY206ZDljNTc3MzI4ZmIzNDlmZQ==@1.1.1.1:1003#HK_1",
+            "ss://YWVzLTEyOC1nY206ZDljNTc3MzI4ZmIzNDlmZQ==@1.1.1.1:1004#JP_1",
+        ];
+        let proxies = links
+            .iter()
+            .map(|l| Proxy::from_link(l).unwrap())
+            .collect::<Vec<_>>();
+        let reports = proxies
+            .iter()
+            .zip([300, 100, 200, 50])
+            .map(|(p, latency)| {
+                let mut r = NodeReport::new(p.get_name(), Some(latency));
+                r.country = Some(p.get_name()[..2].to_string());
+                r
+            })
+            .collect();
+        let renamed = RenameResult { proxies, reports };
+
+        let score = ScoreConfig::default();
+        let selected = renamed.select(&["us".to_string(), "HK".to_string()], Some(2), &score);
+        let names = selected
+            .proxies
+            .iter()
+            .map(|p| p.get_name())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["US_2", "HK_1"]);
+        assert_eq!(selected.reports.len(), 2);
+
+        assert_eq!(renamed.select(&[], None, &score).proxies.len(), 4);
+    }
+
+    #[test]
+    fn test_rename_result_balance() {
+        let nodes = (1..=8)
+            .map(|i| (format!("US_{}", i), i * 100))
+            .chain([
+                ("HK_1".to_string(), 500),
+                ("HK_2".to_string(), 600),
+                ("JP_1".to_string(), 900),
+            ])
+            .collect::<Vec<_>>();
+        let proxies = nodes
+            .iter()
+            .enumerate()
+            .map(|(i, (name, _))| {
+                Proxy::from_link(&format!(
+                    "ss://YWVzLTEyOC1nY206ZDljNTc3MzI4ZmIzNDlmZQ==@1.1.1.1:{}#{}",
+                    1001 + i,
+                    name
+                ))
+                .unwrap()
+            })
+            .collect::<Vec<_>>();
+        let reports = nodes
+            .iter()
+            .map(|(name, latency)| {
+                let mut r = NodeReport::new(name, Some(*latency));
+                r.country = Some(name[..2].to_string());
+                r
+            })
+            .collect();
+        let renamed = RenameResult { proxies, reports };
+        let score = ScoreConfig::default();
+        let names = |result: RenameResult| {
+            result
+                .proxies
+                .iter()
+                .map(|p| p.get_name().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        // 8 个美国节点中最多保留 3 个，共 6 个节点，美国占 50%
+        let share = BalanceConfig {
+            max_country_share: Some(0.5),
+            min_per_country: 0,
+        };
+        assert_eq!(
+            names(renamed.balance(&[], None, &share, &score)),
+            vec!["US_1", "US_2", "US_3", "HK_1", "HK_2", "JP_1"]
+        );
+
+        // 先为每个国家保留 1 个，剩余名额按评分分配
+        let min = BalanceConfig {
+            max_country_share: None,
+            min_per_country: 1,
+        };
+        assert_eq!(
+            names(renamed.balance(&[], Some(4), &min, &score)),
+            vec!["US_1", "US_2", "HK_1", "JP_1"]
+        );
+        assert_eq!(
+            names(renamed.balance(&["hk".to_string(), "JP".to_string()], Some(2), &min, &score)),
+            vec!["HK_1", "JP_1"]
+        );
+    }
+
+    #[test]
+    fn test_rename_result_isps() {
+        let proxies = ["US_1", "US_2", "HK_1", "JP_1"]
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                Proxy::from_link(&format!(
+                    "ss://YWVzLTEyOC1nY206ZDljNTc3MzI4ZmIzNDlmZQ==@1.1.1.1:{}#{}",
+                    1001 + i,
+                    name
+                ))
+                .unwrap()
+            })
+            .collect::<Vec<_>>();
+        let reports = proxies
+            .iter()
+            .zip([
+                Some("Cloudflare, Inc."),
+                Some("Akamai"),
+                None,
+                Some("Akamai"),
+            ])
+            .map(|(p, isp)| {
+                let mut r = NodeReport::new(p.get_name(), Some(100));
+                r.isp = isp.map(str::to_string);
+                r
+            })
+            .collect();
+        let renamed = RenameResult { proxies, reports };
+
+        let excluded = renamed.exclude_isps(&["cloudflare".to_string()]);
+        let names = excluded
+            .proxies
+            .iter()
+            .map(|p| p.get_name())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["US_2", "HK_1", "JP_1"]);
+
+        let groups = renamed.isp_groups("http://www.google.com/generate_204");
+        let groups = groups
+            .iter()
+            .map(|g| (g.name.as_str(), g.proxies.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            groups,
+            vec![
+                ("ISP-Akamai", vec!["US_2".to_string(), "JP_1".to_string()]),
+                ("ISP-Cloudflare, Inc.", vec!["US_1".to_string()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rename_result_tags() {
+        let proxies = ["US_1", "US_2", "HK_1"]
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                Proxy::from_link(&format!(
+                    "ss://YWVzLTEyOC1nY206ZDljNTc3MzI4ZmIzNDlmZQ==@{}.1.1.1:1001#{}",
+                    i + 1,
+                    name
+                ))
+                .unwrap()
+            })
+            .collect::<Vec<_>>();
+        let reports = proxies
+            .iter()
+            .zip([vec!["OpenAI", "Netflix"], vec!["OpenAI"], vec![]])
+            .map(|(p, checks)| {
+                let mut r = NodeReport::new(p.get_name(), Some(100));
+                r.country = Some(p.get_name()[..2].to_string());
+                r.checks = checks.into_iter().map(str::to_string).collect();
+                r
+            })
+            .collect();
+        let mut renamed = RenameResult { proxies, reports };
+        let rules = vec![
+            TagRule {
+                name: "gpt".to_string(),
+                checks: vec!["OpenAI".to_string()],
+                group: true,
+                ..Default::default()
+            },
+            TagRule {
+                name: "us-streaming".to_string(),
+                checks: vec!["OpenAI".to_string(), "Netflix".to_string()],
+                countries: vec!["us".to_string()],
+                group: true,
+                ..Default::default()
+            },
+            TagRule {
+                name: "home".to_string(),
+                servers: vec!["3.1.1.1".to_string()],
+                ..Default::default()
+            },
+        ];
+        renamed.apply_tags(&rules);
+        let tags = renamed
+            .reports
+            .iter()
+            .map(|r| r.tags.join(","))
+            .collect::<Vec<_>>();
+        assert_eq!(tags, vec!["gpt,us-streaming", "gpt", "home"]);
+
+        let names = |r: RenameResult| {
+            r.proxies
+                .iter()
+                .map(|p| p.get_name().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(renamed.with_tags(&["home".to_string(), "us-streaming".to_string()])),
+            vec!["US_1", "HK_1"]
+        );
+        assert_eq!(names(renamed.with_tags(&[])).len(), 3);
+
+        let groups = renamed
+            .tag_groups(&rules, "http://www.google.com/generate_204")
+            .into_iter()
+            .map(|g| (g.name, g.proxies))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            groups,
+            vec![
+                (
+                    "Tag-gpt".to_string(),
+                    vec!["US_1".to_string(), "US_2".to_string()]
+                ),
+                ("Tag-us-streaming".to_string(), vec!["US_1".to_string()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rename_result_top_groups() {
+        let proxies = ["US_1", "US_2", "HK_1"]
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                Proxy::from_link(&format!(
+                    "ss://YWVzLTEyOC1nY206ZDljNTc3MzI4ZmIzNDlmZQ==@1.1.1.1:{}#{}",
+                    1001 + i,
+                    name
+                ))
+                .unwrap()
+            })
+            .collect::<Vec<_>>();
+        let reports = proxies
+            .iter()
+            .zip([(300, true), (100, true), (50, false)])
+            .map(|(p, (latency, openai))| {
+                let mut r = NodeReport::new(p.get_name(), Some(latency));
+                if openai {
+                    r.checks = vec!["OpenAI".to_string()];
+                }
+                r
+            })
+            .collect();
+        let renamed = RenameResult { proxies, reports };
+
+        let mut websites = website::default_websites();
+        websites[0].top_n = Some(1);
+        websites[1].top_n = Some(3);
+        let groups = renamed.top_groups(
+            &websites,
+            &ScoreConfig::default(),
+            "http://www.google.com/generate_204",
+        );
+        // 没有节点通过 Claude 检测，不生成策略组
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].name, "Top-OpenAI");
+        assert_eq!(groups[0].proxies, vec!["US_2"]);
+    }
+
+    #[test]
+    fn test_rename_result_from_test_result() {
+        let proxy = Proxy::from_link(
+            "ss://YWVzLTEyOC1nY206ZDljNTc3MzI4ZmIzNDlmZQ==@120.232.73.68:40676#HK",
+        )
+        .unwrap();
+        let tested = TestResult {
+            proxies: vec![proxy],
+            latencies: HashMap::from([("HK".to_string(), vec![100, 300])]),
+            rounds: HashMap::from([("HK".to_string(), vec![Some(100), None, Some(300)])]),
+        };
+        let renamed = RenameResult::from(tested);
+        assert_eq!(renamed.proxies.len(), 1);
+        assert_eq!(renamed.reports[0].name, "HK");
+        assert_eq!(renamed.reports[0].latency, Some(200));
+        assert_eq!(renamed.reports[0].rounds, vec![Some(100), None, Some(300)]);
+    }
+
+    #[test]
+    fn test_render_preview() {
+        let before = [
+            "ss://YWVzLTEyOC1nY206ZDljNTc3MzI4ZmIzNDlmZQ==@1.1.1.1:1001#a",
+            "ss://YWVzLTEyOC1nY206ZDljNTc3MzI4ZmIzNDlmZQ==@1.1.1.1:1002#b",
+        ]
+        .iter()
+        .map(|l| Proxy::from_link(l).unwrap())
+        .collect::<Vec<_>>();
+        let mut proxy = before[0].clone();
+        proxy.set_name("US_Cloudflare_OpenAI");
+        let mut report = NodeReport::new("US_Cloudflare_OpenAI", Some(100));
+        report.country = Some("US".to_string());
+        report.isp = Some("Cloudflare".to_string());
+        report.checks = vec!["OpenAI".to_string()];
+        let renamed = RenameResult {
+            proxies: vec![proxy],
+            reports: vec![report],
+        };
+
+        let lines = renamed
+            .render_preview(&before)
+            .lines()
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(
+            lines[2],
+            "| a | US_Cloudflare_OpenAI | US | - | Cloudflare | - | OpenAI | - |"
+        );
+        assert_eq!(lines[3], "| b | - | - | - | - | - | - | - |");
+    }
+
+    #[test]
+    fn test_rename_result_select_by_source() {
+        let links = [
+            "ss://YWVzLTEyOC1nY206ZDljNTc3MzI4ZmIzNDlmZQ==@1.1.1.1:1001#US_1",
+            "ss://YWVzLTEyOC1nY206ZDljNTc3MzI4ZmIzNDlmZQ==@1.1.1.1:1002#HK_1",
+            "ss://YWVzLTEyOC1nY206ZDljNTc3MzI4ZmIzNDlmZQ==@1.1.1.1:1003#US_2",
+            "ss://YWVzLTEyOC1nY206ZDljNTc3MzI4ZmIzNDlmZQ==@1.1.1.1:1004#JP_1",
+        ];
+        let proxies = links
+            .iter()
+            .map(|l| Proxy::from_link(l).unwrap())
+            .collect::<Vec<_>>();
+        let reports = proxies
+            .iter()
+            .map(|p| {
+                let mut r = NodeReport::new(p.get_name(), Some(100));
+                r.country = Some(p.get_name()[..2].to_string());
+                r
+            })
+            .collect();
+        let sources = vec![
+            SubConfig {
+                prefix: Some("A_".to_string()),
+                countries: vec!["US".to_string()],
+                ..SubConfig::new("https://a.example.com")
+            },
+            SubConfig::new("https://b.example.com"),
+        ];
+        // JP_1 没有来源，保持不变
+        let origins = proxies[..3]
+            .iter()
+            .cloned()
+            .zip([0, 0, 1])
+            .collect::<HashMap<_, _>>();
+        let renamed = RenameResult { proxies, reports };
+
+        let selected = renamed.select_by_source(&sources, &origins, &ScoreConfig::default());
+        let names = selected
+            .proxies
+            .iter()
+            .map(|p| p.get_name())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["A_US_1", "US_2", "JP_1"]);
+        assert_eq!(selected.reports[0].name, "A_US_1");
+    }
+
+    #[test]
+    fn test_speed_candidates() {
+        let proxies = [
+            "ss://YWVzLTEyOC1nY206ZDljNTc3MzI4ZmIzNDlmZQ==@1.1.1.1:1001#a",
+            "ss://YWVzLTEyOC1nY206ZDljNTc3MzI4ZmIzNDlmZQ==@1.1.1.1:1002#b",
+            "ss://YWVzLTEyOC1nY206ZDljNTc3MzI4ZmIzNDlmZQ==@1.1.1.1:1003#c",
+        ]
+        .iter()
+        .map(|l| Proxy::from_link(l).unwrap())
+        .collect::<Vec<_>>();
+        let latencies = HashMap::from([
+            ("a".to_string(), vec![300]),
+            ("b".to_string(), vec![100, 200]),
+        ]);
+        let tested = TestResult {
+            proxies,
+            latencies,
+            ..Default::default()
+        };
+
+        assert_eq!(speed_candidates(&tested, None).len(), 3);
+        assert_eq!(
+            speed_candidates(&tested, Some(2)),
+            HashSet::from(["a".to_string(), "b".to_string()])
+        );
+        assert_eq!(
+            speed_candidates(&tested, Some(1)),
+            HashSet::from(["b".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_tested_cache() {
+        let link = |port: u16, name: &str| {
+            Proxy::from_link(&format!(
+                "ss://YWVzLTEyOC1nY206ZDljNTc3MzI4ZmIzNDlmZQ==@1.1.1.1:{}#{}",
+                port, name
+            ))
+            .unwrap()
+        };
+        let cache = TestedCache::default();
+        let first = vec![link(1001, "a"), link(1002, "b")];
+        let result = TestResult {
+            proxies: vec![first[0].clone()],
+            latencies: HashMap::from([("a".to_string(), vec![100, 120])]),
+            rounds: HashMap::from([
+                ("a".to_string(), vec![Some(100), Some(120)]),
+                ("b".to_string(), vec![None, None]),
+            ]),
+        };
+        cache.remember(&first, &result);
+
+        // 后续分组中同一节点换了名称，沿用结果时使用新名称
+        let second = vec![link(1001, "a 2"), link(1002, "b 2"), link(1003, "c")];
+        let (reused, untested) = cache.reuse(&second);
+        assert_eq!(reused.proxies, vec![second[0].clone()]);
+        assert_eq!(reused.proxies[0].get_name(), "a 2");
+        assert_eq!(reused.latencies["a 2"], vec![100, 120]);
+        assert_eq!(reused.rounds["b 2"], vec![None, None]);
+        assert!(!reused.latencies.contains_key("b 2"));
+        assert_eq!(untested, vec![second[2].clone()]);
+
+        cache.clear();
+        let (reused, untested) = cache.reuse(&second);
+        assert!(reused.proxies.is_empty() && reused.rounds.is_empty());
+        assert_eq!(untested.len(), 3);
+    }
+
+    #[test]
+    fn test_backup_release() {
+        let dir = env::temp_dir().join("clash_butler_test_backup_release");
+        let history = dir.join("history");
+        fs::create_dir_all(&history).unwrap();
+        let release = dir.join("clash.yaml");
+        fs::write(&release, "proxies: []").unwrap();
+        for name in [
+            "clash-20240101-000000.yaml",
+            "clash-20240102-000000.yaml",
+            "clash.json",
+        ] {
+            fs::write(history.join(name), "").unwrap();
+        }
+
+        let backup = backup_release(&release, &history, 2).unwrap();
+        assert_eq!(fs::read_to_string(&backup).unwrap(), "proxies: []");
+        let mut names = fs::read_dir(&history)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names.len(), 3);
+        assert_eq!(names[0], "clash-20240102-000000.yaml");
+        assert!(names.contains(&"clash.json".to_string()));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_list_releases() {
+        let dir = env::temp_dir().join("clash_butler_test_list_releases");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let content = "proxies:\n  - {name: a, type: ss, server: 1.2.3.4, port: 8388, cipher: aes-256-gcm, password: pwd}\n";
+        fs::write(dir.join("clash-20240101-000000.yaml"), content).unwrap();
+        fs::write(dir.join("clash-20240102-000000.yaml"), "proxies: []").unwrap();
+        fs::write(dir.join("clash-2024010x-000000.yaml"), "").unwrap();
+        fs::write(dir.join("singbox-20240102-000000.json"), "").unwrap();
+
+        let releases = list_releases(&dir).unwrap();
+        assert_eq!(releases.len(), 2);
+        assert_eq!(releases[0].timestamp, "20240102-000000");
+        assert_eq!(releases[0].nodes, None);
+        assert_eq!(releases[1].nodes, Some(1));
+
+        assert!(find_release(&dir, "20240101-000000").is_some());
+        assert!(find_release(&dir, "20240103-000000").is_none());
+        assert!(find_release(&dir, "../../etc/pass").is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_free_port() {
+        let listener = TcpListener::bind(("0.0.0.0", 0)).unwrap();
+        let used = listener.local_addr().unwrap().port();
+        let port = free_port(NetStack::Ipv4.unspecified(), used, false);
+        assert_ne!(port, used);
+        assert!(TcpListener::bind(("0.0.0.0", port)).is_ok());
+    }
+
+    #[test]
+    fn test_seed_templates() {
+        let dir = env::temp_dir().join("clash_butler_test_seed_templates");
+        let _ = fs::remove_dir_all(&dir);
+        let data = dir.join("data");
+        fs::create_dir_all(data.join("conf")).unwrap();
+        // 已存在的模板不会被覆盖
+        fs::write(data.join(RELEASE_CLASH_TEMPLATE_PATH), "custom").unwrap();
+
+        seed_templates(Path::new(env!("CARGO_MANIFEST_DIR")), &data).unwrap();
+        assert!(data.join(TEST_CLASH_TEMPLATE_PATH).is_file());
+        assert_eq!(
+            fs::read_to_string(data.join(RELEASE_CLASH_TEMPLATE_PATH)).unwrap(),
+            "custom"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_sample_candidates() {
+        let proxies = (1..=4)
+            .map(|i| {
+                let link = format!(
+                    "ss://YWVzLTEyOC1nY206ZDljNTc3MzI4ZmIzNDlmZQ==@1.1.1.1:100{}#N_{}",
+                    i, i
+                );
+                Proxy::from_link(&link).unwrap()
+            })
+            .collect::<Vec<_>>();
+        let mut history = NodeHistory::default();
+        let now = Local::now();
+        history.seen(&proxies[..1], now - chrono::Duration::days(3));
+        history.seen(&proxies[1..2], now - chrono::Duration::days(1));
+        let names = |proxies: &[Proxy]| {
+            proxies
+                .iter()
+                .map(|p| p.get_name().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        // 从未出现的节点最优先，其余从新到旧
+        let (kept, dropped) = sample_candidates(proxies.clone(), 3, SampleMode::Newest, &history);
+        assert_eq!(names(&kept), vec!["N_3", "N_4", "N_2"]);
+        assert_eq!(names(&dropped), vec!["N_1"]);
+
+        let (kept, dropped) = sample_candidates(proxies.clone(), 2, SampleMode::Random, &history);
+        assert_eq!(kept.len(), 2);
+        assert_eq!(dropped.len(), 2);
+        assert!(kept.iter().all(|p| !dropped.contains(p)));
+
+        let (kept, dropped) = sample_candidates(proxies, 10, SampleMode::Random, &history);
+        assert_eq!(names(&kept), vec!["N_1", "N_2", "N_3", "N_4"]);
+        assert!(dropped.is_empty());
+    }
+
+    #[test]
+    fn test_active_sources() {
+        let mut history = NodeHistory::default();
+        let run = |alive| SourceRun {
+            at: Local::now(),
+            ok: true,
+            unique: 5,
+            alive,
+        };
+        for _ in 0..3 {
+            history.record_source("https://idle.example.com", run(0));
+        }
+        history.record_source("https://good.example.com", run(3));
+        let sources = vec![
+            SubConfig::new("https://idle.example.com"),
+            SubConfig::new("https://good.example.com"),
+            SubConfig::new("https://new.example.com"),
+        ];
+
+        assert_eq!(active_sources(sources.clone(), &history, None).len(), 3);
+        assert_eq!(active_sources(sources.clone(), &history, Some(4)).len(), 3);
+        let active = active_sources(sources.clone(), &history, Some(3));
+        assert_eq!(active, sources[1..]);
+        // 全部需要跳过时保留所有订阅
+        assert_eq!(
+            active_sources(sources[..1].to_vec(), &history, Some(3)).len(),
+            1
+        );
+
+        let table = render_source_health(&sources, &history);
+        let lines = table.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[2], "| good.example.com | 1 | 100% | 5.0 | 3.0 | 0 |");
+        assert_eq!(lines[3], "| idle.example.com | 3 | 100% | 5.0 | 0.0 | 3 |");
+    }
+
+    #[test]
+    fn test_render_sources() {
+        let sources = vec![
+            SourceStats {
+                sub: "a.example.com".to_string(),
+                bytes: 2048,
+                parsed: 10,
+                skipped: 2,
+                duplicates: 3,
+                sampled_out: 1,
+                error: None,
+            },
+            SourceStats {
+                sub: "b.example.com".to_string(),
+                error: Some("timeout".to_string()),
+                ..Default::default()
+            },
+        ];
+        let table = render_sources(&sources);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[2].starts_with("| a.example.com |"));
+        assert!(lines[2].ends_with("| 10 | 2 | 3 | 7 | 1 |"));
+        assert_eq!(lines[3], "| b.example.com | timeout | - | - | - | - | - |");
+    }
+}
@@ -0,0 +1,25 @@
+use std::time::Duration;
+
+use indicatif::ProgressBar;
+use indicatif::ProgressStyle;
+
+use crate::tr;
+
+const TEMPLATE: &str =
+    "{spinner:.green} {msg} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {pos}/{len}";
+
+/// 创建进度条，未开启时返回不显示的进度条，调用方无需区分
+pub fn new_bar(enabled: bool, len: u64, message: &str) -> ProgressBar {
+    if !enabled {
+        return ProgressBar::hidden();
+    }
+    let bar = ProgressBar::new(len);
+    bar.set_style(
+        ProgressStyle::with_template(&format!("{} {} {{eta}}", TEMPLATE, tr!("预计剩余", "ETA")))
+            .unwrap()
+            .progress_chars("=> "),
+    );
+    bar.set_message(message.to_string());
+    bar.enable_steady_tick(Duration::from_millis(200));
+    bar
+}
@@ -0,0 +1,170 @@
+use std::collections::HashSet;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::sync::RwLock;
+use std::thread;
+use std::time::Duration;
+
+use notify::EventKind;
+use notify::RecommendedWatcher;
+use notify::RecursiveMode;
+use notify::Watcher;
+use tracing::error;
+use tracing::info;
+use tracing::warn;
+
+use crate::settings::SettingsError;
+use crate::tr;
+use crate::Settings;
+
+/// 保存文件时编辑器可能连续触发多次事件，等待一段时间后只重新加载一次
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// 长期运行的进程共享的配置，重新加载后整体替换
+pub type SharedSettings = Arc<RwLock<Arc<Settings>>>;
+
+/// 监听配置文件变化并重新加载配置，drop 后停止监听
+pub struct SettingsWatcher {
+    settings: SharedSettings,
+    _watcher: RecommendedWatcher,
+}
+
+impl SettingsWatcher {
+    /// 监听 paths 中的配置文件，变化时调用 load 重新读取配置，读取失败时保留原配置
+    pub fn watch<F>(settings: Settings, paths: &[PathBuf], load: F) -> notify::Result<Self>
+    where
+        F: Fn() -> Result<Settings, SettingsError> + Send + 'static,
+    {
+        let settings: SharedSettings = Arc::new(RwLock::new(Arc::new(settings)));
+        let files = paths
+            .iter()
+            .map(|path| absolute(path))
+            .collect::<HashSet<_>>();
+
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<_>| {
+            let _ = tx.send(event);
+        })?;
+        // 编辑器保存时可能先删除再重建文件，因此监听所在目录而不是文件本身
+        let dirs = files
+            .iter()
+            .filter_map(|file| file.parent().map(Path::to_path_buf))
+            .filter(|dir| dir.is_dir())
+            .collect::<HashSet<_>>();
+        for dir in &dirs {
+            watcher.watch(dir, RecursiveMode::NonRecursive)?;
+        }
+
+        let shared = settings.clone();
+        thread::spawn(move || {
+            while let Ok(event) = rx.recv() {
+                if !is_config_change(&event, &files) {
+                    continue;
+                }
+                // 合并短时间内的多次变化
+                while rx.recv_timeout(DEBOUNCE).is_ok() {}
+                reload(&shared, &load);
+            }
+        });
+        info!(
+            "{}",
+            tr!(
+                "正在监听配置文件变化：{:?}",
+                "Watching config files: {:?}",
+                files
+            )
+        );
+
+        Ok(SettingsWatcher {
+            settings,
+            _watcher: watcher,
+        })
+    }
+
+    pub fn settings(&self) -> SharedSettings {
+        self.settings.clone()
+    }
+
+    /// 当前生效的配置
+    pub fn current(&self) -> Arc<Settings> {
+        self.settings.read().unwrap().clone()
+    }
+}
+
+fn reload<F>(settings: &SharedSettings, load: &F)
+where
+    F: Fn() -> Result<Settings, SettingsError>,
+{
+    let new = match load() {
+        Ok(new) => new,
+        Err(e) => {
+            error!(
+                "{}",
+                tr!(
+                    "配置文件重新加载失败，继续使用原配置",
+                    "Failed to reload the config file, keeping the current config"
+                )
+            );
+            for issue in e.issues() {
+                error!("  {}", issue);
+            }
+            return;
+        }
+    };
+    let changes = settings.read().unwrap().diff(&new);
+    if changes.is_empty() {
+        info!(
+            "{}",
+            tr!(
+                "配置文件已修改，但配置项没有变化",
+                "Config file modified, but no settings changed"
+            )
+        );
+        return;
+    }
+    info!(
+        "{}",
+        tr!(
+            "配置已重新加载，变化的配置项：",
+            "Config reloaded, changed settings:"
+        )
+    );
+    for change in &changes {
+        info!("  {}", change);
+    }
+    *settings.write().unwrap() = Arc::new(new);
+}
+
+fn is_config_change(event: &notify::Result<notify::Event>, files: &HashSet<PathBuf>) -> bool {
+    match event {
+        Ok(event) => {
+            matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_))
+                && event
+                    .paths
+                    .iter()
+                    .any(|path| files.contains(&absolute(path)))
+        }
+        Err(e) => {
+            warn!(
+                "{}",
+                tr!(
+                    "监听配置文件失败: {}",
+                    "Failed to watch the config file: {}",
+                    e
+                )
+            );
+            false
+        }
+    }
+}
+
+/// 转为绝对路径，文件不存在时无法 canonicalize，退回到拼接当前目录
+fn absolute(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| {
+        std::env::current_dir()
+            .map(|dir| dir.join(path))
+            .unwrap_or_else(|_| path.to_path_buf())
+    })
+}
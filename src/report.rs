@@ -0,0 +1,515 @@
+use std::cmp::Ordering;
+use std::io;
+use std::path::PathBuf;
+
+use chrono::DateTime;
+use chrono::Local;
+use proxrs::sub::write_atomic;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::clash::Traffic;
+use crate::ip::IpDetail;
+
+/// Markdown 汇总配置，生成的表格可直接嵌入 README
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct MarkdownConfig {
+    pub enabled: bool,
+    /// 表格中保留的节点数
+    pub top_n: usize,
+    pub path: String,
+}
+
+impl Default for MarkdownConfig {
+    fn default() -> Self {
+        MarkdownConfig {
+            enabled: false,
+            top_n: 20,
+            path: "nodes.md".to_string(),
+        }
+    }
+}
+
+/// 不经过代理直连测得的基准，用于对比不同机器、不同网络下的运行结果
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Baseline {
+    /// 直连访问 connect_test.url 的平均耗时，单位 ms
+    pub latency: Option<i64>,
+    /// 直连下载 speed_test.url 的速度，单位 KB/s，未开启测速时为 None
+    pub speed: Option<f64>,
+}
+
+impl Baseline {
+    /// 如 "120 ms, 10.00 MB/s"
+    pub fn describe(&self) -> String {
+        format!(
+            "{}, {}",
+            self.latency
+                .map_or("-".to_string(), |l| format!("{} ms", l)),
+            self.speed.map_or("-".to_string(), format_speed)
+        )
+    }
+}
+
+/// 单个节点的测试结果
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NodeReport {
+    pub name: String,
+    pub country: Option<String>,
+    pub city: Option<String>,
+    pub isp: Option<String>,
+    /// 平均延迟，单位 ms
+    pub latency: Option<i64>,
+    /// 下载速度，单位 KB/s
+    pub speed: Option<f64>,
+    /// 相邻两轮测试延迟差的平均值，单位 ms
+    pub jitter: Option<i64>,
+    /// 连通性测试中失败轮数的比例，0 ~ 1
+    pub loss: Option<f64>,
+    /// 连通性测试每一轮的延迟，单位 ms，失败的轮次为 null，用于离线分析波动及调整评分
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rounds: Vec<Option<i64>>,
+    /// 到 websites 中 tcp://、udp:// 目标的平均延迟，单位 ms，有值时评分使用该延迟
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rtt: Option<i64>,
+    /// 检测该节点时消耗的流量
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub traffic: Option<Traffic>,
+    /// 检测开始与结束时的出口 IP 不一致，通常是轮换的代理池，容易导致流媒体登录失效
+    #[serde(default)]
+    pub dynamic_ip: bool,
+    /// 节点服务器所在的 CDN，如 Cloudflare，此时延迟只反映到 CDN 边缘节点的距离
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cdn: Option<String>,
+    /// 通过的节点检测名称，如 OpenAI
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub checks: Vec<String>,
+    pub checked_at: DateTime<Local>,
+    /// 节点首次出现在订阅中的时间，见 history::NodeHistory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub first_seen: Option<DateTime<Local>>,
+    /// 节点所属订阅的名称，见 SubConfig::label，重复节点以先拉取的订阅为准
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// 满足的标签规则名称，见 Settings::tags
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+impl NodeReport {
+    pub fn new(name: &str, latency: Option<i64>) -> Self {
+        NodeReport {
+            name: name.to_string(),
+            country: None,
+            city: None,
+            isp: None,
+            latency,
+            speed: None,
+            jitter: None,
+            loss: None,
+            rounds: vec![],
+            rtt: None,
+            traffic: None,
+            dynamic_ip: false,
+            cdn: None,
+            checks: vec![],
+            checked_at: Local::now(),
+            first_seen: None,
+            source: None,
+            tags: vec![],
+        }
+    }
+
+    /// 由多轮连通性测试的延迟生成报告，rounds 为测试轮数，用于计算丢包率
+    pub fn from_samples(name: &str, samples: Option<&Vec<i64>>, rounds: usize) -> Self {
+        let mut report = NodeReport::new(name, average_latency(samples));
+        if let Some(samples) = samples {
+            if samples.len() > 1 {
+                let diff = samples.windows(2).map(|w| (w[1] - w[0]).abs()).sum::<i64>();
+                report.jitter = Some(diff / (samples.len() - 1) as i64);
+            }
+            if rounds > 0 {
+                report.loss = Some(1.0 - samples.len().min(rounds) as f64 / rounds as f64);
+            }
+        }
+        report
+    }
+
+    /// 评分使用的延迟，测过 tcp://、udp:// 目标时使用到目标的延迟
+    pub fn effective_latency(&self) -> Option<i64> {
+        self.rtt.or(self.latency)
+    }
+
+    pub fn with_ip_detail(mut self, ip_detail: &IpDetail) -> Self {
+        self.country = Some(ip_detail.country_code.clone());
+        self.city = Some(ip_detail.city.clone());
+        self.isp = Some(ip_detail.isp.clone());
+        self
+    }
+}
+
+/// 评分预设
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScorePreset {
+    #[default]
+    Balanced,
+    LatencyFirst,
+    BandwidthFirst,
+    /// 看视频更看重稳定性和带宽
+    Streaming,
+}
+
+/// 各项指标的权重
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScoreWeights {
+    pub latency: f64,
+    pub jitter: f64,
+    pub loss: f64,
+    pub speed: f64,
+}
+
+impl ScorePreset {
+    fn weights(&self) -> ScoreWeights {
+        let (latency, jitter, loss, speed) = match self {
+            ScorePreset::Balanced => (1.0, 0.5, 1.0, 1.0),
+            ScorePreset::LatencyFirst => (3.0, 1.0, 1.0, 0.5),
+            ScorePreset::BandwidthFirst => (0.5, 0.5, 1.0, 3.0),
+            ScorePreset::Streaming => (0.5, 1.0, 2.0, 2.0),
+        };
+        ScoreWeights {
+            latency,
+            jitter,
+            loss,
+            speed,
+        }
+    }
+}
+
+/// 节点评分配置，用于筛选、排序最终导出的节点及 Markdown 汇总
+///
+/// 各项指标先换算为 0 ~ 1 的分数，再按权重加权平均，填写的权重会覆盖预设中的值
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct ScoreConfig {
+    pub preset: ScorePreset,
+    pub latency: Option<f64>,
+    pub jitter: Option<f64>,
+    pub loss: Option<f64>,
+    pub speed: Option<f64>,
+}
+
+impl ScoreConfig {
+    pub fn weights(&self) -> ScoreWeights {
+        let preset = self.preset.weights();
+        ScoreWeights {
+            latency: self.latency.unwrap_or(preset.latency),
+            jitter: self.jitter.unwrap_or(preset.jitter),
+            loss: self.loss.unwrap_or(preset.loss),
+            speed: self.speed.unwrap_or(preset.speed),
+        }
+    }
+
+    /// 节点得分，越高越好
+    ///
+    /// 延迟 1 秒、抖动 100 ms、速度 1 MB/s 时对应的分数为 0.5，没有延迟或未测速时该项为 0，
+    /// 缺少抖动、丢包数据时不扣分
+    pub fn score(&self, report: &NodeReport) -> f64 {
+        let weights = self.weights();
+        let latency = report
+            .effective_latency()
+            .map_or(0.0, |l| 1000.0 / (1000.0 + l.max(0) as f64));
+        let jitter = report
+            .jitter
+            .map_or(1.0, |j| 100.0 / (100.0 + j.max(0) as f64));
+        let loss = report.loss.map_or(1.0, |l| 1.0 - l.clamp(0.0, 1.0));
+        let speed = report.speed.map_or(0.0, |s| s / (s + 1024.0));
+        let total = weights.latency + weights.jitter + weights.loss + weights.speed;
+        if total <= 0.0 {
+            return 0.0;
+        }
+        (weights.latency * latency
+            + weights.jitter * jitter
+            + weights.loss * loss
+            + weights.speed * speed)
+            / total
+    }
+
+    /// 得分高的排在前面，得分相同时按延迟排序
+    pub fn compare(&self, a: &NodeReport, b: &NodeReport) -> Ordering {
+        self.score(b)
+            .partial_cmp(&self.score(a))
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| match (a.effective_latency(), b.effective_latency()) {
+                (Some(x), Some(y)) => x.cmp(&y),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            })
+    }
+}
+
+/// 计算多轮测试的平均延迟
+pub fn average_latency(samples: Option<&Vec<i64>>) -> Option<i64> {
+    match samples {
+        Some(samples) if !samples.is_empty() => {
+            Some(samples.iter().sum::<i64>() / samples.len() as i64)
+        }
+        _ => None,
+    }
+}
+
+pub fn cell(value: Option<&str>) -> String {
+    match value {
+        Some(v) if !v.is_empty() => v.replace('|', "\\|"),
+        _ => "-".to_string(),
+    }
+}
+
+pub fn format_speed(speed: f64) -> String {
+    if speed >= 1024.0 {
+        format!("{:.2} MB/s", speed / 1024.0)
+    } else {
+        format!("{:.2} KB/s", speed)
+    }
+}
+
+/// 流量的可读形式，如 1.50 MB
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.2} {}", value, UNITS[unit])
+}
+
+/// 生成前 top_n 个节点的 Markdown 表格，有直连基准时在表格前注明
+pub fn render_markdown(
+    reports: &[NodeReport],
+    top_n: usize,
+    score: &ScoreConfig,
+    baseline: Option<&Baseline>,
+) -> String {
+    let mut sorted = reports.iter().collect::<Vec<_>>();
+    sorted.sort_by(|a, b| score.compare(a, b));
+
+    let mut content = String::new();
+    if let Some(baseline) = baseline {
+        content.push_str(&format!("> 本机直连基准：{}\n\n", baseline.describe()));
+    }
+    content
+        .push_str("| # | 节点 | 国家 | 城市 | ISP | 延迟 | 速度 | 来源 | 首次出现 | 检测时间 |\n");
+    content.push_str("|---|---|---|---|---|---|---|---|---|---|\n");
+    for (index, report) in sorted.into_iter().take(top_n).enumerate() {
+        content.push_str(&format!(
+            "| {} | {} | {} | {} | {} | {} | {} | {} | {} | {} |\n",
+            index + 1,
+            cell(Some(&report.name)),
+            cell(report.country.as_deref()),
+            cell(report.city.as_deref()),
+            cell(report.isp.as_deref()),
+            report
+                .latency
+                .map_or("-".to_string(), |l| format!("{} ms", l)),
+            report.speed.map_or("-".to_string(), format_speed),
+            cell(report.source.as_deref()),
+            report
+                .first_seen
+                .map_or("-".to_string(), |t| t.format("%Y-%m-%d").to_string()),
+            report.checked_at.format("%Y-%m-%d %H:%M"),
+        ));
+    }
+    content
+}
+
+/// 生成单个节点所有测试结果的 Markdown 表格，用于 probe 命令
+pub fn render_node(report: &NodeReport) -> String {
+    let ms = |value: Option<i64>| value.map_or("-".to_string(), |v| format!("{} ms", v));
+    let rounds = report
+        .rounds
+        .iter()
+        .map(|round| round.map_or("×".to_string(), |v| v.to_string()))
+        .collect::<Vec<_>>()
+        .join(" / ");
+    let rows = [
+        ("节点", cell(Some(&report.name))),
+        ("国家", cell(report.country.as_deref())),
+        ("城市", cell(report.city.as_deref())),
+        ("ISP", cell(report.isp.as_deref())),
+        ("延迟", ms(report.latency)),
+        ("抖动", ms(report.jitter)),
+        (
+            "丢包率",
+            report
+                .loss
+                .map_or("-".to_string(), |loss| format!("{:.0}%", loss * 100.0)),
+        ),
+        ("每轮延迟", cell(Some(&rounds))),
+        ("TCP/UDP 延迟", ms(report.rtt)),
+        ("速度", report.speed.map_or("-".to_string(), format_speed)),
+        ("通过检测", cell(Some(&report.checks.join(", ")))),
+        ("CDN", cell(report.cdn.as_deref())),
+        (
+            "动态 IP",
+            if report.dynamic_ip { "是" } else { "否" }.to_string(),
+        ),
+        (
+            "消耗流量",
+            report
+                .traffic
+                .as_ref()
+                .map_or("-".to_string(), |t| format_bytes(t.total())),
+        ),
+        (
+            "检测时间",
+            report.checked_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+        ),
+    ];
+    let mut content = String::from("| 项目 | 结果 |\n|---|---|\n");
+    for (key, value) in rows {
+        content.push_str(&format!("| {} | {} |\n", key, value));
+    }
+    content
+}
+
+/// 按配置保存 Markdown 汇总，返回文件路径
+pub fn save_markdown(
+    config: &MarkdownConfig,
+    reports: &[NodeReport],
+    score: &ScoreConfig,
+    baseline: Option<&Baseline>,
+) -> io::Result<PathBuf> {
+    let path = PathBuf::from(&config.path);
+    write_atomic(
+        &path,
+        render_markdown(reports, config.top_n, score, baseline).as_bytes(),
+    )?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_average_latency() {
+        assert_eq!(average_latency(None), None);
+        assert_eq!(average_latency(Some(&vec![])), None);
+        assert_eq!(average_latency(Some(&vec![100, 200, 300])), Some(200));
+    }
+
+    #[test]
+    fn test_render_markdown() {
+        let mut fast = NodeReport::new("US_Los Angeles|Cloudflare", Some(300));
+        fast.country = Some("US".to_string());
+        fast.speed = Some(2048.0);
+        let mut slow = NodeReport::new("HK_01", Some(100));
+        slow.source = Some("pool".to_string());
+        let dead = NodeReport::new("JP_01", None);
+
+        let content = render_markdown(
+            &[dead.clone(), slow.clone(), fast.clone()],
+            2,
+            &ScoreConfig::default(),
+            None,
+        );
+        let lines = content.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[2]
+            .starts_with("| 1 | US_Los Angeles\\|Cloudflare | US | - | - | 300 ms | 2.00 MB/s |"));
+        assert!(lines[3].starts_with("| 2 | HK_01 | - | - | - | 100 ms | - | pool | - |"));
+
+        let baseline = Baseline {
+            latency: Some(20),
+            speed: None,
+        };
+        let content = render_markdown(
+            &[dead, slow, fast],
+            1,
+            &ScoreConfig::default(),
+            Some(&baseline),
+        );
+        assert!(content.starts_with("> 本机直连基准：20 ms, -\n\n| # |"));
+    }
+
+    #[test]
+    fn test_render_node() {
+        let mut report = NodeReport::new("HK|01", Some(120));
+        report.loss = Some(0.2);
+        report.rounds = vec![Some(100), None, Some(140)];
+        report.checks = vec!["OpenAI".to_string(), "Netflix".to_string()];
+        let content = render_node(&report);
+        let lines = content.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 17);
+        assert_eq!(lines[2], "| 节点 | HK\\|01 |");
+        assert!(lines.contains(&"| 延迟 | 120 ms |"));
+        assert!(lines.contains(&"| 丢包率 | 20% |"));
+        assert!(lines.contains(&"| 每轮延迟 | 100 / × / 140 |"));
+        assert!(lines.contains(&"| 通过检测 | OpenAI, Netflix |"));
+        assert!(lines.contains(&"| 速度 | - |"));
+    }
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(1536), "1.50 KB");
+        assert_eq!(format_bytes(100 * 1024 * 1024), "100.00 MB");
+        assert_eq!(format_bytes(3 * 1024 * 1024 * 1024), "3.00 GB");
+    }
+
+    #[test]
+    fn test_from_samples() {
+        let report = NodeReport::from_samples("HK", Some(&vec![100, 300, 200]), 5);
+        assert_eq!(report.latency, Some(200));
+        assert_eq!(report.jitter, Some(150));
+        assert_eq!(report.loss, Some(0.4));
+        let report = NodeReport::from_samples("HK", None, 5);
+        assert_eq!(
+            (report.latency, report.jitter, report.loss),
+            (None, None, None)
+        );
+    }
+
+    #[test]
+    fn test_score() {
+        let mut far = NodeReport::new("far", Some(1000));
+        far.speed = Some(4000.0);
+        let mut near = NodeReport::new("near", Some(100));
+        near.speed = Some(1000.0);
+        let dead = NodeReport::new("dead", None);
+
+        let latency_first = ScoreConfig {
+            preset: ScorePreset::LatencyFirst,
+            ..Default::default()
+        };
+        assert_eq!(latency_first.compare(&near, &far), Ordering::Less);
+        let bandwidth_first = ScoreConfig {
+            preset: ScorePreset::BandwidthFirst,
+            ..Default::default()
+        };
+        assert_eq!(bandwidth_first.compare(&near, &far), Ordering::Greater);
+        assert_eq!(bandwidth_first.compare(&far, &dead), Ordering::Less);
+
+        // 只看延迟
+        let custom = ScoreConfig {
+            latency: Some(1.0),
+            jitter: Some(0.0),
+            loss: Some(0.0),
+            speed: Some(0.0),
+            ..Default::default()
+        };
+        assert_eq!(custom.score(&far), 0.5);
+
+        // 到游戏服务器的延迟优先于连通性测试的延迟
+        let mut game = NodeReport::new("game", Some(1000));
+        game.rtt = Some(30);
+        assert_eq!(custom.compare(&game, &near), Ordering::Less);
+    }
+}
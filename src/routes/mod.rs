@@ -1,2 +1,5 @@
 pub mod config;
+pub mod nodes;
+pub mod releases;
 pub mod sub;
+pub mod trends;
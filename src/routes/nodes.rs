@@ -0,0 +1,249 @@
+use std::path::Path as FsPath;
+
+use axum::extract::Path;
+use axum::extract::State;
+use axum::http::header;
+use axum::http::HeaderMap;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::response::Response;
+use axum::routing::get;
+use axum::routing::post;
+use axum::Json;
+use axum::Router;
+use chrono::Local;
+use proxrs::protocol::Proxy;
+use serde::Serialize;
+use tracing::info;
+use tracing::warn;
+
+use crate::banlist::BanList;
+use crate::banlist::BAN_LIST_PATH;
+use crate::history::fingerprint;
+use crate::pinlist::PinList;
+use crate::pinlist::PIN_LIST_PATH;
+use crate::pipeline::RenameResult;
+use crate::pipeline::RENAMED_YAML_PATH;
+use crate::reload::SharedSettings;
+use crate::report::NodeReport;
+use crate::server::ReleaseHook;
+use crate::tr;
+
+#[derive(Clone)]
+struct NodesState {
+    settings: SharedSettings,
+    release: ReleaseHook,
+}
+
+/// 最近一次重命名结果中的节点及禁用、固定状态，发布后发现问题节点时可以禁用并立即重新生成 release 文件，
+/// 表现好的节点可以固定，之后的运行中即使测试失败也会保留
+pub fn nodes_router(settings: SharedSettings, release: ReleaseHook) -> Router {
+    Router::new()
+        .route("/api/nodes", get(list_handler))
+        .route("/api/nodes/:fingerprint/ban", post(ban_handler))
+        .route("/api/nodes/:fingerprint/unban", post(unban_handler))
+        .route("/api/nodes/:fingerprint/pin", post(pin_handler))
+        .route("/api/nodes/:fingerprint/unpin", post(unpin_handler))
+        .with_state(NodesState { settings, release })
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Action {
+    Ban,
+    Unban,
+    Pin,
+    Unpin,
+}
+
+#[derive(Serialize)]
+struct NodeEntry {
+    fingerprint: String,
+    name: String,
+    banned: bool,
+    pinned: bool,
+}
+
+#[derive(Serialize)]
+struct UpdateResponse {
+    fingerprint: String,
+    /// 禁用或固定状态是否有变化
+    changed: bool,
+    /// 重新生成的 release 文件中的节点数，没有重命名结果时为 None
+    exported: Option<usize>,
+}
+
+async fn list_handler() -> Response {
+    let bans = BanList::load(FsPath::new(BAN_LIST_PATH)).unwrap_or_default();
+    let pins = PinList::load(FsPath::new(PIN_LIST_PATH)).unwrap_or_default();
+    // 还没有完整运行过时没有重命名结果
+    let nodes = RenameResult::load(FsPath::new(RENAMED_YAML_PATH))
+        .map(|renamed| {
+            renamed
+                .proxies
+                .iter()
+                .map(|proxy| NodeEntry {
+                    fingerprint: fingerprint(proxy),
+                    name: proxy.get_name().to_string(),
+                    banned: bans.contains(proxy),
+                    pinned: pins.contains(proxy),
+                })
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    Json(nodes).into_response()
+}
+
+async fn ban_handler(
+    State(state): State<NodesState>,
+    Path(fingerprint): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    update(state, fingerprint, headers, Action::Ban).await
+}
+
+async fn unban_handler(
+    State(state): State<NodesState>,
+    Path(fingerprint): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    update(state, fingerprint, headers, Action::Unban).await
+}
+
+async fn pin_handler(
+    State(state): State<NodesState>,
+    Path(fingerprint): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    update(state, fingerprint, headers, Action::Pin).await
+}
+
+async fn unpin_handler(
+    State(state): State<NodesState>,
+    Path(fingerprint): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    update(state, fingerprint, headers, Action::Unpin).await
+}
+
+async fn update(state: NodesState, id: String, headers: HeaderMap, action: Action) -> Response {
+    if let Err(response) = authorize(&state.settings, &headers) {
+        return response;
+    }
+    let changed = match action {
+        Action::Ban | Action::Unban => update_bans(&id, action == Action::Ban),
+        Action::Pin | Action::Unpin => update_pins(&id, action == Action::Pin),
+    };
+    let changed = match changed {
+        Ok(changed) => changed,
+        Err(response) => return response,
+    };
+    if changed {
+        info!(
+            "{}",
+            match action {
+                Action::Ban => tr!("已禁用节点 {}", "Banned node {}", id),
+                Action::Unban => tr!("已解除禁用节点 {}", "Unbanned node {}", id),
+                Action::Pin => tr!("已固定节点 {}", "Pinned node {}", id),
+                Action::Unpin => tr!("已取消固定节点 {}", "Unpinned node {}", id),
+            }
+        );
+    }
+
+    // 导出会启动上传等耗时操作，放到独立线程中执行
+    let release = state.release.clone();
+    let exported = if !changed || !FsPath::new(RENAMED_YAML_PATH).exists() {
+        None
+    } else {
+        match tokio::task::spawn_blocking(move || release()).await {
+            Ok(Ok(count)) => Some(count),
+            Ok(Err(e)) => {
+                warn!(
+                    "{}",
+                    tr!(
+                        "重新生成 release 文件失败：{}",
+                        "Failed to regenerate the release file: {}",
+                        e
+                    )
+                );
+                return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response();
+            }
+            Err(e) => return internal_error(e),
+        }
+    };
+    Json(UpdateResponse {
+        fingerprint: id,
+        changed,
+        exported,
+    })
+    .into_response()
+}
+
+/// 禁用时记录重命名结果中的节点名称便于查看，返回禁用状态是否有变化
+fn update_bans(id: &str, ban: bool) -> Result<bool, Response> {
+    let path = FsPath::new(BAN_LIST_PATH);
+    let mut bans = BanList::load(path).map_err(internal_error)?;
+    let changed = if ban {
+        let name = find_node(id).map(|(proxy, _)| proxy.get_name().to_string());
+        bans.ban(id, name.as_deref(), Local::now())
+    } else {
+        bans.unban(id)
+    };
+    if changed {
+        bans.save(path).map_err(internal_error)?;
+    }
+    Ok(changed)
+}
+
+/// 固定时保存重命名结果中的节点配置及测试结果，节点不在其中时返回 404
+fn update_pins(id: &str, pin: bool) -> Result<bool, Response> {
+    let path = FsPath::new(PIN_LIST_PATH);
+    let mut pins = PinList::load(path).map_err(internal_error)?;
+    let changed = if pin {
+        let (proxy, report) = find_node(id)
+            .ok_or_else(|| (StatusCode::NOT_FOUND, "node not found").into_response())?;
+        pins.pin(&proxy, &report, Local::now())
+            .map_err(internal_error)?
+    } else {
+        pins.unpin(id)
+    };
+    if changed {
+        pins.save(path).map_err(internal_error)?;
+    }
+    Ok(changed)
+}
+
+/// 在最近一次的重命名结果中按指纹查找节点及其测试结果
+fn find_node(id: &str) -> Option<(Proxy, NodeReport)> {
+    let renamed = RenameResult::load(FsPath::new(RENAMED_YAML_PATH)).ok()?;
+    renamed
+        .proxies
+        .into_iter()
+        .zip(renamed.reports)
+        .find(|(proxy, _)| fingerprint(proxy) == id)
+}
+
+/// 未配置 secrets.api_token 时拒绝所有管理请求
+fn authorize(settings: &SharedSettings, headers: &HeaderMap) -> Result<(), Response> {
+    let settings = settings.read().unwrap().clone();
+    let token = &settings.secrets.api_token;
+    if token.is_empty() {
+        return Err((StatusCode::FORBIDDEN, "api_token not configured").into_response());
+    }
+    let expected = format!("Bearer {}", token.expose());
+    match headers.get(header::AUTHORIZATION) {
+        Some(value) if value.as_bytes() == expected.as_bytes() => Ok(()),
+        _ => Err(StatusCode::UNAUTHORIZED.into_response()),
+    }
+}
+
+fn internal_error(e: impl std::fmt::Display) -> Response {
+    warn!(
+        "{}",
+        tr!(
+            "更新节点列表失败：{}",
+            "Failed to update the node list: {}",
+            e
+        )
+    );
+    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+}
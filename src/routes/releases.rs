@@ -0,0 +1,65 @@
+use std::fs;
+use std::path::Path as FsPath;
+
+use axum::extract::Path;
+use axum::http::header;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::response::Response;
+use axum::routing::get;
+use axum::Json;
+use axum::Router;
+use tracing::warn;
+
+use crate::pipeline;
+use crate::pipeline::RELEASE_HISTORY_PATH;
+use crate::tr;
+
+/// release 文件的历史备份，便于当天节点质量很差时回退到之前的配置
+pub fn releases_router() -> Router {
+    Router::new()
+        .route("/api/releases", get(list_handler))
+        .route("/api/releases/:timestamp", get(download_handler))
+}
+
+async fn list_handler() -> Response {
+    match pipeline::list_releases(FsPath::new(RELEASE_HISTORY_PATH)) {
+        Ok(releases) => Json(releases).into_response(),
+        // 还没有备份过 release 文件时目录不存在
+        Err(_) => Json(Vec::<pipeline::ReleaseSnapshot>::new()).into_response(),
+    }
+}
+
+async fn download_handler(Path(timestamp): Path<String>) -> Response {
+    let Some(path) = pipeline::find_release(FsPath::new(RELEASE_HISTORY_PATH), &timestamp) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    match fs::read_to_string(&path) {
+        Ok(content) => {
+            let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+            (
+                [
+                    (header::CONTENT_TYPE, "text/yaml; charset=utf-8".to_string()),
+                    (
+                        header::CONTENT_DISPOSITION,
+                        format!("attachment; filename=\"{}\"", file_name),
+                    ),
+                ],
+                content,
+            )
+                .into_response()
+        }
+        Err(e) => {
+            warn!(
+                "{}",
+                tr!(
+                    "读取 release 备份 {} 失败：{}",
+                    "Failed to read release backup {}: {}",
+                    path.display(),
+                    e
+                )
+            );
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
@@ -1,6 +1,23 @@
 #![allow(unused)]
+use std::path::Path as FsPath;
+
+use axum::extract::Path;
+use axum::extract::Query;
+use axum::extract::State;
+use axum::http::header;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::response::Response;
 use axum::routing::get;
 use axum::Router;
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::pipeline::ExportFormat;
+use crate::pipeline::RenameResult;
+use crate::pipeline::RENAMED_YAML_PATH;
+use crate::reload::SharedSettings;
+use crate::tr;
 
 pub fn sub_router() -> Router {
     Router::new().route("/sub", get(sub_handler))
@@ -9,3 +26,69 @@ pub fn sub_router() -> Router {
 async fn sub_handler() -> &'static str {
     "Subscription handler"
 }
+
+/// 配置中 aliases 定义的订阅别名，每次请求时读取最新配置，修改后无需重启
+pub fn alias_router(settings: SharedSettings) -> Router {
+    Router::new()
+        .route("/s/:name", get(alias_handler))
+        .with_state(settings)
+}
+
+#[derive(Deserialize)]
+struct AliasQuery {
+    token: Option<String>,
+}
+
+/// 令牌错误时同样返回 404，避免暴露别名是否存在
+async fn alias_handler(
+    State(settings): State<SharedSettings>,
+    Path(name): Path<String>,
+    Query(query): Query<AliasQuery>,
+) -> Response {
+    let settings = settings.read().unwrap().clone();
+    let Some(alias) = settings
+        .aliases
+        .iter()
+        .find(|a| a.name == name && a.authorized(query.token.as_deref()))
+    else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let content = RenameResult::load(FsPath::new(RENAMED_YAML_PATH)).and_then(|mut renamed| {
+        renamed.apply_tags(&settings.tags);
+        let selected = renamed.with_tags(&alias.tags).select(
+            &alias.countries,
+            alias.max_nodes,
+            &settings.score,
+        );
+        alias.format.render(&selected.proxies)
+    });
+    match content {
+        Ok(content) => (
+            [(header::CONTENT_TYPE, content_type(alias.format))],
+            content,
+        )
+            .into_response(),
+        Err(e) => {
+            warn!(
+                "{}",
+                tr!(
+                    "生成订阅别名 {} 的内容失败：{}",
+                    "Failed to render subscription alias {}: {}",
+                    alias.name,
+                    e
+                )
+            );
+            // 还没有完成过筛选流程时 release 不存在
+            (StatusCode::SERVICE_UNAVAILABLE, "release not ready").into_response()
+        }
+    }
+}
+
+fn content_type(format: ExportFormat) -> &'static str {
+    match format {
+        ExportFormat::Clash => "text/yaml; charset=utf-8",
+        ExportFormat::Singbox => "application/json",
+        ExportFormat::Links | ExportFormat::Base64 => "text/plain; charset=utf-8",
+    }
+}
@@ -0,0 +1,90 @@
+use std::path::Path;
+
+use axum::extract::Query;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::response::Response;
+use axum::routing::get;
+use axum::Json;
+use axum::Router;
+use chrono::Local;
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::analytics::Analytics;
+use crate::analytics::ANALYTICS_DB_PATH;
+use crate::reload::SharedSettings;
+use crate::tr;
+
+/// 未指定 days 时返回的天数
+const DEFAULT_DAYS: u32 = 30;
+/// 每轮原始延迟数据量较大，未指定 days 时只返回最近一周
+const DEFAULT_ROUND_DAYS: u32 = 7;
+/// 单次查询的最大天数
+const MAX_DAYS: u32 = 365;
+
+/// 按天汇总的统计趋势及每轮的原始延迟，需要开启 analytics
+pub fn trends_router(settings: SharedSettings) -> Router {
+    Router::new()
+        .route("/api/trends", get(trends_handler))
+        .route("/api/rounds", get(rounds_handler))
+        .with_state(settings)
+}
+
+#[derive(Deserialize)]
+struct TrendsQuery {
+    days: Option<u32>,
+}
+
+async fn trends_handler(
+    State(settings): State<SharedSettings>,
+    Query(query): Query<TrendsQuery>,
+) -> Response {
+    if !settings.read().unwrap().analytics {
+        return (StatusCode::NOT_FOUND, "analytics disabled").into_response();
+    }
+    let days = query.days.unwrap_or(DEFAULT_DAYS).clamp(1, MAX_DAYS);
+    let trends = Analytics::open(Path::new(ANALYTICS_DB_PATH))
+        .and_then(|analytics| Ok(analytics.trends(Local::now().date_naive(), days)?));
+    match trends {
+        Ok(trends) => Json(trends).into_response(),
+        Err(e) => {
+            warn!(
+                "{}",
+                tr!(
+                    "查询统计趋势失败：{}",
+                    "Failed to query analytics trends: {}",
+                    e
+                )
+            );
+            (StatusCode::SERVICE_UNAVAILABLE, "analytics unavailable").into_response()
+        }
+    }
+}
+
+async fn rounds_handler(
+    State(settings): State<SharedSettings>,
+    Query(query): Query<TrendsQuery>,
+) -> Response {
+    if !settings.read().unwrap().analytics {
+        return (StatusCode::NOT_FOUND, "analytics disabled").into_response();
+    }
+    let days = query.days.unwrap_or(DEFAULT_ROUND_DAYS).clamp(1, MAX_DAYS);
+    let rounds = Analytics::open(Path::new(ANALYTICS_DB_PATH))
+        .and_then(|analytics| Ok(analytics.rounds(Local::now().date_naive(), days)?));
+    match rounds {
+        Ok(rounds) => Json(rounds).into_response(),
+        Err(e) => {
+            warn!(
+                "{}",
+                tr!(
+                    "查询每轮延迟失败：{}",
+                    "Failed to query latency rounds: {}",
+                    e
+                )
+            );
+            (StatusCode::SERVICE_UNAVAILABLE, "analytics unavailable").into_response()
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use chrono::Local;
+
+/// 每次运行的日志目录为 logs/run-{timestamp}，包含 run.log 及每次启动的内核日志
+const RUN_DIR_PREFIX: &str = "run-";
+pub const RUN_LOG_FILE: &str = "run.log";
+
+/// 创建本次运行的日志目录，返回绝对路径，切换工作目录后仍然有效，只保留最近 keep 次运行的日志
+pub fn create_run_dir(root: &Path, keep: usize) -> io::Result<PathBuf> {
+    let name = format!("{}{}", RUN_DIR_PREFIX, Local::now().format("%Y%m%d-%H%M%S"));
+    let dir = std::path::absolute(root.join(name))?;
+    fs::create_dir_all(&dir)?;
+    rotate(root, keep)?;
+    Ok(dir)
+}
+
+/// 运行日志的写入目标，调用 start 之前的日志不写入文件，serve 模式下每次定时执行都切换到新的运行目录
+pub struct RunLog {
+    root: PathBuf,
+    keep: usize,
+    file: Mutex<Option<File>>,
+}
+
+impl RunLog {
+    /// root 为相对路径时基于调用 start 时的当前目录，即切换后的数据目录
+    pub fn new(root: impl Into<PathBuf>, keep: usize) -> Self {
+        RunLog {
+            root: root.into(),
+            keep,
+            file: Mutex::new(None),
+        }
+    }
+
+    /// 创建新的运行目录，之后的日志写入其中的 run.log，返回目录的绝对路径
+    pub fn start(&self) -> io::Result<PathBuf> {
+        let dir = create_run_dir(&self.root, self.keep)?;
+        let file = File::create(dir.join(RUN_LOG_FILE))?;
+        *self.file.lock().unwrap() = Some(file);
+        Ok(dir)
+    }
+
+    /// 结束本次运行，之后的日志不再写入 run.log
+    pub fn finish(&self) {
+        *self.file.lock().unwrap() = None;
+    }
+}
+
+impl Write for &RunLog {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.file.lock().unwrap().as_mut() {
+            Some(file) => file.write(buf),
+            None => Ok(buf.len()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.file.lock().unwrap().as_mut() {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// 删除较早的运行日志目录，时间戳格式保证按名称排序即按时间排序
+fn rotate(root: &Path, keep: usize) -> io::Result<()> {
+    let mut dirs = fs::read_dir(root)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            entry.path().is_dir()
+                && entry
+                    .file_name()
+                    .to_string_lossy()
+                    .starts_with(RUN_DIR_PREFIX)
+        })
+        .map(|entry| entry.path())
+        .collect::<Vec<_>>();
+    dirs.sort();
+    let expired = dirs.len().saturating_sub(keep);
+    for dir in &dirs[..expired] {
+        fs::remove_dir_all(dir)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_run_dir() {
+        let root = std::env::temp_dir().join("clash_butler_test_run_log");
+        for name in ["run-20240101-000000", "run-20240102-000000", "clash.log"] {
+            let path = root.join(name);
+            if name.ends_with(".log") {
+                fs::create_dir_all(&root).unwrap();
+                fs::write(path, "").unwrap();
+            } else {
+                fs::create_dir_all(path).unwrap();
+            }
+        }
+
+        let dir = create_run_dir(&root, 2).unwrap();
+        assert!(dir.is_absolute() && dir.is_dir());
+        assert!(!root.join("run-20240101-000000").exists());
+        assert!(root.join("run-20240102-000000").exists());
+        assert!(root.join("clash.log").exists());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_run_log() {
+        let root = std::env::temp_dir().join("clash_butler_test_run_log_writer");
+        let run_log = RunLog::new(&root, 1);
+        (&run_log).write_all(b"before\n").unwrap();
+
+        let dir = run_log.start().unwrap();
+        (&run_log).write_all(b"during\n").unwrap();
+        run_log.finish();
+        (&run_log).write_all(b"after\n").unwrap();
+        let content = fs::read_to_string(dir.join(RUN_LOG_FILE)).unwrap();
+        assert_eq!(content, "during\n");
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,218 @@
+use std::fmt;
+use std::path::Path;
+
+use proxrs::protocol::Proxy;
+use rhai::Dynamic;
+use rhai::Engine;
+use rhai::Map;
+use rhai::Scope;
+use rhai::AST;
+
+use crate::report::NodeReport;
+
+/// 单次执行允许的最大操作数，避免脚本死循环卡住整个流程
+const MAX_OPERATIONS: u64 = 100_000;
+
+/// 节点检测后执行的 Rhai 脚本，根据节点信息决定是否保留及新的名称
+///
+/// 脚本中可以读取 node 变量，包含 name（原名称）、new_name（按 rename_pattern 生成的名称）、
+/// type、server、country、city、isp、latency、speed、jitter、loss、rtt（到 tcp://、udp:// 目标的延迟）、
+/// dynamic_ip（检测期间出口 IP 是否变化）、cdn（服务器所在的 CDN），缺失的字段为 ()，返回值：
+///
+/// - `false` 丢弃节点，`true` 或 `()` 保留节点且不修改名称
+/// - 字符串作为节点的新名称
+/// - `#{ keep: bool, name: string }`，两个字段均可省略
+///
+/// ```rhai
+/// if node.country == "CN" || node.latency > 800 {
+///     return false;
+/// }
+/// `${node.country}-${node.isp}`
+/// ```
+pub struct NodeScript {
+    engine: Engine,
+    ast: AST,
+}
+
+/// 脚本对单个节点的处理结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptDecision {
+    /// 保留节点，Some 时使用新的名称
+    Keep(Option<String>),
+    Drop,
+}
+
+#[derive(Debug)]
+pub struct ScriptError(String);
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+impl NodeScript {
+    pub fn load(path: &Path) -> Result<Self, ScriptError> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| ScriptError(format!("failed to read {}: {}", path.display(), e)))?;
+        Self::compile(&source)
+    }
+
+    pub fn compile(source: &str) -> Result<Self, ScriptError> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        let ast = engine
+            .compile(source)
+            .map_err(|e| ScriptError(e.to_string()))?;
+        Ok(NodeScript { engine, ast })
+    }
+
+    pub fn eval(
+        &self,
+        proxy: &Proxy,
+        new_name: Option<&str>,
+        report: Option<&NodeReport>,
+    ) -> Result<ScriptDecision, ScriptError> {
+        let mut scope = Scope::new();
+        scope.push("node", node_map(proxy, new_name, report));
+        let result = self
+            .engine
+            .eval_ast_with_scope::<Dynamic>(&mut scope, &self.ast)
+            .map_err(|e| ScriptError(e.to_string()))?;
+        decision(result)
+    }
+}
+
+fn node_map(proxy: &Proxy, new_name: Option<&str>, report: Option<&NodeReport>) -> Map {
+    fn opt<T: rhai::Variant + Clone>(value: Option<T>) -> Dynamic {
+        value.map(Dynamic::from).unwrap_or(Dynamic::UNIT)
+    }
+
+    let proxy_type = serde_json::to_value(proxy.proxy_type())
+        .ok()
+        .and_then(|v| v.as_str().map(str::to_string));
+    let mut map = Map::new();
+    map.insert("name".into(), Dynamic::from(proxy.get_name().to_string()));
+    map.insert("new_name".into(), opt(new_name.map(str::to_string)));
+    map.insert("type".into(), opt(proxy_type));
+    map.insert(
+        "server".into(),
+        Dynamic::from(proxy.get_server().to_string()),
+    );
+    map.insert(
+        "country".into(),
+        opt(report.and_then(|r| r.country.clone())),
+    );
+    map.insert("city".into(), opt(report.and_then(|r| r.city.clone())));
+    map.insert("isp".into(), opt(report.and_then(|r| r.isp.clone())));
+    map.insert("latency".into(), opt(report.and_then(|r| r.latency)));
+    map.insert("speed".into(), opt(report.and_then(|r| r.speed)));
+    map.insert("jitter".into(), opt(report.and_then(|r| r.jitter)));
+    map.insert("loss".into(), opt(report.and_then(|r| r.loss)));
+    map.insert("rtt".into(), opt(report.and_then(|r| r.rtt)));
+    map.insert("dynamic_ip".into(), opt(report.map(|r| r.dynamic_ip)));
+    map.insert("cdn".into(), opt(report.and_then(|r| r.cdn.clone())));
+    map
+}
+
+fn decision(result: Dynamic) -> Result<ScriptDecision, ScriptError> {
+    if result.is_unit() {
+        return Ok(ScriptDecision::Keep(None));
+    }
+    if let Some(keep) = result.clone().try_cast::<bool>() {
+        return Ok(if keep {
+            ScriptDecision::Keep(None)
+        } else {
+            ScriptDecision::Drop
+        });
+    }
+    if result.is_string() {
+        return Ok(ScriptDecision::Keep(Some(result.to_string())));
+    }
+    if let Some(map) = result.clone().try_cast::<Map>() {
+        let keep = match map.get("keep") {
+            None => true,
+            Some(keep) => keep
+                .as_bool()
+                .map_err(|_| ScriptError("keep must be a bool".to_string()))?,
+        };
+        if !keep {
+            return Ok(ScriptDecision::Drop);
+        }
+        let name = match map.get("name") {
+            None => None,
+            Some(name) if name.is_unit() => None,
+            Some(name) if name.is_string() => Some(name.to_string()),
+            Some(_) => return Err(ScriptError("name must be a string".to_string())),
+        };
+        return Ok(ScriptDecision::Keep(name));
+    }
+    Err(ScriptError(format!(
+        "script must return (), bool, string or map, got {}",
+        result.type_name()
+    )))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn proxy() -> Proxy {
+        Proxy::from_link("ss://YWVzLTEyOC1nY206cGFzc3dvcmQ=@1.2.3.4:8388#HK").unwrap()
+    }
+
+    fn report() -> NodeReport {
+        let mut report = NodeReport::new("node", Some(120));
+        report.country = Some("US".to_string());
+        report.isp = Some("Cloudflare".to_string());
+        report
+    }
+
+    #[test]
+    fn test_eval() {
+        let script = NodeScript::compile(
+            r#"
+            if node.latency == () || node.latency > 500 {
+                return false;
+            }
+            if node.country == "US" {
+                return `${node.country}-${node.isp}`;
+            }
+            #{ name: node.new_name }
+            "#,
+        )
+        .unwrap();
+
+        let decision = script.eval(&proxy(), None, Some(&report())).unwrap();
+        assert_eq!(
+            decision,
+            ScriptDecision::Keep(Some("US-Cloudflare".to_string()))
+        );
+        assert_eq!(
+            script.eval(&proxy(), None, None).unwrap(),
+            ScriptDecision::Drop
+        );
+
+        let mut report = report();
+        report.country = Some("JP".to_string());
+        assert_eq!(
+            script
+                .eval(&proxy(), Some("1.2.3.4_JP"), Some(&report))
+                .unwrap(),
+            ScriptDecision::Keep(Some("1.2.3.4_JP".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_invalid_script() {
+        assert!(NodeScript::compile("if {").is_err());
+
+        let script = NodeScript::compile("42").unwrap();
+        assert!(script.eval(&proxy(), None, None).is_err());
+
+        let script = NodeScript::compile("loop {}").unwrap();
+        assert!(script.eval(&proxy(), None, None).is_err());
+    }
+}
@@ -3,13 +3,20 @@
 use std::fs;
 use std::fs::File;
 use std::io::Write;
+use std::sync::Arc;
+use std::sync::RwLock;
 use std::time::Duration;
 
 use axum::extract::Query;
+use axum::http::StatusCode;
 use axum::routing::get;
+use axum::Json;
 use axum::Router;
+use chrono::DateTime;
+use chrono::Local;
 use reqwest::Client;
 use serde::Deserialize;
+use serde::Serialize;
 use tokio::net::TcpListener;
 use tokio::signal;
 use tower_http::services::ServeDir;
@@ -17,32 +24,86 @@ use tracing::info;
 use walkdir::WalkDir;
 
 use crate::clash;
+use crate::daemon;
+use crate::netstack::NetStack;
+use crate::pipeline::RunSummary;
+use crate::reload::SharedSettings;
 use crate::routes;
-use crate::Settings;
 
-pub async fn start_server(_config: Settings) {
+/// serve 模式下定时任务的运行状态，通过 /status 查询
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ServerStatus {
+    /// 是否正在执行筛选流程
+    pub running: bool,
+    pub last_run: Option<LastRun>,
+}
+
+/// 最近一次完成的筛选流程
+#[derive(Debug, Clone, Serialize)]
+pub struct LastRun {
+    pub finished_at: DateTime<Local>,
+    pub exit_code: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(flatten)]
+    pub summary: RunSummary,
+}
+
+pub type SharedStatus = Arc<RwLock<ServerStatus>>;
+
+/// 按当前配置及命令行参数重新导出 release 文件，返回导出的节点数，在独立线程中调用
+pub type ReleaseHook = Arc<dyn Fn() -> Result<usize, String> + Send + Sync>;
+
+pub async fn start_server(
+    settings: SharedSettings,
+    status: SharedStatus,
+    port: u16,
+    release: ReleaseHook,
+) -> std::io::Result<()> {
+    let stack = settings
+        .read()
+        .unwrap()
+        .ip_stack
+        .unwrap_or_else(NetStack::detect);
     let app = Router::new()
         .route("/", get(root))
+        // 供容器编排探测存活状态
+        .route("/healthz", get(|| async { "ok" }))
+        .route("/status", get(move || status_handler(status.clone())))
         .nest_service("/subs", ServeDir::new("subs"))
         // .route("/add", get(add_sub))
         // .route("/test", get(test_config))
         // .route("/test/all", get(test_all_sub))
         .merge(routes::sub::sub_router())
+        .merge(routes::sub::alias_router(settings.clone()))
+        .merge(routes::nodes::nodes_router(settings.clone(), release))
+        .merge(routes::trends::trends_router(settings))
+        .merge(routes::releases::releases_router())
         .merge(routes::config::config_router());
 
-    let listener = TcpListener::bind("0.0.0.0:3003").await.unwrap();
+    let listener = TcpListener::bind((stack.unspecified(), port)).await?;
 
-    info!("listening on {}", listener.local_addr().unwrap());
+    info!("listening on {}", listener.local_addr()?);
+    daemon::notify_ready();
 
     axum::serve(listener, app)
         .with_graceful_shutdown(shutdown_signal())
         .await
-        .unwrap();
 }
 
 async fn root() -> &'static str {
     "👋 Clash-Butler!"
 }
+
+/// 最近一次定时任务失败时返回 503，便于就绪探针发现问题
+async fn status_handler(status: SharedStatus) -> (StatusCode, Json<ServerStatus>) {
+    let status = status.read().unwrap().clone();
+    let code = match &status.last_run {
+        Some(run) if run.exit_code != 0 => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::OK,
+    };
+    (code, Json(status))
+}
 //
 // #[derive(Deserialize, Debug)]
 // struct Sub {
@@ -241,7 +302,8 @@ async fn root() -> &'static str {
 // }
 //
 //
-async fn shutdown_signal() {
+/// 等待 Ctrl+C 或 SIGTERM，容器停止时发送的是 SIGTERM
+pub async fn shutdown_signal() {
     let ctrl_c = async {
         signal::ctrl_c()
             .await
@@ -263,4 +325,5 @@ async fn shutdown_signal() {
         _ = ctrl_c => {},
         _ = terminate => {},
     }
+    daemon::notify_stopping();
 }
@@ -1,30 +1,1881 @@
+use std::collections::BTreeSet;
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+use std::path::PathBuf;
+
 use config::Config;
 use config::ConfigError;
+use config::Environment;
 use config::File;
+use config::Source;
+use proxrs::protocol::Proxy;
+use reqwest::header::HeaderName;
+use reqwest::Url;
+use serde::de;
+use serde::de::value::MapAccessDeserializer;
+use serde::de::MapAccess;
+use serde::de::Visitor;
 use serde::Deserialize;
+use serde::Deserializer;
+use serde_json::Value;
 
+use crate::bundle::BundleConfig;
 use crate::clash::DelayTestConfig;
+use crate::i18n::Lang;
+use crate::netstack::NetStack;
+use crate::pacing::PacingConfig;
+use crate::pipeline::ExportFormat;
+use crate::report::MarkdownConfig;
+use crate::report::ScoreConfig;
+use crate::script::NodeScript;
+use crate::speedtest::SpeedTestBackend;
 use crate::speedtest::SpeedTestConfig;
+use crate::target::Protocol;
+use crate::target::Target;
+use crate::upload::UploadTarget;
+use crate::website;
+use crate::website::WebsiteTest;
 
 #[derive(Deserialize, Debug)]
 #[allow(unused)]
 pub struct Settings {
     pub fast_mode: bool,
-    pub subs: Vec<String>,
+    pub subs: Vec<SubConfig>,
     pub rename_node: bool,
     pub rename_pattern: String,
+    /// 是否拉取 pools 中的代理池，新配置可直接在 subs 中标记 pool = true
+    #[serde(default)]
     pub need_add_pool: bool,
     pub test_group_size: usize,
+    /// 分组测试时，检测上一组可用节点的同时测试下一组，需要同时运行两个内核
+    #[serde(default = "default_overlap_stages")]
+    pub overlap_stages: bool,
+    #[serde(default)]
     pub pools: Vec<String>,
+    /// 订阅连续多少次运行都没有产出可用节点时自动跳过，不填或为 0 时不跳过，记录见 history::NODE_HISTORY_PATH
+    #[serde(default)]
+    pub disable_idle_sources: Option<usize>,
     pub connect_test: DelayTestConfig,
     pub speed_test: SpeedTestConfig,
+    /// 节点检测时访问的网站，默认检测 OpenAI 与 Claude
+    #[serde(default = "website::default_websites")]
+    pub websites: Vec<WebsiteTest>,
+    /// 启用的内置节点检测，如 ["youtube_music"]，与 websites 一起在节点检测阶段执行
+    #[serde(default)]
+    pub checks: Vec<String>,
+    /// 节点检测后执行的 Rhai 脚本，决定是否保留节点及新的名称，相对路径基于数据目录
+    #[serde(default)]
+    pub script: Option<PathBuf>,
+    /// 连通性测试的平均延迟上限，单位 ms，超过的节点视为不可用
+    #[serde(default)]
+    pub max_latency_ms: Option<u64>,
+    /// 连通性测试成功轮数的最低占比，0 ~ 1，如 0.6 表示 5 轮中至少 3 轮成功
+    #[serde(default)]
+    pub min_success_rate: Option<f64>,
+    /// 最终保留的节点数上限，按速度、延迟排序
+    #[serde(default)]
+    pub max_nodes: Option<usize>,
+    /// 只保留指定国家的节点，如 ["US", "HK"]，需要开启节点重命名
+    #[serde(default)]
+    pub countries: Vec<String>,
+    /// 测试前按节点服务器地址（入口）所在国家预先剔除不在 countries 中的节点，节省测试时间
+    /// 中转节点的入口与落地国家不同，可能被误剔除，落地国家仍在节点检测阶段确认
+    #[serde(default)]
+    pub prefilter_countries: bool,
+    /// 测试前预先解析节点服务器域名并写入测试内核配置的 hosts，避免内核内 DNS 解析的波动及失败影响延迟测试
+    #[serde(default)]
+    pub warm_dns: bool,
+    /// 覆盖测试内核配置模板的 dns，本机 DNS 被污染时可指定可靠的上游
+    #[serde(default)]
+    pub test_dns: TestDnsConfig,
+    /// 本机的网络协议栈，ipv4、ipv6 或 dual，不填时自动探测，只有 IPv6 的机器上内核改为监听 [::] 并通过 [::1] 访问
+    #[serde(default)]
+    pub ip_stack: Option<NetStack>,
+    /// 测试内核出站使用的网卡，如 "eth1"，多线路的机器上可指定测试走哪条线路
+    #[serde(default)]
+    pub interface: Option<String>,
+    /// 测试内核出站流量的 fwmark（Linux），配合策略路由选择线路
+    #[serde(default)]
+    pub routing_mark: Option<u32>,
+    /// 按国家平衡最终导出的节点，避免节点集中在少数国家
+    #[serde(default)]
+    pub balance: BalanceConfig,
+    /// testing 与 stable 两个发布通道
+    #[serde(default)]
+    pub channels: ChannelConfig,
+    /// 节点检测阶段的并发、超时及出口 IP 查询的重试
+    #[serde(default)]
+    pub inspect: InspectConfig,
+    /// 导出后将所有产物打包为 zip 或 tar.gz
+    #[serde(default)]
+    pub bundle: BundleConfig,
+    /// 节点排名稳定后提前结束连通性测试的剩余轮次
+    #[serde(default)]
+    pub early_stop: EarlyStopConfig,
+    /// 剔除 ISP 名称包含其中任一项的节点，如 ["Cloudflare", "ChinaNet"]，不区分大小写，需要开启节点重命名
+    #[serde(default)]
+    pub exclude_isps: Vec<String>,
+    /// 导出 Clash 配置时按 ISP 生成 url-test 策略组，并加入 PROXY 策略组
+    #[serde(default)]
+    pub group_by_isp: bool,
+    /// 订阅未声明 skip-cert-verify 的 TLS 节点默认跳过证书校验，会降低安全性，默认关闭
+    #[serde(default)]
+    pub insecure_default: bool,
+    /// 数据目录，subs、logs、conf 中的模板等相对路径均基于该目录，不填时使用当前目录
+    #[serde(default)]
+    pub data_dir: Option<PathBuf>,
+    /// mihomo 内核路径，相对路径基于数据目录，不填时使用 clash-meta/mihomo
+    #[serde(default)]
+    pub core_path: Option<PathBuf>,
+    /// serve 模式下每隔多少分钟完整执行一次筛选流程，不填或为 0 时不定时执行
+    #[serde(default)]
+    pub schedule_interval: Option<u64>,
+    /// serve 模式下的订阅别名，通过 /s/{name} 获取 release 中节点的筛选视图
+    #[serde(default)]
+    pub aliases: Vec<SubAlias>,
+    /// 节点标签规则，标签可用于生成策略组及在订阅别名中筛选节点
+    #[serde(default)]
+    pub tags: Vec<TagRule>,
+    /// 日志及错误信息的语言，zh 或 en，命令行 --lang 优先
+    #[serde(default)]
+    pub lang: Lang,
+    /// 运行开始时不经过代理直连测量延迟及速度，作为节点结果的参照，记录在运行统计及 Markdown 汇总中
+    #[serde(default)]
+    pub baseline: bool,
+    /// 每次完整运行后将节点数及各国家平均延迟按天汇总到 subs/analytics.db，通过 /api/trends 查询趋势
+    #[serde(default)]
+    pub analytics: bool,
+    /// 节点评分，用于按 max_nodes 筛选及排序
+    #[serde(default)]
+    pub score: ScoreConfig,
+    #[serde(default)]
+    pub markdown: MarkdownConfig,
+    /// 订阅拉取、IP 查询及测速的节奏控制，默认不限制
+    #[serde(default)]
+    pub pacing: PacingConfig,
+    /// subs/release/history 中保留的 release 文件备份数，0 表示不备份
+    #[serde(default = "default_release_history")]
+    pub release_history: usize,
+    #[serde(default)]
+    pub uploads: Vec<UploadTarget>,
+    #[serde(default)]
+    pub secrets: Secrets,
+    /// 合并后的原始配置，用于对比配置变化
+    #[serde(skip)]
+    raw: RawSettings,
+}
+
+/// 原始配置中包含密钥明文，Debug 输出时省略
+#[derive(Default)]
+struct RawSettings(Value);
+
+impl fmt::Debug for RawSettings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "RawSettings(..)")
+    }
+}
+
+/// 单个订阅的配置，可以直接写订阅地址，也可以写成包含单独选项的表：
+///
+/// ```toml
+/// subs = [
+///     "https://a.example.com",
+///     { url = "https://b.example.com", prefix = "B_", countries = ["US"], max_nodes = 10 },
+///     { url = "https://pool.example.com", pool = true, label = "pool" },
+/// ]
+/// ```
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(remote = "Self")]
+pub struct SubConfig {
+    pub url: String,
+    /// 最终导出时节点名称的前缀
+    #[serde(default)]
+    pub prefix: Option<String>,
+    /// 订阅的简短名称，用于 ${SOURCE} 占位符及节点报告，不填时使用订阅地址的域名
+    #[serde(default)]
+    pub label: Option<String>,
+    /// 只保留该订阅中指定国家的节点，需要开启节点重命名
+    #[serde(default)]
+    pub countries: Vec<String>,
+    /// 该订阅最终保留的节点数上限，按速度、延迟排序
+    #[serde(default)]
+    pub max_nodes: Option<usize>,
+    /// 该订阅参与测试的节点数上限，去重后超过时按 sample 抽取，避免节点很多的代理池占满候选节点及测试时间
+    #[serde(default)]
+    pub max_candidates: Option<usize>,
+    /// 超过 max_candidates 时抽取节点的方式
+    #[serde(default)]
+    pub sample: SampleMode,
+    /// 受信任的订阅，节点检测时无法获取 IP 信息也不会被剔除
+    #[serde(default)]
+    pub trusted: bool,
+    /// 代理池，在普通订阅之后拉取，重复节点以普通订阅为准
+    #[serde(default)]
+    pub pool: bool,
+}
+
+/// 订阅节点数超过 max_candidates 时的抽取方式
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SampleMode {
+    /// 随机抽取，多次运行可以覆盖更多节点
+    #[default]
+    Random,
+    /// 优先最近首次出现的节点，见 history::NodeHistory
+    Newest,
+}
+
+impl SubConfig {
+    pub fn new(url: impl Into<String>) -> Self {
+        SubConfig {
+            url: url.into(),
+            ..Default::default()
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for SubConfig {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct SubVisitor;
+
+        impl<'de> Visitor<'de> for SubVisitor {
+            type Value = SubConfig;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a subscription url or table")
+            }
+
+            fn visit_str<E: de::Error>(self, url: &str) -> Result<SubConfig, E> {
+                Ok(SubConfig::new(url))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<SubConfig, A::Error> {
+                SubConfig::deserialize(MapAccessDeserializer::new(map))
+            }
+        }
+
+        deserializer.deserialize_any(SubVisitor)
+    }
+}
+
+/// serve 模式下的订阅别名，如 /s/home?token=xxx，每个别名可单独设置筛选条件、格式及访问令牌：
+///
+/// ```toml
+/// [[aliases]]
+/// name = "tv"
+/// token = "xxx"
+/// countries = ["HK"]
+/// max_nodes = 5
+/// format = "base64"
+/// ```
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct SubAlias {
+    /// 访问路径中的名称，只能包含字母、数字、- 及 _
+    pub name: String,
+    /// 访问令牌，通过 token 参数传入，不填时无需令牌
+    #[serde(default)]
+    pub token: Secret,
+    /// 只保留指定国家的节点，不填时不限制
+    #[serde(default)]
+    pub countries: Vec<String>,
+    /// 保留的节点数上限，按速度、延迟排序
+    #[serde(default)]
+    pub max_nodes: Option<usize>,
+    /// 返回的格式，clash、singbox、links 或 base64，默认为 clash
+    #[serde(default)]
+    pub format: ExportFormat,
+    /// 只保留带有其中任一标签的节点，不填时不限制
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl SubAlias {
+    /// 未设置令牌时允许任意访问
+    pub fn authorized(&self, token: Option<&str>) -> bool {
+        self.token.is_empty() || token == Some(self.token.expose())
+    }
+}
+
+/// 节点标签规则，节点满足所有已填写的条件时打上该标签，如：
+///
+/// ```toml
+/// [[tags]]
+/// name = "gpt"
+/// checks = ["OpenAI"]
+/// group = true
+///
+/// [[tags]]
+/// name = "home"
+/// servers = ["home.example.com"]
+/// ```
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct TagRule {
+    /// 标签名称，只能包含字母、数字、- 及 _
+    pub name: String,
+    /// 通过其中所有节点检测，如 ["OpenAI", "Netflix"]
+    #[serde(default)]
+    pub checks: Vec<String>,
+    /// 落地国家为其中之一
+    #[serde(default)]
+    pub countries: Vec<String>,
+    /// ISP 名称包含其中任一项，不区分大小写
+    #[serde(default)]
+    pub isps: Vec<String>,
+    /// 节点服务器地址为其中之一，用于固定标记自建节点
+    #[serde(default)]
+    pub servers: Vec<String>,
+    /// 节点名称包含其中任一项，不区分大小写
+    #[serde(default)]
+    pub names: Vec<String>,
+    /// 导出 Clash 配置时生成包含所有带该标签节点的 Tag-{name} url-test 策略组
+    #[serde(default)]
+    pub group: bool,
+}
+
+impl TagRule {
+    fn has_conditions(&self) -> bool {
+        !(self.checks.is_empty()
+            && self.countries.is_empty()
+            && self.isps.is_empty()
+            && self.servers.is_empty()
+            && self.names.is_empty())
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+fn default_overlap_stages() -> bool {
+    true
+}
+
+fn default_release_history() -> usize {
+    5
+}
+
+/// 敏感信息，如密码、密钥，Debug 输出时隐藏具体内容，避免写入日志
+#[derive(Deserialize, Clone, Default, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: impl Into<String>) -> Self {
+        Secret(value.into())
+    }
+
+    /// 获取明文，仅在实际使用时调用
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0.is_empty() {
+            write!(f, "\"\"")
+        } else {
+            write!(f, "\"***\"")
+        }
+    }
+}
+
+/// 按国家平衡最终导出的节点，需要开启节点重命名
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct BalanceConfig {
+    /// 单个国家的节点最多占比，0 ~ 1，如 0.3 表示任一国家不超过 30%
+    #[serde(default)]
+    pub max_country_share: Option<f64>,
+    /// 每个国家至少保留的节点数（节点足够时），countries 不为空时只作用于其中的国家，0 表示不限制
+    #[serde(default)]
+    pub min_per_country: usize,
+}
+
+impl BalanceConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.max_country_share.is_some() || self.min_per_country > 0
+    }
+}
+
+/// 发布通道，testing 包含最近一次运行的所有可用节点，stable 只包含最近连续多次运行都可用的节点
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ChannelConfig {
+    pub enabled: bool,
+    /// 节点进入 stable 通道需要连续通过的完整运行次数
+    pub stable_runs: u32,
+}
+
+impl Default for ChannelConfig {
+    fn default() -> Self {
+        ChannelConfig {
+            enabled: false,
+            stable_runs: 3,
+        }
+    }
+}
+
+/// 节点检测阶段的并发及超时
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct InspectConfig {
+    /// 同时检测的节点数，大于 1 时检测内核为每一路生成独立的策略组及入站端口
+    pub concurrency: usize,
+    /// 单个节点检测（含测速）的超时时间，单位秒，超时的节点按检测失败处理
+    pub node_timeout_secs: u64,
+    /// 查询出口 IP 的尝试次数，全部失败才判定节点不可用
+    pub trace_attempts: u32,
+}
+
+impl Default for InspectConfig {
+    fn default() -> Self {
+        InspectConfig {
+            concurrency: 1,
+            node_timeout_secs: 120,
+            trace_attempts: 3,
+        }
+    }
+}
+
+/// 连通性测试的提前结束，延迟最低的 top_k 个节点连续 stable_rounds 轮不变时跳过剩余轮次
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct EarlyStopConfig {
+    pub enabled: bool,
+    /// 比较排名的节点数
+    pub top_k: usize,
+    /// 排名连续不变的轮数
+    pub stable_rounds: usize,
+}
+
+impl Default for EarlyStopConfig {
+    fn default() -> Self {
+        EarlyStopConfig {
+            enabled: false,
+            top_k: 10,
+            stable_rounds: 3,
+        }
+    }
+}
+
+/// 测试内核的 DNS 配置，未填写的项沿用 conf/clash_test.yaml 模板中的设置
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct TestDnsConfig {
+    #[serde(default)]
+    pub enable: Option<bool>,
+    /// 替换模板中的 nameserver，如 ["https://1.1.1.1/dns-query"]
+    #[serde(default)]
+    pub nameservers: Vec<String>,
+    /// 替换模板中的 fallback
+    #[serde(default)]
+    pub fallback: Vec<String>,
+    #[serde(default)]
+    pub enhanced_mode: Option<DnsMode>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DnsMode {
+    FakeIp,
+    RedirHost,
+}
+
+impl DnsMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DnsMode::FakeIp => "fake-ip",
+            DnsMode::RedirHost => "redir-host",
+        }
+    }
+}
+
+/// 集中配置的密钥，上传目标中未填写的凭据从这里读取，
+/// 便于只通过环境变量传入，如 CLASH_BUTLER__SECRETS__S3_SECRET_KEY
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct Secrets {
+    #[serde(default)]
+    pub s3_access_key: Secret,
+    #[serde(default)]
+    pub s3_secret_key: Secret,
+    #[serde(default)]
+    pub webdav_password: Secret,
+    /// serve 模式下管理接口（如禁用节点）的令牌，通过 Authorization: Bearer 传入，不填时禁用管理接口
+    #[serde(default)]
+    pub api_token: Secret,
+}
+
+/// 默认配置文件路径
+pub const DEFAULT_CONFIG_PATH: &str = "conf/config.toml";
+/// 环境变量前缀，层级之间使用双下划线分隔，如 CLASH_BUTLER__CONNECT_TEST__TIMEOUT=800
+const ENV_PREFIX: &str = "CLASH_BUTLER";
+/// 以逗号分隔的列表字段
+const ENV_LIST_KEYS: &[&str] = &["subs", "pools", "countries", "exclude_isps"];
+/// rename_pattern 中支持的占位符
+const RENAME_PLACEHOLDERS: &[&str] = &["IP", "COUNTRYCODE", "ISP", "CITY", "CDN", "AGE", "SOURCE"];
+
+/// 配置中的单个问题，path 为配置项的完整路径，如 connect_test.url、subs[0]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SettingsIssue {
+    pub path: String,
+    pub message: String,
+}
+
+impl SettingsIssue {
+    fn new(path: impl Into<String>, message: impl Into<String>) -> Self {
+        SettingsIssue {
+            path: path.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for SettingsIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.path, self.message)
+        }
+    }
+}
+
+/// 配置读取或校验失败，包含所有发现的问题
+#[derive(Debug)]
+pub struct SettingsError {
+    issues: Vec<SettingsIssue>,
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let issues = self
+            .issues
+            .iter()
+            .map(|issue| issue.to_string())
+            .collect::<Vec<_>>();
+        write!(f, "{}", issues.join("; "))
+    }
+}
+
+impl std::error::Error for SettingsError {}
+
+impl SettingsError {
+    pub fn issues(&self) -> &[SettingsIssue] {
+        &self.issues
+    }
+}
+
+impl From<ConfigError> for SettingsError {
+    fn from(e: ConfigError) -> Self {
+        let path = match &e {
+            ConfigError::NotFound(key) => key.clone(),
+            ConfigError::Type { key: Some(key), .. } => key.clone(),
+            _ => String::new(),
+        };
+        SettingsError {
+            issues: vec![SettingsIssue::new(path, e.to_string())],
+        }
+    }
 }
 
 impl Settings {
-    pub fn new() -> Result<Self, ConfigError> {
-        let settings = Config::builder()
-            .add_source(File::with_name("conf/config.toml"))
-            .build()?;
-        settings.try_deserialize::<Settings>()
+    /// 读取 conf/config.toml，再使用 CLASH_BUTLER__* 环境变量覆盖
+    /// 配置文件不存在时完全由环境变量提供，便于在 Docker 中使用
+    pub fn new() -> Result<Self, SettingsError> {
+        Self::load(None, None)
+    }
+
+    /// 读取指定的配置文件，未指定时使用 conf/config.toml
+    /// profile 在基础配置之上覆盖同名配置项，可以是配置文件同级 profiles 目录下的名称，如 home 对应
+    /// conf/profiles/home.toml，也可以是 toml 文件路径
+    pub fn load(path: Option<&Path>, profile: Option<&str>) -> Result<Self, SettingsError> {
+        Self::load_in(path, profile, None)
+    }
+
+    /// 与 load 相同，data_dir 不为空时替换配置中的 data_dir
+    /// 切换到数据目录后重新加载配置需要传入数据目录的绝对路径，否则配置中的相对路径会再次基于数据目录解析
+    pub fn load_in(
+        path: Option<&Path>,
+        profile: Option<&str>,
+        data_dir: Option<&Path>,
+    ) -> Result<Self, SettingsError> {
+        // 显式指定的配置文件必须存在，默认配置文件可以不存在
+        let files = Self::source_paths(path, profile)
+            .iter()
+            .enumerate()
+            .map(|(i, file)| File::from(file.as_path()).required(i > 0 || path.is_some()))
+            .collect::<Vec<_>>();
+        Self::from_sources(files, Environment::with_prefix(ENV_PREFIX), data_dir)
+    }
+
+    fn from_sources(
+        file: impl Source + Send + Sync + 'static,
+        env: Environment,
+        data_dir: Option<&Path>,
+    ) -> Result<Self, SettingsError> {
+        let env = ENV_LIST_KEYS.iter().fold(
+            env.prefix_separator("__")
+                .separator("__")
+                .try_parsing(true)
+                .list_separator(","),
+            |env, key| env.with_list_parse_key(key),
+        );
+        let config = Config::builder().add_source(file).add_source(env).build()?;
+
+        // 记录未知的配置项，并在反序列化失败时给出出错的配置路径
+        let mut unknown = vec![];
+        let mut on_unknown = |path: serde_ignored::Path| unknown.push(path.to_string());
+        let raw = config
+            .clone()
+            .try_deserialize::<Value>()
+            .unwrap_or_default();
+        let mut settings: Settings = serde_path_to_error::deserialize(
+            serde_ignored::Deserializer::new(config, &mut on_unknown),
+        )
+        .map_err(|e| SettingsError {
+            issues: vec![SettingsIssue::new(
+                e.path().to_string(),
+                e.inner().to_string(),
+            )],
+        })?;
+
+        settings.raw = RawSettings(raw);
+        settings.apply_secrets();
+        if let Some(dir) = data_dir {
+            settings.data_dir = Some(dir.to_path_buf());
+        }
+
+        let mut issues = unknown
+            .into_iter()
+            .map(|path| SettingsIssue::new(path, "unknown key"))
+            .collect::<Vec<_>>();
+        issues.extend(settings.validate());
+        if issues.is_empty() {
+            Ok(settings)
+        } else {
+            Err(SettingsError { issues })
+        }
+    }
+
+    /// 使用 secrets 中的密钥补全上传目标中未填写的凭据
+    fn apply_secrets(&mut self) {
+        let secrets = &self.secrets;
+        for target in &mut self.uploads {
+            match target {
+                UploadTarget::S3(config) => {
+                    if config.access_key.is_empty() {
+                        config.access_key = secrets.s3_access_key.clone();
+                    }
+                    if config.secret_key.is_empty() {
+                        config.secret_key = secrets.s3_secret_key.clone();
+                    }
+                }
+                UploadTarget::Webdav(config) => {
+                    if config.password.is_none() && !secrets.webdav_password.is_empty() {
+                        config.password = Some(secrets.webdav_password.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    /// 校验配置内容，返回所有问题
+    pub fn validate(&self) -> Vec<SettingsIssue> {
+        let mut issues = vec![];
+        for (i, sub) in self.subs.iter().enumerate() {
+            if let Err(message) = check_sub(&sub.url, self.data_dir.as_deref()) {
+                issues.push(SettingsIssue::new(format!("subs[{}]", i), message));
+            }
+            issues.extend(check_countries(
+                &format!("subs[{}].countries", i),
+                &sub.countries,
+            ));
+            if sub.max_candidates == Some(0) {
+                issues.push(SettingsIssue::new(
+                    format!("subs[{}].max_candidates", i),
+                    "must be greater than 0",
+                ));
+            }
+            if sub
+                .label
+                .as_deref()
+                .is_some_and(|label| label.trim().is_empty())
+            {
+                issues.push(SettingsIssue::new(
+                    format!("subs[{}].label", i),
+                    "must not be empty",
+                ));
+            }
+        }
+        for (i, url) in self.pools.iter().enumerate() {
+            if let Err(message) = check_sub(url, self.data_dir.as_deref()) {
+                issues.push(SettingsIssue::new(format!("pools[{}]", i), message));
+            }
+        }
+        if self.rename_node {
+            if let Err(message) = check_rename_pattern(&self.rename_pattern) {
+                issues.push(SettingsIssue::new("rename_pattern", message));
+            }
+        }
+        if self.test_group_size == 0 {
+            issues.push(SettingsIssue::new(
+                "test_group_size",
+                "must be greater than 0",
+            ));
+        }
+        issues.extend(check_countries("countries", &self.countries));
+        if self.max_latency_ms == Some(0) {
+            issues.push(SettingsIssue::new(
+                "max_latency_ms",
+                "must be greater than 0",
+            ));
+        }
+        if let Some(rate) = self.min_success_rate {
+            if !(rate > 0.0 && rate <= 1.0) {
+                issues.push(SettingsIssue::new(
+                    "min_success_rate",
+                    "must be greater than 0 and at most 1",
+                ));
+            }
+        }
+
+        let mut alias_names = HashSet::new();
+        for (i, alias) in self.aliases.iter().enumerate() {
+            if !is_identifier(&alias.name) {
+                issues.push(SettingsIssue::new(
+                    format!("aliases[{}].name", i),
+                    "must be non-empty and only contain letters, digits, - and _",
+                ));
+            } else if !alias_names.insert(alias.name.as_str()) {
+                issues.push(SettingsIssue::new(
+                    format!("aliases[{}].name", i),
+                    format!("duplicate alias {:?}", alias.name),
+                ));
+            }
+            issues.extend(check_countries(
+                &format!("aliases[{}].countries", i),
+                &alias.countries,
+            ));
+            if alias.max_nodes == Some(0) {
+                issues.push(SettingsIssue::new(
+                    format!("aliases[{}].max_nodes", i),
+                    "must be greater than 0",
+                ));
+            }
+            for tag in &alias.tags {
+                if !self.tags.iter().any(|rule| &rule.name == tag) {
+                    issues.push(SettingsIssue::new(
+                        format!("aliases[{}].tags", i),
+                        format!("unknown tag {:?}", tag),
+                    ));
+                }
+            }
+        }
+
+        if self
+            .interface
+            .as_deref()
+            .is_some_and(|name| name.trim().is_empty())
+        {
+            issues.push(SettingsIssue::new("interface", "must not be empty"));
+        }
+
+        for (field, servers) in [
+            ("nameservers", &self.test_dns.nameservers),
+            ("fallback", &self.test_dns.fallback),
+        ] {
+            for (i, server) in servers.iter().enumerate() {
+                if server.trim().is_empty() {
+                    issues.push(SettingsIssue::new(
+                        format!("test_dns.{}[{}]", field, i),
+                        "must not be empty",
+                    ));
+                }
+            }
+        }
+
+        if self.channels.stable_runs == 0 {
+            issues.push(SettingsIssue::new(
+                "channels.stable_runs",
+                "must be greater than 0",
+            ));
+        }
+        for (field, value) in [
+            ("inspect.concurrency", self.inspect.concurrency as u64),
+            ("inspect.node_timeout_secs", self.inspect.node_timeout_secs),
+            ("inspect.trace_attempts", self.inspect.trace_attempts as u64),
+            ("early_stop.top_k", self.early_stop.top_k as u64),
+            ("bundle.keep", self.bundle.keep as u64),
+            (
+                "early_stop.stable_rounds",
+                self.early_stop.stable_rounds as u64,
+            ),
+        ] {
+            if value == 0 {
+                issues.push(SettingsIssue::new(field, "must be greater than 0"));
+            }
+        }
+
+        let mut tag_names = HashSet::new();
+        for (i, rule) in self.tags.iter().enumerate() {
+            if !is_identifier(&rule.name) {
+                issues.push(SettingsIssue::new(
+                    format!("tags[{}].name", i),
+                    "must be non-empty and only contain letters, digits, - and _",
+                ));
+            } else if !tag_names.insert(rule.name.as_str()) {
+                issues.push(SettingsIssue::new(
+                    format!("tags[{}].name", i),
+                    format!("duplicate tag {:?}", rule.name),
+                ));
+            }
+            if !rule.has_conditions() {
+                issues.push(SettingsIssue::new(
+                    format!("tags[{}]", i),
+                    "requires at least one of checks, countries, isps, servers and names",
+                ));
+            }
+            issues.extend(check_countries(
+                &format!("tags[{}].countries", i),
+                &rule.countries,
+            ));
+        }
+
+        if let Some(share) = self.balance.max_country_share {
+            if !(share > 0.0 && share <= 1.0) {
+                issues.push(SettingsIssue::new(
+                    "balance.max_country_share",
+                    "must be greater than 0 and at most 1",
+                ));
+            }
+        }
+
+        for (field, limit) in [
+            ("pacing.fetches_per_minute", self.pacing.fetches_per_minute),
+            (
+                "pacing.geo_lookups_per_second",
+                self.pacing.geo_lookups_per_second,
+            ),
+        ] {
+            if limit == Some(0) {
+                issues.push(SettingsIssue::new(field, "must be greater than 0"));
+            }
+        }
+
+        if let Err(message) = check_http_url(&self.connect_test.url) {
+            issues.push(SettingsIssue::new("connect_test.url", message));
+        }
+        issues.extend(check_expected("connect_test.expected", &self.connect_test));
+        if self.connect_test.has_http_options() {
+            issues.push(SettingsIssue::new(
+                "connect_test",
+                "method, headers, contains, not_contains, payload, trace_header and echo_ip are only supported in websites",
+            ));
+        }
+        if self.connect_test.timeout == 0 {
+            issues.push(SettingsIssue::new(
+                "connect_test.timeout",
+                "must be greater than 0",
+            ));
+        }
+
+        for (i, target) in self.uploads.iter().enumerate() {
+            for (field, secret) in target.missing_secrets() {
+                issues.push(SettingsIssue::new(
+                    format!("uploads[{}].{}", i, field),
+                    format!("missing, set it here or in secrets.{}", secret),
+                ));
+            }
+        }
+
+        if let Some(script) = &self.script {
+            if let Err(e) = NodeScript::load(&data_path(self.data_dir.as_deref(), script)) {
+                issues.push(SettingsIssue::new("script", e.to_string()));
+            }
+        }
+
+        for (i, site) in self.websites.iter().enumerate() {
+            if site.name.is_empty() {
+                issues.push(SettingsIssue::new(
+                    format!("websites[{}].name", i),
+                    "must not be empty",
+                ));
+            }
+            if site.top_n == Some(0) {
+                issues.push(SettingsIssue::new(
+                    format!("websites[{}].top_n", i),
+                    "must be greater than 0",
+                ));
+            }
+            if Target::is_target_url(&site.test.url) {
+                issues.extend(check_target(&format!("websites[{}]", i), &site.test));
+                continue;
+            }
+            if let Err(message) = check_http_url(&site.test.url) {
+                issues.push(SettingsIssue::new(format!("websites[{}].url", i), message));
+            }
+            if site.test.payload.is_some() {
+                issues.push(SettingsIssue::new(
+                    format!("websites[{}].payload", i),
+                    "only supported for tcp:// and udp:// targets",
+                ));
+            }
+            if let Some(header) = &site.test.trace_header {
+                if HeaderName::from_bytes(header.as_bytes()).is_err() {
+                    issues.push(SettingsIssue::new(
+                        format!("websites[{}].trace_header", i),
+                        format!("invalid header name {:?}", header),
+                    ));
+                }
+            }
+            issues.extend(check_expected(
+                &format!("websites[{}].expected", i),
+                &site.test,
+            ));
+        }
+
+        if self.speed_test.enabled {
+            // speedtest 测速自动选择服务器，不使用 url
+            if self.speed_test.backend == SpeedTestBackend::Http {
+                if let Err(message) = check_http_url(&self.speed_test.url) {
+                    issues.push(SettingsIssue::new("speed_test.url", message));
+                }
+            }
+            if self.speed_test.timeout == 0 {
+                issues.push(SettingsIssue::new(
+                    "speed_test.timeout",
+                    "must be greater than 0",
+                ));
+            }
+            if self.speed_test.candidate_factor == 0 {
+                issues.push(SettingsIssue::new(
+                    "speed_test.candidate_factor",
+                    "must be greater than 0",
+                ));
+            }
+            if self.speed_test.max_download_mb == Some(0) {
+                issues.push(SettingsIssue::new(
+                    "speed_test.max_download_mb",
+                    "must be greater than 0",
+                ));
+            }
+            if self.speed_test.max_download_secs == Some(0) {
+                issues.push(SettingsIssue::new(
+                    "speed_test.max_download_secs",
+                    "must be greater than 0",
+                ));
+            }
+        }
+
+        let weights = self.score.weights();
+        for (key, weight) in [
+            ("latency", weights.latency),
+            ("jitter", weights.jitter),
+            ("loss", weights.loss),
+            ("speed", weights.speed),
+        ] {
+            if weight < 0.0 || weight.is_nan() {
+                issues.push(SettingsIssue::new(
+                    format!("score.{}", key),
+                    "must not be negative",
+                ));
+            }
+        }
+
+        if self.markdown.enabled {
+            if self.markdown.path.is_empty() {
+                issues.push(SettingsIssue::new("markdown.path", "must not be empty"));
+            }
+            if self.markdown.top_n == 0 {
+                issues.push(SettingsIssue::new(
+                    "markdown.top_n",
+                    "must be greater than 0",
+                ));
+            }
+        }
+        issues
+    }
+
+    /// 读取配置时使用的所有配置文件，包括 profile
+    pub fn source_paths(path: Option<&Path>, profile: Option<&str>) -> Vec<PathBuf> {
+        let base = path.unwrap_or(Path::new(DEFAULT_CONFIG_PATH));
+        let mut paths = vec![base.to_path_buf()];
+        if let Some(profile) = profile {
+            paths.push(profile_path(base, profile));
+        }
+        paths
+    }
+
+    /// 与新配置相比发生变化的配置项，格式为 "path: old -> new"，密码等敏感字段不输出具体值
+    pub fn diff(&self, new: &Settings) -> Vec<String> {
+        let mut changes = vec![];
+        diff_value("", &self.raw.0, &new.raw.0, &mut changes);
+        changes
+    }
+
+    /// 需要拉取的所有订阅，代理池排在普通订阅之后，开启 need_add_pool 时包含 pools
+    pub fn sources(&self) -> Vec<SubConfig> {
+        let (pools, mut sources): (Vec<_>, Vec<_>) =
+            self.subs.iter().cloned().partition(|sub| sub.pool);
+        sources.extend(pools);
+        if self.need_add_pool {
+            sources.extend(self.pools.iter().map(|url| SubConfig {
+                pool: true,
+                ..SubConfig::new(url)
+            }));
+        }
+        sources
+    }
+
+    /// 需要拉取的所有订阅地址
+    pub fn sub_urls(&self) -> Vec<String> {
+        self.sources().into_iter().map(|sub| sub.url).collect()
+    }
+
+    /// 使用指定的订阅地址替换配置中的普通订阅，保留代理池
+    pub fn override_subs(&mut self, urls: &[String]) {
+        self.subs.retain(|sub| sub.pool);
+        self.subs.splice(0..0, urls.iter().map(SubConfig::new));
+    }
+}
+
+/// profile 名称对应基础配置同级 profiles 目录下的同名 toml 文件
+fn profile_path(base: &Path, profile: &str) -> PathBuf {
+    if profile.ends_with(".toml") || profile.contains(['/', '\\']) {
+        return PathBuf::from(profile);
+    }
+    base.parent()
+        .unwrap_or(Path::new(""))
+        .join("profiles")
+        .join(format!("{}.toml", profile))
+}
+
+fn diff_value(path: &str, old: &Value, new: &Value, changes: &mut Vec<String>) {
+    match (old, new) {
+        (Value::Object(old), Value::Object(new)) => {
+            let keys = old.keys().chain(new.keys()).collect::<BTreeSet<_>>();
+            for key in keys {
+                let path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                diff_value(
+                    &path,
+                    old.get(key).unwrap_or(&Value::Null),
+                    new.get(key).unwrap_or(&Value::Null),
+                    changes,
+                );
+            }
+        }
+        (old, new) if old != new => {
+            let secret = ["password", "secret", "token", "key"]
+                .iter()
+                .any(|s| path.rsplit('.').next().unwrap_or(path).contains(s));
+            if secret || path.starts_with("uploads") || path.starts_with("secrets") {
+                changes.push(format!("{}: changed", path));
+            } else {
+                changes.push(format!("{}: {} -> {}", path, old, new));
+            }
+        }
+        _ => {}
+    }
+}
+
+fn check_countries(path: &str, countries: &[String]) -> Vec<SettingsIssue> {
+    countries
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| c.len() != 2 || !c.chars().all(|c| c.is_ascii_alphabetic()))
+        .map(|(i, c)| {
+            SettingsIssue::new(
+                format!("{}[{}]", path, i),
+                format!("invalid country code {:?}, expected e.g. \"US\"", c),
+            )
+        })
+        .collect()
+}
+
+fn check_expected(path: &str, test: &DelayTestConfig) -> Vec<SettingsIssue> {
+    let ranges = test
+        .expected
+        .as_ref()
+        .map(|e| e.ranges())
+        .unwrap_or_default();
+    ranges
+        .iter()
+        .filter(|(start, end)| start > end || *start < 100 || *end > 599)
+        .map(|(start, end)| {
+            SettingsIssue::new(path, format!("invalid HTTP status range {}-{}", start, end))
+        })
+        .collect()
+}
+
+/// tcp://、udp:// 目标，UDP 目标必须配置 payload
+fn check_target(path: &str, test: &DelayTestConfig) -> Vec<SettingsIssue> {
+    let mut issues = vec![];
+    let target = match Target::parse(&test.url) {
+        Ok(target) => target,
+        Err(message) => return vec![SettingsIssue::new(format!("{}.url", path), message)],
+    };
+    match &test.payload {
+        Some(payload) => {
+            if let Err(e) = hex::decode(payload) {
+                issues.push(SettingsIssue::new(
+                    format!("{}.payload", path),
+                    format!("invalid hex payload: {}", e),
+                ));
+            }
+        }
+        None if target.protocol == Protocol::Udp => {
+            issues.push(SettingsIssue::new(
+                format!("{}.payload", path),
+                "required for udp targets",
+            ));
+        }
+        None => {}
+    }
+    if test.method.is_some()
+        || !test.headers.is_empty()
+        || test.contains.is_some()
+        || test.not_contains.is_some()
+        || test.trace_header.is_some()
+        || test.echo_ip
+    {
+        issues.push(SettingsIssue::new(
+            path,
+            "method, headers, contains, not_contains, trace_header and echo_ip are not supported for tcp:// and udp:// targets",
+        ));
+    }
+    issues
+}
+
+fn check_http_url(url: &str) -> Result<(), String> {
+    match Url::parse(url) {
+        Ok(parsed) if parsed.scheme() == "http" || parsed.scheme() == "https" => Ok(()),
+        Ok(_) => Err(format!("unsupported url {:?}, expected http(s)", url)),
+        Err(e) => Err(format!("invalid url {:?}: {}", url, e)),
+    }
+}
+
+/// 订阅支持网络地址、单个分享链接以及本地文件
+/// 运行时已切换到数据目录，校验相对路径时同样基于数据目录
+fn data_path(data_dir: Option<&Path>, path: &Path) -> PathBuf {
+    match data_dir {
+        Some(dir) => dir.join(path),
+        None => path.to_path_buf(),
+    }
+}
+
+/// 本地订阅文件的相对路径基于数据目录
+fn check_sub(sub: &str, data_dir: Option<&Path>) -> Result<(), String> {
+    if sub.starts_with("http://") || sub.starts_with("https://") {
+        return check_http_url(sub);
+    }
+    if sub.contains("://") {
+        return Proxy::from_link(sub)
+            .map(|_| ())
+            .map_err(|e| format!("invalid share link: {}", e));
+    }
+    if !data_path(data_dir, Path::new(sub)).exists() {
+        return Err(format!("file {:?} not found", sub));
+    }
+    Ok(())
+}
+
+fn check_rename_pattern(pattern: &str) -> Result<(), String> {
+    if pattern.is_empty() {
+        return Err("must not be empty when rename_node is enabled".to_string());
+    }
+    let mut rest = pattern;
+    while let Some(start) = rest.find("${") {
+        let Some(end) = rest[start..].find('}') else {
+            return Err(format!("unclosed placeholder in {:?}", pattern));
+        };
+        let name = &rest[start + 2..start + end];
+        if !RENAME_PLACEHOLDERS.contains(&name) {
+            return Err(format!(
+                "unknown placeholder ${{{}}}, supported: {}",
+                name,
+                RENAME_PLACEHOLDERS
+                    .iter()
+                    .map(|p| format!("${{{}}}", p))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+        rest = &rest[start + end + 1..];
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use config::FileFormat;
+
+    use super::*;
+
+    const CONFIG: &str = r#"
+fast_mode = false
+subs = ["https://a.example.com"]
+rename_node = true
+rename_pattern = "${COUNTRYCODE}"
+need_add_pool = false
+test_group_size = 50
+pools = []
+
+[connect_test]
+url = "http://www.google.com/generate_204"
+expected = 204
+timeout = 500
+
+[speed_test]
+enabled = false
+url = "https://speed.cloudflare.com/__down?bytes=104857600"
+timeout = 3000
+"#;
+
+    fn env(vars: &[(&str, &str)]) -> Environment {
+        let vars = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<config::Map<_, _>>();
+        Environment::with_prefix(ENV_PREFIX).source(Some(vars))
+    }
+
+    #[test]
+    fn test_env_override() {
+        let settings = Settings::from_sources(
+            File::from_str(CONFIG, FileFormat::Toml),
+            env(&[
+                ("CLASH_BUTLER__FAST_MODE", "true"),
+                ("CLASH_BUTLER__SUBS", "https://b.example.com,ss://xxx"),
+                ("CLASH_BUTLER__CONNECT_TEST__TIMEOUT", "800"),
+                ("CLASH_BUTLER__MARKDOWN__TOP_N", "5"),
+            ]),
+            None,
+        )
+        .unwrap();
+        assert!(settings.fast_mode);
+        assert_eq!(
+            settings.sub_urls(),
+            vec!["https://b.example.com", "ss://xxx"]
+        );
+        assert_eq!(settings.connect_test.timeout, 800);
+        assert!(settings.connect_test.is_expected(204));
+        assert_eq!(settings.websites.len(), 3);
+        assert_eq!(settings.markdown.top_n, 5);
+        assert_eq!(settings.test_group_size, 50);
+    }
+
+    #[test]
+    fn test_unknown_key() {
+        let config = format!(
+            "fast_mod = true\n{}\n[connect_test.extra]\nfoo = 1\n",
+            CONFIG
+        );
+        let err = Settings::from_sources(File::from_str(&config, FileFormat::Toml), env(&[]), None)
+            .unwrap_err();
+        let paths = err
+            .issues()
+            .iter()
+            .map(|i| i.path.as_str())
+            .collect::<Vec<_>>();
+        assert!(paths.contains(&"fast_mod"));
+        assert!(paths.contains(&"connect_test.extra"));
+    }
+
+    #[test]
+    fn test_invalid_field_path() {
+        let err = Settings::from_sources(
+            File::from_str(CONFIG, FileFormat::Toml),
+            env(&[("CLASH_BUTLER__CONNECT_TEST__TIMEOUT", "abc")]),
+            None,
+        )
+        .unwrap_err();
+        assert_eq!(err.issues()[0].path, "connect_test.timeout");
+    }
+
+    #[test]
+    fn test_validate() {
+        let err = Settings::from_sources(
+            File::from_str(CONFIG, FileFormat::Toml),
+            env(&[
+                (
+                    "CLASH_BUTLER__SUBS",
+                    "htp//a,ss://xxx,https://a.example.com",
+                ),
+                ("CLASH_BUTLER__RENAME_PATTERN", "${COUNTRY}_${ISP}"),
+                ("CLASH_BUTLER__CONNECT_TEST__URL", "ftp://example.com"),
+            ]),
+            None,
+        )
+        .unwrap_err();
+        let paths = err
+            .issues()
+            .iter()
+            .map(|i| i.path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            vec!["subs[0]", "subs[1]", "rename_pattern", "connect_test.url"]
+        );
+        assert!(err.to_string().contains("${COUNTRY}"));
+    }
+
+    #[test]
+    fn test_validate_script() {
+        let err = Settings::from_sources(
+            File::from_str(CONFIG, FileFormat::Toml),
+            env(&[("CLASH_BUTLER__SCRIPT", "conf/not_exists.rhai")]),
+            None,
+        )
+        .unwrap_err();
+        let paths = err
+            .issues()
+            .iter()
+            .map(|i| i.path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, vec!["script"]);
+    }
+
+    #[test]
+    fn test_relative_data_dir() {
+        // 首次加载时相对路径的数据目录基于启动目录，切换到数据目录后以其绝对路径重新加载
+        let dir = Path::new("target/clash_butler_test_data_dir");
+        std::fs::create_dir_all(dir).unwrap();
+        std::fs::write(dir.join("filter.rhai"), "true").unwrap();
+        std::fs::write(dir.join("local.yaml"), "proxies: []\n").unwrap();
+        let vars = [
+            ("CLASH_BUTLER__DATA_DIR", dir.to_str().unwrap()),
+            ("CLASH_BUTLER__SCRIPT", "filter.rhai"),
+            ("CLASH_BUTLER__SUBS", "local.yaml"),
+        ];
+        let load = |data_dir: Option<&Path>| {
+            Settings::from_sources(
+                File::from_str(CONFIG, FileFormat::Toml),
+                env(&vars),
+                data_dir,
+            )
+        };
+
+        let first = load(None).unwrap();
+        assert_eq!(first.data_dir.as_deref(), Some(dir));
+        let absolute = std::path::absolute(dir).unwrap();
+        let reloaded = load(Some(&absolute)).unwrap();
+        assert_eq!(reloaded.data_dir, Some(absolute));
+
+        // 相对路径再次基于数据目录解析时找不到脚本及本地订阅
+        let err = load(Some(&dir.join(dir))).unwrap_err();
+        let paths = err
+            .issues()
+            .iter()
+            .map(|i| i.path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, vec!["subs[0]", "script"]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_validate_pacing() {
+        let settings = Settings::from_sources(
+            File::from_str(CONFIG, FileFormat::Toml),
+            env(&[("CLASH_BUTLER__PACING__GEO_LOOKUPS_PER_SECOND", "2")]),
+            None,
+        )
+        .unwrap();
+        assert_eq!(settings.pacing.geo_lookups_per_second, Some(2));
+        assert_eq!(settings.pacing.fetches_per_minute, None);
+        assert_eq!(settings.pacing.speed_test_jitter_ms, 0);
+
+        let err = Settings::from_sources(
+            File::from_str(CONFIG, FileFormat::Toml),
+            env(&[("CLASH_BUTLER__PACING__FETCHES_PER_MINUTE", "0")]),
+            None,
+        )
+        .unwrap_err();
+        let paths = err
+            .issues()
+            .iter()
+            .map(|i| i.path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, vec!["pacing.fetches_per_minute"]);
+    }
+
+    #[test]
+    fn test_validate_inspect() {
+        let settings = Settings::from_sources(
+            File::from_str(CONFIG, FileFormat::Toml),
+            env(&[("CLASH_BUTLER__INSPECT__CONCURRENCY", "4")]),
+            None,
+        )
+        .unwrap();
+        assert_eq!(settings.inspect.concurrency, 4);
+        assert_eq!(settings.inspect.node_timeout_secs, 120);
+        assert_eq!(settings.inspect.trace_attempts, 3);
+
+        let err = Settings::from_sources(
+            File::from_str(CONFIG, FileFormat::Toml),
+            env(&[("CLASH_BUTLER__INSPECT__TRACE_ATTEMPTS", "0")]),
+            None,
+        )
+        .unwrap_err();
+        let paths = err
+            .issues()
+            .iter()
+            .map(|i| i.path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, vec!["inspect.trace_attempts"]);
+    }
+
+    #[test]
+    fn test_validate_early_stop() {
+        let settings = Settings::from_sources(
+            File::from_str(CONFIG, FileFormat::Toml),
+            env(&[("CLASH_BUTLER__EARLY_STOP__ENABLED", "true")]),
+            None,
+        )
+        .unwrap();
+        assert!(settings.early_stop.enabled);
+        assert_eq!(settings.early_stop.top_k, 10);
+        assert_eq!(settings.early_stop.stable_rounds, 3);
+
+        let err = Settings::from_sources(
+            File::from_str(CONFIG, FileFormat::Toml),
+            env(&[("CLASH_BUTLER__EARLY_STOP__TOP_K", "0")]),
+            None,
+        )
+        .unwrap_err();
+        let paths = err
+            .issues()
+            .iter()
+            .map(|i| i.path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, vec!["early_stop.top_k"]);
+    }
+
+    #[test]
+    fn test_validate_aliases() {
+        let aliases = r#"
+[[aliases]]
+name = "home"
+
+[[aliases]]
+name = "tv"
+token = "secret"
+countries = ["HK"]
+max_nodes = 5
+format = "base64"
+
+[[aliases]]
+name = "tv"
+
+[[aliases]]
+name = "a/b"
+max_nodes = 0
+"#;
+        let err = Settings::from_sources(
+            vec![
+                File::from_str(CONFIG, FileFormat::Toml),
+                File::from_str(aliases, FileFormat::Toml),
+            ],
+            env(&[]),
+            None,
+        )
+        .unwrap_err();
+        let paths = err
+            .issues()
+            .iter()
+            .map(|i| i.path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            vec!["aliases[2].name", "aliases[3].name", "aliases[3].max_nodes"]
+        );
+    }
+
+    #[test]
+    fn test_validate_tags() {
+        let tags = r#"
+[[tags]]
+name = "gpt"
+checks = ["OpenAI"]
+group = true
+
+[[tags]]
+name = "gpt"
+servers = ["1.2.3.4"]
+
+[[tags]]
+name = "empty"
+
+[[aliases]]
+name = "ai"
+tags = ["gpt", "home"]
+"#;
+        let err = Settings::from_sources(
+            vec![
+                File::from_str(CONFIG, FileFormat::Toml),
+                File::from_str(tags, FileFormat::Toml),
+            ],
+            env(&[]),
+            None,
+        )
+        .unwrap_err();
+        let paths = err
+            .issues()
+            .iter()
+            .map(|i| i.path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, vec!["aliases[0].tags", "tags[1].name", "tags[2]"]);
+    }
+
+    #[test]
+    fn test_alias_authorized() {
+        let open = SubAlias::default();
+        assert!(open.authorized(None));
+        assert!(open.authorized(Some("anything")));
+
+        let alias = SubAlias {
+            token: Secret::new("secret"),
+            ..Default::default()
+        };
+        assert!(alias.authorized(Some("secret")));
+        assert!(!alias.authorized(Some("wrong")));
+        assert!(!alias.authorized(None));
+    }
+
+    #[test]
+    fn test_check_rename_pattern() {
+        assert!(check_rename_pattern("${COUNTRYCODE}_${CITY}_${ISP}").is_ok());
+        assert!(check_rename_pattern("${CDN}${COUNTRYCODE}").is_ok());
+        assert!(check_rename_pattern("node-${IP}").is_ok());
+        assert!(check_rename_pattern("${SOURCE}_${COUNTRYCODE}").is_ok());
+        assert!(check_rename_pattern("${COUNTRYCODE").is_err());
+        assert!(check_rename_pattern("").is_err());
+    }
+
+    #[test]
+    fn test_profile() {
+        let profile = r#"
+subs = ["https://home.example.com"]
+rename_pattern = "home_${COUNTRYCODE}"
+
+[speed_test]
+enabled = true
+"#;
+        let settings = Settings::from_sources(
+            vec![
+                File::from_str(CONFIG, FileFormat::Toml),
+                File::from_str(profile, FileFormat::Toml),
+            ],
+            env(&[]),
+            None,
+        )
+        .unwrap();
+        assert_eq!(settings.sub_urls(), vec!["https://home.example.com"]);
+        assert_eq!(settings.rename_pattern, "home_${COUNTRYCODE}");
+        assert!(settings.speed_test.enabled);
+        assert_eq!(settings.speed_test.timeout, 3000);
+        assert_eq!(settings.test_group_size, 50);
+    }
+
+    #[test]
+    fn test_profile_path() {
+        let base = Path::new(DEFAULT_CONFIG_PATH);
+        assert_eq!(
+            profile_path(base, "home"),
+            PathBuf::from("conf/profiles/home.toml")
+        );
+        assert_eq!(
+            profile_path(base, "./vps.toml"),
+            PathBuf::from("./vps.toml")
+        );
+        assert_eq!(
+            profile_path(Path::new("config.toml"), "vps"),
+            PathBuf::from("profiles/vps.toml")
+        );
+    }
+
+    #[test]
+    fn test_sub_config() {
+        let subs = r#"
+subs = [
+    { url = "https://pool.example.com", pool = true },
+    "https://a.example.com",
+    { url = "https://b.example.com", prefix = "B_", label = "b", countries = ["US"], max_nodes = 10, max_candidates = 200, sample = "newest", trusted = true },
+]
+need_add_pool = true
+pools = ["https://legacy.example.com"]
+"#;
+        let settings = Settings::from_sources(
+            vec![
+                File::from_str(CONFIG, FileFormat::Toml),
+                File::from_str(subs, FileFormat::Toml),
+            ],
+            env(&[]),
+            None,
+        )
+        .unwrap();
+        assert_eq!(settings.subs[1], SubConfig::new("https://a.example.com"));
+        assert_eq!(
+            settings.subs[2],
+            SubConfig {
+                url: "https://b.example.com".to_string(),
+                prefix: Some("B_".to_string()),
+                label: Some("b".to_string()),
+                countries: vec!["US".to_string()],
+                max_nodes: Some(10),
+                max_candidates: Some(200),
+                sample: SampleMode::Newest,
+                trusted: true,
+                pool: false,
+            }
+        );
+        assert_eq!(
+            settings.sub_urls(),
+            vec![
+                "https://a.example.com",
+                "https://b.example.com",
+                "https://pool.example.com",
+                "https://legacy.example.com",
+            ]
+        );
+
+        let mut settings = settings;
+        settings.override_subs(&["https://c.example.com".to_string()]);
+        assert_eq!(
+            settings.sub_urls(),
+            vec![
+                "https://c.example.com",
+                "https://pool.example.com",
+                "https://legacy.example.com",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sub_config_unknown_key() {
+        let subs = r#"subs = [{ url = "https://a.example.com", prefx = "A_" }]"#;
+        let err = Settings::from_sources(
+            vec![
+                File::from_str(CONFIG, FileFormat::Toml),
+                File::from_str(subs, FileFormat::Toml),
+            ],
+            env(&[]),
+            None,
+        )
+        .unwrap_err();
+        assert!(err.issues()[0].path.ends_with("prefx"));
+    }
+
+    #[test]
+    fn test_websites() {
+        let websites = r#"
+[[websites]]
+name = "YouTube"
+url = "https://music.youtube.com/generate_204"
+method = "HEAD"
+expected = [204, 301]
+
+[[websites]]
+name = "Netflix"
+url = "https://www.netflix.com/title/81280792"
+expected = "200-299"
+contains = "watch"
+headers = { Accept-Language = "en-US" }
+
+[[websites]]
+name = "Echo"
+url = "https://echo.example.com/ip"
+trace_header = "X-Trace-Id"
+echo_ip = true
+"#;
+        let settings = Settings::from_sources(
+            vec![
+                File::from_str(CONFIG, FileFormat::Toml),
+                File::from_str(websites, FileFormat::Toml),
+            ],
+            env(&[]),
+            None,
+        )
+        .unwrap();
+        assert_eq!(settings.websites.len(), 2);
+        let youtube = &settings.websites[0].test;
+        assert_eq!(youtube.method.as_deref(), Some("HEAD"));
+        assert!(youtube.is_expected(301));
+        assert_eq!(youtube.timeout, 5000);
+        let netflix = &settings.websites[1].test;
+        assert_eq!(netflix.contains.as_deref(), Some("watch"));
+        assert_eq!(netflix.headers.len(), 1);
+        let echo = &settings.websites[2].test;
+        assert_eq!(echo.trace_header.as_deref(), Some("X-Trace-Id"));
+        assert!(echo.echo_ip);
+
+        let invalid = r#"
+[connect_test]
+url = "http://www.google.com/generate_204"
+expected = "600"
+contains = "ok"
+
+[[websites]]
+name = "Echo"
+url = "https://echo.example.com/ip"
+trace_header = "X Trace"
+"#;
+        let err = Settings::from_sources(
+            vec![
+                File::from_str(CONFIG, FileFormat::Toml),
+                File::from_str(invalid, FileFormat::Toml),
+            ],
+            env(&[]),
+            None,
+        )
+        .unwrap_err();
+        let paths = err
+            .issues()
+            .iter()
+            .map(|i| i.path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            vec![
+                "connect_test.expected",
+                "connect_test",
+                "websites[0].trace_header"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_target_websites() {
+        let targets = r#"
+[[websites]]
+name = "CS2"
+url = "udp://203.0.113.10:27015"
+payload = "ffffffff54536f7572636520456e67696e6520517565727900"
+timeout = 1000
+
+[[websites]]
+name = "SSH"
+url = "tcp://203.0.113.10:22"
+
+[[websites]]
+name = "Missing"
+url = "udp://203.0.113.10:3478"
+
+[[websites]]
+name = "Hex"
+url = "tcp://203.0.113.10"
+payload = "zz"
+"#;
+        let err = Settings::from_sources(
+            vec![
+                File::from_str(CONFIG, FileFormat::Toml),
+                File::from_str(targets, FileFormat::Toml),
+            ],
+            env(&[]),
+            None,
+        )
+        .unwrap_err();
+        let paths = err
+            .issues()
+            .iter()
+            .map(|i| i.path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, vec!["websites[2].payload", "websites[3].url"]);
+    }
+
+    #[test]
+    fn test_diff() {
+        let load = |extra: &str| {
+            Settings::from_sources(
+                vec![
+                    File::from_str(CONFIG, FileFormat::Toml),
+                    File::from_str(extra, FileFormat::Toml),
+                ],
+                env(&[]),
+                None,
+            )
+            .unwrap()
+        };
+        let old = load("");
+        let new = load(
+            r#"
+fast_mode = true
+subs = ["https://a.example.com", "https://b.example.com"]
+
+[connect_test]
+timeout = 800
+"#,
+        );
+        assert_eq!(
+            old.diff(&new),
+            vec![
+                "connect_test.timeout: 500 -> 800",
+                "fast_mode: false -> true",
+                r#"subs: ["https://a.example.com"] -> ["https://a.example.com","https://b.example.com"]"#,
+            ]
+        );
+        assert!(old.diff(&load("")).is_empty());
+    }
+
+    #[test]
+    fn test_secrets() {
+        const UPLOADS: &str = r#"
+[[uploads]]
+type = "s3"
+endpoint = "https://xxx.r2.cloudflarestorage.com"
+bucket = "subs"
+access_key = "ak"
+
+[[uploads]]
+type = "webdav"
+url = "https://nas.local/dav"
+username = "u"
+"#;
+        let load = |vars: &[(&str, &str)]| {
+            Settings::from_sources(
+                vec![
+                    File::from_str(CONFIG, FileFormat::Toml),
+                    File::from_str(UPLOADS, FileFormat::Toml),
+                ],
+                env(vars),
+                None,
+            )
+        };
+
+        let err = load(&[]).unwrap_err();
+        let paths = err
+            .issues()
+            .iter()
+            .map(|i| i.path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, vec!["uploads[0].secret_key", "uploads[1].password"]);
+
+        let settings = load(&[
+            ("CLASH_BUTLER__SECRETS__S3_SECRET_KEY", "sk"),
+            ("CLASH_BUTLER__SECRETS__WEBDAV_PASSWORD", "p"),
+        ])
+        .unwrap();
+        match &settings.uploads[0] {
+            UploadTarget::S3(config) => {
+                assert_eq!(config.access_key.expose(), "ak");
+                assert_eq!(config.secret_key.expose(), "sk");
+            }
+            _ => panic!("expected s3"),
+        }
+        let debug = format!("{:?}", settings);
+        assert!(!debug.contains("\"sk\"") && !debug.contains("\"p\""));
     }
 }
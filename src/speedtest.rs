@@ -2,25 +2,105 @@ use std::time::Duration;
 use std::time::Instant;
 
 use futures_util::StreamExt;
+use reqwest::Client;
 use reqwest::Proxy;
 use serde::Deserialize;
 use serde::Serialize;
+use tokio::time::timeout_at;
+
+/// 按 /traffic 采样测速时，连续多少个采样点的速率相差不超过 STABLE_TOLERANCE 视为稳定
+const STABLE_SAMPLES: usize = 3;
+const STABLE_TOLERANCE: f64 = 0.1;
+
+/// Speedtest.net 的服务器列表，按请求来源 IP 的距离从近到远排列，经节点请求时即为离出口 IP 最近的服务器
+const OOKLA_SERVERS_URL: &str = "https://www.speedtest.net/api/js/servers?engine=js&limit=10";
+/// 依次尝试的服务器数，前面的服务器不可用时换下一个
+const OOKLA_CANDIDATES: usize = 3;
+/// 测量延迟的请求次数，取最小值
+const OOKLA_LATENCY_SAMPLES: usize = 3;
+/// 下载测速使用的文件，约 30 MB，受下载预算限制
+const OOKLA_DOWNLOAD_FILE: &str = "random4000x4000.jpg";
+
+const CLOUDFLARE_URL: &str = "https://speed.cloudflare.com";
+/// 依次下载的数据量，单位字节，小文件受 TCP 慢启动影响速度偏低，取各次中的最大速度
+const CLOUDFLARE_DOWN_SIZES: &[u64] = &[100_000, 1_000_000, 10_000_000, 25_000_000];
+const CLOUDFLARE_UP_SIZES: &[u64] = &[100_000, 1_000_000, 5_000_000];
+/// 空闲及负载下测量延迟的请求次数，取中位数
+const CLOUDFLARE_LATENCY_SAMPLES: usize = 5;
+/// 负载下测量延迟的请求间隔，等待下载占满线路
+const CLOUDFLARE_LOADED_INTERVAL: Duration = Duration::from_millis(300);
+
+/// 测速方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpeedTestBackend {
+    /// 下载 url 计算速度
+    #[default]
+    Http,
+    /// 使用 Speedtest.net（Ookla）兼容的服务器，结果与测速 App 中看到的接近
+    Speedtest,
+    /// 使用 speed.cloudflare.com 的 __down、__up 接口测量下载、上传速度及空闲、负载下的延迟
+    Cloudflare,
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 #[allow(unused)]
 pub struct SpeedTestConfig {
     pub enabled: bool,
+    /// 测速方式，speedtest 时自动选择离出口 IP 最近的 Speedtest.net 服务器，忽略 url 及 sample_traffic
+    #[serde(default)]
+    pub backend: SpeedTestBackend,
     pub url: String,
     pub timeout: u16,
+    /// 设置了 max_nodes 时只对延迟最低的 max_nodes × candidate_factor 个节点测速
+    #[serde(default = "default_candidate_factor")]
+    pub candidate_factor: usize,
+    /// 读取内核 /traffic 接口每秒上报的速率计算速度，不依赖测速地址返回 Content-Length，速率稳定后提前结束下载
+    #[serde(default)]
+    pub sample_traffic: bool,
+    /// 单个节点测速最多下载的数据量，单位 MB，达到后中止下载，不填则不限制
+    #[serde(default)]
+    pub max_download_mb: Option<u64>,
+    /// 单个节点测速最长的下载时间，单位秒，达到后中止下载并按已下载的数据计算速度，不填则不限制
+    #[serde(default)]
+    pub max_download_secs: Option<u64>,
+}
+
+impl SpeedTestConfig {
+    pub fn budget(&self) -> DownloadBudget {
+        DownloadBudget {
+            max_bytes: self.max_download_mb.map(|mb| mb * 1024 * 1024),
+            max_duration: self.max_download_secs.map(Duration::from_secs),
+        }
+    }
+}
+
+fn default_candidate_factor() -> usize {
+    2
+}
+
+/// 单个节点测速的下载预算，数据量或时间任一项先达到即中止下载，保护本机线路及免费节点的流量
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DownloadBudget {
+    pub max_bytes: Option<u64>,
+    pub max_duration: Option<Duration>,
 }
 
-#[allow(dead_code)]
-async fn test_download(
+impl DownloadBudget {
+    fn bytes_reached(&self, bytes: u64) -> bool {
+        self.max_bytes.is_some_and(|max| bytes >= max)
+    }
+}
+
+/// 设置了时间预算时，timeout 只限制等待响应的时间，下载时间由预算控制
+pub async fn test_download(
     url: &str,
     timeout: Duration,
     proxy_url: Option<&str>,
+    budget: DownloadBudget,
 ) -> Result<(Duration, f64, Duration), reqwest::Error> {
-    let client_builder = reqwest::Client::builder().timeout(timeout);
+    let client_builder =
+        reqwest::Client::builder().timeout(timeout + budget.max_duration.unwrap_or_default());
 
     let client = if let Some(proxy) = proxy_url {
         client_builder.proxy(Proxy::all(proxy)?).build()?
@@ -30,29 +110,438 @@ async fn test_download(
 
     let start = Instant::now();
     let response = client.get(url).send().await?;
+    let deadline = budget.max_duration.map(|d| tokio::time::Instant::now() + d);
 
     // Stream the response body
     let mut stream = response.bytes_stream();
     let mut total_bytes = 0;
-    let first_byte_time = if let Some(chunk) = stream.next().await {
-        total_bytes += chunk?.len();
-        start.elapsed() // TTFB is the elapsed time when the first byte is received
-    } else {
-        Duration::from_secs(0) // No bytes received
-    };
-
-    while let Some(chunk) = stream.next().await {
-        total_bytes += chunk?.len();
+    let mut first_byte_time = None;
+    loop {
+        let chunk = match deadline {
+            Some(deadline) => match timeout_at(deadline, stream.next()).await {
+                Ok(chunk) => chunk,
+                // 达到时间预算，丢弃 stream 即中止下载
+                Err(_) => break,
+            },
+            None => stream.next().await,
+        };
+        let Some(chunk) = chunk else {
+            break;
+        };
+        total_bytes += chunk?.len() as u64;
+        // TTFB is the elapsed time when the first byte is received
+        if first_byte_time.is_none() {
+            first_byte_time = Some(start.elapsed());
+        }
+        if budget.bytes_reached(total_bytes) {
+            break;
+        }
     }
+    let first_byte_time = first_byte_time.unwrap_or_default();
     let total_duration = start.elapsed();
     let bandwidth = (total_bytes as f64 / 1024.0) / total_duration.as_secs_f64(); // KB per second
     Ok((total_duration, bandwidth, first_byte_time))
 }
 
+/// 经代理下载 url，同时通过带有控制接口认证的 traffic_client 读取内核 traffic_url（/traffic）
+/// 每秒上报的下载速率，返回平均速度，单位 KB/s
+///
+/// 连续 STABLE_SAMPLES 个采样点稳定、超时或达到下载预算后结束下载，第一个采样点可能只覆盖部分时间，不计入结果
+pub async fn sample_download(
+    url: &str,
+    timeout: Duration,
+    proxy_url: &str,
+    traffic_client: &Client,
+    traffic_url: &str,
+    budget: DownloadBudget,
+) -> Result<f64, Box<dyn std::error::Error>> {
+    let timeout = budget.max_duration.map_or(timeout, |d| d.min(timeout));
+    let deadline = tokio::time::Instant::now() + timeout;
+    let traffic = traffic_client.get(traffic_url).send().await?;
+    if !traffic.status().is_success() {
+        return Err(Box::from(format!(
+            "failed to get traffic: {}",
+            traffic.status()
+        )));
+    }
+    let mut rates = traffic.bytes_stream();
+
+    let client = Client::builder()
+        .proxy(Proxy::all(proxy_url)?)
+        .timeout(timeout)
+        .build()?;
+    let response = client.get(url).send().await?;
+    let download = tokio::spawn(async move {
+        let mut body = response.bytes_stream();
+        let mut bytes = 0;
+        while let Some(Ok(chunk)) = body.next().await {
+            bytes += chunk.len() as u64;
+            if budget.bytes_reached(bytes) {
+                break;
+            }
+        }
+    });
+
+    let mut samples = vec![];
+    let mut buffer = vec![];
+    let mut first = true;
+    loop {
+        let chunk = match timeout_at(deadline, rates.next()).await {
+            Ok(Some(chunk)) => chunk?,
+            Ok(None) | Err(_) => break,
+        };
+        buffer.extend_from_slice(&chunk);
+        while let Some(end) = buffer.iter().position(|b| *b == b'\n') {
+            let line = buffer.drain(..=end).collect::<Vec<_>>();
+            let Some(rate) = parse_rate(&line) else {
+                continue;
+            };
+            // 下载结束后速率回落为 0
+            if rate == 0 && !samples.is_empty() && download.is_finished() {
+                break;
+            }
+            if first {
+                first = false;
+            } else if rate > 0 {
+                samples.push(rate);
+            }
+        }
+        if stable_rate(&samples).is_some() || (download.is_finished() && !samples.is_empty()) {
+            break;
+        }
+    }
+    download.abort();
+
+    let rate = stable_rate(&samples).or_else(|| mean(&samples));
+    rate.map(|bytes| bytes / 1024.0)
+        .ok_or_else(|| Box::from("no traffic sampled"))
+}
+
+/// Speedtest.net 服务器列表中的一项
+#[derive(Debug, Clone, Deserialize)]
+struct OoklaServer {
+    /// 上传地址，如 http://speedtest.example.com:8080/speedtest/upload.php
+    url: String,
+    name: String,
+    sponsor: String,
+}
+
+impl OoklaServer {
+    /// 测速文件所在的目录，以 / 结尾
+    fn base(&self) -> Option<&str> {
+        self.url
+            .rfind('/')
+            .map(|end| &self.url[..=end])
+            .filter(|base| base.starts_with("http"))
+    }
+}
+
+/// Speedtest.net 兼容服务器的测速结果
+#[derive(Debug, Clone)]
+pub struct OoklaResult {
+    /// 服务器的运营商及城市，如 "Example ISP (Tokyo)"
+    pub server: String,
+    pub latency: Duration,
+    /// 下载速度，单位 KB/s
+    pub speed: f64,
+}
+
+/// 经代理获取离出口 IP 最近的 Speedtest.net 服务器，测量延迟后下载测速文件，
+/// 前 OOKLA_CANDIDATES 个服务器依次尝试，延迟测量成功的第一个服务器用于下载
+pub async fn ookla_test(
+    timeout: Duration,
+    proxy_url: Option<&str>,
+    budget: DownloadBudget,
+) -> Result<OoklaResult, Box<dyn std::error::Error>> {
+    let mut builder = Client::builder().timeout(timeout);
+    if let Some(proxy) = proxy_url {
+        builder = builder.proxy(Proxy::all(proxy)?);
+    }
+    let client = builder.build()?;
+    let servers = client
+        .get(OOKLA_SERVERS_URL)
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<Vec<OoklaServer>>()
+        .await?;
+
+    let mut last_error = None;
+    for server in servers.iter().take(OOKLA_CANDIDATES) {
+        let Some(base) = server.base() else {
+            continue;
+        };
+        let latency = match ookla_latency(&client, base).await {
+            Ok(latency) => latency,
+            Err(e) => {
+                last_error = Some(e);
+                continue;
+            }
+        };
+        let url = format!("{}{}", base, OOKLA_DOWNLOAD_FILE);
+        let (_, speed, _) = test_download(&url, timeout, proxy_url, budget).await?;
+        return Ok(OoklaResult {
+            server: format!("{} ({})", server.sponsor, server.name),
+            latency,
+            speed,
+        });
+    }
+    Err(match last_error {
+        Some(e) => Box::from(format!("no speedtest server available, {}", e)),
+        None => Box::from("no speedtest server available"),
+    })
+}
+
+/// 请求服务器的 latency.txt，取 OOKLA_LATENCY_SAMPLES 次中的最小耗时
+async fn ookla_latency(client: &Client, base: &str) -> Result<Duration, reqwest::Error> {
+    let url = format!("{}latency.txt", base);
+    let mut best = Duration::MAX;
+    for _ in 0..OOKLA_LATENCY_SAMPLES {
+        let start = Instant::now();
+        client
+            .get(&url)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        best = best.min(start.elapsed());
+    }
+    Ok(best)
+}
+
+/// speed.cloudflare.com 的测速结果
+#[derive(Debug, Clone)]
+pub struct CloudflareResult {
+    pub latency: Duration,
+    /// 下载最大的文件时测得的延迟，与空闲时差距大说明线路缓冲过深（bufferbloat）
+    pub loaded_latency: Option<Duration>,
+    /// 下载速度，单位 KB/s
+    pub download: f64,
+    /// 上传速度，单位 KB/s，所有上传都失败时为 None
+    pub upload: Option<f64>,
+}
+
+/// 经代理测量空闲延迟，再按 CLOUDFLARE_DOWN_SIZES、CLOUDFLARE_UP_SIZES 依次下载、上传，
+/// 下载最大的文件时同时测量负载下的延迟；下载预算限制总数据量及总时间，超出的部分不再测试
+pub async fn cloudflare_test(
+    timeout: Duration,
+    proxy_url: Option<&str>,
+    budget: DownloadBudget,
+) -> Result<CloudflareResult, Box<dyn std::error::Error>> {
+    let mut builder = Client::builder().timeout(timeout);
+    if let Some(proxy) = proxy_url {
+        builder = builder.proxy(Proxy::all(proxy)?);
+    }
+    let client = builder.build()?;
+    let start = Instant::now();
+    let remaining = || DownloadBudget {
+        max_bytes: None,
+        max_duration: budget
+            .max_duration
+            .map(|d| d.saturating_sub(start.elapsed())),
+    };
+    let expired = || budget.max_duration.is_some_and(|d| start.elapsed() >= d);
+
+    let latency = cloudflare_latency(&client, Duration::ZERO).await?;
+
+    let down_sizes = fit_sizes(CLOUDFLARE_DOWN_SIZES, budget.max_bytes);
+    let mut download = None::<f64>;
+    let mut loaded_latency = None;
+    for (i, size) in down_sizes.iter().enumerate() {
+        if i > 0 && expired() {
+            break;
+        }
+        let url = format!("{}/__down?bytes={}", CLOUDFLARE_URL, size);
+        let (result, loaded) = if i == down_sizes.len() - 1 {
+            let (result, loaded) = tokio::join!(
+                test_download(&url, timeout, proxy_url, remaining()),
+                cloudflare_latency(&client, CLOUDFLARE_LOADED_INTERVAL)
+            );
+            (result, loaded.ok())
+        } else {
+            (
+                test_download(&url, timeout, proxy_url, remaining()).await,
+                None,
+            )
+        };
+        let (_, speed, _) = result?;
+        download = Some(download.map_or(speed, |best| best.max(speed)));
+        loaded_latency = loaded.or(loaded_latency);
+    }
+    let download = download.ok_or("no download finished")?;
+
+    let used = down_sizes.iter().sum::<u64>();
+    let up_sizes = fit_sizes(
+        CLOUDFLARE_UP_SIZES,
+        budget.max_bytes.map(|max| max.saturating_sub(used)),
+    );
+    let mut upload = None::<f64>;
+    for size in up_sizes {
+        if expired() {
+            break;
+        }
+        let begin = Instant::now();
+        let sent = client
+            .post(format!("{}/__up", CLOUDFLARE_URL))
+            .body(vec![0u8; size as usize])
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        if sent.is_err() {
+            break;
+        }
+        let speed = size as f64 / 1024.0 / begin.elapsed().as_secs_f64();
+        upload = Some(upload.map_or(speed, |best| best.max(speed)));
+    }
+
+    Ok(CloudflareResult {
+        latency,
+        loaded_latency,
+        download,
+        upload,
+    })
+}
+
+/// 请求 CLOUDFLARE_LATENCY_SAMPLES 次空的 __down，每次之前等待 interval，返回耗时的中位数
+async fn cloudflare_latency(
+    client: &Client,
+    interval: Duration,
+) -> Result<Duration, reqwest::Error> {
+    let url = format!("{}/__down?bytes=0", CLOUDFLARE_URL);
+    let mut samples = vec![];
+    for _ in 0..CLOUDFLARE_LATENCY_SAMPLES {
+        tokio::time::sleep(interval).await;
+        let start = Instant::now();
+        client
+            .get(&url)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        samples.push(start.elapsed());
+    }
+    samples.sort();
+    Ok(samples[samples.len() / 2])
+}
+
+/// 按顺序累计不超过 max_bytes 的数据量，至少保留第一个
+fn fit_sizes(sizes: &[u64], max_bytes: Option<u64>) -> Vec<u64> {
+    let mut total = 0;
+    sizes
+        .iter()
+        .copied()
+        .enumerate()
+        .take_while(|(i, size)| {
+            total += size;
+            *i == 0 || !max_bytes.is_some_and(|max| total > max)
+        })
+        .map(|(_, size)| size)
+        .collect()
+}
+
+/// 解析 /traffic 的一行，如 {"up":1024,"down":2048}，返回下载速率，单位字节/秒
+fn parse_rate(line: &[u8]) -> Option<u64> {
+    #[derive(Deserialize)]
+    struct Rate {
+        down: u64,
+    }
+
+    serde_json::from_slice::<Rate>(line).ok().map(|r| r.down)
+}
+
+/// 最近 STABLE_SAMPLES 个采样点都在平均值的 STABLE_TOLERANCE 范围内时返回平均值
+fn stable_rate(samples: &[u64]) -> Option<f64> {
+    if samples.len() < STABLE_SAMPLES {
+        return None;
+    }
+    let recent = &samples[samples.len() - STABLE_SAMPLES..];
+    let avg = mean(recent)?;
+    recent
+        .iter()
+        .all(|r| (*r as f64 - avg).abs() <= avg * STABLE_TOLERANCE)
+        .then_some(avg)
+}
+
+fn mean(samples: &[u64]) -> Option<f64> {
+    if samples.is_empty() {
+        return None;
+    }
+    Some(samples.iter().sum::<u64>() as f64 / samples.len() as f64)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
+    #[test]
+    fn test_parse_rate() {
+        assert_eq!(parse_rate(br#"{"up":1024,"down":2048}"#), Some(2048));
+        assert_eq!(parse_rate(b"{\"up\":0,\"down\":0}\n"), Some(0));
+        assert_eq!(parse_rate(b"not json"), None);
+    }
+
+    #[test]
+    fn test_stable_rate() {
+        assert_eq!(stable_rate(&[1000, 1000]), None);
+        assert_eq!(stable_rate(&[100, 1000, 1050, 950]), Some(1000.0));
+        assert_eq!(stable_rate(&[1000, 2000, 3000]), None);
+        assert_eq!(mean(&[1000, 2000, 3000]), Some(2000.0));
+    }
+
+    #[test]
+    fn test_ookla_servers() {
+        let servers: Vec<OoklaServer> = serde_json::from_str(
+            r#"[
+                {"url":"http://sp1.example.com:8080/speedtest/upload.php","name":"Tokyo","sponsor":"Example ISP","country":"Japan","id":"1"},
+                {"url":"upload.php","name":"Osaka","sponsor":"Other","country":"Japan","id":"2"}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            servers[0].base(),
+            Some("http://sp1.example.com:8080/speedtest/")
+        );
+        assert_eq!(servers[1].base(), None);
+
+        let config: SpeedTestConfig = serde_json::from_str(
+            r#"{"enabled":true,"url":"","timeout":3000,"backend":"speedtest"}"#,
+        )
+        .unwrap();
+        assert_eq!(config.backend, SpeedTestBackend::Speedtest);
+    }
+
+    #[test]
+    fn test_fit_sizes() {
+        assert_eq!(
+            fit_sizes(CLOUDFLARE_DOWN_SIZES, None),
+            CLOUDFLARE_DOWN_SIZES
+        );
+        assert_eq!(
+            fit_sizes(CLOUDFLARE_DOWN_SIZES, Some(20_000_000)),
+            vec![100_000, 1_000_000, 10_000_000]
+        );
+        assert_eq!(fit_sizes(CLOUDFLARE_DOWN_SIZES, Some(0)), vec![100_000]);
+        assert_eq!(
+            fit_sizes(CLOUDFLARE_UP_SIZES, Some(1_100_000)),
+            vec![100_000, 1_000_000]
+        );
+    }
+
+    #[test]
+    fn test_download_budget() {
+        let config: SpeedTestConfig = serde_json::from_str(
+            r#"{"enabled":true,"url":"https://a.com","timeout":3000,"max_download_mb":20,"max_download_secs":8}"#,
+        )
+        .unwrap();
+        let budget = config.budget();
+        assert_eq!(budget.max_duration, Some(Duration::from_secs(8)));
+        assert!(!budget.bytes_reached(20 * 1024 * 1024 - 1));
+        assert!(budget.bytes_reached(20 * 1024 * 1024));
+        assert!(!DownloadBudget::default().bytes_reached(u64::MAX));
+    }
+
     #[tokio::test]
     async fn test_download() {
         let url = "https://speed.cloudflare.com/__down?bytes=1024"; // 100MB download
@@ -60,6 +549,7 @@ mod test {
             url,
             Duration::from_secs(10),
             Some("http://127.0.0.1:7890"),
+            DownloadBudget::default(),
         )
         .await
         {
@@ -0,0 +1,365 @@
+use std::fmt;
+use std::io;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
+use std::net::SocketAddr;
+use std::time::Duration;
+use std::time::Instant;
+
+use reqwest::Url;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use tokio::net::UdpSocket;
+use tokio::time::timeout;
+
+/// 每个目标测量的次数，取成功结果的平均值
+const SAMPLES: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    Tcp,
+    Udp,
+}
+
+/// websites 中以 tcp:// 或 udp:// 开头的目标，如游戏服务器、区域接入点
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Target {
+    pub protocol: Protocol,
+    pub host: String,
+    pub port: u16,
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let scheme = match self.protocol {
+            Protocol::Tcp => "tcp",
+            Protocol::Udp => "udp",
+        };
+        write!(f, "{}://{}:{}", scheme, self.host, self.port)
+    }
+}
+
+impl Target {
+    /// 是否为 tcp://、udp:// 目标，不是时按网站检测处理
+    pub fn is_target_url(url: &str) -> bool {
+        url.starts_with("tcp://") || url.starts_with("udp://")
+    }
+
+    pub fn parse(url: &str) -> Result<Target, String> {
+        let parsed = Url::parse(url).map_err(|e| format!("invalid target {}: {}", url, e))?;
+        let protocol = match parsed.scheme() {
+            "tcp" => Protocol::Tcp,
+            "udp" => Protocol::Udp,
+            scheme => return Err(format!("unsupported target scheme {}", scheme)),
+        };
+        let host = parsed
+            .host_str()
+            .filter(|host| !host.is_empty())
+            .ok_or_else(|| format!("target {} has no host", url))?;
+        let port = parsed
+            .port()
+            .ok_or_else(|| format!("target {} has no port", url))?;
+        Ok(Target {
+            protocol,
+            host: host.trim_matches(['[', ']']).to_string(),
+            port,
+        })
+    }
+}
+
+/// 通过代理测量到目标的往返延迟，单位 ms
+///
+/// 内核在建立隧道前就会回复 SOCKS5 握手，因此需要目标返回数据才能得到真实的延迟：
+/// TCP 发送 payload（可为空）后等待目标的第一个字节，适用于会主动发送 banner 或响应 payload 的服务；
+/// UDP 必须配置目标会响应的 payload，如 Source 引擎的 A2S_INFO 查询，第一个包用于建立连接不计入结果
+pub async fn measure(
+    proxy_url: &str,
+    target: &Target,
+    payload: &[u8],
+    timeout: Duration,
+) -> io::Result<i64> {
+    let proxy = proxy_addr(proxy_url)?;
+    let mut samples = vec![];
+    let mut last_error = None;
+    match target.protocol {
+        Protocol::Tcp => {
+            for _ in 0..SAMPLES {
+                match tcp_rtt(proxy, target, payload, timeout).await {
+                    Ok(rtt) => samples.push(rtt),
+                    Err(e) => last_error = Some(e),
+                }
+            }
+        }
+        Protocol::Udp => match udp_rtt(proxy, target, payload, timeout).await {
+            Ok(rtt) => samples = rtt,
+            Err(e) => last_error = Some(e),
+        },
+    }
+    if samples.is_empty() {
+        return Err(last_error.unwrap_or_else(|| io::Error::other("no response from target")));
+    }
+    let total = samples.iter().map(Duration::as_millis).sum::<u128>();
+    Ok((total / samples.len() as u128) as i64)
+}
+
+fn proxy_addr(proxy_url: &str) -> io::Result<SocketAddr> {
+    let url = Url::parse(proxy_url).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    url.socket_addrs(|| Some(7890))?
+        .into_iter()
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid proxy url"))
+}
+
+async fn tcp_rtt(
+    proxy: SocketAddr,
+    target: &Target,
+    payload: &[u8],
+    limit: Duration,
+) -> io::Result<Duration> {
+    let mut stream = timeout(limit, TcpStream::connect(proxy)).await??;
+    timeout(limit, socks5_request(&mut stream, CMD_CONNECT, target)).await??;
+
+    let start = Instant::now();
+    if !payload.is_empty() {
+        stream.write_all(payload).await?;
+    }
+    let mut buf = [0u8; 1];
+    match timeout(limit, stream.read(&mut buf)).await?? {
+        0 => Err(io::Error::new(
+            io::ErrorKind::ConnectionAborted,
+            "connection closed by target",
+        )),
+        _ => Ok(start.elapsed()),
+    }
+}
+
+async fn udp_rtt(
+    proxy: SocketAddr,
+    target: &Target,
+    payload: &[u8],
+    limit: Duration,
+) -> io::Result<Vec<Duration>> {
+    let mut control = timeout(limit, TcpStream::connect(proxy)).await??;
+    let unspecified = Target {
+        protocol: Protocol::Udp,
+        host: Ipv4Addr::UNSPECIFIED.to_string(),
+        port: 0,
+    };
+    let mut relay = timeout(
+        limit,
+        socks5_request(&mut control, CMD_UDP_ASSOCIATE, &unspecified),
+    )
+    .await??;
+    // 内核返回 0.0.0.0 时使用代理本身的地址
+    if relay.ip().is_unspecified() {
+        relay.set_ip(proxy.ip());
+    }
+
+    let socket = UdpSocket::bind(SocketAddr::new(
+        match proxy {
+            SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        },
+        0,
+    ))
+    .await?;
+    socket.connect(relay).await?;
+
+    let mut packet = vec![0, 0, 0];
+    packet.extend(encode_addr(target)?);
+    packet.extend_from_slice(payload);
+
+    let mut samples = vec![];
+    let mut last_error = None;
+    let mut buf = vec![0u8; 65535];
+    for i in 0..=SAMPLES {
+        let start = Instant::now();
+        socket.send(&packet).await?;
+        match timeout(limit, socket.recv(&mut buf)).await {
+            Ok(Ok(_)) if i > 0 => samples.push(start.elapsed()),
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => last_error = Some(e),
+            Err(e) => last_error = Some(e.into()),
+        }
+    }
+    if samples.is_empty() {
+        return Err(last_error.unwrap_or_else(|| io::Error::other("no response from target")));
+    }
+    Ok(samples)
+}
+
+/// 探测 QUIC 的 UDP 包：长包头，版本号为 RFC 9000 保留用于触发版本协商的 0x?a?a?a?a，
+/// 按 QUIC 对初始包的要求填充到 1200 字节，支持 QUIC 的服务器会回复版本协商包
+pub fn quic_probe() -> Vec<u8> {
+    let mut packet = vec![0xc0, 0x1a, 0x2a, 0x3a, 0x4a];
+    // 目标连接 ID 及源连接 ID，各 8 字节
+    packet.push(8);
+    packet.extend_from_slice(&[0x5a; 8]);
+    packet.push(8);
+    packet.extend_from_slice(&[0xa5; 8]);
+    packet.resize(1200, 0);
+    packet
+}
+
+const CMD_CONNECT: u8 = 0x01;
+const CMD_UDP_ASSOCIATE: u8 = 0x03;
+
+/// 完成 SOCKS5 握手并发送请求，返回代理回复的绑定地址
+async fn socks5_request(
+    stream: &mut TcpStream,
+    command: u8,
+    target: &Target,
+) -> io::Result<SocketAddr> {
+    // 不需要认证
+    stream.write_all(&[0x05, 0x01, 0x00]).await?;
+    let mut reply = [0u8; 2];
+    stream.read_exact(&mut reply).await?;
+    if reply != [0x05, 0x00] {
+        return Err(io::Error::other(format!(
+            "socks5 authentication rejected: {:?}",
+            reply
+        )));
+    }
+
+    let mut request = vec![0x05, command, 0x00];
+    request.extend(encode_addr(target)?);
+    stream.write_all(&request).await?;
+
+    let mut head = [0u8; 4];
+    stream.read_exact(&mut head).await?;
+    if head[1] != 0x00 {
+        return Err(io::Error::other(format!(
+            "socks5 request failed with code {}",
+            head[1]
+        )));
+    }
+    let ip = match head[3] {
+        0x01 => {
+            let mut addr = [0u8; 4];
+            stream.read_exact(&mut addr).await?;
+            IpAddr::from(addr)
+        }
+        0x04 => {
+            let mut addr = [0u8; 16];
+            stream.read_exact(&mut addr).await?;
+            IpAddr::from(addr)
+        }
+        0x03 => {
+            let len = stream.read_u8().await?;
+            let mut domain = vec![0u8; len as usize];
+            stream.read_exact(&mut domain).await?;
+            IpAddr::V4(Ipv4Addr::UNSPECIFIED)
+        }
+        atyp => {
+            return Err(io::Error::other(format!(
+                "unsupported socks5 address type {}",
+                atyp
+            )))
+        }
+    };
+    let port = stream.read_u16().await?;
+    Ok(SocketAddr::new(ip, port))
+}
+
+/// SOCKS5 地址格式：ATYP + 地址 + 端口
+fn encode_addr(target: &Target) -> io::Result<Vec<u8>> {
+    let mut buf = vec![];
+    match target.host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => {
+            buf.push(0x01);
+            buf.extend_from_slice(&ip.octets());
+        }
+        Ok(IpAddr::V6(ip)) => {
+            buf.push(0x04);
+            buf.extend_from_slice(&ip.octets());
+        }
+        Err(_) => {
+            let len = u8::try_from(target.host.len()).map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidInput, "target host is too long")
+            })?;
+            buf.push(0x03);
+            buf.push(len);
+            buf.extend_from_slice(target.host.as_bytes());
+        }
+    }
+    buf.extend_from_slice(&target.port.to_be_bytes());
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_target() {
+        assert_eq!(
+            Target::parse("udp://203.0.113.10:27015").unwrap(),
+            Target {
+                protocol: Protocol::Udp,
+                host: "203.0.113.10".to_string(),
+                port: 27015,
+            }
+        );
+        let target = Target::parse("tcp://[2001:db8::1]:443").unwrap();
+        assert_eq!(target.host, "2001:db8::1");
+        assert_eq!(encode_addr(&target).unwrap()[0], 0x04);
+        assert!(Target::parse("tcp://example.com").is_err());
+        assert!(Target::parse("https://example.com:443").is_err());
+        assert!(Target::is_target_url("tcp://example.com:22"));
+        assert!(!Target::is_target_url("https://example.com"));
+    }
+
+    #[test]
+    fn test_quic_probe() {
+        let packet = quic_probe();
+        assert_eq!(packet.len(), 1200);
+        // 长包头，版本号的每个字节低 4 位均为 0xa
+        assert_eq!(packet[0] & 0x80, 0x80);
+        assert!(packet[1..5].iter().all(|b| b & 0x0f == 0x0a));
+        assert_eq!(packet[5], 8);
+        assert_eq!(packet[14], 8);
+    }
+
+    /// 模拟只支持 CONNECT 的 SOCKS5 代理，隧道建立后直接回复一个字节
+    async fn fake_proxy() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut greeting = [0u8; 3];
+                    stream.read_exact(&mut greeting).await.unwrap();
+                    stream.write_all(&[0x05, 0x00]).await.unwrap();
+                    // 127.0.0.1:22
+                    let mut request = [0u8; 10];
+                    stream.read_exact(&mut request).await.unwrap();
+                    stream
+                        .write_all(&[0x05, 0x00, 0x00, 0x01, 0, 0, 0, 0, 0, 0])
+                        .await
+                        .unwrap();
+                    stream.write_all(b"S").await.unwrap();
+                });
+            }
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_measure_tcp() {
+        let proxy = fake_proxy().await;
+        let target = Target::parse("tcp://127.0.0.1:22").unwrap();
+        let rtt = measure(
+            &format!("http://{}", proxy),
+            &target,
+            &[],
+            Duration::from_secs(1),
+        )
+        .await
+        .unwrap();
+        assert!(rtt >= 0);
+    }
+}
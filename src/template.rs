@@ -0,0 +1,112 @@
+use std::path::Path;
+
+use proxrs::config::ClashConfig;
+use proxrs::config::ProxyGroupType;
+use serde_yaml::Value;
+
+use crate::pipeline::TEST_PROXY_GROUP_NAME;
+
+/// 模板的用途，测试模板还需要满足内核测试的要求
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TemplateKind {
+    Test,
+    Release,
+}
+
+/// 检查内核配置模板的结构，返回发现的问题，模板无法读取或解析时只返回该错误
+pub fn lint(path: &Path, kind: TemplateKind) -> Vec<String> {
+    match ClashConfig::from_path(path) {
+        Ok(config) => lint_config(&config, kind),
+        Err(e) => vec![e.to_string()],
+    }
+}
+
+fn lint_config(config: &ClashConfig, kind: TemplateKind) -> Vec<String> {
+    let mut issues = config.lint();
+    if kind == TemplateKind::Release {
+        return issues;
+    }
+
+    // 测试时通过 PROXY 策略组逐个切换节点
+    match config.group(TEST_PROXY_GROUP_NAME) {
+        None => issues.push(format!("missing the {} proxy group", TEST_PROXY_GROUP_NAME)),
+        Some(group) => {
+            if group.group_type != ProxyGroupType::Select {
+                issues.push(format!(
+                    "proxy group {} must be a select group",
+                    TEST_PROXY_GROUP_NAME
+                ));
+            }
+            if group.filter.is_none() && !group.has_implicit_proxies() {
+                issues.push(format!(
+                    "proxy group {} needs a filter such as \".*\" to include the nodes under test",
+                    TEST_PROXY_GROUP_NAME
+                ));
+            }
+        }
+    }
+    if config.extra["tun"]["enable"].as_bool() == Some(true) {
+        issues.push(
+            "tun must be disabled, it would take over the routing of this machine".to_string(),
+        );
+    }
+    // 各内核实例的 DNS 端口替换 listen 中的端口
+    match &config.extra["dns"]["listen"] {
+        Value::Null => {}
+        Value::String(listen) if has_port(listen) => {}
+        _ => issues.push("dns.listen must be in the form of host:port".to_string()),
+    }
+    issues
+}
+
+fn has_port(listen: &str) -> bool {
+    listen
+        .rsplit_once(':')
+        .is_some_and(|(_, port)| port.parse::<u16>().is_ok())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_lint_templates() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        assert!(lint(&root.join("conf/clash_test.yaml"), TemplateKind::Test).is_empty());
+        assert!(lint(&root.join("conf/clash_release.yaml"), TemplateKind::Release).is_empty());
+        assert_eq!(
+            lint(&root.join("conf/missing.yaml"), TemplateKind::Test).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_lint_test_template() {
+        let config = r#"
+tun: {enable: true}
+dns: {listen: "0.0.0.0"}
+proxy-groups:
+  - {name: PROXY, type: url-test, proxies: [DIRECT]}
+rules:
+  - MATCH,PROXY
+"#
+        .parse::<ClashConfig>()
+        .unwrap();
+        assert_eq!(
+            lint_config(&config, TemplateKind::Test),
+            vec![
+                "proxy group PROXY must be a select group",
+                "proxy group PROXY needs a filter such as \".*\" to include the nodes under test",
+                "tun must be disabled, it would take over the routing of this machine",
+                "dns.listen must be in the form of host:port",
+            ]
+        );
+        assert!(lint_config(&config, TemplateKind::Release).is_empty());
+
+        let config = "rules:\n  - MATCH,DIRECT\n".parse::<ClashConfig>().unwrap();
+        assert_eq!(
+            lint_config(&config, TemplateKind::Test),
+            vec!["missing the PROXY proxy group"]
+        );
+    }
+}
@@ -0,0 +1,336 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use ratatui::crossterm::event;
+use ratatui::crossterm::event::Event;
+use ratatui::crossterm::event::KeyCode;
+use ratatui::crossterm::event::KeyEventKind;
+use ratatui::layout::Constraint;
+use ratatui::layout::Layout;
+use ratatui::style::Color;
+use ratatui::style::Style;
+use ratatui::style::Stylize;
+use ratatui::widgets::Block;
+use ratatui::widgets::Paragraph;
+use ratatui::widgets::Row;
+use ratatui::widgets::Table;
+use ratatui::widgets::TableState;
+use ratatui::DefaultTerminal;
+use ratatui::Frame;
+use tokio::sync::mpsc::UnboundedReceiver;
+
+use crate::pipeline::ExportResult;
+use crate::pipeline::PipelineError;
+use crate::pipeline::PipelineEvent;
+use crate::pipeline::RenameResult;
+use crate::report::NodeReport;
+use crate::Pipeline;
+
+const TICK: Duration = Duration::from_millis(200);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Status {
+    Pending,
+    Alive,
+    Dead,
+    Removed,
+    Checked,
+}
+
+impl Status {
+    fn label(&self) -> (&'static str, Color) {
+        match self {
+            Status::Pending => ("等待", Color::DarkGray),
+            Status::Alive => ("可用", Color::Green),
+            Status::Dead => ("超时", Color::Red),
+            Status::Removed => ("剔除", Color::Red),
+            Status::Checked => ("完成", Color::Cyan),
+        }
+    }
+}
+
+struct NodeRow {
+    name: String,
+    status: Status,
+    latency: Option<i64>,
+    report: Option<NodeReport>,
+    included: bool,
+}
+
+impl NodeRow {
+    fn new(name: &str) -> Self {
+        NodeRow {
+            name: name.to_string(),
+            status: Status::Pending,
+            latency: None,
+            report: None,
+            included: true,
+        }
+    }
+}
+
+struct App {
+    stage: String,
+    rows: Vec<NodeRow>,
+    index: HashMap<String, usize>,
+    table: TableState,
+    /// 测试完成，进入人工筛选
+    reviewing: bool,
+}
+
+impl App {
+    fn new() -> Self {
+        App {
+            stage: "准备中".to_string(),
+            rows: vec![],
+            index: HashMap::new(),
+            table: TableState::default(),
+            reviewing: false,
+        }
+    }
+
+    fn handle_event(&mut self, event: PipelineEvent) {
+        match event {
+            PipelineEvent::Stage(stage) => self.stage = stage,
+            PipelineEvent::Fetched(names) => {
+                self.rows = names.iter().map(|n| NodeRow::new(n)).collect();
+                self.index = names.into_iter().enumerate().map(|(i, n)| (n, i)).collect();
+            }
+            PipelineEvent::Tested { name, latency } => {
+                if let Some(row) = self.row_mut(&name) {
+                    row.latency = latency;
+                    row.status = if latency.is_some() {
+                        Status::Alive
+                    } else {
+                        Status::Dead
+                    };
+                }
+            }
+            PipelineEvent::Inspected {
+                name,
+                new_name,
+                report,
+            } => {
+                if let Some(row) = self.row_mut(&name) {
+                    row.status = if report.is_some() || new_name.is_some() {
+                        Status::Checked
+                    } else {
+                        Status::Removed
+                    };
+                    if let Some(new_name) = new_name {
+                        row.name = new_name;
+                    }
+                    row.report = report;
+                }
+            }
+        }
+    }
+
+    fn row_mut(&mut self, name: &str) -> Option<&mut NodeRow> {
+        let index = *self.index.get(name)?;
+        self.rows.get_mut(index)
+    }
+
+    /// 测试结束后以最终结果重建列表，供人工筛选
+    fn review(&mut self, renamed: &RenameResult) {
+        self.stage = "人工筛选".to_string();
+        self.reviewing = true;
+        self.rows = renamed
+            .reports
+            .iter()
+            .map(|report| NodeRow {
+                name: report.name.clone(),
+                status: Status::Checked,
+                latency: report.latency,
+                report: Some(report.clone()),
+                included: true,
+            })
+            .collect();
+        self.table
+            .select(if self.rows.is_empty() { None } else { Some(0) });
+    }
+
+    fn move_cursor(&mut self, offset: isize) {
+        if self.rows.is_empty() {
+            return;
+        }
+        let current = self.table.selected().unwrap_or(0) as isize;
+        let next = (current + offset).clamp(0, self.rows.len() as isize - 1);
+        self.table.select(Some(next as usize));
+    }
+
+    fn toggle(&mut self) {
+        if let Some(row) = self.table.selected().and_then(|i| self.rows.get_mut(i)) {
+            row.included = !row.included;
+        }
+    }
+
+    fn toggle_all(&mut self) {
+        let included = !self.rows.iter().all(|r| r.included);
+        self.rows.iter_mut().for_each(|r| r.included = included);
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [header, body, footer] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Fill(1),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let alive = self
+            .rows
+            .iter()
+            .filter(|r| matches!(r.status, Status::Alive | Status::Checked))
+            .count();
+        let included = self.rows.iter().filter(|r| r.included).count();
+        let summary = if self.reviewing {
+            format!(
+                " Clash-Butler | {} | 节点 {} 个，已选择 {} 个",
+                self.stage,
+                self.rows.len(),
+                included
+            )
+        } else {
+            format!(
+                " Clash-Butler | {} | 节点 {} 个，可用 {} 个",
+                self.stage,
+                self.rows.len(),
+                alive
+            )
+        };
+        frame.render_widget(Paragraph::new(summary).bold(), header);
+
+        let reviewing = self.reviewing;
+        let rows = self.rows.iter().map(|row| {
+            let (status, color) = row.status.label();
+            let report = row.report.as_ref();
+            let selected = if !reviewing {
+                ""
+            } else if row.included {
+                "[x]"
+            } else {
+                "[ ]"
+            };
+            Row::new(vec![
+                selected.to_string(),
+                row.name.clone(),
+                status.to_string(),
+                row.latency.map_or("-".to_string(), |l| format!("{} ms", l)),
+                report
+                    .and_then(|r| r.speed)
+                    .map_or("-".to_string(), |s| format!("{:.2} KB/s", s)),
+                report
+                    .and_then(|r| r.country.clone())
+                    .unwrap_or("-".to_string()),
+                report
+                    .and_then(|r| r.isp.clone())
+                    .unwrap_or("-".to_string()),
+            ])
+            .style(Style::new().fg(color))
+        });
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(3),
+                Constraint::Fill(2),
+                Constraint::Length(4),
+                Constraint::Length(8),
+                Constraint::Length(14),
+                Constraint::Length(4),
+                Constraint::Fill(1),
+            ],
+        )
+        .header(Row::new(vec!["", "节点", "状态", "延迟", "速度", "国家", "ISP"]).bold())
+        .block(Block::bordered())
+        .highlight_style(Style::new().reversed());
+        frame.render_stateful_widget(table, body, &mut self.table);
+
+        let help = if self.reviewing {
+            " ↑/↓ 移动  空格 选择/取消  a 全选/全不选  Enter 导出  q 放弃导出"
+        } else {
+            " 测试中，详细日志见日志文件"
+        };
+        frame.render_widget(Paragraph::new(help).dark_gray(), footer);
+    }
+}
+
+/// 以终端界面运行筛选流程，测试完成后可人工选择导出的节点
+///
+/// pipeline 需通过 with_events 关联 events 的发送端，放弃导出时返回 Ok(None)
+pub async fn run(
+    pipeline: &Pipeline,
+    events: UnboundedReceiver<PipelineEvent>,
+) -> Result<Option<ExportResult>, PipelineError> {
+    let mut terminal = ratatui::init();
+    let result = run_app(&mut terminal, pipeline, events).await;
+    ratatui::restore();
+
+    match result? {
+        Some(selected) => pipeline.export(&selected).await.map(Some),
+        None => Ok(None),
+    }
+}
+
+async fn run_app(
+    terminal: &mut DefaultTerminal,
+    pipeline: &Pipeline,
+    mut rx: UnboundedReceiver<PipelineEvent>,
+) -> Result<Option<RenameResult>, PipelineError> {
+    let mut app = App::new();
+    let mut interval = tokio::time::interval(TICK);
+
+    let prepare = pipeline.prepare();
+    tokio::pin!(prepare);
+    let renamed = loop {
+        tokio::select! {
+            result = &mut prepare => break result?,
+            _ = interval.tick() => {
+                while let Ok(event) = rx.try_recv() {
+                    app.handle_event(event);
+                }
+                terminal.draw(|frame| app.draw(frame))?;
+                // 测试过程中不响应按键，丢弃输入避免堆积
+                while event::poll(Duration::ZERO)? {
+                    let _ = event::read()?;
+                }
+            }
+        }
+    };
+
+    app.review(&renamed);
+    loop {
+        terminal.draw(|frame| app.draw(frame))?;
+        if !event::poll(TICK)? {
+            continue;
+        }
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => app.move_cursor(-1),
+            KeyCode::Down | KeyCode::Char('j') => app.move_cursor(1),
+            KeyCode::PageUp => app.move_cursor(-10),
+            KeyCode::PageDown => app.move_cursor(10),
+            KeyCode::Char(' ') => app.toggle(),
+            KeyCode::Char('a') => app.toggle_all(),
+            KeyCode::Enter => break,
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(None),
+            _ => {}
+        }
+    }
+
+    let included = app.rows.iter().map(|r| r.included).collect::<Vec<_>>();
+    let (proxies, reports) = renamed
+        .proxies
+        .into_iter()
+        .zip(renamed.reports)
+        .zip(included)
+        .filter(|(_, included)| *included)
+        .map(|(pair, _)| pair)
+        .unzip();
+    Ok(Some(RenameResult { proxies, reports }))
+}
@@ -0,0 +1,175 @@
+use std::path::Path;
+use std::path::PathBuf;
+
+use serde::Deserialize;
+use tracing::error;
+use tracing::info;
+
+use crate::tr;
+use crate::upload::s3::S3Config;
+use crate::upload::webdav::WebdavConfig;
+
+mod s3;
+mod webdav;
+
+/// 上传目标，在 config.toml 中以 `[[uploads]]` 配置，通过 type 区分后端
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum UploadTarget {
+    /// S3 兼容的对象存储，AWS S3、Cloudflare R2、MinIO 等
+    S3(S3Config),
+    /// WebDAV，一般为 NAS 或网盘
+    Webdav(WebdavConfig),
+}
+
+impl UploadTarget {
+    fn kind(&self) -> &'static str {
+        match self {
+            UploadTarget::S3(_) => "s3",
+            UploadTarget::Webdav(_) => "webdav",
+        }
+    }
+
+    /// 缺少的凭据，返回字段名及 secrets 中对应的字段名
+    pub(crate) fn missing_secrets(&self) -> Vec<(&'static str, &'static str)> {
+        let mut missing = vec![];
+        match self {
+            UploadTarget::S3(config) => {
+                if config.access_key.is_empty() {
+                    missing.push(("access_key", "s3_access_key"));
+                }
+                if config.secret_key.is_empty() {
+                    missing.push(("secret_key", "s3_secret_key"));
+                }
+            }
+            UploadTarget::Webdav(config) => {
+                if config.username.is_some() && config.password.is_none() {
+                    missing.push(("password", "webdav_password"));
+                }
+            }
+        }
+        missing
+    }
+
+    async fn upload(&self, file: &Path) -> anyhow::Result<String> {
+        match self {
+            UploadTarget::S3(config) => s3::upload(config, file).await,
+            UploadTarget::Webdav(config) => webdav::upload(config, file).await,
+        }
+    }
+}
+
+/// 将文件依次上传至所有目标，单个目标失败不影响其他目标
+pub async fn upload_files(targets: &[UploadTarget], files: &[PathBuf]) {
+    for target in targets {
+        for file in files {
+            match target.upload(file).await {
+                Ok(location) => {
+                    info!(
+                        "{}",
+                        tr!(
+                            "[{}] 上传 {} 成功：{}",
+                            "[{}] uploaded {}: {}",
+                            target.kind(),
+                            file.display(),
+                            location
+                        )
+                    )
+                }
+                Err(e) => {
+                    error!(
+                        "{}",
+                        tr!(
+                            "[{}] 上传 {} 失败, {:#}",
+                            "[{}] failed to upload {}, {:#}",
+                            target.kind(),
+                            file.display(),
+                            e
+                        )
+                    )
+                }
+            }
+        }
+    }
+}
+
+/// 从本地路径中获取上传时使用的文件名
+fn file_name(file: &Path) -> anyhow::Result<String> {
+    file.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .ok_or_else(|| {
+            anyhow::anyhow!(tr!(
+                "无效的文件路径：{}",
+                "Invalid file path: {}",
+                file.display()
+            ))
+        })
+}
+
+/// 拼接远程目录和文件名，去除多余的斜杠
+fn join_remote_path(prefix: Option<&str>, name: &str) -> String {
+    match prefix
+        .map(|p| p.trim_matches('/'))
+        .filter(|p| !p.is_empty())
+    {
+        Some(prefix) => format!("{}/{}", prefix, name),
+        None => name.to_string(),
+    }
+}
+
+/// 编码路径中的单个片段，仅保留 A-Za-z0-9-._~，与 SigV4 规范一致
+fn uri_encode(segment: &str) -> String {
+    urlencoding::encode(segment).into_owned()
+}
+
+/// 逐段编码远程路径，保留分隔的斜杠
+fn encode_remote_path(path: &str) -> String {
+    path.split('/')
+        .map(uri_encode)
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_join_remote_path() {
+        assert_eq!(join_remote_path(None, "clash.yaml"), "clash.yaml");
+        assert_eq!(join_remote_path(Some(""), "clash.yaml"), "clash.yaml");
+        assert_eq!(
+            join_remote_path(Some("/subs/release/"), "clash.yaml"),
+            "subs/release/clash.yaml"
+        );
+    }
+
+    #[test]
+    fn test_uri_encode() {
+        assert_eq!(uri_encode("clash.yaml"), "clash.yaml");
+        assert_eq!(uri_encode("节点 1.yaml"), "%E8%8A%82%E7%82%B9%201.yaml");
+        assert_eq!(
+            encode_remote_path("subs/节点 1.yaml"),
+            "subs/%E8%8A%82%E7%82%B9%201.yaml"
+        );
+    }
+
+    #[test]
+    fn test_parse_targets() {
+        #[derive(Deserialize)]
+        struct Conf {
+            uploads: Vec<UploadTarget>,
+        }
+        let conf: Conf = serde_json::from_str(
+            r#"{"uploads": [
+                {"type": "s3", "endpoint": "https://xxx.r2.cloudflarestorage.com", "bucket": "subs",
+                 "access_key": "ak", "secret_key": "sk"},
+                {"type": "webdav", "url": "https://nas.local/dav", "username": "u", "password": "p"}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(conf.uploads.len(), 2);
+        assert_eq!(conf.uploads[0].kind(), "s3");
+        assert_eq!(conf.uploads[1].kind(), "webdav");
+    }
+}
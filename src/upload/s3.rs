@@ -0,0 +1,171 @@
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use chrono::Utc;
+use hmac::Hmac;
+use hmac::Mac;
+use reqwest::Client;
+use reqwest::Url;
+use serde::Deserialize;
+use sha2::Digest;
+use sha2::Sha256;
+
+use crate::settings::Secret;
+use crate::tr;
+use crate::upload::encode_remote_path;
+use crate::upload::file_name;
+use crate::upload::join_remote_path;
+use crate::upload::uri_encode;
+
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// S3 兼容存储配置，统一使用 path-style 地址：{endpoint}/{bucket}/{key}
+#[derive(Deserialize, Debug, Clone)]
+pub struct S3Config {
+    /// 服务地址，如 https://<account>.r2.cloudflarestorage.com、http://127.0.0.1:9000
+    pub endpoint: String,
+    /// 区域，R2 使用 auto
+    #[serde(default = "default_region")]
+    pub region: String,
+    pub bucket: String,
+    /// 未填写时使用 secrets.s3_access_key
+    #[serde(default)]
+    pub access_key: Secret,
+    /// 未填写时使用 secrets.s3_secret_key
+    #[serde(default)]
+    pub secret_key: Secret,
+    /// 对象前缀，相当于远程目录
+    pub prefix: Option<String>,
+}
+
+fn default_region() -> String {
+    "us-east-1".to_string()
+}
+
+pub async fn upload(config: &S3Config, file: &Path) -> Result<String> {
+    let body = tokio::fs::read(file)
+        .await
+        .with_context(|| tr!("读取文件 {} 失败", "Failed to read file {}", file.display()))?;
+    let key = join_remote_path(config.prefix.as_deref(), &file_name(file)?);
+
+    let endpoint = Url::parse(&config.endpoint)
+        .with_context(|| tr!("无效的 S3 endpoint", "Invalid S3 endpoint"))?;
+    let host = match (endpoint.host_str(), endpoint.port()) {
+        (Some(host), Some(port)) => format!("{}:{}", host, port),
+        (Some(host), None) => host.to_string(),
+        _ => {
+            return Err(anyhow!(tr!(
+                "S3 endpoint 缺少 host",
+                "S3 endpoint is missing the host"
+            )))
+        }
+    };
+    let canonical_uri = canonical_uri(&endpoint, &config.bucket, &key);
+
+    let now = Utc::now();
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let date = now.format("%Y%m%d").to_string();
+    let payload_hash = hex::encode(Sha256::digest(&body));
+
+    let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+    let canonical_request = format!(
+        "PUT\n{}\n\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+        canonical_uri, host, payload_hash, amz_date, signed_headers, payload_hash
+    );
+    let scope = format!("{}/{}/s3/aws4_request", date, config.region);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        hex::encode(Sha256::digest(canonical_request.as_bytes()))
+    );
+    let key_bytes = signing_key(config.secret_key.expose(), &date, &config.region, "s3");
+    let signature = hex::encode(hmac_sha256(&key_bytes, string_to_sign.as_bytes()));
+    let authorization = format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        config.access_key.expose(),
+        scope,
+        signed_headers,
+        signature
+    );
+
+    let url = format!("{}://{}{}", endpoint.scheme(), host, canonical_uri);
+    let client = Client::builder().timeout(TIMEOUT).build()?;
+    let response = client
+        .put(&url)
+        .header("x-amz-content-sha256", &payload_hash)
+        .header("x-amz-date", &amz_date)
+        .header("Authorization", authorization)
+        .body(body)
+        .send()
+        .await?;
+    let status = response.status();
+    if !status.is_success() {
+        let text = response.text().await.unwrap_or_default();
+        return Err(anyhow!("http status code: {}, {}", status, text));
+    }
+    Ok(url)
+}
+
+/// 请求的完整路径，endpoint 带有路径前缀（如 https://host/s3）时签名同样需要包含
+fn canonical_uri(endpoint: &Url, bucket: &str, key: &str) -> String {
+    format!(
+        "{}/{}/{}",
+        endpoint.path().trim_end_matches('/'),
+        uri_encode(bucket),
+        encode_remote_path(key)
+    )
+}
+
+/// SigV4 签名密钥：kSecret -> kDate -> kRegion -> kService -> kSigning
+fn signing_key(secret_key: &str, date: &str, region: &str, service: &str) -> Vec<u8> {
+    let k_date = hmac_sha256(format!("AWS4{}", secret_key).as_bytes(), date.as_bytes());
+    let k_region = hmac_sha256(&k_date, region.as_bytes());
+    let k_service = hmac_sha256(&k_region, service.as_bytes());
+    hmac_sha256(&k_service, b"aws4_request")
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC can take key of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signing_key() {
+        // https://docs.aws.amazon.com/general/latest/gr/signature-v4-examples.html
+        let key = signing_key(
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            "20120215",
+            "us-east-1",
+            "iam",
+        );
+        assert_eq!(
+            hex::encode(key),
+            "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d"
+        );
+    }
+
+    #[test]
+    fn test_canonical_uri() {
+        let endpoint = Url::parse("http://127.0.0.1:9000").unwrap();
+        assert_eq!(
+            canonical_uri(&endpoint, "subs", "release/clash.yaml"),
+            "/subs/release/clash.yaml"
+        );
+        let endpoint = Url::parse("https://host/s3/").unwrap();
+        assert_eq!(
+            canonical_uri(&endpoint, "subs", "节点 1.yaml"),
+            "/s3/subs/%E8%8A%82%E7%82%B9%201.yaml"
+        );
+    }
+}
@@ -0,0 +1,78 @@
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use reqwest::Client;
+use reqwest::Method;
+use reqwest::RequestBuilder;
+use serde::Deserialize;
+
+use crate::settings::Secret;
+use crate::tr;
+use crate::upload::encode_remote_path;
+use crate::upload::file_name;
+use crate::upload::join_remote_path;
+
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// WebDAV 配置
+#[derive(Deserialize, Debug, Clone)]
+pub struct WebdavConfig {
+    /// WebDAV 根地址，如 https://nas.local:5006/dav
+    pub url: String,
+    pub username: Option<String>,
+    /// 未填写时使用 secrets.webdav_password
+    pub password: Option<Secret>,
+    /// 远程目录，不存在时会尝试创建
+    pub dir: Option<String>,
+}
+
+impl WebdavConfig {
+    fn with_auth(&self, builder: RequestBuilder) -> RequestBuilder {
+        match &self.username {
+            Some(username) => {
+                builder.basic_auth(username, self.password.as_ref().map(Secret::expose))
+            }
+            None => builder,
+        }
+    }
+}
+
+pub async fn upload(config: &WebdavConfig, file: &Path) -> Result<String> {
+    let body = tokio::fs::read(file)
+        .await
+        .with_context(|| tr!("读取文件 {} 失败", "Failed to read file {}", file.display()))?;
+    let client = Client::builder().timeout(TIMEOUT).build()?;
+    let base_url = config.url.trim_end_matches('/');
+
+    if let Some(dir) = config.dir.as_deref().map(|d| d.trim_matches('/')) {
+        // 逐级创建目录，已存在时服务端返回 405，忽略即可
+        let mut current = String::new();
+        for part in dir.split('/').filter(|p| !p.is_empty()) {
+            current = join_remote_path(Some(&current), part);
+            let mkcol = Method::from_bytes(b"MKCOL")?;
+            let _ = config
+                .with_auth(client.request(
+                    mkcol,
+                    format!("{}/{}/", base_url, encode_remote_path(&current)),
+                ))
+                .send()
+                .await;
+        }
+    }
+
+    // 文件名及目录中的中文、空格等需要编码
+    let url = format!(
+        "{}/{}",
+        base_url,
+        encode_remote_path(&join_remote_path(config.dir.as_deref(), &file_name(file)?))
+    );
+    let response = config.with_auth(client.put(&url)).body(body).send().await?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(anyhow!("http status code: {}", status));
+    }
+    Ok(url)
+}
@@ -1,75 +1,238 @@
+use std::net::IpAddr;
 use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
 
 use anyhow::anyhow;
 use anyhow::Context;
 use anyhow::Result;
 use reqwest::Client;
+use reqwest::Method;
 use reqwest::StatusCode;
+use reqwest::Version;
+use serde::Deserialize;
+use sha2::Digest;
+use sha2::Sha256;
+
+use crate::clash::DelayTestConfig;
+use crate::target;
+use crate::target::Protocol;
+use crate::target::Target;
 
 const USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/102.0.5005.63 Safari/537.36";
 const TIMEOUT: Duration = Duration::from_secs(5);
+/// HTTP/2、HTTP/3 探测使用的参考地址，同时支持 h2 与 QUIC
+const PROTOCOL_PROBE_HOST: &str = "www.cloudflare.com";
+
+/// 节点检测时访问的网站，检测通过时节点名称会加上 _{name} 后缀
+#[derive(Deserialize, Debug, Clone)]
+pub struct WebsiteTest {
+    pub name: String,
+    /// 导出 Clash 配置时生成 Top-{name} 策略组，包含通过检测且评分最高的 top_n 个节点
+    #[serde(default)]
+    pub top_n: Option<usize>,
+    #[serde(flatten)]
+    pub test: DelayTestConfig,
+}
+
+/// 默认检测 OpenAI 与 Claude 是否可用
+pub fn default_websites() -> Vec<WebsiteTest> {
+    vec![
+        WebsiteTest {
+            name: "OpenAI".to_string(),
+            top_n: None,
+            test: DelayTestConfig::new("https://auth.openai.com/favicon.ico", 200, 5000),
+        },
+        WebsiteTest {
+            name: "Claude".to_string(),
+            top_n: None,
+            test: DelayTestConfig {
+                url: "https://claude.ai/login".to_string(),
+                timeout: 5000,
+                not_contains: Some("unavailable".to_string()),
+                ..Default::default()
+            },
+        },
+    ]
+}
 
 fn build_client(proxy_url: &str) -> Result<Client> {
+    build_client_with_timeout(proxy_url, TIMEOUT)
+}
+
+fn build_client_with_timeout(proxy_url: &str, timeout: Duration) -> Result<Client> {
     Client::builder()
         .proxy(reqwest::Proxy::all(proxy_url).context("Failed to create proxy configuration")?)
-        .timeout(TIMEOUT)
+        .timeout(timeout)
         .build()
         .context("Failed to build HTTP client")
 }
 
-pub async fn claude_is_ok(proxy_url: &str) -> Result<()> {
-    let url = "https://claude.ai/login";
-    let client = build_client(proxy_url)?;
-    let resp = client
-        .get(url)
-        .header("User-Agent", USER_AGENT)
+/// 通过代理请求网站，按配置的状态码及响应内容判断是否可用
+///
+/// 配置了 echo_ip 时响应内容必须包含节点的出口 IP exit_ip，未知出口 IP 时检测不通过
+pub async fn check(proxy_url: &str, test: &DelayTestConfig, exit_ip: Option<IpAddr>) -> Result<()> {
+    let client = build_client_with_timeout(proxy_url, Duration::from_millis(test.timeout as u64))?;
+    let method = match &test.method {
+        Some(method) => Method::from_bytes(method.to_uppercase().as_bytes())
+            .with_context(|| format!("Invalid http method {}", method))?,
+        None => Method::GET,
+    };
+    let mut request = client
+        .request(method, &test.url)
+        .header("User-Agent", USER_AGENT);
+    for (key, value) in &test.headers {
+        request = request.header(key, value);
+    }
+    let trace_id = test.trace_header.as_ref().map(|_| trace_id(&test.url));
+    if let (Some(header), Some(trace_id)) = (&test.trace_header, &trace_id) {
+        request = request.header(header, trace_id);
+    }
+    let resp = request
         .send()
-        .await?;
+        .await
+        .with_context(|| format!("Failed to send request to {}", test.url))?;
     let status = resp.status();
-    let text = resp.text().await?;
-    if text.contains("unavailable") {
-        return Err(anyhow!("app unavailable"));
+    if !test.is_expected(status.as_u16()) {
+        return Err(anyhow!("http status code: {}", status));
     }
-    if status.is_success() {
+    if test.contains.is_none() && test.not_contains.is_none() && trace_id.is_none() && !test.echo_ip
+    {
         return Ok(());
     }
-    Err(anyhow!("http status code: {}", status))
+    let text = resp.text().await?;
+    if let Some(contains) = &test.contains {
+        if !text.contains(contains.as_str()) {
+            return Err(anyhow!("response does not contain {:?}", contains));
+        }
+    }
+    if let Some(not_contains) = &test.not_contains {
+        if text.contains(not_contains.as_str()) {
+            return Err(anyhow!("response contains {:?}", not_contains));
+        }
+    }
+    if let Some(trace_id) = &trace_id {
+        if !text.contains(trace_id.as_str()) {
+            return Err(anyhow!("response does not echo trace id {}", trace_id));
+        }
+    }
+    if test.echo_ip {
+        let exit_ip = exit_ip.ok_or_else(|| anyhow!("exit IP of the node is unknown"))?;
+        if !contains_ip(&text, exit_ip) {
+            return Err(anyhow!(
+                "response does not contain exit IP {}, request did not go through the node",
+                exit_ip
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// 每次请求不同的 trace id，避免缓存或劫持页面恰好包含固定内容
+fn trace_id(url: &str) -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let seed = format!("{}-{}-{}", std::process::id(), url, nanos);
+    hex::encode(&Sha256::digest(seed.as_bytes())[..8])
+}
+
+/// 响应内容中是否有与 ip 相同的地址，按地址比较，IPv6 的不同写法视为相同
+fn contains_ip(text: &str, ip: IpAddr) -> bool {
+    text.split(|c: char| !(c.is_ascii_hexdigit() || c == '.' || c == ':'))
+        .filter_map(|token| {
+            // 兼容 ip:1.2.3.4 这类键值紧挨着的写法
+            token
+                .parse::<IpAddr>()
+                .or_else(|_| token.trim_matches(':').parse::<IpAddr>())
+                .ok()
+        })
+        .any(|found| found == ip)
+}
+
+#[allow(dead_code)]
+pub async fn claude_is_ok(proxy_url: &str) -> Result<()> {
+    check(proxy_url, &default_websites()[1].test, None).await
 }
 
+#[allow(dead_code)]
 pub async fn openai_is_ok(proxy_url: &str) -> Result<()> {
-    let url = "https://auth.openai.com/favicon.ico";
+    check(proxy_url, &default_websites()[0].test, None).await
+}
+
+pub async fn youtube_music_is_ok(proxy_url: &str) -> Result<bool> {
+    let url = "https://music.youtube.com/generate_204";
     let client = build_client(proxy_url)?;
     let resp = client
-        .get(url)
+        .head(url)
         .header("User-Agent", USER_AGENT)
         .send()
         .await
         .with_context(|| "Failed to send request to OpenAI")?;
     let status = resp.status();
-    if status == StatusCode::OK {
-        return Ok(());
+    if status == StatusCode::NO_CONTENT {
+        return Ok(true);
     }
     Err(anyhow!("error status code: {}", status))
 }
 
-#[allow(dead_code)]
-pub async fn youtube_music_is_ok(proxy_url: &str) -> Result<bool> {
-    let url = "https://music.youtube.com/generate_204";
+/// 通过代理完成一次 HTTP/2 请求，TLS 握手时经 ALPN 协商，回退到 HTTP/1.1 时视为不支持
+pub async fn http2_is_ok(proxy_url: &str) -> Result<()> {
+    let url = format!("https://{}/cdn-cgi/trace", PROTOCOL_PROBE_HOST);
     let client = build_client(proxy_url)?;
     let resp = client
-        .head(url)
+        .get(&url)
         .header("User-Agent", USER_AGENT)
         .send()
         .await
-        .with_context(|| "Failed to send request to OpenAI")?;
-    let status = resp.status();
-    if status == StatusCode::NO_CONTENT {
-        return Ok(true);
+        .with_context(|| format!("Failed to send request to {}", url))?;
+    match resp.version() {
+        Version::HTTP_2 => Ok(()),
+        version => Err(anyhow!("negotiated {:?} instead of HTTP/2", version)),
     }
-    Err(anyhow!("error status code: {}", status))
 }
 
+/// 经代理的 UDP 转发向参考地址的 443 端口发送 QUIC 探测包，收到版本协商回复说明节点能承载 HTTP/3
+pub async fn http3_is_ok(proxy_url: &str) -> Result<()> {
+    let target = Target {
+        protocol: Protocol::Udp,
+        host: PROTOCOL_PROBE_HOST.to_string(),
+        port: 443,
+    };
+    target::measure(proxy_url, &target, &target::quic_probe(), TIMEOUT)
+        .await
+        .map(|_| ())
+        .with_context(|| format!("No QUIC response from {}", target))
+}
+
+#[cfg(test)]
 mod test {
+    use super::*;
+
+    #[test]
+    fn test_contains_ip() {
+        let v4 = "1.2.3.4".parse().unwrap();
+        assert!(contains_ip(r#"{"ip":"1.2.3.4","trace":"ab12"}"#, v4));
+        assert!(contains_ip("X-Forwarded-For: 1.2.3.4\n", v4));
+        assert!(contains_ip("ip:1.2.3.4", v4));
+        assert!(!contains_ip(r#"{"ip":"11.2.3.45"}"#, v4));
+        let v6 = "2001:db8::1".parse().unwrap();
+        assert!(contains_ip(
+            "ip=2001:0db8:0000:0000:0000:0000:0000:0001",
+            v6
+        ));
+        assert!(!contains_ip("ip=2001:db8::2", v6));
+        assert!(contains_ip("::1", "::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_trace_id() {
+        let id = trace_id("https://echo.example.com");
+        assert_eq!(id.len(), 16);
+        assert!(id.chars().all(|c| c.is_ascii_hexdigit()));
+    }
+
     #[tokio::test]
     #[ignore]
     async fn test_claude_is_ok() {